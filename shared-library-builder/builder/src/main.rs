@@ -1,24 +1,133 @@
-use libcairo_library::libcairo;
-use shared_library_builder::{Library, LibraryCompilationContext, LibraryTarget};
+use libcairo_library::{
+    libcairo, BuildSystem, CairoLibrary, CompilationContext, GitHubRelease, ParallelBuild,
+    SelfTest, UploadTarget, Watch, SOURCE_LOCK_FILE,
+};
+use shared_library_builder::{Library, LibraryLocation, PathLocation};
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let arguments = std::env::args().skip(1).collect::<Vec<String>>();
+    match arguments.first().map(|command| command.as_str()) {
+        Some("publish") => return publish(&arguments[1..]),
+        Some("upload") => return upload(&arguments[1..]),
+        Some("compile") => return compile(&arguments[1..]),
+        Some("self-test") => return self_test(&arguments[1..]),
+        Some("lock") => return lock(&arguments[1..]),
+        Some("watch") => return watch(&arguments[1..]),
+        _ => {}
+    }
+
     let version: Option<String> = None;
     let cairo = libcairo(version);
 
-    let src_path = Path::new("target/src");
-    if !src_path.exists() {
-        std::fs::create_dir_all(&src_path)?;
+    let context = CompilationContext::new("target").create()?;
+    cairo.check_requirements(&context)?;
+    cairo.ensure_all_sources(&context)?;
+    if let Some(dependencies) = cairo.dependencies() {
+        ParallelBuild::available().compile(dependencies.iter(), &context)?;
     }
+    let compiled_cairo = cairo.compile(&context)?;
+    println!("Compiled {}", compiled_cairo.display());
 
-    let context = LibraryCompilationContext::new(
-        src_path,
-        "target",
-        LibraryTarget::for_current_platform(),
-        false,
-    );
+    if let Some(output_directory) = compiled_cairo.parent() {
+        for symbols in cairo.copy_debug_symbols(&context, output_directory)? {
+            println!("Copied {}", symbols.display());
+        }
+    }
+    Ok(())
+}
+
+/// builder publish <tag> <artifact>...
+fn publish(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let (tag, artifacts) = arguments
+        .split_first()
+        .ok_or("Usage: builder publish <tag> <artifact>...")?;
+
+    let artifacts = artifacts.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+    GitHubRelease::libcairo(tag).publish(&artifacts)
+}
+
+/// builder upload <s3://bucket/prefix | url> <artifact>...
+fn upload(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let (destination, artifacts) = arguments
+        .split_first()
+        .ok_or("Usage: builder upload <s3://bucket/prefix | url> <artifact>...")?;
+
+    let artifacts = artifacts.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+    UploadTarget::parse(destination)?.upload(&artifacts)
+}
+
+/// builder compile <configuration.json> <build root> [debug | release]
+/// Used by remote builders to compile a configuration serialized by another machine
+fn compile(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage = "Usage: builder compile <configuration.json> <build root> [debug | release]";
+    let configuration = arguments.first().ok_or(usage)?;
+    let build_root = Path::new(arguments.get(1).ok_or(usage)?);
+    let debug = arguments.get(2).is_some_and(|profile| profile == "debug");
+
+    let context = CompilationContext::new(build_root)
+        .with_debug(debug)
+        .create()?;
+    let cairo = CairoLibrary::load(configuration)?;
+    cairo.check_requirements(&context)?;
+    cairo.ensure_all_sources(&context)?;
     let compiled_cairo = cairo.compile(&context)?;
     println!("Compiled {}", compiled_cairo.display());
     Ok(())
 }
+
+/// builder self-test [workspace]
+/// Builds cairo for this machine in a temporary or the given workspace and runs its checks
+fn self_test(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut self_test = SelfTest::new();
+    if let Some(workspace) = arguments.first() {
+        self_test = self_test.with_workspace(workspace).with_keep_workspace(true);
+    }
+    let report = self_test.run();
+    println!("{}", &report);
+    report.into_result()
+}
+
+/// builder lock [lock file]
+/// Fetches the sources of cairo and its dependencies anew and records them in the lock file
+fn lock(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = arguments
+        .first()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(SOURCE_LOCK_FILE));
+
+    let context = CompilationContext::new("target").create()?;
+    let version: Option<String> = None;
+    let lock = libcairo(version).lock_sources(&context, &path)?;
+    for source in &lock.sources {
+        println!("{} {}", source.sha256, source.library);
+    }
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// builder watch <cairo sources> [--smoke-tests]
+/// Rebuilds cairo from a local source tree whenever one of its sources changes
+fn watch(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let sources = Path::new(
+        arguments
+            .first()
+            .ok_or("Usage: builder watch <cairo sources> [--smoke-tests]")?,
+    );
+    let smoke_tests = arguments.iter().any(|argument| argument == "--smoke-tests");
+
+    let build_system = if sources.join("meson.build").exists() {
+        BuildSystem::Meson
+    } else {
+        BuildSystem::Autotools
+    };
+    let cairo = CairoLibrary::gt_default()
+        .with_source_location(LibraryLocation::Path(PathLocation::new(sources)))
+        .with_build_system(build_system);
+
+    let context = CompilationContext::new("target").create()?;
+    Watch::new(cairo)
+        .with_smoke_tests(smoke_tests)
+        .run(&context)
+}
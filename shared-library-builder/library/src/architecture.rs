@@ -0,0 +1,166 @@
+use shared_library_builder::LibraryTarget;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// The processor architecture a binary contains code for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Architecture {
+    X86,
+    X86_64,
+    Arm,
+    Aarch64,
+}
+
+impl Architecture {
+    /// The architecture of a target, `None` for one we do not know how to recognise
+    pub fn of_target(target: &LibraryTarget) -> Option<Self> {
+        let target = target.to_string();
+        match target.split('-').next().unwrap_or("") {
+            "i386" | "i586" | "i686" => Some(Self::X86),
+            "x86_64" => Some(Self::X86_64),
+            "aarch64" | "arm64" => Some(Self::Aarch64),
+            cpu if cpu.starts_with("arm") || cpu.starts_with("thumb") => Some(Self::Arm),
+            _ => None,
+        }
+    }
+
+    /// The architectures in the header of an ELF, Mach-O (including universal) or PE binary.
+    /// Empty when the format or the machine is not recognised
+    pub fn of_binary(binary: &Path) -> Result<Vec<Self>, Box<dyn Error>> {
+        let bytes = std::fs::read(binary)?;
+        Ok(architectures(&bytes))
+    }
+}
+
+impl Display for Architecture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::X86 => "x86",
+            Self::X86_64 => "x86_64",
+            Self::Arm => "arm",
+            Self::Aarch64 => "aarch64",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Fail if any of the binaries does not contain code for the architecture of the target.
+/// A cross build that silently fell back to the host compiler is caught here
+/// instead of when the application fails to load on the device
+pub fn verify_architecture(
+    binaries: &[PathBuf],
+    target: &LibraryTarget,
+) -> Result<(), Box<dyn Error>> {
+    let expected = match Architecture::of_target(target) {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    for binary in binaries {
+        let architectures = Architecture::of_binary(binary)?;
+        if architectures.is_empty() || architectures.contains(&expected) {
+            continue;
+        }
+        return Err(UserFacingError::new(format!(
+            "{} was built for the wrong architecture",
+            binary.display()
+        ))
+        .reason(format!(
+            "It contains {} code but {} needs {}",
+            architectures
+                .iter()
+                .map(|architecture| architecture.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+            target,
+            expected
+        ))
+        .help("Check that the cross toolchain is installed and that CC is not overridden")
+        .into());
+    }
+    Ok(())
+}
+
+fn architectures(bytes: &[u8]) -> Vec<Architecture> {
+    if bytes.starts_with(b"\x7fELF") {
+        return elf_architecture(bytes).into_iter().collect();
+    }
+    if bytes.starts_with(b"MZ") {
+        return pe_architecture(bytes).into_iter().collect();
+    }
+    // a universal binary, its header is big endian
+    if read_u32(bytes, 0, true) == Some(0xcafebabe) {
+        return universal_architectures(bytes);
+    }
+    match read_u32(bytes, 0, false) {
+        Some(0xfeedface) | Some(0xfeedfacf) => read_u32(bytes, 4, false)
+            .and_then(mach_o_architecture)
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn elf_architecture(bytes: &[u8]) -> Option<Architecture> {
+    let big_endian = *bytes.get(5)? == 2;
+    match read_u16(bytes, 18, big_endian)? {
+        3 => Some(Architecture::X86),
+        62 => Some(Architecture::X86_64),
+        40 => Some(Architecture::Arm),
+        183 => Some(Architecture::Aarch64),
+        _ => None,
+    }
+}
+
+fn pe_architecture(bytes: &[u8]) -> Option<Architecture> {
+    let header = read_u32(bytes, 0x3c, false)? as usize;
+    if bytes.get(header..header + 4)? != b"PE\0\0" {
+        return None;
+    }
+    match read_u16(bytes, header + 4, false)? {
+        0x14c => Some(Architecture::X86),
+        0x8664 => Some(Architecture::X86_64),
+        0x1c0 | 0x1c4 => Some(Architecture::Arm),
+        0xaa64 => Some(Architecture::Aarch64),
+        _ => None,
+    }
+}
+
+fn universal_architectures(bytes: &[u8]) -> Vec<Architecture> {
+    let count = read_u32(bytes, 4, true).unwrap_or(0) as usize;
+    (0..count)
+        .filter_map(|index| read_u32(bytes, 8 + index * 20, true))
+        .filter_map(mach_o_architecture)
+        .collect()
+}
+
+fn mach_o_architecture(cpu_type: u32) -> Option<Architecture> {
+    match cpu_type {
+        7 => Some(Architecture::X86),
+        0x0100_0007 => Some(Architecture::X86_64),
+        12 => Some(Architecture::Arm),
+        0x0100_000c => Some(Architecture::Aarch64),
+        _ => None,
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = bytes.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The files a build installed, so callers do not have to guess file names inside the prefix
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuiltArtifacts {
+    pub prefix: PathBuf,
+    pub shared_libraries: Vec<PathBuf>,
+    pub static_libraries: Vec<PathBuf>,
+    /// The folder to add to the include path
+    pub include_directory: Option<PathBuf>,
+    pub headers: Vec<PathBuf>,
+    pub pkg_config_files: Vec<PathBuf>,
+    /// The shared libraries of dependencies that must be shipped next to cairo
+    pub dependency_binaries: Vec<PathBuf>,
+}
+
+/// The files with the extension in the folder and its subfolders, sorted
+pub(crate) fn files_with_extension(directory: &Path, extension: &str) -> Vec<PathBuf> {
+    fn collect(directory: &Path, extension: &str, files: &mut Vec<PathBuf>) {
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.is_dir() {
+                collect(&path, extension, files);
+            } else if path.extension().is_some_and(|each| each == extension) {
+                files.push(path);
+            }
+        }
+    }
+    let mut files = vec![];
+    collect(directory, extension, &mut files);
+    files.sort();
+    files
+}
@@ -0,0 +1,103 @@
+use crate::binaries::shared_libraries_in;
+use crate::fetch::{ensure_sources, libraries_to_fetch};
+use crate::scheduler::{library_key, ParallelBuild};
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Builds cairo together with sibling libraries as one dependency graph and packages
+/// the result. Libraries configured the same way, such as the zlib of both libpng and
+/// freetype, are compiled once and their install prefix is shared by everything depending on it
+#[derive(Debug)]
+pub struct BuildBatch {
+    libraries: Vec<Box<dyn Library>>,
+    parallel_build: ParallelBuild,
+}
+
+impl Default for BuildBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildBatch {
+    pub fn new() -> Self {
+        Self {
+            libraries: vec![],
+            parallel_build: ParallelBuild::available(),
+        }
+    }
+
+    pub fn with_library(mut self, library: impl Into<Box<dyn Library>>) -> Self {
+        self.libraries.push(library.into());
+        self
+    }
+
+    pub fn with_parallel_build(mut self, parallel_build: ParallelBuild) -> Self {
+        self.parallel_build = parallel_build;
+        self
+    }
+
+    /// The libraries of the batch with all of their dependencies, each once
+    pub fn graph(&self) -> Vec<Box<dyn Library>> {
+        let mut keys = BTreeSet::new();
+        self.libraries
+            .iter()
+            .flat_map(|library| libraries_to_fetch(library.as_ref()))
+            .filter(|library| keys.insert(library_key(library.as_ref())))
+            .collect()
+    }
+
+    /// Download all sources, then compile the whole graph. Every library of the batch fetches
+    /// and prepares its sources first, cairo for example its whole graph including the patches
+    /// of freetype. The remaining dependencies are downloaded concurrently afterwards
+    pub fn build(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        // one after the other, their graphs may share the sources of a dependency
+        for library in &self.libraries {
+            library.ensure_sources(context)?;
+        }
+        let fetched = self
+            .libraries
+            .iter()
+            .map(|library| library_key(library.as_ref()))
+            .collect::<BTreeSet<String>>();
+        let graph = self.graph();
+        ensure_sources(
+            &graph
+                .iter()
+                .map(|library| library.as_ref())
+                .filter(|library| !fetched.contains(&library_key(*library)))
+                .collect::<Vec<&dyn Library>>(),
+            context,
+        )?;
+        self.parallel_build.compile(self.libraries.iter(), context)
+    }
+
+    /// Build the batch and copy the shared libraries of the whole graph into one folder,
+    /// returning the copied files
+    pub fn package(
+        &self,
+        context: &LibraryCompilationContext,
+        destination: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        self.build(context)?;
+        std::fs::create_dir_all(destination)?;
+
+        let mut copied = vec![];
+        for library in self.graph().iter().filter(|library| library.is_shared()) {
+            let directories = library.compiled_library_directories(context);
+            for binary in shared_libraries_in(&directories, context) {
+                if let Some(file_name) = binary.file_name() {
+                    let target = destination.join(file_name);
+                    if copied.contains(&target) {
+                        continue;
+                    }
+                    std::fs::copy(&binary, &target)?;
+                    copied.push(target);
+                }
+            }
+        }
+        Ok(copied)
+    }
+}
@@ -0,0 +1,279 @@
+use crate::cairo_library::CairoLibrary;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// An installed cairo with its perf tools, one side of a [`Benchmark`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchmarkedBuild {
+    name: String,
+    prefix: PathBuf,
+    library_directories: Vec<PathBuf>,
+}
+
+impl BenchmarkedBuild {
+    /// The install prefix of a cairo built with its perf tools, the libraries are loaded from its `lib`
+    pub fn new(name: impl Into<String>, prefix: impl Into<PathBuf>) -> Self {
+        let prefix = prefix.into();
+        Self {
+            name: name.into(),
+            library_directories: vec![prefix.join("lib")],
+            prefix,
+        }
+    }
+
+    /// A configuration compiled with [`CairoLibrary::with_perf_tools`], together with the
+    /// pixman, freetype and other dependencies it was built with
+    pub fn of(
+        name: impl Into<String>,
+        cairo: &CairoLibrary,
+        context: &LibraryCompilationContext,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            prefix: cairo.native_library_prefix(context),
+            library_directories: cairo.compiled_library_directories_with_dependencies(context),
+        }
+    }
+
+    pub fn with_library_directories(mut self, library_directories: Vec<PathBuf>) -> Self {
+        self.library_directories = library_directories;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn perf_trace(&self) -> PathBuf {
+        self.prefix.join("bin").join("cairo-perf-trace")
+    }
+}
+
+/// Replays the same cairo traces with `cairo-perf-trace` of two builds, for example a baseline
+/// and one with LTO, PGO or other CPU tuning, and compares the median time of every trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Benchmark {
+    baseline: BenchmarkedBuild,
+    candidate: BenchmarkedBuild,
+    traces: Vec<PathBuf>,
+    iterations: usize,
+    targets: Option<String>,
+}
+
+impl Benchmark {
+    pub fn new(baseline: BenchmarkedBuild, candidate: BenchmarkedBuild) -> Self {
+        Self {
+            baseline,
+            candidate,
+            traces: vec![],
+            iterations: 10,
+            targets: None,
+        }
+    }
+
+    /// A trace recorded with `cairo-trace`, or a folder of them
+    pub fn trace(mut self, trace: impl Into<PathBuf>) -> Self {
+        self.traces.push(trace.into());
+        self
+    }
+
+    /// How many times every trace is replayed, 10 by default
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// The backends the traces are replayed on, for example `image`. By default every
+    /// backend cairo-perf-trace supports in the build, see `CAIRO_TEST_TARGET`
+    pub fn with_targets(mut self, targets: impl Into<String>) -> Self {
+        self.targets = Some(targets.into());
+        self
+    }
+
+    /// Replay the traces with the baseline and then with the candidate
+    pub fn run(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<BenchmarkReport, Box<dyn Error>> {
+        if !context.is_unix() {
+            return Err(UserFacingError::new("Could not run the benchmark")
+                .reason("The cairo perf tools are only built on Unix")
+                .into());
+        }
+        if self.traces.is_empty() {
+            return Err(UserFacingError::new("Could not run the benchmark")
+                .reason("There are no traces to replay")
+                .help("Add traces recorded with cairo-trace with Benchmark::trace")
+                .into());
+        }
+        let mut missing = vec![];
+        for build in [&self.baseline, &self.candidate] {
+            if !build.perf_trace().is_file() {
+                missing.push(format!("{} does not exist", build.perf_trace().display()));
+            }
+        }
+        for trace in &self.traces {
+            if !trace.exists() {
+                missing.push(format!("{} does not exist", trace.display()));
+            }
+        }
+        if !missing.is_empty() {
+            let mut error = UserFacingError::new("Could not run the benchmark");
+            for reason in missing {
+                error = error.reason(reason);
+            }
+            return Err(error
+                .help("Build both configurations with CairoLibrary::with_perf_tools")
+                .into());
+        }
+
+        let baseline = self.replay(&self.baseline, context)?;
+        let candidate = self.replay(&self.candidate, context)?;
+        let results = baseline
+            .iter()
+            .filter_map(|(test, baseline_ms)| {
+                candidate.get(test).map(|candidate_ms| BenchmarkResult {
+                    test: test.clone(),
+                    baseline_ms: *baseline_ms,
+                    candidate_ms: *candidate_ms,
+                })
+            })
+            .collect();
+
+        Ok(BenchmarkReport {
+            baseline: self.baseline.name.clone(),
+            candidate: self.candidate.name.clone(),
+            results,
+        })
+    }
+
+    /// The median time in milliseconds of every test, by backend and trace
+    fn replay(
+        &self,
+        build: &BenchmarkedBuild,
+        context: &LibraryCompilationContext,
+    ) -> Result<BTreeMap<String, f64>, Box<dyn Error>> {
+        let library_path_variable = if context.is_mac() {
+            "DYLD_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+        let mut library_path = build.library_directories.clone();
+        if let Ok(ref path) = std::env::var(library_path_variable) {
+            std::env::split_paths(path).for_each(|path| library_path.push(path));
+        }
+
+        let mut command = Command::new(build.perf_trace());
+        command
+            .arg("-r")
+            .arg("-i")
+            .arg(self.iterations.to_string())
+            .args(&self.traces)
+            .env(library_path_variable, std::env::join_paths(&library_path)?);
+        if let Some(ref targets) = self.targets {
+            command.env("CAIRO_TEST_TARGET", targets);
+        }
+        println!("{:?}", &command);
+
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(UserFacingError::new(format!(
+                "Could not replay the traces with {}",
+                build.name
+            ))
+            .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+            .into());
+        }
+        Ok(parse_raw_times(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// The `[*] backend.content name.size ticks-per-ms time...` rows of `cairo-perf-trace -r`
+fn parse_raw_times(output: &str) -> BTreeMap<String, f64> {
+    let mut times = BTreeMap::new();
+    for line in output.lines() {
+        let columns = line.split_whitespace().collect::<Vec<&str>>();
+        if let ["[*]", backend, name, ticks_per_ms, samples @ ..] = columns.as_slice() {
+            let ticks_per_ms = match ticks_per_ms.parse::<f64>() {
+                Ok(ticks_per_ms) if ticks_per_ms > 0.0 => ticks_per_ms,
+                _ => continue,
+            };
+            let mut samples = samples
+                .iter()
+                .filter_map(|sample| sample.parse::<f64>().ok())
+                .collect::<Vec<f64>>();
+            if samples.is_empty() {
+                continue;
+            }
+            samples.sort_by(|a, b| a.total_cmp(b));
+            let median = samples[samples.len() / 2];
+            times.insert(format!("{} {}", backend, name), median / ticks_per_ms);
+        }
+    }
+    times
+}
+
+/// The median times of the traces replayed by both builds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    pub baseline: String,
+    pub candidate: String,
+    pub results: Vec<BenchmarkResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    /// The backend and the trace, for example `image.rgba firefox-talos-gfx.0`
+    pub test: String,
+    pub baseline_ms: f64,
+    pub candidate_ms: f64,
+}
+
+impl BenchmarkResult {
+    /// How much longer the candidate takes in percent, negative when it is faster
+    pub fn change_percent(&self) -> f64 {
+        (self.candidate_ms - self.baseline_ms) / self.baseline_ms * 100.0
+    }
+}
+
+impl BenchmarkReport {
+    /// The tests the candidate is slower at by more than the given percentage
+    pub fn regressions(&self, tolerance_percent: f64) -> Vec<&BenchmarkResult> {
+        self.results
+            .iter()
+            .filter(|result| result.change_percent() > tolerance_percent)
+            .collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl Display for BenchmarkReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<48} {:>12} {:>12} {:>8}",
+            "test", &self.baseline, &self.candidate, "change"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:<48} {:>10.3}ms {:>10.3}ms {:>+7.1}%",
+                result.test,
+                result.baseline_ms,
+                result.candidate_ms,
+                result.change_percent()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use shared_library_builder::LibraryCompilationContext;
+use std::path::PathBuf;
+
+/// The dylibs in the given folders, skipping version symlinks
+pub fn dylibs_in(directories: &[PathBuf]) -> Vec<PathBuf> {
+    binaries_in(directories, |file_name| file_name.ends_with(".dylib"))
+}
+
+/// The DLLs in the given folders
+pub fn dlls_in(directories: &[PathBuf]) -> Vec<PathBuf> {
+    binaries_in(directories, |file_name| file_name.ends_with(".dll"))
+}
+
+/// The ELF shared objects in the given folders, including versioned ones
+/// like `libcairo.so.2.11704.0` but skipping version symlinks
+pub fn shared_objects_in(directories: &[PathBuf]) -> Vec<PathBuf> {
+    binaries_in(directories, |file_name| {
+        file_name.ends_with(".so") || file_name.contains(".so.")
+    })
+}
+
+/// The shared libraries of the target platform in the given folders
+pub fn shared_libraries_in(
+    directories: &[PathBuf],
+    context: &LibraryCompilationContext,
+) -> Vec<PathBuf> {
+    if context.is_windows() {
+        dlls_in(directories)
+    } else if context.is_mac() {
+        dylibs_in(directories)
+    } else {
+        shared_objects_in(directories)
+    }
+}
+
+/// The static libraries in the given folders, `.a` archives and the `-static.lib` of MSVC
+pub fn static_libraries_in(directories: &[PathBuf]) -> Vec<PathBuf> {
+    binaries_in(directories, |file_name| {
+        file_name.ends_with(".a") || file_name.ends_with("-static.lib")
+    })
+}
+
+fn binaries_in(directories: &[PathBuf], matches: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    directories
+        .iter()
+        .filter_map(|directory| std::fs::read_dir(directory).ok())
+        .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|file_name| matches(&file_name.to_string_lossy()))
+        })
+        .filter(|path| {
+            std::fs::symlink_metadata(path)
+                .map(|metadata| !metadata.file_type().is_symlink())
+                .unwrap_or(false)
+        })
+        .collect()
+}
@@ -0,0 +1,222 @@
+use crate::compiler_flags::CompilerFlags;
+use crate::environment_log::EnvironmentLog;
+use crate::glibc_baseline::GlibcBaseline;
+use crate::requirements::{Requirements, Tool};
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{
+    Library, LibraryCompilationContext, LibraryDependencies, LibraryLocation, LibraryOptions,
+    TarArchive, TarUrlLocation,
+};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The brotli decoder FreeType decompresses WOFF2 fonts with, built with CMake
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrotliLibrary {
+    location: LibraryLocation,
+    options: LibraryOptions,
+    flags: CompilerFlags,
+    glibc_baseline: Option<GlibcBaseline>,
+}
+
+impl Default for BrotliLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BrotliLibrary {
+    pub fn new() -> Self {
+        Self {
+            location: LibraryLocation::Tar(
+                TarUrlLocation::new(
+                    "https://github.com/google/brotli/archive/refs/tags/v1.1.0.tar.gz",
+                )
+                .archive(TarArchive::Gz)
+                .sources(Path::new("brotli-1.1.0")),
+            ),
+            options: Default::default(),
+            flags: Default::default(),
+            glibc_baseline: None,
+        }
+    }
+
+    /// Adjust the options of brotli, for example `.with_options(|options| options.be_static())`
+    pub fn with_options(mut self, configure: impl FnOnce(&mut LibraryOptions)) -> Self {
+        configure(&mut self.options);
+        self
+    }
+
+    /// Extra compiler and linker flags used when building brotli
+    pub fn with_flags(mut self, flags: CompilerFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Compile against the sysroot of the glibc baseline of cairo
+    pub fn with_glibc_baseline(mut self, glibc_baseline: Option<GlibcBaseline>) -> Self {
+        self.glibc_baseline = glibc_baseline;
+        self
+    }
+
+    fn compiler_flags(&self, options: &LibraryCompilationContext) -> CompilerFlags {
+        match self.glibc_baseline {
+            Some(ref glibc_baseline) if options.is_linux() => {
+                self.flags.clone().extend(&glibc_baseline.flags())
+            }
+            _ => self.flags.clone(),
+        }
+    }
+
+    pub fn check_requirements(
+        &self,
+        _options: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut requirements = Requirements::new(self.name());
+        requirements.tool(Tool::CMAKE);
+        requirements.check()
+    }
+
+    fn build_type(options: &LibraryCompilationContext) -> &'static str {
+        if options.is_debug() {
+            "Debug"
+        } else {
+            "Release"
+        }
+    }
+
+    fn run(
+        &self,
+        mut command: Command,
+        options: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        println!("{:?}", &command);
+        EnvironmentLog::in_prefix(&self.native_library_prefix(options)).record(&command)?;
+        if !command.status()?.success() {
+            return Err(
+                UserFacingError::new(format!("Could not compile {}", self.name()))
+                    .reason(format!("{:?} failed", command.get_program()))
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl Library for BrotliLibrary {
+    fn location(&self) -> &LibraryLocation {
+        &self.location
+    }
+
+    fn name(&self) -> &str {
+        "brotli"
+    }
+
+    fn dependencies(&self) -> Option<&LibraryDependencies> {
+        None
+    }
+
+    fn options(&self) -> &LibraryOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut LibraryOptions {
+        &mut self.options
+    }
+
+    fn force_compile(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let prefix = self.native_library_prefix(options);
+        let build = prefix.join("build");
+        EnvironmentLog::in_prefix(&prefix).clear()?;
+
+        let mut command = Command::new(Tool::CMAKE.name);
+        command
+            .arg("-S")
+            .arg(self.source_directory(options))
+            .arg("-B")
+            .arg(&build)
+            .arg(format!("-DCMAKE_INSTALL_PREFIX={}", prefix.display()))
+            .arg("-DCMAKE_INSTALL_LIBDIR=lib")
+            .arg(format!("-DCMAKE_BUILD_TYPE={}", Self::build_type(options)))
+            .arg(format!(
+                "-DBUILD_SHARED_LIBS={}",
+                if self.is_shared() { "ON" } else { "OFF" }
+            ))
+            .arg("-DCMAKE_POSITION_INDEPENDENT_CODE=ON")
+            .arg("-DBROTLI_DISABLE_TESTS=ON");
+        let flags = self.compiler_flags(options);
+        if !flags.is_empty() {
+            command
+                .env("CFLAGS", flags.cflags_env())
+                .env("LDFLAGS", flags.ldflags_env());
+        }
+        self.run(command, options)?;
+
+        let mut command = Command::new(Tool::CMAKE.name);
+        command
+            .arg("--build")
+            .arg(&build)
+            .arg("--target")
+            .arg("install")
+            .arg("--config")
+            .arg(Self::build_type(options));
+        self.run(command, options)
+    }
+
+    fn compiled_library_directories(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let prefix = self.native_library_prefix(options);
+        if options.target().is_windows() {
+            // cmake installs the DLLs next to the executables
+            return vec![prefix.join("bin"), prefix.join("lib")];
+        }
+        vec![prefix.join("lib")]
+    }
+
+    fn ensure_requirements(&self, options: &LibraryCompilationContext) {
+        if let Err(error) = self.check_requirements(options) {
+            panic!("{}", error);
+        }
+    }
+
+    fn native_library_prefix(&self, options: &LibraryCompilationContext) -> PathBuf {
+        options
+            .build_root()
+            .join(self.name())
+            .join(options.target().to_string())
+            .join(options.profile())
+    }
+
+    fn native_library_include_headers(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        vec![self.native_library_prefix(options).join("include")]
+    }
+
+    fn native_library_linker_libraries(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        vec![self.native_library_prefix(options).join("lib")]
+    }
+
+    fn pkg_config_directory(&self, context: &LibraryCompilationContext) -> Option<PathBuf> {
+        let directory = self
+            .native_library_prefix(context)
+            .join("lib")
+            .join("pkgconfig");
+
+        if directory.exists() {
+            return Some(directory);
+        }
+
+        None
+    }
+
+    fn clone_library(&self) -> Box<dyn Library> {
+        Box::new(Clone::clone(self))
+    }
+}
+
+impl From<BrotliLibrary> for Box<dyn Library> {
+    fn from(library: BrotliLibrary) -> Self {
+        Box::new(library)
+    }
+}
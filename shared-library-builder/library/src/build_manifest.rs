@@ -0,0 +1,361 @@
+use crate::checksums::{sha256, sha256_file};
+use crate::prebuilt::unpack;
+use crate::security_patches::AppliedSecurityPatch;
+use crate::size_report::SizeReport;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The file in the install prefix describing everything that went into the build
+pub const BUILD_MANIFEST_FILE: &str = "build-manifest.json";
+
+/// What to do when an existing install prefix was built with a different configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DriftPolicy {
+    /// Remove the prefix and compile again
+    #[default]
+    Rebuild,
+    /// Stop with an error listing the differences
+    Fail,
+}
+
+/// Everything that went into a build of cairo: versions, sources, patches, flags, toolchain
+/// and the checksums of the produced binaries. Written into the install prefix, so it
+/// is part of every archive made from it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub library: String,
+    pub version: String,
+    pub target: String,
+    pub profile: String,
+    pub configuration_hash: String,
+    /// The serialized configuration the hash is computed from
+    pub configuration: serde_json::Value,
+    pub sources: Vec<ManifestSource>,
+    pub patches: Vec<ManifestFile>,
+    pub security_patches: Vec<AppliedSecurityPatch>,
+    pub cflags: Vec<String>,
+    pub ldflags: Vec<String>,
+    pub toolchain: Toolchain,
+    /// Binaries relative to the install prefix
+    pub binaries: Vec<ManifestFile>,
+    /// The size of the binaries when a size report was requested
+    #[serde(default)]
+    pub size: Option<SizeReport>,
+}
+
+/// Where the sources of a library were taken from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestSource {
+    pub library: String,
+    /// The urls and git reference of the sources, see [`crate::SourceOrigin`]
+    pub location: String,
+    /// The checksum of the fetched source tree, the one of the source lock. It is only
+    /// known once the sources are fetched, so it is not compared when checking for drift
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// A file with its SHA-256 checksum
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub name: String,
+    pub sha256: String,
+}
+
+impl ManifestFile {
+    pub fn for_contents(name: impl Into<String>, contents: impl AsRef<[u8]>) -> Self {
+        Self {
+            name: name.into(),
+            sha256: sha256(contents.as_ref()),
+        }
+    }
+}
+
+/// Identification of the tools that produced the binaries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Toolchain {
+    /// The first line of `$CC --version`, empty when it can not be run
+    pub compiler: String,
+    pub container: Option<String>,
+    pub host: String,
+    pub builder: String,
+}
+
+impl Toolchain {
+    /// The toolchain of this machine with the version line of the compiler the build runs
+    pub fn current(container: Option<String>, compiler: String) -> Self {
+        Self {
+            compiler,
+            container,
+            host: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            builder: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// The first line `--version` prints for a C compiler given with its arguments like `CC`,
+/// `None` for the one of the environment, `$CC` or `cc`. A compiler update changes it.
+/// `in_container` runs the compiler where the build does, the MSVC tools print it to stderr
+pub(crate) fn compiler_version(
+    compiler: Option<String>,
+    in_container: impl FnOnce(Command) -> Command,
+) -> String {
+    let compiler = compiler
+        .or_else(|| std::env::var("CC").ok())
+        .unwrap_or_else(|| "cc".to_owned());
+    let mut words = compiler.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("cc"));
+    command.args(words).arg("--version");
+
+    in_container(command)
+        .output()
+        .map(|output| {
+            let first_line = |bytes: &[u8]| {
+                String::from_utf8_lossy(bytes)
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .find(|line| !line.is_empty())
+            };
+            first_line(&output.stdout)
+                .or_else(|| first_line(&output.stderr))
+                .unwrap_or_default()
+        })
+        .unwrap_or_default()
+}
+
+impl BuildManifest {
+    /// Record the checksums of the binaries in the given folders of the install prefix
+    pub fn with_binaries_in(
+        mut self,
+        prefix: &Path,
+        directories: &[PathBuf],
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut files = directories
+            .iter()
+            .filter_map(|directory| std::fs::read_dir(directory).ok())
+            .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+
+        for file in files {
+            let name = file.strip_prefix(prefix).unwrap_or(&file);
+            self.binaries.push(ManifestFile {
+                name: name.display().to_string().replace('\\', "/"),
+                sha256: sha256_file(&file)?,
+            });
+        }
+        Ok(self)
+    }
+
+    /// The differences between the inputs recorded here and the ones of the requested build,
+    /// empty if the binaries can be reused. Binaries and the builder itself are not compared
+    pub fn drift(&self, requested: &BuildManifest) -> Vec<String> {
+        let mut differences = vec![];
+        let mut compare = |name: &str, recorded: String, requested: String| {
+            if recorded != requested {
+                differences.push(format!(
+                    "{} was {} but {} is requested",
+                    name, recorded, requested
+                ));
+            }
+        };
+        compare("version", self.version.clone(), requested.version.clone());
+        compare("target", self.target.clone(), requested.target.clone());
+        compare("profile", self.profile.clone(), requested.profile.clone());
+        let sources = |manifest: &BuildManifest| {
+            manifest
+                .sources
+                .iter()
+                .map(|source| format!("{} from {}", source.library, source.location))
+                .collect::<Vec<String>>()
+        };
+        compare(
+            "sources",
+            format!("{:?}", sources(self)),
+            format!("{:?}", sources(requested)),
+        );
+        compare(
+            "patches",
+            format!("{:?}", self.patches),
+            format!("{:?}", requested.patches),
+        );
+        // skipped hunks are only known once the sources are patched
+        let security_patches = |manifest: &BuildManifest| {
+            manifest
+                .security_patches
+                .iter()
+                .map(|patch| patch.id.clone())
+                .collect::<Vec<String>>()
+        };
+        compare(
+            "security patches",
+            format!("{:?}", security_patches(self)),
+            format!("{:?}", security_patches(requested)),
+        );
+        compare("cflags", self.cflags.join(" "), requested.cflags.join(" "));
+        compare("ldflags", self.ldflags.join(" "), requested.ldflags.join(" "));
+        compare(
+            "compiler",
+            format!("{:?}", self.toolchain.compiler),
+            format!("{:?}", requested.toolchain.compiler),
+        );
+        compare(
+            "container",
+            format!("{:?}", self.toolchain.container),
+            format!("{:?}", requested.toolchain.container),
+        );
+
+        // name the settings that changed rather than dumping both configurations
+        let empty = serde_json::Map::new();
+        let recorded = self.configuration.as_object().unwrap_or(&empty);
+        let wanted = requested.configuration.as_object().unwrap_or(&empty);
+        let mut settings = recorded.keys().chain(wanted.keys()).collect::<Vec<&String>>();
+        settings.sort();
+        settings.dedup();
+        for setting in settings {
+            if recorded.get(setting) != wanted.get(setting) {
+                differences.push(format!("the {} setting changed", setting));
+            }
+        }
+        differences
+    }
+
+    pub fn write(&self, prefix: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(prefix)?;
+        std::fs::write(
+            prefix.join(BUILD_MANIFEST_FILE),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// The manifest recorded in an install prefix, `None` if there is none
+    pub fn read(prefix: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        let file = prefix.join(BUILD_MANIFEST_FILE);
+        if !file.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&file)?;
+        serde_json::from_str(&contents).map(Some).map_err(|error| {
+            UserFacingError::new(format!("Could not read {}", file.display()))
+                .reason(error.to_string())
+                .into()
+        })
+    }
+
+    /// The manifest packaged in a downloaded `.zip` or tar archive, `None` if there is none.
+    /// The archive is unpacked next to itself, into a folder named after it
+    pub fn read_from_archive(archive: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        let name = archive
+            .file_name()
+            .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+            .to_string_lossy()
+            .to_string();
+        let directory = archive.with_file_name(format!("{}.contents", name));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory)?;
+        }
+        std::fs::create_dir_all(&directory)?;
+        unpack(archive, &directory)?;
+
+        match find_manifest(&directory) {
+            Some(prefix) => Self::read(&prefix),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The folder containing the manifest, archives may wrap the prefix in folders of their own
+fn find_manifest(directory: &Path) -> Option<PathBuf> {
+    if directory.join(BUILD_MANIFEST_FILE).is_file() {
+        return Some(directory.to_path_buf());
+    }
+    let mut directories = std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+        .collect::<Vec<PathBuf>>();
+    directories.sort();
+    directories
+        .iter()
+        .find_map(|directory| find_manifest(directory))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> BuildManifest {
+        BuildManifest {
+            library: "cairo".to_string(),
+            version: "1.17.4".to_string(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            profile: "release".to_string(),
+            configuration_hash: "0123abcd".to_string(),
+            configuration: serde_json::json!({ "minimal": false, "woff2": false }),
+            sources: vec![ManifestSource {
+                library: "cairo".to_string(),
+                location: "https://dl.feenk.com/cairo/cairo-1.17.4.tar.xz".to_string(),
+                sha256: None,
+            }],
+            patches: vec![ManifestFile::for_contents("fix.diff", "contents")],
+            security_patches: vec![],
+            cflags: vec!["-O2".to_string()],
+            ldflags: vec![],
+            toolchain: Toolchain {
+                compiler: "gcc (GCC) 13.2.0".to_string(),
+                container: None,
+                host: "x86_64-linux".to_string(),
+                builder: "libcairo-library 1.0.0".to_string(),
+            },
+            binaries: vec![],
+            size: None,
+        }
+    }
+
+    #[test]
+    fn the_same_inputs_do_not_drift() {
+        let mut built = manifest();
+        // only known after the build
+        built.sources[0].sha256 = Some("fetched".to_string());
+        built.binaries = vec![ManifestFile::for_contents("lib/libcairo.so", "binary")];
+        built.toolchain.builder = "libcairo-library 1.0.1".to_string();
+
+        assert_eq!(built.drift(&manifest()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn names_the_inputs_that_drifted() {
+        let mut requested = manifest();
+        requested.cflags.push("-g".to_string());
+        requested.toolchain.compiler = "gcc (GCC) 14.1.0".to_string();
+        requested.configuration = serde_json::json!({ "minimal": false, "woff2": true });
+
+        assert_eq!(
+            manifest().drift(&requested),
+            vec![
+                "cflags was -O2 but -O2 -g is requested".to_string(),
+                "compiler was \"gcc (GCC) 13.2.0\" but \"gcc (GCC) 14.1.0\" is requested"
+                    .to_string(),
+                "the woff2 setting changed".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_what_was_written() {
+        let prefix =
+            std::env::temp_dir().join(format!("libcairo-build-manifest-{}", std::process::id()));
+        assert_eq!(BuildManifest::read(&prefix).unwrap(), None);
+
+        manifest().write(&prefix).unwrap();
+        assert_eq!(BuildManifest::read(&prefix).unwrap(), Some(manifest()));
+        std::fs::remove_dir_all(&prefix).unwrap();
+    }
+}
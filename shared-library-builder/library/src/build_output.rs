@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// How much the autotools builds of cairo and pixman print. By default automake's
+/// silent rules are left as configured by the package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildOutput {
+    /// One `CC file.lo` line per compiled file
+    Quiet,
+    /// The full command line of every compiler and linker invocation
+    Verbose,
+}
+
+impl BuildOutput {
+    pub fn configure_argument(&self) -> &'static str {
+        match self {
+            Self::Quiet => "--enable-silent-rules",
+            Self::Verbose => "--disable-silent-rules",
+        }
+    }
+
+    /// Overrides the choice of configure for a single `make`
+    pub fn make_variable(&self) -> &'static str {
+        match self {
+            Self::Quiet => "V=0",
+            Self::Verbose => "V=1",
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::hash::short_hash;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Whether the intermediate build tree is removed once a library is compiled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CleanupPolicy {
+    /// Keep the tree for incremental rebuilds and debugging
+    #[default]
+    Keep,
+    /// Remove the tree of successful builds, keep the one of a failed build to inspect it
+    RemoveOnSuccess,
+    /// Remove the tree of failed builds, so that the next build starts from scratch
+    RemoveOnFailure,
+    Remove,
+}
+
+impl CleanupPolicy {
+    fn removes(&self, succeeded: bool) -> bool {
+        match self {
+            Self::Keep => false,
+            Self::RemoveOnSuccess => succeeded,
+            Self::RemoveOnFailure => !succeeded,
+            Self::Remove => true,
+        }
+    }
+}
+
+/// Where the autotools and meson builds keep their object files, generated makefiles and logs.
+/// By default the autotools builds configure in the install prefix and meson in its `build`
+/// folder, both under the build root. A location such as a local SSD or a tmpfs moves the
+/// trees out of the workspace, one per library, target, profile and install prefix
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildTree {
+    location: Option<PathBuf>,
+    cleanup: CleanupPolicy,
+}
+
+impl BuildTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build in folders of the given directory instead of the build root
+    pub fn in_directory(location: impl Into<PathBuf>) -> Self {
+        Self {
+            location: Some(location.into()),
+            cleanup: CleanupPolicy::default(),
+        }
+    }
+
+    pub fn with_cleanup(mut self, cleanup: CleanupPolicy) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    pub fn location(&self) -> Option<&Path> {
+        self.location.as_deref()
+    }
+
+    pub fn cleanup(&self) -> CleanupPolicy {
+        self.cleanup
+    }
+
+    /// The tree of the library, the default directory unless a location is set.
+    /// Prefixes are named after the configuration of the library, so are the trees
+    pub fn directory(
+        &self,
+        library: &str,
+        default: PathBuf,
+        context: &LibraryCompilationContext,
+    ) -> PathBuf {
+        match self.location {
+            Some(ref location) => location
+                .join(library)
+                .join(context.target().to_string())
+                .join(context.profile())
+                .join(short_hash(default.to_string_lossy().as_bytes())),
+            None => default,
+        }
+    }
+
+    /// Remove the tree after a build if the policy says so
+    pub fn clean_up(&self, directory: &Path, succeeded: bool) -> Result<(), Box<dyn Error>> {
+        if self.cleanup.removes(succeeded) && directory.exists() {
+            println!("Removing the build tree {}", directory.display());
+            std::fs::remove_dir_all(directory)?;
+        }
+        Ok(())
+    }
+}
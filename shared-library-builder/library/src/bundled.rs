@@ -0,0 +1,45 @@
+use shared_library_builder::Library;
+use std::error::Error;
+#[cfg(not(feature = "freetype"))]
+use user_error::UserFacingError;
+
+/// The freetype we build together with libpng and zlib, downloading the binaries of the
+/// release when given. The builders come from feenkcom/libfreetype and are only
+/// compiled with the `freetype` feature
+#[cfg(feature = "freetype")]
+pub fn freetype(binary_version: Option<String>) -> Result<Box<dyn Library>, Box<dyn Error>> {
+    Ok(libfreetype_library::libfreetype(binary_version).into())
+}
+
+#[cfg(feature = "freetype")]
+pub fn libpng() -> Result<Box<dyn Library>, Box<dyn Error>> {
+    Ok(libfreetype_library::libpng().into())
+}
+
+#[cfg(feature = "freetype")]
+pub fn libzlib() -> Result<Box<dyn Library>, Box<dyn Error>> {
+    Ok(libfreetype_library::libzlib().into())
+}
+
+#[cfg(not(feature = "freetype"))]
+pub fn freetype(_binary_version: Option<String>) -> Result<Box<dyn Library>, Box<dyn Error>> {
+    Err(missing_feature("freetype"))
+}
+
+#[cfg(not(feature = "freetype"))]
+pub fn libpng() -> Result<Box<dyn Library>, Box<dyn Error>> {
+    Err(missing_feature("libpng"))
+}
+
+#[cfg(not(feature = "freetype"))]
+pub fn libzlib() -> Result<Box<dyn Library>, Box<dyn Error>> {
+    Err(missing_feature("zlib"))
+}
+
+#[cfg(not(feature = "freetype"))]
+fn missing_feature(library: &str) -> Box<dyn Error> {
+    UserFacingError::new(format!("Can not build {}", library))
+        .reason("libcairo-library is compiled without the `freetype` feature")
+        .help("Enable the `freetype` feature, or use a system FreeType or the minimal cairo")
+        .into()
+}
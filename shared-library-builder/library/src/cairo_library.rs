@@ -1,24 +1,179 @@
+use crate::architecture::verify_architecture;
+use crate::artifacts::{files_with_extension, BuiltArtifacts};
+use crate::build_manifest::{
+    compiler_version, BuildManifest, DriftPolicy, ManifestFile, ManifestSource, Toolchain,
+};
+use crate::build_output::BuildOutput;
+use crate::build_tree::{BuildTree, CleanupPolicy};
+use crate::certificates::CertificateAuthorities;
+use crate::brotli_library::BrotliLibrary;
+use crate::compilation_context::CompilationContext;
+use crate::compile_commands::{intercept_with_bear, COMPILE_COMMANDS};
+use crate::compiler_flags::{CompilerFlags, FlagList};
+use crate::config_log::configure_failure;
+use crate::configure_cache::configure_cache_argument;
+use crate::container::ContainerBuild;
+use crate::cpu_tuning::CpuTuning;
+use crate::cross::{cross_toolchain, retarget, CrossToolchain};
+use crate::environment_log::EnvironmentLog;
+use crate::fetch::{block_on, download_sources, ensure_library_sources, libraries_to_fetch};
+use crate::freetype_options::{
+    color_fonts_patch, freetype_cmake_patch, verify_freetype_options, COLOR_FONT_OPTIONS,
+    WOFF2_OPTIONS,
+};
+use crate::features::{
+    CairoFeature, FeatureSummary, CAIRO_FEATURES_HEADER, MINIMAL_AUTOTOOLS_OPTIONS,
+    MINIMAL_MESON_OPTIONS,
+};
+use crate::dependency::{Dependency, LinkMode};
+use crate::download_progress::DownloadProgress;
+use crate::glibc_baseline::GlibcBaseline;
+use crate::hash::short_hash;
+use crate::import_library::{dependency_library, write_def_file};
+use crate::local_git::LocalGitCheckout;
+use crate::long_paths::{extended_length, too_long_paths, MAX_PATH};
+use crate::make_jobs::MakeJobs;
+use crate::make_tool::MakeTool;
+use crate::msvc::{self, cairo_features_header, MakefileVariables, WindowsToolchain};
+use crate::naming::{ArtifactNameValues, ArtifactNaming};
+use crate::network::NetworkSettings;
+use crate::pkg_config_leaks::LeakPolicy;
+use crate::patch::{cmake_project_patch, makefile_flag, makefile_quoted, FilePatch, PatchReport};
+use crate::pgo::{PgoPhase, ProfileGuidedOptimization};
 use crate::pixman_library::PixmanLibrary;
-use libfreetype_library::{libfreetype, libpng, libzlib};
+use crate::proxy::Proxy;
+use crate::prebuilt::{unpack, PrebuiltRelease, VerificationFailurePolicy};
+use crate::relocatable::RelocatableBundle;
+use crate::remote::RemoteBuilder;
+use crate::requirements::{Requirements, Tool};
+use crate::bundled::{self, libpng, libzlib};
+use crate::binaries::{
+    dlls_in, dylibs_in, shared_libraries_in, shared_objects_in, static_libraries_in,
+};
+use crate::rpath::RpathPolicy;
+use crate::scheduler::library_key;
+use crate::security_patches::SecurityPatch;
+use crate::size_report::SizeReport;
+use crate::source_cache::{CachedSources, SourceCache};
+use crate::source_lock::{sources_hash, SourceLock, SourceOrigin};
+use crate::smoke_tests::SmokeTest;
+use crate::unified_diff::UnifiedDiff;
+use crate::source_archive::SourceArchive;
+use crate::static_runtime::{verify_static_runtime, STATIC_RUNTIME_FLAGS};
+use crate::symbol_prefix::{SymbolPrefix, SYMBOL_PREFIX_HEADER};
+use crate::signing::{MacSigning, WindowsSigning};
+use crate::version::{BinaryVersion, BuildSystem, CairoVersion};
+use crate::version_resource::{compile_version_resource, crate_release_tag, RELEASE_VERSION};
+use crate::windows_crt::WindowsCrt;
+use crate::x11::missing_x11_headers;
 use shared_library_builder::{
-    Library, LibraryCompilationContext, LibraryDependencies, LibraryLocation, LibraryOptions,
-    TarArchive, TarUrlLocation,
+    GitLocation, Library, LibraryCompilationContext, LibraryDependencies, LibraryLocation,
+    LibraryOptions, LibraryTarget, TarArchive, TarUrlLocation,
 };
 use serde::{Serialize, Deserialize};
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
-use std::fs::{read_to_string, OpenOptions};
-use std::io::Write;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
 use user_error::UserFacingError;
 
+/// The benchmarks of cairo's `perf/` folder installed by `with_perf_tools`
+const PERF_TOOLS: [&str; 2] = ["cairo-perf-micro", "cairo-perf-trace"];
+
+/// The upstream repository of cairo
+pub const CAIRO_GIT_REPOSITORY: &str = "https://gitlab.freedesktop.org/cairo/cairo.git";
+
+/// A branch, tag or commit to check out when building cairo from git
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GitReference {
+    Branch(String),
+    Tag(String),
+    Commit(String),
+}
+
+impl GitReference {
+    pub fn branch(branch: impl Into<String>) -> Self {
+        Self::Branch(branch.into())
+    }
+
+    pub fn tag(tag: impl Into<String>) -> Self {
+        Self::Tag(tag.into())
+    }
+
+    pub fn commit(commit: impl Into<String>) -> Self {
+        Self::Commit(commit.into())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CairoLibrary {
+    version: CairoVersion,
+    build_system: BuildSystem,
     source_location: LibraryLocation,
+    source_archive: Option<SourceArchive>,
+    source_lock: Option<PathBuf>,
+    source_cache: Option<SourceCache>,
+    local_git: Option<LocalGitCheckout>,
     release_location: Option<LibraryLocation>,
+    prebuilt: Option<PrebuiltRelease>,
+    release_public_key: Option<String>,
+    verification_failure: VerificationFailurePolicy,
+    drift_policy: DriftPolicy,
+    leak_policy: LeakPolicy,
+    pixman: PixmanLibrary,
     dependencies: LibraryDependencies,
+    added_dependencies: LibraryDependencies,
+    custom_dependencies: bool,
+    system_dependencies: BTreeSet<Dependency>,
+    link_modes: BTreeMap<Dependency, LinkMode>,
+    prebuilt_dependencies: BTreeMap<Dependency, String>,
+    dependency_flags: BTreeMap<Dependency, CompilerFlags>,
+    bundle: bool,
+    symbol_prefix: Option<SymbolPrefix>,
+    smoke_tests: bool,
+    trace: bool,
+    perf_tools: bool,
+    minimal: bool,
+    win32_surface: bool,
+    color_fonts: bool,
+    woff2: bool,
+    extra_features: BTreeSet<CairoFeature>,
+    patches: Vec<UnifiedDiff>,
+    security_patches: bool,
+    container: Option<ContainerBuild>,
+    remote: Option<RemoteBuilder>,
     options: LibraryOptions,
+    flags: CompilerFlags,
+    cpu_tuning: CpuTuning,
+    windows_crt: WindowsCrt,
+    windows_toolchain: WindowsToolchain,
+    cross_toolchain: Option<CrossToolchain>,
+    target: Option<String>,
+    debug_symbols: bool,
+    debug_checks: bool,
+    compile_commands: bool,
+    size_report: bool,
+    configure_cache: bool,
+    build_output: Option<BuildOutput>,
+    make_tool: MakeTool,
+    build_tree: BuildTree,
+    static_runtime: bool,
+    glibc_baseline: Option<GlibcBaseline>,
+    version_resource: bool,
+    module_definition: bool,
+    rpath: Option<RpathPolicy>,
+    artifact_naming: ArtifactNaming,
+    mac_signing: Option<MacSigning>,
+    windows_signing: Option<WindowsSigning>,
+    relocatable_bundle: Option<RelocatableBundle>,
+    proxy: Option<Proxy>,
+    download_progress: Option<DownloadProgress>,
+    certificate_authorities: Option<CertificateAuthorities>,
+    pgo: Option<ProfileGuidedOptimization>,
+    pgo_phase: Option<PgoPhase>,
 }
 
 impl Default for CairoLibrary {
@@ -29,72 +184,2484 @@ impl Default for CairoLibrary {
 
 impl CairoLibrary {
     pub fn new() -> Self {
+        let version = CairoVersion::DEFAULT;
+        Self::for_version(version).with_source_location(LibraryLocation::Tar(
+            TarUrlLocation::new(version.mirror_archive_url())
+                .archive(TarArchive::Xz)
+                .sources(Path::new(&version.sources_directory_name())),
+        ))
+    }
+
+    /// Build a specific cairo release or development snapshot downloaded from cairographics.org.
+    /// Versions starting from 1.17.6 are built with meson
+    pub fn for_version(version: CairoVersion) -> Self {
         Self {
+            version,
+            build_system: version.build_system(),
             source_location: LibraryLocation::Tar(
-                TarUrlLocation::new("https://dl.feenk.com/cairo/cairo-1.17.4.tar.xz")
+                TarUrlLocation::new(version.upstream_archive_url())
                     .archive(TarArchive::Xz)
-                    .sources(Path::new("cairo-1.17.4")),
+                    .sources(Path::new(&version.sources_directory_name())),
             ),
+            source_archive: None,
+            source_lock: None,
+            source_cache: None,
+            local_git: None,
             release_location: None,
-            dependencies: LibraryDependencies::new()
-                .push(PixmanLibrary::new().into())
-                .push(libfreetype(None as Option<String>).into()),
+            prebuilt: None,
+            release_public_key: None,
+            verification_failure: VerificationFailurePolicy::default(),
+            drift_policy: DriftPolicy::default(),
+            leak_policy: LeakPolicy::default(),
+            pixman: PixmanLibrary::new(),
+            dependencies: Self::default_dependencies(
+                &PixmanLibrary::new(),
+                bundled::freetype(None).ok(),
+                None,
+                &BTreeMap::new(),
+            ),
+            added_dependencies: LibraryDependencies::new(),
+            custom_dependencies: false,
+            system_dependencies: BTreeSet::new(),
+            link_modes: BTreeMap::new(),
+            prebuilt_dependencies: BTreeMap::new(),
+            dependency_flags: BTreeMap::new(),
+            bundle: false,
+            symbol_prefix: None,
+            smoke_tests: false,
+            trace: false,
+            perf_tools: false,
+            minimal: false,
+            win32_surface: true,
+            color_fonts: false,
+            woff2: false,
+            extra_features: BTreeSet::new(),
+            patches: vec![],
+            security_patches: true,
+            container: None,
+            remote: None,
             options: LibraryOptions::default(),
+            flags: CompilerFlags::default(),
+            cpu_tuning: CpuTuning::default(),
+            windows_crt: WindowsCrt::default(),
+            windows_toolchain: WindowsToolchain::default(),
+            cross_toolchain: None,
+            target: None,
+            debug_symbols: false,
+            debug_checks: false,
+            compile_commands: false,
+            size_report: false,
+            configure_cache: false,
+            build_output: None,
+            make_tool: MakeTool::default(),
+            build_tree: BuildTree::default(),
+            static_runtime: false,
+            glibc_baseline: None,
+            version_resource: false,
+            module_definition: true,
+            rpath: None,
+            artifact_naming: ArtifactNaming::default(),
+            mac_signing: None,
+            windows_signing: None,
+            relocatable_bundle: None,
+            proxy: None,
+            download_progress: None,
+            certificate_authorities: None,
+            pgo: None,
+            pgo_phase: None,
+        }
+    }
+
+    /// cairo with nothing but the image surface: no PNG, FreeType, fontconfig, PDF, PostScript,
+    /// SVG or platform backends. Only pixman is built, for in-memory rasterization
+    /// with the smallest binary and dependency set
+    pub fn minimal() -> Self {
+        Self::new().with_minimal(true)
+    }
+
+    /// cairo with every feature we can build on all platforms: the PDF surface and color and
+    /// WOFF2 fonts on top of the defaults. The native surfaces (Quartz, GDI) are built on their
+    /// platforms anyway, backends that need system libraries such as fontconfig or X11 stay
+    /// opt-in through [`CairoLibrary::with_system_dependency`] and [`CairoLibrary::with_feature`]
+    pub fn full() -> Self {
+        Self::new()
+            .with_feature(CairoFeature::Pdf)
+            .with_color_fonts(true)
+            .with_woff2(true)
+    }
+
+    /// cairo exactly as Glamorous Toolkit ships it and as the feenkcom/libcairo releases
+    /// are built: image, PNG and FreeType with the platform's native surfaces
+    pub fn gt_default() -> Self {
+        Self::new()
+    }
+
+    /// Turn off every surface and font backend except the image surface, see [`CairoLibrary::minimal`].
+    /// Features enabled with [`CairoLibrary::with_feature`] are still built, their libraries
+    /// must then be provided by the system. Not supported by the Windows builds
+    pub fn with_minimal(mut self, minimal: bool) -> Self {
+        self.minimal = minimal;
+        let pixman = self.pixman.clone();
+        self.with_pixman(pixman)
+    }
+
+    pub fn is_minimal(&self) -> bool {
+        self.minimal
+    }
+
+    /// The dependencies we build from sources or download, libraries taken from the system are left out
+    fn default_dependencies(
+        pixman: &PixmanLibrary,
+        freetype: Option<Box<dyn Library>>,
+        brotli: Option<BrotliLibrary>,
+        link_modes: &BTreeMap<Dependency, LinkMode>,
+    ) -> LibraryDependencies {
+        let mut pixman = pixman.clone();
+        if let Some(link_mode) = link_modes.get(&Dependency::Pixman) {
+            link_mode.apply(pixman.options_mut());
+        }
+        let mut dependencies = LibraryDependencies::new().push(pixman.into());
+
+        // a leaf like pixman, so it is compiled before freetype looks for it
+        if let Some(mut brotli) = brotli {
+            if let Some(link_mode) = link_modes.get(&Dependency::Brotli) {
+                link_mode.apply(brotli.options_mut());
+            }
+            dependencies = dependencies.push(brotli.into());
+        }
+
+        let mut freetype = match freetype {
+            Some(freetype) => freetype,
+            None => return dependencies,
+        };
+        if let Some(link_mode) = link_modes.get(&Dependency::Freetype) {
+            link_mode.apply(freetype.options_mut());
+        }
+        dependencies.push(freetype)
+    }
+
+    /// Replace the pixman we depend on, for example to compile it with different flags.
+    /// A prebuilt pixman and flags given with [`CairoLibrary::with_dependency_flags`]
+    /// apply to it no matter in which order they are set
+    pub fn with_pixman(mut self, pixman: PixmanLibrary) -> Self {
+        let mut pixman = pixman;
+        if let Some(tag) = self.prebuilt_dependencies.get(&Dependency::Pixman) {
+            pixman = pixman.with_release_location(Some(LibraryLocation::Git(
+                GitLocation::github("feenkcom", "libpixman").tag(tag.clone()),
+            )));
+        }
+        if let Some(flags) = self.dependency_flags.get(&Dependency::Pixman) {
+            pixman = pixman.with_flags(flags.clone());
+        }
+        if !self.custom_dependencies {
+            let freetype = if self.builds_freetype() {
+                self.freetype().ok()
+            } else {
+                None
+            };
+            self.dependencies = self
+                .added_dependencies
+                .iter()
+                .fold(
+                    Self::default_dependencies(&pixman, freetype, self.brotli(), &self.link_modes),
+                    |dependencies, dependency| dependencies.push(dependency.clone_library()),
+                );
+        }
+        self.pixman = pixman;
+        self
+    }
+
+    /// Replace the whole dependency graph, for example to inject a custom built freetype
+    /// or to drop a dependency. Later changes to pixman, freetype or their link modes
+    /// no longer affect the graph, the libraries passed here are compiled as they are
+    pub fn with_dependencies(mut self, dependencies: LibraryDependencies) -> Self {
+        self.dependencies = dependencies;
+        self.added_dependencies = LibraryDependencies::new();
+        self.custom_dependencies = true;
+        self
+    }
+
+    /// Compile another library before cairo, in addition to pixman and freetype
+    pub fn add_dependency(mut self, dependency: Box<dyn Library>) -> Self {
+        self.dependencies = self.dependencies.push(dependency.clone_library());
+        self.added_dependencies = self.added_dependencies.push(dependency);
+        self
+    }
+
+    /// The freetype we build, or download when it is a prebuilt dependency
+    fn freetype(&self) -> Result<Box<dyn Library>, Box<dyn Error>> {
+        bundled::freetype(self.prebuilt_dependencies.get(&Dependency::Freetype).cloned())
+    }
+
+    /// The library of the dependency graph with the name of the given one, so that cairo is
+    /// configured against a library injected with [`CairoLibrary::with_dependencies`]
+    /// and not against one that was dropped from the graph
+    fn graph_library(&self, library: &dyn Library) -> Option<Box<dyn Library>> {
+        self.dependencies
+            .iter()
+            .flat_map(|dependency| libraries_to_fetch(dependency.as_ref()))
+            .find(|each| each.name() == library.name())
+    }
+
+    fn graph_pixman(&self) -> Option<Box<dyn Library>> {
+        self.graph_library(&self.pixman)
+    }
+
+    fn graph_freetype(&self) -> Option<Box<dyn Library>> {
+        if !self.builds_freetype() {
+            return None;
+        }
+        self.graph_library(self.freetype().ok()?.as_ref())
+    }
+
+    fn graph_libpng(&self) -> Option<Box<dyn Library>> {
+        self.graph_library(libpng().ok()?.as_ref())
+    }
+
+    fn graph_libzlib(&self) -> Option<Box<dyn Library>> {
+        self.graph_library(libzlib().ok()?.as_ref())
+    }
+
+    /// A library the Windows builds can not do without
+    fn required_graph_library(
+        &self,
+        library: Option<Box<dyn Library>>,
+        name: &str,
+    ) -> Result<Box<dyn Library>, Box<dyn Error>> {
+        library.ok_or_else(|| {
+            UserFacingError::new(format!("Could not compile {}", self.name()))
+                .reason(format!("There is no {} in the dependency graph", name))
+                .help("The Windows builds need pixman, freetype, libpng and zlib")
+                .into()
+        })
+    }
+
+    /// Download the binaries of pixman (from feenkcom/libpixman) or freetype together with
+    /// libpng and zlib (from feenkcom/libfreetype) published under the given release tag
+    /// instead of compiling them, so that iterating on cairo does not rebuild the whole stack.
+    /// The prebuilt binaries do not follow the flags and CPU tuning configured here.
+    /// There are no binaries of the other dependencies, asking for them fails the requirements
+    pub fn with_prebuilt_dependency(mut self, dependency: Dependency, tag: impl Into<String>) -> Self {
+        self.prebuilt_dependencies.insert(dependency, tag.into());
+        let pixman = self.pixman.clone();
+        self.with_pixman(pixman)
+    }
+
+    /// Build a dependency as a static library folded into cairo or as a shared library
+    /// shipped alongside it. libpng and zlib are built the same way as freetype.
+    /// Dependencies without an explicit choice keep their library's default
+    pub fn with_link_mode(mut self, dependency: Dependency, link_mode: LinkMode) -> Self {
+        self.link_modes.insert(dependency, link_mode);
+        let pixman = self.pixman.clone();
+        self.with_pixman(pixman)
+    }
+
+    /// Extra compiler and linker flags used when building only the given dependency, for example
+    /// `-fno-omit-frame-pointer` for pixman or a define for freetype. They replace the flags
+    /// of the pixman set with [`CairoLibrary::with_pixman`] and are added to freetype's
+    /// CMake target, so libpng and zlib are not affected
+    pub fn with_dependency_flags(mut self, dependency: Dependency, flags: CompilerFlags) -> Self {
+        self.dependency_flags.insert(dependency, flags);
+        let pixman = self.pixman.clone();
+        self.with_pixman(pixman)
+    }
+
+    /// Statically link pixman, freetype, libpng and zlib into a single cairo shared library
+    /// that only exports cairo's own symbols, so that there is one native file to ship
+    pub fn with_bundled_dependencies(mut self) -> Self {
+        self.bundle = true;
+        self.options.be_shared();
+        self.with_link_mode(Dependency::Pixman, LinkMode::Static)
+            .with_link_mode(Dependency::Freetype, LinkMode::Static)
+    }
+
+    pub fn is_bundle(&self) -> bool {
+        self.bundle
+    }
+
+    /// Rename cairo's exported functions with a prefix such as `gt_`, see [`SymbolPrefix`]
+    pub fn with_symbol_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.symbol_prefix = Some(SymbolPrefix::new(prefix));
+        self
+    }
+
+    pub fn symbol_prefix(&self) -> Option<&SymbolPrefix> {
+        self.symbol_prefix.as_ref()
+    }
+
+    /// Enable an additional surface or font backend, for example the X backends on Linux
+    pub fn with_feature(mut self, feature: CairoFeature) -> Self {
+        self.extra_features.insert(feature);
+        self
+    }
+
+    /// Build the native GDI surfaces on Windows (the default), or only render into image
+    /// surfaces. The Win32 font backend needs the GDI surfaces and goes away with them,
+    /// fonts are then always rendered by FreeType
+    pub fn with_win32_surface(mut self, win32_surface: bool) -> Self {
+        self.win32_surface = win32_surface;
+        if !win32_surface {
+            self.extra_features.remove(&CairoFeature::Win32);
+        }
+        self
+    }
+
+    pub fn has_win32_surface(&self) -> bool {
+        self.win32_surface
+    }
+
+    /// The features we configure cairo with and expect to find in `cairo-features.h`
+    pub fn requested_features(&self) -> Vec<CairoFeature> {
+        if self.minimal {
+            let mut features = vec![CairoFeature::Image];
+            features.extend(
+                self.extra_features
+                    .iter()
+                    .filter(|feature| **feature != CairoFeature::Image),
+            );
+            return features;
+        }
+        let mut features = vec![CairoFeature::Image, CairoFeature::Png, CairoFeature::FreeType];
+        if self.uses_system(Dependency::Fontconfig) {
+            features.push(CairoFeature::Fontconfig);
+        }
+        for feature in &self.extra_features {
+            if !features.contains(feature) {
+                features.push(*feature);
+            }
+        }
+        features
+    }
+
+    /// After installing, compile and run a tiny C program for every enabled feature against
+    /// the installed headers and libraries. Can not be used when cross-compiling
+    pub fn with_smoke_tests(mut self, smoke_tests: bool) -> Self {
+        self.smoke_tests = smoke_tests;
+        self
+    }
+
+    /// Also build and install `cairo-trace` with its interposition library `libcairo-trace`,
+    /// to record the rendering of an application with exactly this cairo for bug reports.
+    /// Only supported by the autotools build of a shared cairo on Unix
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Also build the benchmarks of `perf/` against this cairo and install them into `bin/`
+    /// of the prefix, to compare rendering workloads between build configurations.
+    /// Only supported by the autotools build on Unix
+    pub fn with_perf_tools(mut self, perf_tools: bool) -> Self {
+        self.perf_tools = perf_tools;
+        self
+    }
+
+    pub fn run_smoke_tests(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let prefix = self.native_library_prefix(context);
+        let features_header = prefix.join("include").join("cairo").join(CAIRO_FEATURES_HEADER);
+        let features = FeatureSummary::read(&features_header)?.enabled();
+
+        let directory = context
+            .build_root()
+            .join("smoke-tests")
+            .join(self.install_prefix_name(context));
+
+        let mut library_directories = self.compiled_library_directories(context);
+        library_directories.extend(self.dependency_library_directories(context));
+
+        for feature in features {
+            let smoke_test = SmokeTest::for_feature(feature);
+            if context.is_windows() {
+                let mut include_directories = vec![prefix.join("include").join("cairo")];
+                for dependency in self.dependencies.iter() {
+                    include_directories.extend(dependency.native_library_include_headers(context));
+                }
+                smoke_test.run_with_msvc(
+                    self.windows_toolchain.compiler().name,
+                    &directory,
+                    &include_directories,
+                    &library_directories,
+                )?;
+            } else {
+                let mut pkg_config_paths = vec![prefix.join("lib").join("pkgconfig")];
+                pkg_config_paths.extend(self.dependency_pkg_config_directories(context));
+                if let Ok(ref path) = std::env::var("PKG_CONFIG_PATH") {
+                    std::env::split_paths(path).for_each(|path| pkg_config_paths.push(path));
+                }
+                self.check_pkg_config_leaks(&pkg_config_paths, true, context)?;
+
+                let library_path_variable = if context.is_mac() {
+                    "DYLD_LIBRARY_PATH"
+                } else {
+                    "LD_LIBRARY_PATH"
+                };
+                smoke_test.run_with_pkg_config(
+                    &directory,
+                    &pkg_config_paths,
+                    &library_directories,
+                    library_path_variable,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_features(&self, build_directory: &Path) -> Result<(), Box<dyn Error>> {
+        FeatureSummary::read(&build_directory.join("src").join(CAIRO_FEATURES_HEADER))?
+            .verify(&self.requested_features())
+    }
+
+    fn symbol_prefix_header(&self, context: &LibraryCompilationContext) -> PathBuf {
+        self.source_directory(context)
+            .join("src")
+            .join(SYMBOL_PREFIX_HEADER)
+    }
+
+    pub fn link_mode(&self, dependency: Dependency) -> Option<LinkMode> {
+        self.link_modes.get(&dependency).copied()
+    }
+
+    /// The binaries of dependencies built as shared libraries that must be shipped next to cairo,
+    /// including those of their own dependencies such as the libpng and zlib of freetype
+    pub fn shared_dependency_binaries(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let context = &self.target_context(context);
+        let mut directories = vec![];
+        for dependency in self.dependencies.iter() {
+            for library in libraries_to_fetch(dependency.as_ref()) {
+                if library.is_shared() {
+                    for directory in library.compiled_library_directories(context) {
+                        if !directories.contains(&directory) {
+                            directories.push(directory);
+                        }
+                    }
+                }
+            }
+        }
+        shared_libraries_in(&directories, context)
+    }
+
+    /// The library folders of cairo followed by the ones of pixman, freetype, libpng, zlib and
+    /// the other dependencies, each library before the libraries it links against. Static
+    /// dependencies are included as well, for linking against a static cairo
+    pub fn compiled_library_directories_with_dependencies(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Vec<PathBuf> {
+        fn collect(
+            dependencies: &LibraryDependencies,
+            context: &LibraryCompilationContext,
+            directories: &mut Vec<PathBuf>,
+        ) {
+            for dependency in dependencies.iter() {
+                for directory in dependency.compiled_library_directories(context) {
+                    if !directories.contains(&directory) {
+                        directories.push(directory);
+                    }
+                }
+                if let Some(dependencies) = dependency.dependencies() {
+                    collect(dependencies, context, directories);
+                }
+            }
+        }
+
+        let context = &self.target_context(context);
+        let mut directories = self.compiled_library_directories(context);
+        collect(&self.dependencies, context, &mut directories);
+        directories
+    }
+
+    /// The shared libraries cairo was compiled into
+    fn shared_binaries(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        shared_libraries_in(&self.compiled_library_directories(context), context)
+    }
+
+    /// Every shared library the build produced: cairo itself followed by pixman, freetype,
+    /// libpng, zlib and the other dependencies built as shared libraries.
+    /// Packaging collects exactly these instead of globbing the lib directories
+    pub fn produced_binaries(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let context = &self.target_context(context);
+        let mut binaries = self.shared_binaries(context);
+        for binary in self.shared_dependency_binaries(context) {
+            if !binaries.contains(&binary) {
+                binaries.push(binary);
+            }
+        }
+        binaries
+    }
+
+    /// Link against the platform's copy of a dependency discovered with pkg-config
+    /// instead of building it from sources. Only FreeType and fontconfig on macOS and Linux
+    pub fn with_system_dependency(mut self, dependency: Dependency) -> Self {
+        self.system_dependencies.insert(dependency);
+        let pixman = self.pixman.clone();
+        self.with_pixman(pixman)
+    }
+
+    pub fn uses_system(&self, dependency: Dependency) -> bool {
+        self.system_dependencies.contains(&dependency)
+    }
+
+    /// Whether freetype with libpng and zlib is part of the libraries we build
+    fn builds_freetype(&self) -> bool {
+        cfg!(feature = "freetype") && !self.minimal && !self.uses_system(Dependency::Freetype)
+    }
+
+    /// Make sure the freetype we build renders color fonts such as emoji through cairo-ft:
+    /// COLR/CPAL layers and sbix/CBDT bitmaps, decoded with libpng. freetype is patched when
+    /// its sources are fetched with [`CairoLibrary::ensure_all_sources`] and verified after the build
+    pub fn with_color_fonts(mut self, color_fonts: bool) -> Self {
+        self.color_fonts = color_fonts;
+        self
+    }
+
+    /// Add brotli to the libraries we build and let the freetype we build decompress WOFF2
+    /// web fonts with it. freetype finds brotli through the `CMAKE_PREFIX_PATH` and `PKG_CONFIG_PATH`
+    /// of its CMake project, which are patched when the sources are fetched with
+    /// [`CairoLibrary::ensure_all_sources`]
+    pub fn with_woff2(mut self, woff2: bool) -> Self {
+        self.woff2 = woff2;
+        let pixman = self.pixman.clone();
+        self.with_pixman(pixman)
+    }
+
+    /// The brotli we build for freetype when WOFF2 fonts are supported
+    fn brotli(&self) -> Option<BrotliLibrary> {
+        if self.woff2 && self.builds_freetype() {
+            let flags = self.dependency_flags.get(&Dependency::Brotli).cloned();
+            Some(
+                BrotliLibrary::new()
+                    .with_flags(flags.unwrap_or_default())
+                    .with_glibc_baseline(self.glibc_baseline.clone()),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Patch the sources of the freetype we build, and of its libpng and zlib on Windows,
+    /// for the options we need, unless its binaries are downloaded
+    fn prepare_freetype(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        if self.prebuilt_dependencies.contains_key(&Dependency::Freetype) {
+            return Ok(());
+        }
+        let freetype = match self.graph_freetype() {
+            Some(freetype) => freetype,
+            None => return Ok(()),
+        };
+        if self.color_fonts {
+            self.apply_patch(color_fonts_patch(&freetype.source_directory(context)))?;
+        }
+        let prefixes = self
+            .brotli()
+            .map(|brotli| brotli.native_library_prefix(context))
+            .into_iter()
+            .collect::<Vec<PathBuf>>();
+        let mut flags = self.dependency_flags.get(&Dependency::Freetype).cloned();
+        // libpng and zlib are configured by their own CMake projects
+        let mut cmake_options = vec![];
+        if context.is_windows() {
+            flags = Some(
+                flags
+                    .unwrap_or_default()
+                    .cflag(self.windows_crt.msvc_flag()),
+            );
+            cmake_options.push(self.windows_crt.cmake_option());
+        }
+        if let Some(ref glibc_baseline) = self.glibc_baseline {
+            if context.is_linux() {
+                flags = Some(flags.unwrap_or_default().extend(&glibc_baseline.flags()));
+                cmake_options.extend(glibc_baseline.cmake_options());
+            }
+        }
+        if let Some(patch) = freetype_cmake_patch(
+            &freetype.source_directory(context),
+            flags.as_ref(),
+            &prefixes,
+        ) {
+            self.apply_patch(patch)?;
+        }
+        if !cmake_options.is_empty() {
+            for library in vec![self.graph_libzlib(), self.graph_libpng()]
+                .into_iter()
+                .flatten()
+            {
+                self.apply_patch(cmake_project_patch(
+                    &library.source_directory(context),
+                    &cmake_options,
+                )?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adjust the options of cairo, for example `.with_options(|options| options.be_static())`
+    pub fn with_options(mut self, configure: impl FnOnce(&mut LibraryOptions)) -> Self {
+        configure(&mut self.options);
+        self
+    }
+
+    /// Build cairo as a static library
+    pub fn be_static(self) -> Self {
+        self.with_options(|options| options.be_static())
+    }
+
+    /// Build cairo as a shared library, the default
+    pub fn be_shared(self) -> Self {
+        self.with_options(|options| options.be_shared())
+    }
+
+    /// Extra compiler and linker flags used when building cairo
+    pub fn with_flags(mut self, flags: CompilerFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Compile both cairo and pixman for the given CPUs, by default a portable baseline
+    pub fn with_cpu_tuning(mut self, cpu_tuning: CpuTuning) -> Self {
+        let pixman = self.pixman.clone().with_cpu_tuning(cpu_tuning.clone());
+        self.cpu_tuning = cpu_tuning;
+        self.with_pixman(pixman)
+    }
+
+    /// Choose how the MSVC runtime is linked into cairo, pixman, freetype, zlib and libpng
+    pub fn with_windows_crt(mut self, windows_crt: WindowsCrt) -> Self {
+        let pixman = self.pixman.clone().with_windows_crt(windows_crt);
+        self.windows_crt = windows_crt;
+        self.with_pixman(pixman)
+    }
+
+    /// Compile cairo and pixman with debug information. On Windows this produces
+    /// `.pdb` files next to `cairo.dll`, see `debug_symbols`
+    pub fn with_debug_symbols(mut self, debug_symbols: bool) -> Self {
+        let pixman = self.pixman.clone().with_debug_symbols(debug_symbols);
+        self.debug_symbols = debug_symbols;
+        self.with_pixman(pixman)
+    }
+
+    /// Build a checked cairo to reproduce rendering bugs with: unoptimized, with debug information
+    /// and with its assertions kept. cairo's configure has no debug switch, the autotools builds
+    /// get the compiler flags and meson builds the `debug` build type without `NDEBUG`.
+    /// It is installed into a prefix of its own and packaged as `cairo-debug`
+    pub fn with_debug_checks(mut self, debug_checks: bool) -> Self {
+        self.debug_checks = debug_checks;
+        self
+    }
+
+    pub fn has_debug_checks(&self) -> bool {
+        self.debug_checks
+    }
+
+    /// The `.pdb` files produced by a Windows build with debug symbols
+    pub fn debug_symbols(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let context = &self.target_context(context);
+        self.compiled_library_directories(context)
+            .iter()
+            .filter_map(|directory| std::fs::read_dir(directory).ok())
+            .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "pdb"))
+            .collect()
+    }
+
+    /// The libraries, headers and pkg-config files the build installed into the prefix,
+    /// together with the shared libraries of the dependencies that must be shipped with cairo
+    pub fn artifacts(&self, context: &LibraryCompilationContext) -> BuiltArtifacts {
+        let context = &self.target_context(context);
+        let prefix = self.native_library_prefix(context);
+        let include = prefix.join("include");
+        let library_directories = self.compiled_library_directories(context);
+        BuiltArtifacts {
+            shared_libraries: self.shared_binaries(context),
+            static_libraries: static_libraries_in(&library_directories),
+            headers: files_with_extension(&include, "h"),
+            include_directory: Some(include).filter(|include| include.is_dir()),
+            pkg_config_files: files_with_extension(&prefix.join("lib").join("pkgconfig"), "pc"),
+            dependency_binaries: self.shared_dependency_binaries(context),
+            prefix,
+        }
+    }
+
+    /// Copy the `.pdb` files next to the compiled library, returning the copied files
+    pub fn copy_debug_symbols(
+        &self,
+        context: &LibraryCompilationContext,
+        destination: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let destination = destination.as_ref();
+        let mut copied = vec![];
+        for symbols in self.debug_symbols(context) {
+            if let Some(file_name) = symbols.file_name() {
+                let target = destination.join(file_name);
+                std::fs::copy(&symbols, &target)?;
+                copied.push(target);
+            }
+        }
+        Ok(copied)
+    }
+
+    /// Write a `compile_commands.json` for cairo and pixman into their install prefixes
+    /// so that the sources can be browsed with clangd. Autotools builds are intercepted
+    /// with `bear`, which does not support MSVC
+    pub fn with_compile_commands(mut self, compile_commands: bool) -> Self {
+        let pixman = self.pixman.clone().with_compile_commands(compile_commands);
+        self.compile_commands = compile_commands;
+        self.with_pixman(pixman)
+    }
+
+    /// Measure the produced libraries per section and for their largest symbols with `llvm-size`
+    /// and `llvm-nm`, the report is recorded in the build manifest. Comparing it with the one of
+    /// a previous release shows what each enabled backend costs, see [`SizeReport::growth`]
+    pub fn with_size_report(mut self, size_report: bool) -> Self {
+        self.size_report = size_report;
+        self
+    }
+
+    /// Reuse the results of configure checks of cairo and pixman between builds on the same machine
+    pub fn with_configure_cache(mut self, configure_cache: bool) -> Self {
+        let pixman = self.pixman.clone().with_configure_cache(configure_cache);
+        self.configure_cache = configure_cache;
+        self.with_pixman(pixman)
+    }
+
+    /// Switch the autotools builds of cairo and pixman between readable logs and the full
+    /// compiler command lines. Meson builds print the command lines with `Verbose` as well
+    pub fn with_build_output(mut self, build_output: BuildOutput) -> Self {
+        let pixman = self.pixman.clone().with_build_output(Some(build_output));
+        self.build_output = Some(build_output);
+        self.with_pixman(pixman)
+    }
+
+    /// Run the makefiles of cairo and pixman with another make, see [`MakeTool`]
+    pub fn with_make_tool(mut self, make_tool: MakeTool) -> Self {
+        let pixman = self.pixman.clone().with_make_tool(make_tool);
+        self.make_tool = make_tool;
+        self.with_pixman(pixman)
+    }
+
+    /// Link the GCC runtime statically into the Linux shared objects of cairo and pixman,
+    /// so that they run on distributions with an older `libgcc_s` or `libstdc++`
+    pub fn with_static_runtime(mut self, static_runtime: bool) -> Self {
+        let pixman = self.pixman.clone().with_static_runtime(static_runtime);
+        self.static_runtime = static_runtime;
+        self.with_pixman(pixman)
+    }
+
+    /// Make sure the Linux binaries run with the given glibc or newer. cairo and all libraries
+    /// we build for it are compiled against the sysroot of the baseline if it has one, and every
+    /// produced binary, including those of the dependencies, is checked for newer glibc symbol versions
+    pub fn with_glibc_baseline(mut self, glibc_baseline: GlibcBaseline) -> Self {
+        let pixman = self
+            .pixman
+            .clone()
+            .with_glibc_baseline(Some(glibc_baseline.clone()));
+        self.glibc_baseline = Some(glibc_baseline);
+        self.with_pixman(pixman)
+    }
+
+    /// Where the autotools and meson builds of cairo and pixman keep their intermediate files
+    /// and whether they are removed after compiling
+    pub fn with_build_tree(mut self, build_tree: BuildTree) -> Self {
+        let pixman = self.pixman.clone().with_build_tree(build_tree.clone());
+        self.build_tree = build_tree;
+        self.with_pixman(pixman)
+    }
+
+    /// Run the Linux build of cairo and pixman inside a Docker or Podman image for a controlled
+    /// glibc baseline. The builders of freetype, libpng and zlib can not run in the image,
+    /// so they are only supported with the freetype of the image or a minimal cairo
+    pub fn with_container(mut self, container: ContainerBuild) -> Self {
+        let pixman = self.pixman.clone().with_container(Some(container.clone()));
+        self.container = Some(container);
+        self.with_pixman(pixman)
+    }
+
+    /// Apply a unified diff to the cairo sources before building. Patches are applied
+    /// in the order they were added, paths are relative to the root of the sources
+    pub fn with_patch(mut self, patch: UnifiedDiff) -> Self {
+        self.patches.push(patch);
+        self
+    }
+
+    /// Apply the security backports shipped for the pinned release, enabled by default
+    pub fn with_security_patches(mut self, security_patches: bool) -> Self {
+        self.security_patches = security_patches;
+        self
+    }
+
+    /// The security backports applied to the sources. They only apply to the release
+    /// archive, sources from git are expected to contain the fixes already
+    pub fn security_patches(&self) -> Vec<SecurityPatch> {
+        match self.source_location {
+            LibraryLocation::Tar(_) if self.security_patches => {
+                SecurityPatch::for_version(self.version)
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The security patches followed by the patches set with [`CairoLibrary::with_patch`]
+    fn patch_series(&self) -> Vec<UnifiedDiff> {
+        self.security_patches()
+            .iter()
+            .map(|patch| patch.diff().clone())
+            .chain(self.patches.iter().cloned())
+            .collect()
+    }
+
+    fn apply_patches(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<Vec<PatchReport>, Box<dyn Error>> {
+        let series = self.patch_series();
+        if series.is_empty() {
+            return Ok(vec![]);
+        }
+        let reports = UnifiedDiff::apply_series(&series, &self.source_directory(context))?;
+        for report in &reports {
+            println!("{}", report);
+        }
+        Ok(reports)
+    }
+
+    /// Delegate the compilation of cairo and its dependencies to another machine and fetch
+    /// back the installed prefixes. The dependency graph stays as it is, only
+    /// [`Library::dependencies`] is empty, so that the libraries the remote builds are not
+    /// compiled locally as well
+    pub fn with_remote(mut self, remote: RemoteBuilder) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Read a configuration written with [`CairoLibrary::save`], used by remote builders
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn compile_remotely(
+        &self,
+        remote: &RemoteBuilder,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut library = self.clone();
+        library.remote = None;
+        // paths of this machine mean nothing on the remote one
+        library.local_git = None;
+        library.source_cache = None;
+        library.source_lock = None;
+        if library
+            .windows_signing
+            .as_ref()
+            .is_some_and(|signing| signing.uses_certificate_file())
+        {
+            library.windows_signing = None;
+        }
+        let mut dependency_prefixes: Vec<PathBuf> = vec![];
+        for dependency in self.dependencies.iter() {
+            for library in libraries_to_fetch(dependency.as_ref()) {
+                let prefix = library.native_library_prefix(context);
+                if !dependency_prefixes.contains(&prefix) {
+                    dependency_prefixes.push(prefix);
+                }
+            }
+        }
+        remote.build(
+            &serde_json::to_string(&library)?,
+            &self.native_library_prefix(context),
+            &dependency_prefixes,
+            &self.network_settings(context)?,
+            context,
+        )
+    }
+
+    fn in_container(&self, command: Command, context: &LibraryCompilationContext) -> Command {
+        match self.container {
+            Some(ref container) if context.is_linux() => container.wrap(&command, context),
+            _ => command,
         }
     }
 
-    pub fn with_release_location(mut self, release_location: Option<LibraryLocation>) -> Self {
-        self.release_location = release_location;
-        self
+    /// Embed a VERSIONINFO resource with the cairo version and the release of this crate
+    /// into `cairo.dll`. Disabled by default since it requires `rc.exe` of the Windows SDK.
+    /// Not supported by the Meson build
+    pub fn with_version_resource(mut self, version_resource: bool) -> Self {
+        self.version_resource = version_resource;
+        self
+    }
+
+    /// Write a `.def` file listing the exports of `cairo.dll` next to `cairo.lib`, enabled
+    /// by default. The exports are read with `dumpbin`, which is only required when enabled
+    pub fn with_module_definition(mut self, module_definition: bool) -> Self {
+        self.module_definition = module_definition;
+        self
+    }
+
+    /// How packaged archives and produced libraries are named
+    pub fn with_artifact_naming(mut self, artifact_naming: ArtifactNaming) -> Self {
+        self.artifact_naming = artifact_naming;
+        self
+    }
+
+    pub fn artifact_name_values(&self, context: &LibraryCompilationContext) -> ArtifactNameValues {
+        let context = &self.target_context(context);
+        ArtifactNameValues {
+            name: if self.debug_checks {
+                format!("{}-debug", self.name())
+            } else {
+                self.name().to_string()
+            },
+            version: self.version.to_string(),
+            target: context.target().to_string(),
+            profile: context.profile().to_string(),
+            hash: self.configuration_hash(),
+        }
+    }
+
+    /// The file name of the packaged archive
+    pub fn archive_name(&self, context: &LibraryCompilationContext) -> Result<String, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        self.artifact_naming
+            .archive_name(&self.artifact_name_values(context))
+    }
+
+    /// The file name the produced library should be published under
+    pub fn library_file_name(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<String, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let stem = self
+            .artifact_naming
+            .library_name(&self.artifact_name_values(context))?
+            .unwrap_or_else(|| self.name().to_string());
+
+        Ok(if context.is_windows() {
+            format!("{}.dll", stem)
+        } else if context.is_mac() {
+            format!("lib{}.dylib", stem)
+        } else {
+            format!("lib{}.so", stem)
+        })
+    }
+
+    /// Rewrite the rpath of the produced Linux shared objects and the install name of the
+    /// macOS dylibs, by default both are left as set by libtool
+    pub fn with_rpath(mut self, rpath: Option<RpathPolicy>) -> Self {
+        self.rpath = rpath;
+        self
+    }
+
+    /// Sign the produced dylibs on macOS, by default they are not signed
+    pub fn with_mac_signing(mut self, mac_signing: Option<MacSigning>) -> Self {
+        self.mac_signing = mac_signing;
+        self
+    }
+
+    /// Authenticode sign `cairo.dll` and the DLLs of pixman, freetype, libpng, zlib and the
+    /// other shared dependencies, before they are copied into a relocatable bundle
+    pub fn with_windows_signing(mut self, windows_signing: Option<WindowsSigning>) -> Self {
+        self.windows_signing = windows_signing;
+        self
+    }
+
+    /// After the build, copy cairo and its shared dependencies into the directory and make them
+    /// load each other from there, see [`RelocatableBundle`]
+    pub fn with_relocatable_bundle(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.relocatable_bundle = Some(RelocatableBundle::new(directory));
+        self
+    }
+
+    /// Enable a profile guided optimization build, see `compile_with_pgo`
+    pub fn with_pgo(mut self, pgo: ProfileGuidedOptimization) -> Self {
+        self.pgo = Some(pgo);
+        self
+    }
+
+    fn with_pgo_phase(self, phase: PgoPhase, context: &LibraryCompilationContext) -> Self {
+        let pgo_flags = match &self.pgo {
+            Some(pgo) => pgo.flags(phase, context),
+            None => return self,
+        };
+        let pixman = self.pixman.clone().with_pgo_flags(pgo_flags);
+
+        let mut library = self.with_pixman(pixman);
+        library.pgo_phase = Some(phase);
+        library
+    }
+
+    /// The flags used for cairo itself including the ones required by the current PGO phase
+    fn compiler_flags(&self, context: &LibraryCompilationContext) -> CompilerFlags {
+        let mut flags = self
+            .flags
+            .clone()
+            .extend(&self.cpu_tuning.flags(context));
+        if self.debug_symbols && self.build_system != BuildSystem::Meson {
+            flags = if context.is_windows() {
+                flags
+                    .cflag("-Zi")
+                    .ldflag("-DEBUG")
+                    .ldflag("-OPT:REF")
+                    .ldflag("-OPT:ICF")
+            } else {
+                flags.cflag("-g")
+            };
+        }
+        // MSVC only exports what is marked with dllexport, so static dependencies stay hidden on Windows
+        if self.bundle {
+            if context.is_linux() {
+                flags = flags.ldflag("-Wl,--exclude-libs,ALL");
+            }
+            if context.is_mac() {
+                // the renaming header prefixes every public function
+                let prefix = self
+                    .symbol_prefix
+                    .as_ref()
+                    .map_or("", |symbol_prefix| symbol_prefix.prefix());
+                flags = flags.ldflag(format!("-Wl,-exported_symbol,_{}cairo_*", prefix));
+            }
+        }
+        if let Some(ref symbol_prefix) = self.symbol_prefix {
+            flags = flags.extend(&symbol_prefix.flags(&self.symbol_prefix_header(context), context));
+        }
+        if self.static_runtime && context.is_linux() {
+            for flag in STATIC_RUNTIME_FLAGS {
+                flags = flags.ldflag(flag);
+            }
+        }
+        if let Some(ref glibc_baseline) = self.glibc_baseline {
+            if context.is_linux() {
+                flags = flags.extend(&glibc_baseline.flags());
+            }
+        }
+        // after the CPU tuning, so that the optimization level is not raised again
+        if self.debug_checks && self.build_system != BuildSystem::Meson {
+            flags = if context.is_windows() {
+                flags.cflag("-Od").cflag("-Zi").cflag("-UNDEBUG")
+            } else {
+                flags.cflag("-O0").cflag("-g").cflag("-UNDEBUG")
+            };
+        }
+        flags = match (&self.pgo, self.pgo_phase) {
+            (Some(pgo), Some(phase)) => flags.extend(&pgo.flags(phase, context)),
+            _ => flags,
+        };
+        if context.is_windows() {
+            flags = self.windows_toolchain.translate(flags);
+        }
+        flags
+    }
+
+    /// Compile cairo and pixman with instrumentation, run the configured workload
+    /// and compile them again using the collected profiles. Only supported with GCC and clang
+    pub fn compile_with_pgo(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let pgo = self.pgo.as_ref().ok_or_else(|| {
+            UserFacingError::new("Profile guided optimization is not configured")
+                .help("Use CairoLibrary::with_pgo to provide a workload")
+        })?;
+
+        if !context.is_unix() {
+            return Err(UserFacingError::new(
+                "Profile guided optimization is only supported on Unix platforms",
+            )
+            .into());
+        }
+        self.check_requirements(context)?;
+
+        // the profiles of an earlier run would hide a workload that records nothing
+        let profile_directory = pgo.profile_directory(context);
+        if profile_directory.exists() {
+            std::fs::remove_dir_all(&profile_directory)?;
+        }
+        std::fs::create_dir_all(&profile_directory)?;
+
+        let instrumented = self.clone().with_pgo_phase(PgoPhase::Instrument, context);
+        instrumented.compile(context)?;
+
+        let mut library_directories = instrumented.compiled_library_directories(context);
+        library_directories.extend(instrumented.dependency_library_directories(context));
+        pgo.run_workload(&library_directories, context)?;
+        pgo.merge_profiles(context)?;
+
+        self.clone()
+            .with_pgo_phase(PgoPhase::Optimize, context)
+            .compile(context)
+    }
+
+    /// Build cairo from a git repository checked out at the given reference.
+    /// The sources are bootstrapped with autotools before configuring
+    pub fn from_git(repository: impl AsRef<str>, reference: GitReference) -> Self {
+        let build_system = Self::build_system_of(&reference);
+
+        let location = GitLocation::new(repository.as_ref());
+        let location = match reference {
+            GitReference::Branch(branch) => location.branch(branch),
+            GitReference::Tag(tag) => location.tag(tag),
+            GitReference::Commit(commit) => location.commit(commit),
+        };
+
+        Self::new()
+            .with_source_location(LibraryLocation::Git(location))
+            .with_build_system(build_system)
+    }
+
+    /// Build cairo from its upstream repository
+    pub fn from_upstream_git(reference: GitReference) -> Self {
+        Self::from_git(CAIRO_GIT_REPOSITORY, reference)
+    }
+
+    /// Build cairo from a local clone at the given reference, the fastest loop for developing
+    /// patches. Every build checks the reference out again, see [`LocalGitCheckout`]
+    pub fn from_local_git(repository: impl Into<PathBuf>, reference: GitReference) -> Self {
+        let checkout = LocalGitCheckout::new(repository, reference);
+        let mut cairo = Self::from_git(
+            checkout.repository().display().to_string(),
+            checkout.reference().clone(),
+        );
+        cairo.local_git = Some(checkout);
+        cairo
+    }
+
+    /// Tags name a release, everything else is assumed to be recent development
+    fn build_system_of(reference: &GitReference) -> BuildSystem {
+        match reference {
+            GitReference::Tag(tag) => tag
+                .parse::<CairoVersion>()
+                .map(|version| version.build_system())
+                .unwrap_or(BuildSystem::Meson),
+            _ => BuildSystem::Meson,
+        }
+    }
+
+    /// Build for the given Rust target triple, for example `aarch64-unknown-linux-gnu`, whatever
+    /// the compilation context reports. Selects the cross toolchain, the prefix and the artifact names,
+    /// freetype, libpng and zlib still follow the context
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        let target = target.into();
+        let pixman = self.pixman.clone().with_target(Some(target.clone()));
+        self.target = Some(target);
+        self.with_pixman(pixman)
+    }
+
+    /// Compile cairo and pixman for another machine with the given toolchain. Builds for another
+    /// target than the host use the default toolchain of the target without it.
+    /// freetype, libpng and zlib are compiled by their own builders
+    pub fn with_cross_toolchain(mut self, cross_toolchain: CrossToolchain) -> Self {
+        let pixman = self
+            .pixman
+            .clone()
+            .with_cross_toolchain(Some(cross_toolchain.clone()));
+        self.cross_toolchain = Some(cross_toolchain);
+        self.with_pixman(pixman)
+    }
+
+    /// Compile cairo and pixman on Windows with clang-cl and lld-link instead of the MSVC tools
+    pub fn with_windows_toolchain(mut self, windows_toolchain: WindowsToolchain) -> Self {
+        let pixman = self
+            .pixman
+            .clone()
+            .with_windows_toolchain(windows_toolchain);
+        self.windows_toolchain = windows_toolchain;
+        self.with_pixman(pixman)
+    }
+
+    /// Choose how cairo and pixman are built. `BuildSystem::Msvc` also builds pixman without GNU make
+    pub fn with_build_system(mut self, build_system: BuildSystem) -> Self {
+        let pixman = self
+            .pixman
+            .clone()
+            .with_msvc(build_system == BuildSystem::Msvc);
+        self.build_system = build_system;
+        self.with_pixman(pixman)
+    }
+
+    pub fn version(&self) -> CairoVersion {
+        self.version
+    }
+
+    pub fn build_system(&self) -> BuildSystem {
+        self.build_system
+    }
+
+    /// The configuration without the settings that do not influence the produced binaries
+    fn binary_configuration(&self) -> Self {
+        // both PGO phases must build in the same folder for GCC to match the profiles
+        let pixman = self.pixman.clone().with_pgo_flags(CompilerFlags::new());
+        let mut library = self.clone().with_pixman(pixman);
+        library.pgo_phase = None;
+        library.release_location = None;
+        library.prebuilt = None;
+        library.release_public_key = None;
+        library.remote = None;
+        library.drift_policy = DriftPolicy::default();
+        library.source_lock = None;
+        library.source_cache = None;
+        library.leak_policy = LeakPolicy::default();
+        library.size_report = false;
+        library.proxy = None;
+        library.certificate_authorities = None;
+        // the target is part of every artifact name already, archives of one
+        // configuration for different targets share the hash
+        library.target = None;
+        library
+    }
+
+    /// A short hash of everything that influences the produced binaries
+    pub fn configuration_hash(&self) -> String {
+        short_hash(format!("{:?}", self.binary_configuration()))
+    }
+
+    /// Describe the inputs of a build with this configuration, without any binaries
+    pub fn requested_manifest(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<BuildManifest, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let sources = self.manifest_sources(false, context)?;
+
+        let flags = self.compiler_flags(context);
+        Ok(BuildManifest {
+            library: self.name().to_string(),
+            version: self.version.to_string(),
+            target: context.target().to_string(),
+            profile: context.profile(),
+            configuration_hash: self.configuration_hash(),
+            configuration: serde_json::to_value(self.binary_configuration())?,
+            sources,
+            patches: self
+                .patches
+                .iter()
+                .map(|patch| ManifestFile::for_contents(patch.name(), patch.contents()))
+                .collect(),
+            security_patches: self
+                .security_patches()
+                .iter()
+                .map(|patch| patch.applied(&[], &self.source_directory(context)))
+                .collect(),
+            cflags: flags.cflags().to_vec(),
+            ldflags: flags.ldflags().to_vec(),
+            toolchain: Toolchain::current(
+                self.container
+                    .as_ref()
+                    .map(|container| format!("{:?}", container)),
+                self.compiler_version(context),
+            ),
+            binaries: vec![],
+            size: None,
+        })
+    }
+
+    /// Where the sources of cairo and of every library in its dependency graph come from,
+    /// with the checksums of the fetched source trees when asked for
+    fn manifest_sources(
+        &self,
+        checksums: bool,
+        context: &LibraryCompilationContext,
+    ) -> Result<Vec<ManifestSource>, Box<dyn Error>> {
+        let mut libraries = libraries_to_fetch(self);
+        libraries.sort_by(|first, second| first.name().cmp(second.name()));
+
+        let mut sources = vec![];
+        for library in libraries {
+            let source_directory = library.source_directory(context);
+            let sha256 = if checksums && source_directory.exists() {
+                Some(sources_hash(&source_directory)?)
+            } else {
+                None
+            };
+            sources.push(ManifestSource {
+                library: library.name().to_string(),
+                location: SourceOrigin::of(library.as_ref())?.to_string(),
+                sha256,
+            });
+        }
+        Ok(sources)
+    }
+
+    /// The version of the C compiler the build runs, the one of the cross toolchain
+    /// or of the Windows toolchain, inside the container if there is one
+    fn compiler_version(&self, context: &LibraryCompilationContext) -> String {
+        let compiler = if context.is_windows() {
+            Some(self.windows_toolchain.compiler().name.to_string())
+        } else {
+            self.cross_toolchain(context)
+                .map(|cross| cross.c_compiler().join(" "))
+        };
+        compiler_version(compiler, |command| self.in_container(command, context))
+    }
+
+    /// Describe the inputs of the build and the binaries installed into the prefix
+    pub fn build_manifest(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<BuildManifest, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let mut manifest = self.requested_manifest(context)?.with_binaries_in(
+            &self.native_library_prefix(context),
+            &self.compiled_library_directories(context),
+        )?;
+        manifest.sources = self.manifest_sources(true, context)?;
+        // with the hunks that were skipped while patching
+        manifest.security_patches =
+            SecurityPatch::read_applied(&self.native_library_prefix(context))?;
+        if self.size_report {
+            let mut binaries = self.shared_binaries(context);
+            binaries.extend(static_libraries_in(&self.compiled_library_directories(context)));
+            let size = SizeReport::for_binaries(&binaries)?;
+            for binary in &size.binaries {
+                println!("{} is {} bytes", &binary.name, binary.size);
+            }
+            manifest.size = Some(size);
+        }
+        Ok(manifest)
+    }
+
+    /// Warn about or refuse libraries we build that pkg-config finds elsewhere, see [`LeakPolicy`]
+    pub fn with_leak_policy(mut self, policy: LeakPolicy) -> Self {
+        self.leak_policy = policy;
+        self
+    }
+
+    /// What to do when the existing install prefix was built with a different configuration
+    pub fn with_drift_policy(mut self, policy: DriftPolicy) -> Self {
+        self.drift_policy = policy;
+        self
+    }
+
+    /// Compare the manifest of an existing install prefix with the requested build.
+    /// Depending on the drift policy a prefix that differs is removed or reported as an error
+    pub fn check_drift(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let prefix = self.native_library_prefix(context);
+        let recorded = match BuildManifest::read(&prefix)? {
+            Some(recorded) => recorded,
+            None => return Ok(()),
+        };
+        let differences = recorded.drift(&self.requested_manifest(context)?);
+        if differences.is_empty() {
+            return Ok(());
+        }
+
+        match self.drift_policy {
+            DriftPolicy::Rebuild => {
+                println!(
+                    "Removing {} built with a different configuration: {}",
+                    prefix.display(),
+                    differences.join(", ")
+                );
+                std::fs::remove_dir_all(&prefix)?;
+                Ok(())
+            }
+            DriftPolicy::Fail => {
+                let mut error = UserFacingError::new(format!(
+                    "{} was built with a different configuration",
+                    prefix.display()
+                ));
+                for difference in differences {
+                    error = error.reason(difference);
+                }
+                Err(error
+                    .help(format!(
+                        "Remove {} or use DriftPolicy::Rebuild",
+                        prefix.display()
+                    ))
+                    .into())
+            }
+        }
+    }
+
+    /// The name of the folder cairo is installed into, unique per version, target, profile and configuration
+    pub fn install_prefix_name(&self, context: &LibraryCompilationContext) -> String {
+        let context = &self.target_context(context);
+        format!(
+            "{}-{}-{}-{}",
+            self.version,
+            context.target(),
+            context.profile(),
+            self.configuration_hash()
+        )
+    }
+
+    /// The folder cairo is installed into. Builds of different versions, targets, profiles or configurations
+    /// live side by side in the same build root. The autotools based Windows build still
+    /// compiles in the source directory and then copies the results into the prefix
+    pub fn install_prefix(&self, context: &LibraryCompilationContext) -> PathBuf {
+        let context = &self.target_context(context);
+        self.native_library_prefix(context)
+    }
+
+    pub fn with_source_location(mut self, source_location: LibraryLocation) -> Self {
+        self.source_location = source_location;
+        self.source_archive = None;
+        self.local_git = None;
+        self
+    }
+
+    /// Download the sources from an archive in any of the supported formats, for mirrors that
+    /// only provide `.tar.gz` or `.zip` archives, see [`SourceArchive`]
+    pub fn with_source_archive(mut self, archive: SourceArchive) -> Self {
+        self.source_location = archive.location();
+        self.source_archive = Some(archive);
+        self.local_git = None;
+        self
+    }
+
+    pub fn with_release_location(mut self, release_location: Option<LibraryLocation>) -> Self {
+        self.release_location = release_location;
+        self
+    }
+
+    /// Use the prebuilt binaries of the given feenkcom/libcairo release
+    pub fn with_release(mut self, tag: impl Into<String>) -> Self {
+        let tag = tag.into();
+        self.release_location = Some(LibraryLocation::Git(
+            GitLocation::github("feenkcom", "libcairo").tag(tag.clone()),
+        ));
+        let release = PrebuiltRelease::libcairo(tag);
+        self.prebuilt = Some(match self.release_public_key {
+            Some(ref public_key) => release.with_public_key(public_key.clone()),
+            None => release,
+        });
+        self
+    }
+
+    /// Use the prebuilt binaries of the given feenkcom/libcairo release, failing early with
+    /// the available tags if there is no such release. When the releases can not be listed,
+    /// for example offline, the version must at least be a release tag such as `v1.2.3`
+    pub fn try_with_release(self, version: impl AsRef<str>) -> Result<Self, Box<dyn Error>> {
+        let release = PrebuiltRelease::libcairo(version.as_ref());
+        match release.release_tags() {
+            Ok(tags) => {
+                let release = release.resolve_in(&tags)?;
+                Ok(self.with_release(release.tag()))
+            }
+            Err(_) => {
+                let version = version.as_ref().parse::<BinaryVersion>()?;
+                Ok(self.with_release(version.tag()))
+            }
+        }
+    }
+
+    /// Use the prebuilt binaries of the feenkcom/libcairo release built from this version
+    /// of the crate, so that a lockfile pinning the crate also pins matching binaries.
+    /// The tag is `v` followed by the crate version unless `LIBCAIRO_RELEASE_TAG` was set at compile time
+    pub fn try_with_crate_release(self) -> Result<Self, Box<dyn Error>> {
+        let release = crate_release_tag()?;
+        Ok(self.with_release(release.tag()))
+    }
+
+    /// Require the prebuilt binaries to be signed with the given minisign public key,
+    /// both of the release set before and of the ones set later
+    pub fn with_release_public_key(mut self, public_key: impl Into<String>) -> Self {
+        let public_key = public_key.into();
+        self.prebuilt = self
+            .prebuilt
+            .map(|release| release.with_public_key(public_key.clone()));
+        self.release_public_key = Some(public_key);
+        self
+    }
+
+    /// What to do when a downloaded prebuilt binary does not match the published checksum or signature
+    pub fn with_verification_failure_policy(mut self, policy: VerificationFailurePolicy) -> Self {
+        self.verification_failure = policy;
+        self
+    }
+
+    /// Download and verify the prebuilt archive for the current target, returning
+    /// the folder it was unpacked to. Returns `None` when there is no release to download
+    /// from or when verification failed and we should build from source instead.
+    /// [`Library::compile`] uses the prebuilt binaries whenever a release is set
+    pub fn fetch_prebuilt(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let release = match self.prebuilt {
+            Some(ref release) => release.clone().with_network(self.network_settings(context)?),
+            None => return Ok(None),
+        };
+
+        let archive_name = self.archive_name(context)?;
+        // a tag that does not exist is a mistake in the configuration, not a failed download
+        let release = &match release.release_tags() {
+            Ok(tags) => release.resolve_in(&tags)?,
+            Err(error) => return self.unusable_prebuilt(&archive_name, error),
+        };
+        let directory = context
+            .build_root()
+            .join("prebuilt")
+            .join(release.tag())
+            .join(context.target().to_string());
+
+        match release.download_verified(&archive_name, &directory) {
+            Ok(archive) => {
+                unpack(&archive, &directory)?;
+                Ok(Some(directory))
+            }
+            Err(error) => self.unusable_prebuilt(&archive_name, error),
+        }
+    }
+
+    fn unusable_prebuilt(
+        &self,
+        archive_name: &str,
+        error: Box<dyn Error>,
+    ) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        match self.verification_failure {
+            VerificationFailurePolicy::Fail => Err(error),
+            VerificationFailurePolicy::BuildFromSource => {
+                println!("Could not use the prebuilt {}: {}", archive_name, error);
+                Ok(None)
+            }
+        }
+    }
+
+    /// The targets a release tag has prebuilt archives for, named the way this configuration
+    /// names them. The repository of the configured prebuilt release is used, feenkcom/libcairo otherwise
+    pub fn prebuilt_targets(
+        &self,
+        tag: impl Into<String>,
+        context: &LibraryCompilationContext,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let tag = tag.into();
+        let release = match self.prebuilt {
+            Some(ref release) => release.clone().with_tag(tag),
+            None => PrebuiltRelease::libcairo(tag),
+        }
+        .with_network(self.network_settings(context)?);
+        let release = release.resolve_in(&release.release_tags()?)?;
+        release.available_targets(&self.artifact_naming, &self.artifact_name_values(context))
+    }
+
+    /// Download the sources of cairo and of all libraries it depends on before compiling anything.
+    /// Runs [`CairoLibrary::ensure_all_sources_async`] to completion, so it must not be called
+    /// from within an async task
+    pub fn ensure_all_sources(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        block_on(self.ensure_all_sources_async(context))
+    }
+
+    /// [`CairoLibrary::ensure_all_sources`] for async orchestrators: all sources are downloaded
+    /// concurrently on tokio's blocking pool, so no worker thread of the runtime is blocked
+    pub async fn ensure_all_sources_async(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let context = self.target_context(context);
+        let network = self
+            .network_settings(&context)
+            .map_err(|error| error.to_string())?;
+        // once before any download starts, the drift check may remove the prefix
+        self.check_drift(&context)
+            .map_err(|error| error.to_string())?;
+
+        let libraries = libraries_to_fetch(self);
+        let lock = self.source_lock().map_err(|error| error.to_string())?;
+        if let Some(ref lock) = lock {
+            lock.verify_locations(
+                &libraries
+                    .iter()
+                    .map(|library| library.as_ref())
+                    .collect::<Vec<&dyn Library>>(),
+            )
+            .map_err(|error| error.to_string())?;
+        }
+        // sources fetched before were checked when they were fetched
+        let fetched = libraries
+            .iter()
+            .filter(|library| !library.source_directory(&context).exists())
+            .map(|library| library.clone_library())
+            .collect::<Vec<Box<dyn Library>>>();
+        let fetched = fetched
+            .iter()
+            .map(|library| library.as_ref())
+            .collect::<Vec<&dyn Library>>();
+        let restored = self.restore_cached_sources(&fetched, lock.is_some(), &context);
+        self.ensure_graph_sources(libraries, &context, network).await?;
+        self.prepare_fetched_sources(&fetched, restored, lock.as_ref(), &context)
+            .map_err(|error| error.to_string().into())
+    }
+
+    /// Check the sources that were missing against the lock file, patch them and keep
+    /// them in the source cache, both as fetched and patched
+    fn prepare_fetched_sources(
+        &self,
+        fetched: &[&dyn Library],
+        restored: BTreeMap<String, CachedSources>,
+        lock: Option<&SourceLock>,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(lock) = lock {
+            let (patched, unpatched): (Vec<&dyn Library>, Vec<&dyn Library>) =
+                fetched.iter().partition(|library| {
+                    restored
+                        .get(library.name())
+                        .is_some_and(|cached| cached.patched)
+                });
+            lock.verify_sources(&unpatched, context)?;
+            lock.verify_cached_sources(
+                &patched
+                    .iter()
+                    .map(|library| (*library, restored[library.name()].sha256.as_str()))
+                    .collect::<Vec<(&dyn Library, &str)>>(),
+                context,
+            )?;
+        }
+        let checksums = self.store_cached_sources(fetched, &restored, context);
+
+        // the only place the patches are applied, compiling copies the record into the prefix
+        let patch_reports = self.apply_patches(context)?;
+        let sources = self.source_directory(context);
+        SecurityPatch::write_applied(&self.security_patches(), &patch_reports, &sources, &sources)?;
+        self.prepare_freetype(context)?;
+
+        if let Some(cache) = self.source_cache() {
+            for library in fetched {
+                let patches = match self.source_patches(*library) {
+                    Some(patches) => patches,
+                    None => continue,
+                };
+                let stored = match restored.get(library.name()) {
+                    Some(cached) if cached.patched => continue,
+                    Some(cached) => Some(&cached.sha256),
+                    None => checksums.get(library.name()),
+                };
+                if let Some(sha256) = stored {
+                    if let Err(error) = cache.store_patched(*library, sha256, &patches, context) {
+                        println!(
+                            "Warning: could not cache the patched sources of {}: {}",
+                            library.name(),
+                            error
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The patches applied to the sources of the library while preparing them, `None`
+    /// for libraries that are built from the sources as they were fetched
+    fn source_patches(&self, library: &dyn Library) -> Option<String> {
+        if library.name() == self.name() {
+            let series = self.patch_series();
+            return if series.is_empty() {
+                None
+            } else {
+                serde_json::to_string(&series).ok()
+            };
+        }
+        let freetype = self.graph_freetype()?;
+        if library.name() != freetype.name()
+            || self.prebuilt_dependencies.contains_key(&Dependency::Freetype)
+        {
+            return None;
+        }
+        let flags = self.dependency_flags.get(&Dependency::Freetype);
+        let brotli = self.brotli().is_some();
+        if !self.color_fonts && flags.is_none() && !brotli {
+            return None;
+        }
+        Some(format!(
+            "color fonts: {}, flags: {:?}, brotli: {}",
+            self.color_fonts, flags, brotli
+        ))
+    }
+
+    /// Check the fetched sources against a lock file written by [`CairoLibrary::lock_sources`].
+    /// A library fetched from another location fails the build, and so do freshly fetched
+    /// sources that differ from the ones that were locked
+    pub fn with_source_lock(mut self, path: impl Into<PathBuf>) -> Self {
+        self.source_lock = Some(path.into());
+        self
+    }
+
+    /// Copy the sources of clean build roots from a cache shared between build roots instead
+    /// of downloading them, see [`SourceCache`]. Without one the cache in `LIBCAIRO_SOURCE_CACHE`
+    /// is used, if set
+    pub fn with_source_cache(mut self, source_cache: SourceCache) -> Self {
+        self.source_cache = Some(source_cache);
+        self
+    }
+
+    fn source_cache(&self) -> Option<SourceCache> {
+        self.source_cache
+            .clone()
+            .or_else(SourceCache::from_environment)
+    }
+
+    /// The libraries whose missing sources were copied from the cache, by name.
+    /// A broken cache only costs a download, so its errors are warnings. With a source
+    /// lock the copies are checked against the checksums the cache recorded
+    fn restore_cached_sources(
+        &self,
+        libraries: &[&dyn Library],
+        verify: bool,
+        context: &LibraryCompilationContext,
+    ) -> BTreeMap<String, CachedSources> {
+        let mut restored = BTreeMap::new();
+        if let Some(cache) = self.source_cache() {
+            for library in libraries {
+                let patches = self.source_patches(*library);
+                match cache.restore(*library, patches.as_deref(), verify, context) {
+                    Ok(Some(cached)) => {
+                        restored.insert(library.name().to_string(), cached);
+                    }
+                    Ok(None) => {}
+                    Err(error) => println!(
+                        "Warning: could not copy the cached sources of {}: {}",
+                        library.name(),
+                        error
+                    ),
+                }
+            }
+        }
+        restored
+    }
+
+    /// Cache the sources that were downloaded, before they are patched.
+    /// Returns the checksums of the cached sources by library name
+    fn store_cached_sources(
+        &self,
+        libraries: &[&dyn Library],
+        restored: &BTreeMap<String, CachedSources>,
+        context: &LibraryCompilationContext,
+    ) -> BTreeMap<String, String> {
+        let mut checksums = BTreeMap::new();
+        if let Some(cache) = self.source_cache() {
+            for library in libraries {
+                if restored.contains_key(library.name()) {
+                    continue;
+                }
+                match cache.store(*library, context) {
+                    Ok(Some(sha256)) => {
+                        checksums.insert(library.name().to_string(), sha256);
+                    }
+                    Ok(None) => {}
+                    Err(error) => println!(
+                        "Warning: could not cache the sources of {}: {}",
+                        library.name(),
+                        error
+                    ),
+                }
+            }
+        }
+        checksums
+    }
+
+    fn source_lock(&self) -> Result<Option<SourceLock>, Box<dyn Error>> {
+        self.source_lock.as_ref().map(SourceLock::load).transpose()
+    }
+
+    /// Fetch the sources of cairo and all of its dependencies into a fresh folder of the build
+    /// root and write their locations and checksums to the lock file. This is the only way
+    /// the lock file changes, builds never update it
+    pub fn lock_sources(
+        &self,
+        context: &LibraryCompilationContext,
+        path: impl AsRef<Path>,
+    ) -> Result<SourceLock, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let network = self.network_settings(context)?;
+
+        // a workspace of its own, so that fetching does not touch the prefixes of the build root
+        let workspace = context.build_root().join("locked-sources");
+        if workspace.exists() {
+            std::fs::remove_dir_all(&workspace)?;
+        }
+        let lock_context = CompilationContext::new(&workspace)
+            .with_target(*context.target())
+            .with_debug(context.is_debug())
+            .create()?;
+
+        let libraries = libraries_to_fetch(self);
+        block_on(self.ensure_graph_sources(
+            libraries.iter().map(|library| library.clone_library()).collect(),
+            &lock_context,
+            network,
+        ))?;
+        let libraries = libraries
+            .iter()
+            .map(|library| library.as_ref())
+            .collect::<Vec<&dyn Library>>();
+        let lock = SourceLock::for_libraries(&libraries, &lock_context)?;
+        std::fs::remove_dir_all(&workspace)?;
+
+        lock.save(path)?;
+        Ok(lock)
+    }
+
+    /// Download sources and release binaries through a proxy. Without one the proxy configured
+    /// in `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` is used, as by curl.
+    /// Source archives, including the ones of the dependencies, are downloaded by us with the proxy.
+    /// Git sources are cloned by shared-library-builder, which only reads the environment
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Verify HTTPS downloads with other root certificates, for example the one of a
+    /// TLS-intercepting proxy added to the system trust store. They are used for the same
+    /// downloads as the proxy, see [`CairoLibrary::with_proxy`]
+    pub fn with_certificate_authorities(mut self, authorities: CertificateAuthorities) -> Self {
+        self.certificate_authorities = Some(authorities);
+        self
+    }
+
+    /// How the downloads of release binaries and source archives show their progress,
+    /// curl's progress meter by default
+    pub fn with_download_progress(mut self, download_progress: DownloadProgress) -> Self {
+        self.download_progress = Some(download_progress);
+        self
+    }
+
+    /// Download the sources of the graph concurrently with the network settings of cairo.
+    /// The drift check runs before, cairo itself only downloads its sources
+    async fn ensure_graph_sources(
+        &self,
+        libraries: Vec<Box<dyn Library>>,
+        context: &LibraryCompilationContext,
+        network: NetworkSettings,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let cairo = self.clone();
+        let key = library_key(self);
+        download_sources(
+            libraries,
+            context.clone(),
+            Arc::new(move |library, context| {
+                if library_key(library) == key {
+                    cairo.download_sources(context, &network)
+                } else {
+                    ensure_library_sources(library, context, &network)
+                }
+            }),
+        )
+        .await
+    }
+
+    /// Download the sources of cairo alone, without the ones of its dependencies.
+    /// Archives are downloaded by us, so that they use the network settings
+    fn download_sources(
+        &self,
+        context: &LibraryCompilationContext,
+        network: &NetworkSettings,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(ref checkout) = self.local_git {
+            return checkout.ensure_checkout(&self.source_directory(context));
+        }
+        let archive = self
+            .source_archive
+            .clone()
+            .or_else(|| SourceArchive::of_location(self.location()));
+        match archive {
+            Some(archive) => archive.ensure_unpacked(&self.source_directory(context), network),
+            None => self
+                .location()
+                .ensure_sources(&self.source_directory(context), context),
+        }
+    }
+
+    /// The configured proxy, certificates and download progress, passed to the commands
+    /// we run without changing the environment of the process
+    fn network_settings(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<NetworkSettings, Box<dyn Error>> {
+        let ca_bundle = match self.certificate_authorities {
+            Some(ref authorities) => authorities.write_bundle(context.build_root())?,
+            None => None,
+        };
+        Ok(NetworkSettings {
+            proxy: self.proxy.clone(),
+            ca_bundle,
+            download_progress: self.download_progress,
+        })
+    }
+
+    /// Use the verified prebuilt binaries if possible, otherwise compile from sources.
+    /// [`Library::compile`] does the same, but installs the binaries into the prefix
+    pub fn compile_or_fetch(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let context = &self.target_context(context);
+        match self.fetch_prebuilt(context)? {
+            Some(directory) => Ok(directory),
+            None => {
+                let mut library = self.clone();
+                library.prebuilt = None;
+                library.check_requirements(context)?;
+                library.compile(context)
+            }
+        }
+    }
+
+    /// Copy the shared libraries of a verified prebuilt archive to where cairo would be compiled into
+    fn install_prebuilt(
+        &self,
+        directory: &Path,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let destination = self
+            .compiled_library_directories(context)
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                UserFacingError::new("Could not install the prebuilt cairo")
+                    .reason(format!("{} is not supported", context.target()))
+            })?;
+        let binaries = shared_libraries_in(&[directory.to_path_buf()], context);
+        if binaries.is_empty() {
+            return Err(UserFacingError::new("Could not install the prebuilt cairo")
+                .reason(format!("There are no libraries in {}", directory.display()))
+                .into());
+        }
+        std::fs::create_dir_all(&destination)?;
+        for binary in binaries {
+            if let Some(file_name) = binary.file_name() {
+                std::fs::copy(&binary, destination.join(file_name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all tools and folders needed to compile cairo are present,
+    /// reporting every missing one at once
+    pub fn check_requirements(
+        &self,
+        options: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let options = &self.target_context(options);
+        let mut requirements = Requirements::new(self.name());
+
+        // everything else is needed on the remote machine
+        if let Some(ref remote) = self.remote {
+            if let Some(tool) = remote.tool() {
+                requirements.tool(tool);
+            }
+            return requirements.check();
+        }
+
+        match self.build_system {
+            BuildSystem::Meson => {
+                requirements
+                    .tool(Tool::MESON)
+                    .tool(Tool::NINJA)
+                    .tool(Tool::PKG_CONFIG);
+            }
+            BuildSystem::Msvc if options.is_windows() => {
+                requirements
+                    .tool(self.windows_toolchain.compiler())
+                    .tool(self.windows_toolchain.linker())
+                    .tool(self.windows_toolchain.archiver());
+            }
+            BuildSystem::Msvc => {
+                requirements.unsupported(format!(
+                    "The MSVC build system can not build for {}",
+                    options.target()
+                ));
+            }
+            BuildSystem::Autotools => {
+                requirements.tool(self.make_tool.tool());
+                if options.is_windows() && !self.make_tool.is_gnu_make() {
+                    requirements.unsupported("The Windows makefiles of cairo need GNU make");
+                }
+            }
+        }
+        if self.size_report {
+            requirements.tool(Tool::LLVM_SIZE).tool(Tool::LLVM_NM);
+        }
+        if !cfg!(feature = "freetype") && !self.minimal && !self.uses_system(Dependency::Freetype) {
+            requirements.unsupported(
+                "Building FreeType, libpng and zlib for cairo needs the `freetype` feature, \
+                 use a system FreeType or the minimal cairo without it",
+            );
+        }
+        if self.minimal && options.is_windows() {
+            requirements.unsupported("The minimal cairo can not be built for Windows");
+        }
+        if !self.win32_surface && options.is_windows() && self.build_system == BuildSystem::Meson {
+            requirements.unsupported(
+                "The meson build of cairo always enables the Win32 surface on Windows",
+            );
+        }
+        if self.color_fonts && !self.builds_freetype() {
+            requirements.unsupported(
+                "Color font support can only be ensured for the FreeType we build",
+            );
+        }
+        if self.relocatable_bundle.is_some() {
+            if self.is_static() {
+                requirements.unsupported("A static cairo can not be bundled with its dependencies");
+            }
+            if options.is_linux() {
+                requirements.tool(Tool::PATCHELF);
+            }
+            if options.is_windows() {
+                requirements.tool(Tool::LLVM_READOBJ);
+            }
+        }
+        if self.woff2 && !self.builds_freetype() {
+            requirements.unsupported("WOFF2 support can only be added to the FreeType we build");
+        }
+        if self.woff2 {
+            requirements.tool(Tool::CMAKE);
+        }
+        if !self.win32_surface && self.extra_features.contains(&CairoFeature::Win32) {
+            requirements.unsupported("The Win32 feature needs the Win32 surface");
+        }
+        if self.static_runtime && options.is_linux() {
+            requirements.tool(Tool::PATCHELF);
+        }
+        if let Some(ref glibc_baseline) = self.glibc_baseline {
+            if options.is_linux() {
+                requirements.tool(Tool::READELF);
+                if let Some(sysroot) = glibc_baseline.sysroot() {
+                    requirements.directory(sysroot, "the sysroot of the glibc baseline");
+                }
+            }
+        }
+        if self.build_tree.cleanup() != CleanupPolicy::Keep
+            && self.build_tree.location().is_none()
+            && self.build_system == BuildSystem::Autotools
+            && options.is_unix()
+        {
+            requirements.unsupported(
+                "The autotools build is configured in the install prefix, set a location with BuildTree::in_directory to remove the build tree",
+            );
+        }
+        if let (Some(_), Some(location)) = (&self.container, self.build_tree.location()) {
+            if options.is_linux() && !location.starts_with(options.build_root()) {
+                requirements.unsupported("A container only sees build trees inside the build root");
+            }
+        }
+
+        if options.is_unix() && self.build_system == BuildSystem::Autotools {
+            requirements.tool(Tool::AUTORECONF).tool(Tool::ACLOCAL);
+
+            if self.compile_commands {
+                requirements.tool(Tool::BEAR);
+            }
+
+            if options.is_linux() && self.rpath.is_some() {
+                requirements.tool(Tool::PATCHELF);
+            }
+
+            if !self.is_bootstrapped(options) {
+                requirements.tool(Tool::LIBTOOLIZE).tool(Tool::PKG_CONFIG);
+            }
+        }
+
+        if let Some(ref container) = self.container {
+            if options.is_linux() {
+                requirements.tool(container.engine().tool());
+
+                // only cairo and pixman are compiled in the image
+                if self.builds_freetype() {
+                    requirements.unsupported(
+                        "freetype, libpng and zlib would be compiled on the host, use with_system_dependency(Dependency::Freetype) to take freetype from the image or CairoLibrary::minimal()",
+                    );
+                }
+                if self.custom_dependencies || self.added_dependencies.iter().next().is_some() {
+                    requirements.unsupported(
+                        "Libraries added to the dependency graph would be compiled on the host",
+                    );
+                }
+            }
+        }
+
+        if self.smoke_tests && options.is_unix() {
+            requirements.tool(Tool::PKG_CONFIG);
+        }
+
+        if let Some(ref target) = self.target {
+            if target.parse::<LibraryTarget>().is_err() {
+                requirements.unsupported(format!("{} is not a known target", target));
+            }
+        }
+
+        if self.smoke_tests && self.cross_toolchain(options).is_some() {
+            requirements.unsupported("Smoke tests can not run binaries built for another machine");
+        }
+
+        if self.perf_tools && (self.build_system != BuildSystem::Autotools || !options.is_unix()) {
+            requirements.unsupported("The perf tools are only built by the autotools build on Unix");
+        }
+
+        if self.trace {
+            if self.build_system != BuildSystem::Autotools || !options.is_unix() {
+                requirements.unsupported("cairo-trace is only built by the autotools build on Unix");
+            }
+            if self.is_static() {
+                requirements.unsupported(
+                    "cairo-trace interposes a shared cairo, it can not be used with a static build",
+                );
+            }
+        }
+
+        if self.bundle && !self.system_dependencies.is_empty() {
+            requirements.unsupported(
+                "A bundled cairo can not link against system libraries, remove the system dependencies",
+            );
+        }
+
+        for dependency in self.prebuilt_dependencies.keys() {
+            if !dependency.has_prebuilt_binaries() {
+                requirements.unsupported(format!(
+                    "There are no prebuilt {:?} binaries, only pixman and freetype can be downloaded",
+                    dependency
+                ));
+            }
+        }
+
+        for dependency in self.link_modes.keys() {
+            if matches!(dependency, Dependency::Libpng | Dependency::Zlib) {
+                requirements.unsupported(format!(
+                    "{:?} is linked the same way as freetype, choose the link mode of freetype",
+                    dependency
+                ));
+            }
+        }
+
+        for (dependency, flags) in &self.dependency_flags {
+            if flags.is_empty() {
+                continue;
+            }
+            let built = match dependency {
+                Dependency::Pixman => true,
+                Dependency::Freetype => self.builds_freetype(),
+                Dependency::Brotli => self.brotli().is_some(),
+                // the flags of freetype are only added to its own CMake target
+                Dependency::Fontconfig | Dependency::Libpng | Dependency::Zlib => false,
+            };
+            if !built || self.prebuilt_dependencies.contains_key(dependency) {
+                requirements.unsupported(format!(
+                    "Flags can only be passed to a {:?} built from sources",
+                    dependency
+                ));
+            }
+        }
+
+        for dependency in &self.system_dependencies {
+            if options.is_windows() || !dependency.can_use_system() {
+                requirements.unsupported(format!(
+                    "{:?} can not be taken from the system when building for {}",
+                    dependency,
+                    options.target()
+                ));
+            } else {
+                requirements
+                    .tool(Tool::PKG_CONFIG)
+                    .pkg_config_package(dependency.pkg_config_name());
+            }
+        }
+
+        if options.is_windows() {
+            if self.build_system == BuildSystem::Autotools {
+                requirements.tool(Tool::COREUTILS);
+                if self.windows_toolchain != WindowsToolchain::Msvc {
+                    requirements
+                        .tool(self.windows_toolchain.compiler())
+                        .tool(self.windows_toolchain.linker())
+                        .tool(self.windows_toolchain.archiver());
+                }
+
+                // make passes the paths to the MSVC tools in the ANSI code page
+                for path in [options.sources_root(), options.build_root()] {
+                    if !path.to_string_lossy().is_ascii() {
+                        requirements.unsupported(format!(
+                            "The make based Windows build can not handle non-ASCII characters in {}, use BuildSystem::Msvc",
+                            path.display()
+                        ));
+                    }
+                }
+            }
+            if self.build_system != BuildSystem::Meson && self.version_resource {
+                requirements.tool(Tool::RC);
+            }
+            if self.module_definition {
+                requirements.tool(Tool::DUMPBIN);
+            }
+
+            // make, cl and link fail with confusing errors on paths longer than MAX_PATH
+            for path in too_long_paths(self.deepest_windows_paths(options)) {
+                requirements.unsupported(format!(
+                    "{} is longer than {} characters, move the build root closer to the root of the drive (for example C:\\b) or map it to a drive letter with subst",
+                    path.display(),
+                    MAX_PATH
+                ));
+            }
+
+            for path in self.msvc_lib_directories() {
+                requirements.directory(path, "Lib");
+            }
+            for path in self.msvc_include_directories() {
+                requirements.directory(path, "Include");
+            }
+        }
+
+        requirements.check()?;
+
+        // a missing tool would otherwise only be noticed when the dependency is compiled
+        let in_container = self.container.is_some() && options.is_linux();
+        if self.graph_pixman().is_some()
+            && !in_container
+            && !self.prebuilt_dependencies.contains_key(&Dependency::Pixman)
+        {
+            self.pixman.check_requirements(options)?;
+        }
+        if let Some(brotli) = self.brotli() {
+            brotli.check_requirements(options)?;
+        }
+        Ok(())
+    }
+
+    /// The deepest files the Windows build writes, used to detect build roots that are too deep
+    fn deepest_windows_paths(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let object = "cairo-win32-display-surface.obj";
+        let build_directory = self
+            .source_directory(options)
+            .join("src")
+            .join(options.profile());
+        vec![
+            build_directory.join("win32").join(object),
+            build_directory.join("static-objects").join(object),
+            self.native_library_prefix(options)
+                .join("include")
+                .join("cairo")
+                .join("cairo-win32.h"),
+        ]
+    }
+
+    /// The context with the target set by `with_target`
+    fn target_context(&self, context: &LibraryCompilationContext) -> LibraryCompilationContext {
+        retarget(context, self.target.as_deref())
+    }
+
+    /// The cross toolchain of builds for another machine, `None` for native builds
+    fn cross_toolchain(&self, context: &LibraryCompilationContext) -> Option<CrossToolchain> {
+        let in_container = self.container.is_some() && context.is_linux();
+        cross_toolchain(self.cross_toolchain.as_ref(), in_container, context)
+    }
+
+    /// The jobs of `make`, the jobserver of cargo is not reachable from a container
+    fn make_jobs(&self, context: &LibraryCompilationContext) -> MakeJobs {
+        let jobs = MakeJobs::from_environment();
+        if self.container.is_some() && context.is_linux() {
+            jobs.without_jobserver()
+        } else {
+            jobs
+        }
+    }
+
+    /// The Windows builds compile inside the sources and have no separate build tree
+    fn uses_build_tree(&self, context: &LibraryCompilationContext) -> bool {
+        match self.build_system {
+            BuildSystem::Meson => true,
+            BuildSystem::Autotools => context.is_unix(),
+            BuildSystem::Msvc => false,
+        }
+    }
+
+    /// The folder cairo is configured and compiled in, see [`BuildTree`]
+    fn build_directory(&self, context: &LibraryCompilationContext) -> PathBuf {
+        let prefix = self.native_library_prefix(context);
+        let default = match self.build_system {
+            BuildSystem::Meson => prefix.join("build"),
+            BuildSystem::Autotools | BuildSystem::Msvc => prefix,
+        };
+        self.build_tree.directory(self.name(), default, context)
+    }
+
+    /// The log of the environments the configure and make steps run with
+    fn environment_log(&self, context: &LibraryCompilationContext) -> EnvironmentLog {
+        EnvironmentLog::in_prefix(&self.native_library_prefix(context))
+    }
+
+    /// Sources checked out from git do not contain a generated `configure` script
+    fn is_bootstrapped(&self, context: &LibraryCompilationContext) -> bool {
+        self.source_directory(context).join("configure").exists()
+    }
+
+    /// Generate the `configure` script and `Makefile.in` files with autotools.
+    /// When `gtkdocize` is not installed we provide the same `gtk-doc.make` stub
+    /// that cairo's own `autogen.sh` creates, since we never build the documentation
+    fn bootstrap_unix(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let source_directory = self.source_directory(context);
+
+        if which::which("gtkdocize").is_err() {
+            std::fs::write(
+                source_directory.join("gtk-doc.make"),
+                "EXTRA_DIST =\nCLEANFILES =\n",
+            )?;
+        }
+
+        let autogen = source_directory.join("autogen.sh");
+
+        let mut command = if autogen.exists() {
+            let mut command = Command::new("sh");
+            command.arg(&autogen).env("NOCONFIGURE", "1");
+            command
+        } else {
+            let mut command = Command::new("autoreconf");
+            command.arg("--install").arg("--force").arg("--verbose");
+            command
+        };
+        command.current_dir(&source_directory);
+        let mut command = self.in_container(command, context);
+
+        println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
+
+        let bootstrap = command.status()?;
+        if !bootstrap.success() {
+            return Err(UserFacingError::new(format!("Could not bootstrap {}", self.name()))
+                .reason("Failed to generate the configure script with autotools")
+                .into());
+        }
+
+        Ok(())
+    }
+
+    /// The `LDFLAGS` of the autotools build: the folders of the dependencies and our flags
+    fn unix_linker_flags(&self, context: &LibraryCompilationContext) -> FlagList {
+        let mut linker_flags = FlagList::from_env("LDFLAGS")
+            .library_directories(self.dependency_library_directories(context));
+        if self.builds_freetype() {
+            linker_flags = linker_flags.flag("-lbz2_static");
+        }
+        linker_flags.flags(self.compiler_flags(context).ldflags())
+    }
+
+    /// Recompile the changed sources of a configured build and install cairo again, without
+    /// fetching, patching or configuring. The dependencies are not touched, see [`crate::Watch`]
+    pub fn rebuild(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let context = &self.target_context(context);
+        let build_directory = self.build_directory(context);
+        if !build_directory.exists() {
+            return Err(UserFacingError::new(format!("Could not rebuild {}", self.name()))
+                .reason(format!("{} does not exist", build_directory.display()))
+                .help("Compile cairo once before rebuilding it")
+                .into());
+        }
+
+        let command = match self.build_system {
+            BuildSystem::Autotools if context.is_unix() => {
+                let mut command = self.make_tool.command();
+                command
+                    .current_dir(&build_directory)
+                    .arg("install")
+                    .args(
+                        self.build_output
+                            .map(|build_output| build_output.make_variable()),
+                    );
+                let linker_flags = self.unix_linker_flags(context);
+                if linker_flags.for_libtool() != linker_flags {
+                    command.arg(format!(
+                        "LDFLAGS={}",
+                        linker_flags.for_libtool().to_unix_string()
+                    ));
+                }
+                self.make_jobs(context).apply(&mut command);
+                command
+            }
+            // meson rebuilds the changed targets before installing them
+            BuildSystem::Meson => {
+                let mut command = Command::new("meson");
+                command.arg("install").arg("-C").arg(&build_directory);
+                command
+            }
+            _ => {
+                return Err(UserFacingError::new(format!("Could not rebuild {}", self.name()))
+                    .reason(format!(
+                        "Only the autotools build on Unix and the meson build can be rebuilt, not {:?} for {}",
+                        self.build_system,
+                        context.target()
+                    ))
+                    .into())
+            }
+        };
+        let mut command = self.in_container(command, context);
+
+        println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!("Could not rebuild {}", self.name())).into());
+        }
+        Ok(())
     }
 
     fn compile_unix(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
-        self.patch_unix_makefile(context)?;
+        if !self.is_bootstrapped(context) {
+            self.bootstrap_unix(context)?;
+        }
 
-        let freetype = libfreetype(None as Option<String>);
+        self.patch_unix_makefile(context)?;
 
         let out_dir = self.native_library_prefix(context);
-        if !out_dir.exists() {
-            std::fs::create_dir_all(&out_dir)
-                .unwrap_or_else(|_| panic!("Could not create {:?}", &out_dir));
-        }
-        let makefile_dir = out_dir.clone();
+        std::fs::create_dir_all(&out_dir)?;
+        let makefile_dir = self.build_directory(context);
+        std::fs::create_dir_all(&makefile_dir)?;
 
-        let mut pkg_config_paths = self.all_pkg_config_directories(context);
-        pkg_config_paths.push(PathBuf::from("../pixman"));
+        let mut pkg_config_paths = self.dependency_pkg_config_directories(context);
         if let Ok(ref path) = std::env::var("PKG_CONFIG_PATH") {
             std::env::split_paths(path).for_each(|path| pkg_config_paths.push(path));
         }
+        self.check_pkg_config_leaks(&pkg_config_paths, false, context)?;
 
-        let mut cpp_flags = std::env::var("CPPFLAGS").unwrap_or_else(|_| "".to_owned());
-        cpp_flags = format!(
-            "{} {}",
-            cpp_flags,
-            self.dependencies.include_headers_flags(context),
-        );
+        let cpp_flags = FlagList::from_env("CPPFLAGS")
+            .include_directories(self.dependency_include_directories(context))
+            .to_unix_string();
 
-        let mut linker_flags = std::env::var("LDFLAGS").unwrap_or_else(|_| "".to_owned());
-        linker_flags = format!("{} {} -lbz2_static", linker_flags, self.dependencies.linker_libraries_flags(context));
+        let linker_flags = self.unix_linker_flags(context);
+        let libtool_linker_flags = linker_flags.for_libtool().to_unix_string();
+        let linker_flags = linker_flags.to_unix_string();
+        let c_flags = self.compiler_flags(context).autoconf_cflags_env();
+        let pkg_config_path = std::env::join_paths(&pkg_config_paths)?;
 
         println!("cpp_flags = {}", &cpp_flags);
         println!("linker_flags = {}", &linker_flags);
 
         let mut command = Command::new(self.source_directory(context).join("configure"));
+        self.set_freetype_config(&mut command, context);
+        if let Some(ref c_flags) = c_flags {
+            command.env("CFLAGS", c_flags);
+        }
         command
-            .current_dir(&out_dir)
-            .env(
-                "PKG_CONFIG_PATH",
-                std::env::join_paths(&pkg_config_paths).unwrap(),
-            )
-            .env(
-                "FREETYPE_CONFIG",
-                freetype
-                    .pkg_config_directory(context)
-                    .expect("Could not find freetype's pkgconfig"),
-            )
+            .current_dir(&makefile_dir)
+            .env("PKG_CONFIG_PATH", &pkg_config_path)
             .env("CPPFLAGS", &cpp_flags)
             .env("LDFLAGS", &linker_flags)
-            .arg("--enable-ft=yes")
+            .args(if self.minimal {
+                &MINIMAL_AUTOTOOLS_OPTIONS[..]
+            } else if self.uses_system(Dependency::Fontconfig) {
+                &["--enable-ft=yes", "--enable-fc=yes"][..]
+            } else {
+                &["--enable-ft=yes"][..]
+            })
+            .args(
+                self.extra_features
+                    .iter()
+                    .filter_map(|feature| feature.autotools_option()),
+            )
+            .args(if self.trace {
+                Some("--enable-trace=yes")
+            } else {
+                None
+            })
+            .args(
+                self.build_output
+                    .map(|build_output| build_output.configure_argument()),
+            )
             .arg(format!(
                 "--prefix={}",
                 self.native_library_prefix(context).display()
@@ -107,51 +2674,461 @@ impl CairoLibrary {
                 "--libdir={}",
                 self.native_library_prefix(context).join("lib").display()
             ));
+        if let Some(cross) = self.cross_toolchain(context) {
+            let pkg_config = cross.write_pkg_config_wrapper(
+                &self.dependency_pkg_config_directories(context),
+                &out_dir,
+            )?;
+            command
+                .arg(cross.host_argument())
+                .envs(cross.environment())
+                .env("PKG_CONFIG", pkg_config);
+        }
+        if self.configure_cache {
+            let cache = configure_cache_argument(
+                self.name(),
+                &command,
+                &self.compiler_version(context),
+                &format!("{:?}", self.container),
+                context,
+            )?;
+            command.arg(cache);
+        }
+        let mut command = self.in_container(command, context);
 
         println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
 
-        let configure = command.status().unwrap();
+        let configure = command.status()?;
 
         if !configure.success() {
-            panic!("Could not configure {}", self.name());
+            if context.is_linux() {
+                if let Some(error) = missing_x11_headers(&self.requested_features()) {
+                    return Err(error.into());
+                }
+            }
+            return Err(configure_failure(self.name(), &makefile_dir).into());
+        }
+
+        self.verify_features(&makefile_dir)?;
+
+        // `make` of the targets in a folder of the configured build tree
+        let make_command = |directory: &Path, targets: &[&str]| {
+            let mut command = self.make_tool.command();
+            self.set_freetype_config(&mut command, context);
+            command
+                .current_dir(directory)
+                .args(targets)
+                .args(
+                    self.build_output
+                        .map(|build_output| build_output.make_variable()),
+                )
+                .env("PKG_CONFIG_PATH", &pkg_config_path)
+                .env("CPPFLAGS", &cpp_flags)
+                .env("LDFLAGS", &linker_flags);
+            if let Some(ref c_flags) = c_flags {
+                command.env("CFLAGS", c_flags);
+            }
+            if libtool_linker_flags != linker_flags {
+                command.arg(format!("LDFLAGS={}", &libtool_linker_flags));
+            }
+            self.make_jobs(context).apply(&mut command);
+            command
+        };
+
+        let mut command = make_command(&makefile_dir, &["install"]);
+        if self.compile_commands {
+            command = intercept_with_bear(&command, &out_dir.join(COMPILE_COMMANDS));
+        }
+        let mut command = self.in_container(command, context);
+
+        println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!("Could not compile {}", self.name()))
+                .reason(format!("make failed in {}", makefile_dir.display()))
+                .into());
+        }
+
+        if self.trace {
+            let command = make_command(&makefile_dir.join("util").join("cairo-trace"), &["install"]);
+            let mut command = self.in_container(command, context);
+
+            println!("{:?}", &command);
+            self.environment_log(context).record(&command)?;
+
+            if !command.status()?.success() {
+                return Err(UserFacingError::new("Could not compile cairo-trace").into());
+            }
+        }
+
+        if self.perf_tools {
+            let command = make_command(&makefile_dir.join("perf"), &PERF_TOOLS);
+            self.install_perf_tools(command, context)?;
+        }
+
+        Ok(())
+    }
+
+    /// Build the perf tools with the given `make` command and install them into `bin/` of the prefix.
+    /// libtool relinks them against the installed cairo instead of the one in the build tree
+    fn install_perf_tools(
+        &self,
+        make: Command,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let build_directory = self.build_directory(context);
+        let bin = self.native_library_prefix(context).join("bin");
+        std::fs::create_dir_all(&bin)?;
+
+        let mut command = self.in_container(make, context);
+
+        println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new("Could not compile the cairo perf tools").into());
+        }
+
+        for tool in PERF_TOOLS {
+            let mut command = Command::new(build_directory.join("libtool"));
+            command
+                .current_dir(&build_directory)
+                .arg("--mode=install")
+                .arg("install")
+                .arg(Path::new("perf").join(tool))
+                .arg(bin.join(tool));
+            let mut command = self.in_container(command, context);
+
+            println!("{:?}", &command);
+            self.environment_log(context).record(&command)?;
+
+            if !command.status()?.success() {
+                return Err(UserFacingError::new(format!("Could not install {}", tool)).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Point the build to the freetype we compiled, a system freetype is found by pkg-config
+    fn set_freetype_config(&self, command: &mut Command, context: &LibraryCompilationContext) {
+        let directory = self
+            .graph_freetype()
+            .and_then(|freetype| freetype.pkg_config_directory(context));
+        if let Some(directory) = directory {
+            command.env("FREETYPE_CONFIG", directory);
+        }
+    }
+
+    /// Check that pkg-config resolves the libraries we build, and cairo itself for the
+    /// smoke tests, to our prefixes. Cross and container builds resolve them with
+    /// another pkg-config and are not checked
+    fn check_pkg_config_leaks(
+        &self,
+        pkg_config_paths: &[PathBuf],
+        cairo: bool,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        if !context.is_unix() || self.cross_toolchain(context).is_some() || self.container.is_some()
+        {
+            return Ok(());
+        }
+        let mut packages = vec![Dependency::Pixman.pkg_config_name()];
+        if self.builds_freetype() {
+            packages.push(Dependency::Freetype.pkg_config_name());
+        }
+        if self.brotli().is_some() {
+            packages.push(Dependency::Brotli.pkg_config_name());
+        }
+        let mut expected_directories = self.dependency_pkg_config_directories(context);
+        if cairo {
+            packages.push("cairo");
+            expected_directories.push(
+                self.native_library_prefix(context)
+                    .join("lib")
+                    .join("pkgconfig"),
+            );
+        }
+        self.leak_policy
+            .check(&packages, pkg_config_paths, &expected_directories)
+    }
+
+    /// The folders with the `.pc` files of the libraries we build for cairo
+    fn dependency_pkg_config_directories(
+        &self,
+        context: &LibraryCompilationContext,
+    ) -> Vec<PathBuf> {
+        let mut directories = self.all_pkg_config_directories(context);
+        if let Some(pixman) = self.graph_pixman() {
+            directories.push(
+                pixman
+                    .native_library_prefix(context)
+                    .join("lib")
+                    .join("pkgconfig"),
+            );
+        }
+        directories
+    }
+
+    /// The header folders of the libraries cairo depends on and of their own dependencies
+    fn dependency_include_directories(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        fn collect(
+            dependencies: &LibraryDependencies,
+            context: &LibraryCompilationContext,
+            directories: &mut Vec<PathBuf>,
+        ) {
+            for dependency in dependencies.iter() {
+                directories.extend(dependency.native_library_include_headers(context));
+                if let Some(dependencies) = dependency.dependencies() {
+                    collect(dependencies, context, directories);
+                }
+            }
+        }
+        let mut directories = vec![];
+        collect(&self.dependencies, context, &mut directories);
+        directories
+    }
+
+    /// The folders with the binaries of the libraries of the dependency graph, such as
+    /// pixman, freetype, libpng and zlib
+    fn dependency_library_directories(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let mut directories = vec![];
+        for dependency in self.dependencies.iter() {
+            for library in libraries_to_fetch(dependency.as_ref()) {
+                for directory in library.native_library_linker_libraries(context) {
+                    if !directories.contains(&directory) {
+                        directories.push(directory);
+                    }
+                }
+            }
+        }
+        directories
+    }
+
+    /// Steps applied to the installed binaries once the native build succeeded
+    fn post_compile(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let binaries = self.produced_binaries(context);
+        verify_architecture(&binaries, context.target())?;
+        if self.color_fonts {
+            verify_freetype_options(
+                &self.dependency_include_directories(context),
+                &COLOR_FONT_OPTIONS,
+                "color font support",
+            )?;
+        }
+        if self.brotli().is_some() {
+            verify_freetype_options(
+                &self.dependency_include_directories(context),
+                &WOFF2_OPTIONS,
+                "WOFF2 support",
+            )?;
+        }
+
+        if self.symbol_prefix.is_some() {
+            let include = self.native_library_prefix(context).join("include").join("cairo");
+            std::fs::create_dir_all(&include)?;
+            std::fs::copy(
+                self.symbol_prefix_header(context),
+                include.join(SYMBOL_PREFIX_HEADER),
+            )?;
+        }
+        if context.is_linux() {
+            if let Some(ref rpath) = self.rpath {
+                let mut library_directories = self.compiled_library_directories(context);
+                library_directories.extend(self.dependency_library_directories(context));
+                for shared_object in shared_objects_in(&self.compiled_library_directories(context)) {
+                    rpath.apply(shared_object, &library_directories)?;
+                }
+            }
+            if let Some(ref glibc_baseline) = self.glibc_baseline {
+                glibc_baseline.verify(&binaries)?;
+            }
+            if self.static_runtime {
+                let shared_objects = shared_objects_in(&self.compiled_library_directories(context));
+                verify_static_runtime(&shared_objects)?;
+            }
+        }
+        if context.is_mac() {
+            let dylibs = dylibs_in(&self.compiled_library_directories(context));
+            if let Some(ref rpath) = self.rpath {
+                for dylib in &dylibs {
+                    rpath.apply_install_name(dylib)?;
+                }
+            }
+            if let Some(ref mac_signing) = self.mac_signing {
+                for dylib in &dylibs {
+                    mac_signing.sign(dylib)?;
+                }
+            }
+        }
+        if context.is_windows() {
+            if self.module_definition {
+                let lib = self.native_library_prefix(context).join("lib");
+                for dll in dlls_in(&self.compiled_library_directories(context)) {
+                    write_def_file(&dll, &lib)?;
+                }
+            }
+            // the DLLs of the dependencies are packaged and bundled next to cairo
+            if let Some(ref windows_signing) = self.windows_signing {
+                for dll in self.produced_binaries(context) {
+                    windows_signing.sign(dll)?;
+                }
+            }
+        }
+        if let Some(ref bundle) = self.relocatable_bundle {
+            let mut bundle = bundle.clone();
+            if let Some(ref mac_signing) = self.mac_signing {
+                bundle = bundle.with_mac_signing(mac_signing.clone());
+            }
+            let in_container = self.container.is_some() && context.is_linux();
+            let native = self.cross_toolchain(context).is_none() && !in_container;
+            for file in bundle.assemble(&self.produced_binaries(context), context, native)? {
+                println!("Bundled {}", file.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_meson(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let prefix = self.native_library_prefix(context);
+        let build_dir = self.build_directory(context);
+
+        let mut pkg_config_paths = self.all_pkg_config_directories(context);
+        if let Ok(ref path) = std::env::var("PKG_CONFIG_PATH") {
+            std::env::split_paths(path).for_each(|path| pkg_config_paths.push(path));
         }
+        self.check_pkg_config_leaks(&pkg_config_paths, false, context)?;
 
-        let mut command = Command::new("make");
+        let flags = self.compiler_flags(context);
+
+        let mut command = Command::new("meson");
         command
-            .current_dir(&makefile_dir)
-            .arg("install")
-            .env(
-                "PKG_CONFIG_PATH",
-                std::env::join_paths(&pkg_config_paths).unwrap(),
-            )
-            .env(
-                "FREETYPE_CONFIG",
-                freetype
-                    .pkg_config_directory(context)
-                    .expect("Could not find freetype's pkgconfig"),
+            .current_dir(self.source_directory(context))
+            .env("PKG_CONFIG_PATH", std::env::join_paths(&pkg_config_paths)?)
+            .env("CFLAGS", flags.cflags_env())
+            .env("LDFLAGS", flags.ldflags_env())
+            .arg("setup")
+            .arg(&build_dir)
+            .arg(format!("--prefix={}", prefix.display()))
+            .arg("--libdir=lib")
+            .arg(if self.debug_checks {
+                "--buildtype=debug"
+            } else if self.debug_symbols {
+                "--buildtype=debugoptimized"
+            } else {
+                "--buildtype=release"
+            })
+            .args(self.debug_checks.then_some("-Db_ndebug=false"))
+            .arg(format!(
+                "--default-library={}",
+                if self.is_static() { "static" } else { "shared" }
+            ))
+            .args(if self.minimal {
+                &MINIMAL_MESON_OPTIONS[..]
+            } else if self.uses_system(Dependency::Fontconfig) {
+                &["-Dfreetype=enabled", "-Dfontconfig=enabled"][..]
+            } else {
+                &["-Dfreetype=enabled"][..]
+            })
+            .args(
+                self.extra_features
+                    .iter()
+                    .filter_map(|feature| feature.meson_option()),
             )
-            .env("CPPFLAGS", &cpp_flags)
-            .env("LDFLAGS", &linker_flags);
+            .arg("-Dtests=disabled");
+
+        if let Some(cross) = self.cross_toolchain(context) {
+            command
+                .arg("--cross-file")
+                .arg(cross.write_meson_cross_file(
+                    &self.dependency_pkg_config_directories(context),
+                    &prefix,
+                )?);
+        }
+        if build_dir.exists() {
+            command.arg("--reconfigure");
+        }
+        let mut command = self.in_container(command, context);
 
         println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
+
+        if !command.status()?.success() {
+            if context.is_linux() {
+                if let Some(error) = missing_x11_headers(&self.requested_features()) {
+                    return Err(error.into());
+                }
+            }
+            return Err(
+                UserFacingError::new(format!("Could not configure {}", self.name()))
+                    .reason(format!("meson setup failed for cairo {}", self.version))
+                    .into(),
+            );
+        }
+
+        self.verify_features(&build_dir)?;
+
+        if self.compile_commands {
+            // meson always writes a compilation database into the build directory
+            std::fs::copy(
+                build_dir.join(COMPILE_COMMANDS),
+                prefix.join(COMPILE_COMMANDS),
+            )?;
+        }
+
+        if self.build_output == Some(BuildOutput::Verbose) {
+            let mut command = Command::new("meson");
+            command
+                .arg("compile")
+                .arg("-C")
+                .arg(&build_dir)
+                .arg("--verbose");
+            let mut command = self.in_container(command, context);
+
+            println!("{:?}", &command);
+            self.environment_log(context).record(&command)?;
+
+            if !command.status()?.success() {
+                return Err(
+                    UserFacingError::new(format!("Could not compile {}", self.name())).into(),
+                );
+            }
+        }
+
+        let mut command = Command::new("meson");
+        command.arg("install").arg("-C").arg(&build_dir);
+        let mut command = self.in_container(command, context);
 
-        let make = command.status().unwrap();
+        println!("{:?}", &command);
+        self.environment_log(context).record(&command)?;
 
-        if !make.success() {
-            panic!("Could not compile {}", self.name());
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!("Could not compile {}", self.name())).into());
         }
 
         Ok(())
     }
 
     fn compile_windows(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
-        self.patch_windows_common_makefile(options)?;
+        let version_resource = if self.version_resource {
+            Some(compile_version_resource(
+                self.version,
+                RELEASE_VERSION,
+                &self.source_directory(options).join("build"),
+            )?)
+        } else {
+            None
+        };
+
+        self.patch_windows_common_makefile(options, version_resource.as_deref())?;
         self.patch_windows_features_makefile(options)?;
         self.patch_windows_makefile(options)?;
 
         let makefile = self.source_directory(options).join("Makefile.win32");
 
-        let mut command = Command::new("make");
+        let mut command = self.make_tool.command();
+        self.windows_crt.apply(&mut command);
         command
             .current_dir(self.source_directory(options))
             .arg("cairo")
@@ -160,205 +3137,369 @@ impl CairoLibrary {
             .arg("CFG=release")
             .arg(format!(
                 "PIXMAN_PATH={}",
-                PixmanLibrary::new()
+                self.required_graph_library(self.graph_pixman(), "pixman")?
                     .native_library_prefix(options)
                     .display()
             ))
             .arg(format!(
                 "ZLIB_PATH={}",
-                libzlib().native_library_prefix(options).display()
+                self.required_graph_library(self.graph_libzlib(), "zlib")?
+                    .native_library_prefix(options)
+                    .display()
             ))
             .arg(format!(
                 "LIBPNG_PATH={}",
-                libpng().native_library_prefix(options).display()
-            ));
+                self.required_graph_library(self.graph_libpng(), "libpng")?
+                    .native_library_prefix(options)
+                    .display()
+            ))
+            .args(self.windows_toolchain.make_variables());
 
         println!("{:?}", &command);
+        self.environment_log(options).record(&command)?;
 
-        let configure = command.status().unwrap();
-
-        if !configure.success() {
-            panic!("Could not configure {}", self.name());
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!("Could not compile {}", self.name()))
+                .reason(format!("{} failed", makefile.display()))
+                .into());
         }
-        Ok(())
+
+        // the features header is generated by the makefile, there is no separate configure step
+        self.verify_features(&self.source_directory(options))?;
+
+        self.install_windows(options)
     }
 
-    fn patch_file_with(
-        &self,
-        path: impl AsRef<Path>,
-        patcher: impl FnOnce(String) -> String,
-    ) -> Result<(), Box<dyn Error>> {
-        let path = path.as_ref().to_path_buf();
-        let file_name = path
-            .file_name()
-            .ok_or_else(|| UserFacingError::new("Could not get file name"))?
-            .to_os_string();
-
-        let mut fixed_file_name = file_name.clone();
-        fixed_file_name.push(".fixed");
-        let mut backup_file_name = file_name;
-        backup_file_name.push(".bak");
-
-        let parent_directory = path
-            .parent()
-            .ok_or_else(|| UserFacingError::new("Could not get parent folder"))?;
-
-        let actual_file = path.clone();
-        let fixed_file = parent_directory.join(&fixed_file_name);
-        let backup_file = parent_directory.join(&backup_file_name);
-
-        if fixed_file.exists() {
-            std::fs::remove_file(&fixed_file)?;
-            std::fs::copy(&backup_file, &actual_file)?;
-        } else {
-            std::fs::copy(&actual_file, &backup_file)?;
+    /// Compile cairo with `cl.exe` and `link.exe` directly, without GNU make. The enabled features
+    /// and the list of sources are read from the same makefiles the GNU make build uses
+    fn compile_msvc(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let source = self.source_directory(options);
+        let sources_directory = source.join("src");
+        let build_directory = sources_directory.join(options.profile());
+
+        let mut variables = MakefileVariables::new();
+        variables.set("top_srcdir", source.display().to_string());
+        variables.include(&source.join("build").join("Makefile.win32.features"))?;
+        // freetype is always enabled, like in patch_windows_features_makefile
+        variables.set("CAIRO_HAS_FT_FONT", "1");
+        if !self.win32_surface {
+            variables.set("CAIRO_HAS_WIN32_SURFACE", "0");
+            variables.set("CAIRO_HAS_WIN32_FONT", "0");
+        }
+        std::fs::write(
+            sources_directory.join(CAIRO_FEATURES_HEADER),
+            cairo_features_header(&variables),
+        )?;
+        self.verify_features(&source)?;
+
+        variables.include(&sources_directory.join("Makefile.win32.features"))?;
+        let sources = variables
+            .words("enabled_cairo_sources")
+            .iter()
+            .filter(|file| file.ends_with(".c"))
+            .map(|file| sources_directory.join(file))
+            .collect::<Vec<PathBuf>>();
+
+        let freetype = self.required_graph_library(self.graph_freetype(), "freetype")?;
+        let pixman = self.required_graph_library(self.graph_pixman(), "pixman")?;
+        let zlib = self
+            .required_graph_library(self.graph_libzlib(), "zlib")?
+            .native_library_prefix(options);
+        let png = self
+            .required_graph_library(self.graph_libpng(), "libpng")?
+            .native_library_prefix(options);
+
+        let mut include_directories = vec![
+            source.clone(),
+            sources_directory.clone(),
+            pixman.native_library_prefix(options).join("pixman"),
+            zlib.join("include"),
+            png.join("include"),
+        ];
+        include_directories.extend(self.msvc_include_directories());
+        include_directories.extend(freetype.native_library_include_headers(options));
+
+        let compiler_flags = self.compiler_flags(options);
+        let mut flags = vec![
+            if options.is_debug() { "-Od" } else { "-O2" }.to_string(),
+            self.windows_crt.msvc_flag().to_string(),
+            "-D_CRT_NONSTDC_NO_DEPRECATE".to_string(),
+            "-D_CRT_SECURE_NO_DEPRECATE".to_string(),
+            "-wd4244".to_string(),
+            "-wd4146".to_string(),
+            "-wd4996".to_string(),
+        ];
+        flags.extend(
+            include_directories
+                .iter()
+                .map(|path| format!("-I{}", path.display())),
+        );
+        flags.extend(compiler_flags.cflags().iter().cloned());
+
+        let objects = msvc::compile(
+            self.windows_toolchain,
+            &sources,
+            &flags,
+            &build_directory.join("objects"),
+        )?;
+
+        let mut libraries = vec![
+            "gdi32.lib".to_string(),
+            "msimg32.lib".to_string(),
+            "user32.lib".to_string(),
+            dependency_library(freetype.as_ref(), options)?
+                .display()
+                .to_string(),
+            png.join("lib").join("libpng16_static.lib").display().to_string(),
+            zlib.join("lib").join("zlibstatic.lib").display().to_string(),
+            pixman.compiled_library_binary(options)?.display().to_string(),
+        ];
+        if self.version_resource {
+            let resource =
+                compile_version_resource(self.version, RELEASE_VERSION, &source.join("build"))?;
+            libraries.push(resource.display().to_string());
+        }
+
+        let mut library_directories = self.msvc_lib_directories();
+        library_directories.extend(freetype.native_library_linker_libraries(options));
+        let link_flags = library_directories
+            .iter()
+            .map(|path| format!("-LIBPATH:{}", path.display()))
+            .chain(compiler_flags.ldflags().iter().cloned())
+            .collect::<Vec<String>>();
+
+        msvc::link_dll(
+            self.windows_toolchain,
+            &objects,
+            &libraries,
+            &link_flags,
+            &build_directory.join("cairo.dll"),
+        )?;
+
+        if self.is_static() {
+            flags.push("-DCAIRO_WIN32_STATIC_BUILD=1".to_string());
+            let objects = msvc::compile(
+                self.windows_toolchain,
+                &sources,
+                &flags,
+                &build_directory.join("static-objects"),
+            )?;
+            msvc::archive(
+                self.windows_toolchain,
+                &objects,
+                &build_directory.join("cairo-static.lib"),
+            )?;
         }
 
-        let mut contents = read_to_string(&actual_file)?;
-        contents = patcher(contents);
+        self.install_windows(options)
+    }
+
+    /// Copy the DLL, import library and symbols out of the build folder into `bin` and `lib`,
+    /// the same layout a meson build installs
+    fn install_windows(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let sources = extended_length(&self.source_directory(options).join("src"));
+        let build_directory = sources.join(options.profile());
+        let prefix = extended_length(&self.native_library_prefix(options));
+        let bin = prefix.join("bin");
+        let lib = prefix.join("lib");
+        std::fs::create_dir_all(&bin)?;
+        std::fs::create_dir_all(&lib)?;
+
+        for (file_name, directory) in [
+            ("cairo.dll", &bin),
+            ("cairo.pdb", &bin),
+            ("cairo.lib", &lib),
+            ("cairo-static.lib", &lib),
+        ] {
+            let file = build_directory.join(file_name);
+            if file.exists() {
+                std::fs::copy(&file, directory.join(file_name))?;
+            }
+        }
 
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&actual_file)?;
-        file.write_all(contents.as_bytes())?;
+        let include = prefix.join("include").join("cairo");
+        std::fs::create_dir_all(&include)?;
+
+        let mut headers = vec![extended_length(
+            &self.source_directory(options).join("cairo-version.h"),
+        )];
+        for entry in std::fs::read_dir(&sources)? {
+            let path = entry?.path();
+            let is_public_header = path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| name == "cairo.h" || name.starts_with("cairo-"))
+                && path.extension().is_some_and(|extension| extension == "h")
+                && path
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .is_some_and(|name| !name.contains("-private") && !name.contains("-inline"));
+            if is_public_header {
+                headers.push(path);
+            }
+        }
+        for header in headers.iter().filter(|header| header.exists()) {
+            if let Some(file_name) = header.file_name() {
+                std::fs::copy(header, include.join(file_name))?;
+            }
+        }
 
-        std::fs::copy(&actual_file, &fixed_file)?;
+        Ok(())
+    }
 
+    /// Apply a patch to the cairo sources and print what it changed
+    fn apply_patch(&self, patch: FilePatch) -> Result<(), Box<dyn Error>> {
+        println!("{}", patch.apply()?);
         Ok(())
     }
 
+    /// Only build the subdirectories we install from, the boilerplate library
+    /// is only needed by the perf tools
     fn patch_unix_makefile(
         &self,
         options: &LibraryCompilationContext,
     ) -> Result<(), Box<dyn Error>> {
-        self.patch_file_with(
-            self.source_directory(options).join("Makefile.in"),
-            |contents| {
-                contents.replace(
-                    "DIST_SUBDIRS = src doc util boilerplate test perf",
-                    "DIST_SUBDIRS = src boilerplate",
-                )
-            },
-        )?;
-        Ok(())
+        let subdirectories = if self.perf_tools {
+            "DIST_SUBDIRS = src boilerplate"
+        } else {
+            "DIST_SUBDIRS = src"
+        };
+        self.apply_patch(
+            FilePatch::new(self.source_directory(options).join("Makefile.in")).replace(
+                "DIST_SUBDIRS = src doc util boilerplate test perf",
+                subdirectories,
+            ),
+        )
     }
 
     fn patch_windows_common_makefile(
         &self,
         options: &LibraryCompilationContext,
+        version_resource: Option<&Path>,
     ) -> Result<(), Box<dyn Error>> {
-        let freetype = libfreetype(None as Option<String>);
+        let freetype = self.required_graph_library(self.graph_freetype(), "freetype")?;
+        let compiler_flags = self.compiler_flags(options);
+
+        let include_flags_to_replace = "DEFAULT_CFLAGS += -I. -I$(top_srcdir) -I$(top_srcdir)/src";
+
+        let mut paths_to_include = self.msvc_include_directories();
+        paths_to_include.extend(freetype.native_library_include_headers(options));
+
+        let new_include_flags = paths_to_include
+            .into_iter()
+            .map(|path| format!("DEFAULT_CFLAGS += -I{}", makefile_quoted(&path)))
+            .chain(
+                compiler_flags
+                    .cflags()
+                    .iter()
+                    .map(|flag| format!("DEFAULT_CFLAGS += {}", makefile_flag(flag))),
+            )
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let ld_flags_to_replace = "DEFAULT_LDFLAGS = -nologo $(CFG_LDFLAGS)";
+
+        let mut paths_to_link = self.msvc_lib_directories();
+        paths_to_link.extend(freetype.native_library_linker_libraries(options));
+
+        let new_ld_flags = paths_to_link
+            .into_iter()
+            .map(|path| format!("DEFAULT_LDFLAGS += -LIBPATH:{}", makefile_quoted(&path)))
+            .chain(
+                compiler_flags
+                    .ldflags()
+                    .iter()
+                    .map(|flag| format!("DEFAULT_LDFLAGS += {}", makefile_flag(flag))),
+            )
+            .collect::<Vec<String>>()
+            .join("\n");
 
-        self.patch_file_with(
+        let mut cairo_libs = format!(
+            "CAIRO_LIBS =  gdi32.lib msimg32.lib user32.lib {}",
+            makefile_quoted(&dependency_library(freetype.as_ref(), options)?)
+        );
+        if let Some(resource) = version_resource {
+            cairo_libs = format!("{} {}", cairo_libs, makefile_quoted(resource));
+        }
+
+        let patch = FilePatch::new(
             self.source_directory(options)
                 .join("build")
                 .join("Makefile.win32.common"),
-            |contents| {
-                let mut contents = contents.replace("-MD", "-MT");
-                contents = contents.replace(
-                    "CAIRO_LIBS += $(ZLIB_PATH)/zdll.lib",
-                    "CAIRO_LIBS += $(ZLIB_PATH)/lib/zlibstatic.lib",
-                );
-
-                contents = contents.replace(
-                    "ZLIB_CFLAGS += -I$(ZLIB_PATH)",
-                    "ZLIB_CFLAGS += -I$(ZLIB_PATH)/include",
-                );
-                contents = contents.replace(
-                    "CAIRO_LIBS +=  $(LIBPNG_PATH)/libpng.lib",
-                    "CAIRO_LIBS +=  $(LIBPNG_PATH)/lib/libpng16_static.lib",
-                );
-                contents = contents.replace(
-                    "LIBPNG_CFLAGS += -I$(LIBPNG_PATH)/",
-                    "LIBPNG_CFLAGS += -I$(LIBPNG_PATH)/include",
-                );
-
-                contents = contents.replace("@mkdir", "@coreutils mkdir");
-                contents = contents.replace("`dirname $<`", "\"$(shell coreutils dirname $<)\"");
-
-                let include_flags_to_replace =
-                    "DEFAULT_CFLAGS += -I. -I$(top_srcdir) -I$(top_srcdir)/src";
-
-                let mut paths_to_include = self.msvc_include_directories();
-                paths_to_include.extend(freetype.native_library_include_headers(options));
-
-                let new_include_flags = paths_to_include
-                    .into_iter()
-                    .map(|path| format!("DEFAULT_CFLAGS += -I\"{}\"", path.display()))
-                    .collect::<Vec<String>>()
-                    .join("\n");
-
-                contents = contents.replace(
-                    include_flags_to_replace,
-                    &format!("{}\n{}", include_flags_to_replace, new_include_flags),
-                );
-
-                let ld_flags_to_replace = "DEFAULT_LDFLAGS = -nologo $(CFG_LDFLAGS)";
-
-                let mut paths_to_link = self.msvc_lib_directories();
-
-                paths_to_link.extend(freetype.native_library_linker_libraries(options));
-
-                let new_ld_flags = paths_to_link
-                    .into_iter()
-                    .map(|path| format!("DEFAULT_LDFLAGS += -LIBPATH:\"{}\"", path.display()))
-                    .collect::<Vec<String>>()
-                    .join("\n");
-
-                contents = contents.replace(
-                    ld_flags_to_replace,
-                    &format!("{}\n{}", ld_flags_to_replace, new_ld_flags),
-                );
-
-                contents = contents.replace(
-                    "CAIRO_LIBS =  gdi32.lib msimg32.lib user32.lib",
-                    "CAIRO_LIBS =  gdi32.lib msimg32.lib user32.lib freetype.lib",
-                );
-
-                contents
-            },
-        )?;
+        );
+        let patch = self
+            .windows_crt
+            .patch_makefile(patch)
+            // the dependency folders are quoted, the build root may contain spaces
+            .replace(
+                "CAIRO_LIBS += $(ZLIB_PATH)/zdll.lib",
+                "CAIRO_LIBS += \"$(ZLIB_PATH)/lib/zlibstatic.lib\"",
+            )
+            .replace(
+                "ZLIB_CFLAGS += -I$(ZLIB_PATH)",
+                "ZLIB_CFLAGS += -I\"$(ZLIB_PATH)/include\"",
+            )
+            .replace(
+                "CAIRO_LIBS +=  $(LIBPNG_PATH)/libpng.lib",
+                "CAIRO_LIBS +=  \"$(LIBPNG_PATH)/lib/libpng16_static.lib\"",
+            )
+            .replace(
+                "LIBPNG_CFLAGS += -I$(LIBPNG_PATH)/",
+                "LIBPNG_CFLAGS += -I\"$(LIBPNG_PATH)/include\"",
+            )
+            .replace(
+                "PIXMAN_CFLAGS := -I$(PIXMAN_PATH)/pixman",
+                "PIXMAN_CFLAGS := -I\"$(PIXMAN_PATH)/pixman\"",
+            )
+            .replace(
+                "PIXMAN_LIBS := $(PIXMAN_PATH)/pixman/$(CFG)/pixman-1.lib",
+                "PIXMAN_LIBS := \"$(PIXMAN_PATH)/pixman/$(CFG)/pixman-1.lib\"",
+            )
+            .replace_all("@mkdir", "@coreutils mkdir")
+            .quote_recipe_directories()
+            .replace(
+                include_flags_to_replace,
+                format!("{}\n{}", include_flags_to_replace, new_include_flags),
+            )
+            .replace(
+                ld_flags_to_replace,
+                format!("{}\n{}", ld_flags_to_replace, new_ld_flags),
+            )
+            .replace("CAIRO_LIBS =  gdi32.lib msimg32.lib user32.lib", cairo_libs);
 
-        Ok(())
+        self.apply_patch(patch)
     }
 
     fn patch_windows_features_makefile(
         &self,
         options: &LibraryCompilationContext,
     ) -> Result<(), Box<dyn Error>> {
-        self.patch_file_with(
-            self.source_directory(options)
-                .join("build")
-                .join("Makefile.win32.features-h"),
-            |contents| contents.replace("@echo", "@coreutils echo"),
-        )?;
-        self.patch_file_with(
-            self.source_directory(options)
-                .join("build")
-                .join("Makefile.win32.features"),
-            |contents| contents.replace("CAIRO_HAS_FT_FONT=0", "CAIRO_HAS_FT_FONT=1"),
+        let build = self.source_directory(options).join("build");
+        self.apply_patch(
+            FilePatch::new(build.join("Makefile.win32.features-h"))
+                .replace_all("@echo", "@coreutils echo"),
         )?;
-        Ok(())
+        let mut features = FilePatch::new(build.join("Makefile.win32.features"))
+            .replace("CAIRO_HAS_FT_FONT=0", "CAIRO_HAS_FT_FONT=1");
+        if !self.win32_surface {
+            features = features
+                .replace("CAIRO_HAS_WIN32_SURFACE=1", "CAIRO_HAS_WIN32_SURFACE=0")
+                .replace("CAIRO_HAS_WIN32_FONT=1", "CAIRO_HAS_WIN32_FONT=0");
+        }
+        self.apply_patch(features)
     }
 
     fn patch_windows_makefile(
         &self,
         options: &LibraryCompilationContext,
     ) -> Result<(), Box<dyn Error>> {
-        self.patch_file_with(
-            self.source_directory(options)
-                .join("src")
-                .join("Makefile.win32"),
-            |contents| {
-                contents.replace(
-                    "@for x in $(enabled_cairo_headers); do echo \"	src/$$x\"; done",
+        self.apply_patch(
+            FilePatch::new(self.source_directory(options).join("src").join("Makefile.win32"))
+                .replace(
+                    "@for x in $(enabled_cairo_headers); do echo \"\tsrc/$$x\"; done",
                     "",
-                )
-            },
-        )?;
-
-        Ok(())
+                ),
+        )
     }
 }
 
@@ -371,20 +3512,24 @@ impl Library for CairoLibrary {
     fn release_location(&self) -> &LibraryLocation {
         self.release_location
             .as_ref()
-            .unwrap_or_else(|| &self.source_location)
+            .unwrap_or(&self.source_location)
     }
 
     fn name(&self) -> &str {
         "cairo"
     }
 
+    /// Fetch and prepare the sources of the whole graph, see [`CairoLibrary::ensure_all_sources`]
     fn ensure_sources(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
-        self.location()
-            .ensure_sources(&self.source_directory(options), options)?;
-        Ok(())
+        self.ensure_all_sources(options)
     }
 
     fn dependencies(&self) -> Option<&LibraryDependencies> {
+        // a remote builder compiles the dependencies itself and copies their prefixes back,
+        // the graph is still used for their folders and binaries
+        if self.remote.is_some() {
+            return None;
+        }
         Some(&self.dependencies)
     }
 
@@ -397,64 +3542,80 @@ impl Library for CairoLibrary {
     }
 
     fn force_compile(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
-        if options.is_unix() {
-            self.compile_unix(options).expect("Failed to compile cairo")
+        let options = &self.target_context(options);
+        if let Some(directory) = self.fetch_prebuilt(options)? {
+            return self.install_prebuilt(&directory, options);
         }
-        if options.is_windows() {
-            self.compile_windows(options)
-                .expect("Failed to compile cairo")
+        if let Some(ref remote) = self.remote {
+            return self.compile_remotely(remote, options);
+        }
+        self.environment_log(options).clear()?;
+        if let Some(ref symbol_prefix) = self.symbol_prefix {
+            symbol_prefix.write_header(&self.source_directory(options))?;
+        }
+        let compiled = match self.build_system {
+            BuildSystem::Meson => self.compile_meson(options),
+            BuildSystem::Msvc => self.compile_msvc(options),
+            BuildSystem::Autotools if options.is_unix() => self.compile_unix(options),
+            BuildSystem::Autotools if options.is_windows() => self.compile_windows(options),
+            BuildSystem::Autotools => Ok(()),
+        };
+        if self.uses_build_tree(options) {
+            self.build_tree
+                .clean_up(&self.build_directory(options), compiled.is_ok())?;
+        }
+        compiled?;
+        self.post_compile(options)?;
+        SecurityPatch::copy_applied(
+            &self.source_directory(options),
+            &self.native_library_prefix(options),
+        )?;
+        self.build_manifest(options)?
+            .write(&self.native_library_prefix(options))?;
+
+        if self.smoke_tests {
+            self.run_smoke_tests(options)?;
         }
         Ok(())
     }
 
     fn compiled_library_directories(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let options = &self.target_context(options);
+        if self.build_system == BuildSystem::Meson {
+            let prefix = self.native_library_prefix(options);
+            if options.is_windows() {
+                return vec![prefix.join("bin"), prefix.join("lib")];
+            }
+            return vec![prefix.join("lib")];
+        }
         if options.is_unix() {
             let lib = self.native_library_prefix(options).join("lib");
             return vec![lib];
         }
         if options.is_windows() {
-            let lib = self
-                .native_library_prefix(options)
-                .join("src")
-                .join(options.profile());
-            return vec![lib];
+            let prefix = self.native_library_prefix(options);
+            return vec![prefix.join("bin"), prefix.join("lib")];
         }
         vec![]
     }
 
     fn ensure_requirements(&self, options: &LibraryCompilationContext) {
-        which::which("make").expect("Could not find `make`");
-
-        if options.is_unix() {
-            which::which("autoreconf").expect("Could not find `autoreconf`");
-            which::which("aclocal").expect("Could not find `aclocal`");
-        }
-
-        if options.is_windows() {
-            which::which("coreutils").expect("Could not find `coreutils`");
-
-            for path in self.msvc_lib_directories() {
-                if !path.exists() {
-                    panic!("Lib folder does not exist: {}", &path.display())
-                }
-            }
-            for path in self.msvc_include_directories() {
-                if !path.exists() {
-                    panic!("Include folder does not exist: {}", &path.display())
-                }
-            }
+        let options = &self.target_context(options);
+        if let Err(error) = self.check_requirements(options) {
+            panic!("{}", error);
         }
     }
 
     fn native_library_prefix(&self, options: &LibraryCompilationContext) -> PathBuf {
-        if options.is_windows() {
-            return self.source_directory(options);
-        }
-
-        options.build_root().join(self.name())
+        let options = &self.target_context(options);
+        options
+            .build_root()
+            .join(self.name())
+            .join(self.install_prefix_name(options))
     }
 
     fn native_library_include_headers(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let context = &self.target_context(context);
         let mut dirs = vec![];
 
         let directory = self.native_library_prefix(context).join("include");
@@ -467,6 +3628,7 @@ impl Library for CairoLibrary {
     }
 
     fn native_library_linker_libraries(&self, context: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let context = &self.target_context(context);
         let mut dirs = vec![];
 
         let directory = self.native_library_prefix(context).join("lib");
@@ -479,6 +3641,7 @@ impl Library for CairoLibrary {
     }
 
     fn pkg_config_directory(&self, context: &LibraryCompilationContext) -> Option<PathBuf> {
+        let context = &self.target_context(context);
         let directory = self
             .native_library_prefix(context)
             .join("lib")
@@ -501,3 +3664,4 @@ impl From<CairoLibrary> for Box<dyn Library> {
         Box::new(library)
     }
 }
+
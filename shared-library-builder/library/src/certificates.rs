@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The file the combined certificate bundle is written to
+pub const CA_BUNDLE_FILE: &str = "ca-bundle.pem";
+
+/// Well known locations of the system bundle on Linux distributions and macOS
+const SYSTEM_BUNDLES: [&str; 5] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/etc/openssl/cert.pem",
+];
+
+/// The root certificates HTTPS downloads are verified with, for networks where a
+/// TLS-intercepting proxy presents its own certificate
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateAuthorities {
+    /// Replaces the trust store of the system
+    bundle: Option<PathBuf>,
+    /// PEM files trusted in addition to the bundle
+    additional: Vec<PathBuf>,
+}
+
+impl CertificateAuthorities {
+    /// Trust only the certificates of the PEM bundle
+    pub fn bundle(bundle: impl Into<PathBuf>) -> Self {
+        Self {
+            bundle: Some(bundle.into()),
+            additional: vec![],
+        }
+    }
+
+    /// Trust the certificates of the PEM file in addition to the bundle or the system trust store
+    pub fn with_additional(mut self, certificate: impl Into<PathBuf>) -> Self {
+        self.additional.push(certificate.into());
+        self
+    }
+
+    /// The bundle that is trusted when no other is given, the one in `SSL_CERT_FILE`
+    /// unless it is our own, the one of the system otherwise
+    fn system_bundle(own_bundle: &Path) -> Option<PathBuf> {
+        std::env::var_os("SSL_CERT_FILE")
+            .map(PathBuf::from)
+            .filter(|bundle| bundle != own_bundle)
+            .or_else(|| {
+                SYSTEM_BUNDLES
+                    .iter()
+                    .map(PathBuf::from)
+                    .find(|bundle| bundle.is_file())
+            })
+    }
+
+    /// The bundle to verify with, additional certificates are appended to a copy
+    /// of the base bundle written into the directory
+    pub fn write_bundle(&self, directory: &Path) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        if self.additional.is_empty() {
+            return Ok(self.bundle.clone());
+        }
+
+        let bundle = directory.join(CA_BUNDLE_FILE);
+        let mut contents = String::new();
+        if let Some(base) = self.bundle.clone().or_else(|| Self::system_bundle(&bundle)) {
+            contents.push_str(&read_certificate(&base)?);
+            contents.push('\n');
+        }
+        for certificate in &self.additional {
+            contents.push_str(&read_certificate(certificate)?);
+            contents.push('\n');
+        }
+
+        std::fs::create_dir_all(directory)?;
+        std::fs::write(&bundle, contents)?;
+        Ok(Some(bundle))
+    }
+}
+
+/// Pass the bundle to the command as `SSL_CERT_FILE` and `CURL_CA_BUNDLE`,
+/// which are read by curl, git and OpenSSL
+pub fn apply_bundle(bundle: &Path, command: &mut Command) {
+    command.env("SSL_CERT_FILE", bundle);
+    command.env("CURL_CA_BUNDLE", bundle);
+}
+
+fn read_certificate(file: &Path) -> Result<String, Box<dyn Error>> {
+    std::fs::read_to_string(file).map_err(|error| {
+        UserFacingError::new(format!(
+            "Could not read certificates from {}",
+            file.display()
+        ))
+        .reason(error.to_string())
+        .help("Certificates must be PEM encoded")
+        .into()
+    })
+}
@@ -0,0 +1,185 @@
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// The name of the published checksum manifest
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// SHA-256 checksums of release artifacts in the format of `sha256sum`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChecksumManifest {
+    entries: Vec<(String, String)>,
+}
+
+impl ChecksumManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn for_files(files: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        let mut manifest = Self::new();
+        for file in files {
+            let name = file
+                .file_name()
+                .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+                .to_string_lossy()
+                .to_string();
+            manifest.entries.push((name, sha256_file(file)?));
+        }
+        Ok(manifest)
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace();
+                let checksum = columns.next()?;
+                let name = columns.next()?.trim_start_matches('*');
+                Some((name.to_string(), checksum.to_lowercase()))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// The checksums of both manifests, the ones of `newer` replace those of the same name.
+    /// The manifest itself has no entry
+    pub fn merge(&self, newer: &ChecksumManifest) -> Self {
+        let mut entries: Vec<(String, String)> = vec![];
+        for (name, checksum) in &self.entries {
+            let is_kept = name != CHECKSUMS_FILE
+                && newer.checksum(name).is_none()
+                && entries.iter().all(|(each, _)| each != name);
+            if is_kept {
+                entries.push((name.clone(), checksum.clone()));
+            }
+        }
+        entries.extend(newer.entries.iter().cloned());
+        Self { entries }
+    }
+
+    pub fn checksum(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(each, _)| each == name)
+            .map(|(_, checksum)| checksum.as_str())
+    }
+
+    /// Check that the file matches the checksum recorded for its name
+    pub fn verify(&self, file: &Path) -> Result<(), Box<dyn Error>> {
+        let name = file
+            .file_name()
+            .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+            .to_string_lossy()
+            .to_string();
+
+        let expected = self.checksum(&name).ok_or_else(|| {
+            UserFacingError::new(format!("No checksum published for {}", &name))
+        })?;
+        let actual = sha256_file(file)?;
+
+        if expected != actual {
+            return Err(UserFacingError::new(format!("Checksum mismatch for {}", &name))
+                .reason(format!("expected {}", expected))
+                .reason(format!("actual {}", actual))
+                .into());
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ChecksumManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, checksum) in &self.entries {
+            writeln!(f, "{}  {}", checksum, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, contents: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("libcairo checksums {}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join(name);
+        std::fs::write(&file, contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn hashes_files_and_data_alike() {
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_file(&file("abc.txt", "abc")).unwrap(),
+            sha256(b"abc")
+        );
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let linux = file("libcairo-x86_64-unknown-linux-gnu.zip", "linux");
+        let windows = file("libcairo-x86_64-pc-windows-msvc.zip", "windows");
+        let manifest = ChecksumManifest::for_files(&[linux.clone(), windows.clone()]).unwrap();
+
+        let path = file(CHECKSUMS_FILE, "");
+        manifest.write(&path).unwrap();
+        let parsed = ChecksumManifest::parse(&std::fs::read_to_string(&path).unwrap());
+
+        assert_eq!(parsed, manifest);
+        parsed.verify(&linux).unwrap();
+        parsed.verify(&windows).unwrap();
+    }
+
+    #[test]
+    fn parses_binary_mode_and_upper_case_checksums() {
+        let manifest = ChecksumManifest::parse("BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD *abc.txt\n\nmalformed\n");
+        assert_eq!(manifest.checksum("abc.txt"), Some(sha256(b"abc").as_str()));
+        assert_eq!(manifest.to_string().lines().count(), 1);
+    }
+
+    #[test]
+    fn verify_rejects_changed_and_unknown_files() {
+        let changed = file("changed.zip", "before");
+        let manifest = ChecksumManifest::for_files(std::slice::from_ref(&changed)).unwrap();
+        std::fs::write(&changed, "after").unwrap();
+
+        assert!(manifest.verify(&changed).is_err());
+        assert!(manifest.verify(&file("unknown.zip", "unknown")).is_err());
+    }
+}
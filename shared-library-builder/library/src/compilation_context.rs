@@ -0,0 +1,72 @@
+use shared_library_builder::{LibraryCompilationContext, LibraryTarget};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// A shortcut to the [`LibraryCompilationContext`] of a standalone build, for scripts and
+/// tools that drive [`crate::CairoLibrary`] without a cargo build script. By default the
+/// release profile is built for the current platform, sources are downloaded into `src`
+/// of the build root
+#[derive(Debug, Clone)]
+pub struct CompilationContext {
+    build_root: PathBuf,
+    sources_root: Option<PathBuf>,
+    target: LibraryTarget,
+    debug: bool,
+}
+
+impl CompilationContext {
+    pub fn new(build_root: impl Into<PathBuf>) -> Self {
+        Self {
+            build_root: build_root.into(),
+            sources_root: None,
+            target: LibraryTarget::for_current_platform(),
+            debug: false,
+        }
+    }
+
+    pub fn with_sources_root(mut self, sources_root: impl Into<PathBuf>) -> Self {
+        self.sources_root = Some(sources_root.into());
+        self
+    }
+
+    pub fn with_target(mut self, target: LibraryTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Build for a target triple such as `aarch64-unknown-linux-gnu`
+    pub fn with_target_triple(self, triple: &str) -> Result<Self, Box<dyn Error>> {
+        let target = triple
+            .parse::<LibraryTarget>()
+            .map_err(|_| UserFacingError::new(format!("{} is not a known target", triple)))?;
+        Ok(self.with_target(target))
+    }
+
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn build_root(&self) -> &Path {
+        &self.build_root
+    }
+
+    pub fn sources_root(&self) -> PathBuf {
+        self.sources_root
+            .clone()
+            .unwrap_or_else(|| self.build_root.join("src"))
+    }
+
+    /// The context, creating the sources root if it does not exist yet
+    pub fn create(&self) -> Result<LibraryCompilationContext, Box<dyn Error>> {
+        let sources_root = self.sources_root();
+        std::fs::create_dir_all(&sources_root)?;
+        Ok(LibraryCompilationContext::new(
+            &sources_root,
+            &self.build_root,
+            self.target,
+            self.debug,
+        ))
+    }
+}
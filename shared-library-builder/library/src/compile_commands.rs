@@ -0,0 +1,27 @@
+use std::path::Path;
+use std::process::Command;
+
+/// The name of the compilation database understood by clangd based tools
+pub const COMPILE_COMMANDS: &str = "compile_commands.json";
+
+/// Wrap a build command with `bear` so that every compiler invocation
+/// is recorded into a compilation database at `output`
+pub fn intercept_with_bear(command: &Command, output: &Path) -> Command {
+    let mut bear = Command::new("bear");
+    bear.arg("--output")
+        .arg(output)
+        .arg("--")
+        .arg(command.get_program())
+        .args(command.get_args());
+
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => bear.env(key, value),
+            None => bear.env_remove(key),
+        };
+    }
+    if let Some(directory) = command.get_current_dir() {
+        bear.current_dir(directory);
+    }
+    bear
+}
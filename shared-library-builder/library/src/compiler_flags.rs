@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Extra compiler and linker flags passed to the native build of a library
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompilerFlags {
+    cflags: Vec<String>,
+    ldflags: Vec<String>,
+}
+
+impl CompilerFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cflag(mut self, flag: impl Into<String>) -> Self {
+        self.cflags.push(flag.into());
+        self
+    }
+
+    pub fn ldflag(mut self, flag: impl Into<String>) -> Self {
+        self.ldflags.push(flag.into());
+        self
+    }
+
+    /// Add the same flag to both the compiler and the linker
+    pub fn flag(self, flag: impl Into<String>) -> Self {
+        let flag = flag.into();
+        self.cflag(flag.clone()).ldflag(flag)
+    }
+
+    pub fn extend(mut self, other: &CompilerFlags) -> Self {
+        self.cflags.extend(other.cflags.iter().cloned());
+        self.ldflags.extend(other.ldflags.iter().cloned());
+        self
+    }
+
+    pub fn cflags(&self) -> &[String] {
+        &self.cflags
+    }
+
+    pub fn ldflags(&self) -> &[String] {
+        &self.ldflags
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cflags.is_empty() && self.ldflags.is_empty()
+    }
+
+    /// The value of the `CFLAGS` environment variable with our flags appended
+    pub fn cflags_env(&self) -> String {
+        FlagList::from_env("CFLAGS")
+            .flags(self.cflags.iter())
+            .to_unix_string()
+    }
+
+    /// [`CompilerFlags::cflags_env`] for a configure script, which only compiles with its
+    /// default `-g -O2` when `CFLAGS` is not set. `None` when there is nothing to set
+    pub fn autoconf_cflags_env(&self) -> Option<String> {
+        if std::env::var_os("CFLAGS").is_some() {
+            return Some(self.cflags_env());
+        }
+        if self.cflags.is_empty() {
+            return None;
+        }
+        Some(
+            FlagList::new()
+                .flags(AUTOCONF_DEFAULT_CFLAGS)
+                .flags(self.cflags.iter())
+                .to_unix_string(),
+        )
+    }
+
+    /// The value of the `LDFLAGS` environment variable with our flags appended
+    pub fn ldflags_env(&self) -> String {
+        FlagList::from_env("LDFLAGS")
+            .flags(self.ldflags.iter())
+            .to_unix_string()
+    }
+
+    /// [`CompilerFlags::ldflags_env`] for the link steps run through libtool
+    pub fn libtool_ldflags_env(&self) -> String {
+        FlagList::from_env("LDFLAGS")
+            .flags(self.ldflags.iter())
+            .for_libtool()
+            .to_unix_string()
+    }
+}
+
+/// The `CFLAGS` of a configure script when the variable is not set
+const AUTOCONF_DEFAULT_CFLAGS: [&str; 2] = ["-g", "-O2"];
+
+/// Linker flags that libtool before 2.4.7 does not pass to the compiler driver
+const LIBTOOL_DROPPED_FLAGS: [&str; 2] = ["-static-libgcc", "-static-libstdc++"];
+
+/// An ordered list of flags for a variable like `CPPFLAGS` or `LDFLAGS`. Flags keep the order
+/// they were added in, the flags of the environment come first. Include and library folders
+/// and defines (`-I`, `-L`, `-D`) are only kept the first time they are added. Libraries (`-l`)
+/// are kept every time, a static library may have to be linked again after the libraries
+/// that use it, and everything else may take an argument in the next flag and is kept as is.
+/// Flags are quoted only when the list is rendered, for the platform that reads it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagList {
+    flags: Vec<String>,
+}
+
+impl FlagList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The flags of an environment variable of the build process, split like a shell would
+    pub fn from_env(variable: &str) -> Self {
+        let value = std::env::var(variable).unwrap_or_else(|_| "".to_owned());
+        Self::new().flags(split_unix_words(&value))
+    }
+
+    fn is_unique(flag: &str) -> bool {
+        flag.len() > 2
+            && ["-I", "-L", "-D"]
+                .iter()
+                .any(|prefix| flag.starts_with(prefix))
+    }
+
+    pub fn flag(mut self, flag: impl Into<String>) -> Self {
+        let flag = flag.into();
+        if flag.is_empty() || (Self::is_unique(&flag) && self.flags.contains(&flag)) {
+            return self;
+        }
+        self.flags.push(flag);
+        self
+    }
+
+    pub fn flags(self, flags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        flags.into_iter().fold(self, |list, flag| list.flag(flag))
+    }
+
+    pub fn include_directory(self, directory: impl AsRef<Path>) -> Self {
+        self.flag(format!("-I{}", directory.as_ref().display()))
+    }
+
+    pub fn include_directories(
+        self,
+        directories: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Self {
+        directories
+            .into_iter()
+            .fold(self, |list, directory| list.include_directory(directory))
+    }
+
+    pub fn library_directory(self, directory: impl AsRef<Path>) -> Self {
+        self.flag(format!("-L{}", directory.as_ref().display()))
+    }
+
+    pub fn library_directories(
+        self,
+        directories: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Self {
+        directories
+            .into_iter()
+            .fold(self, |list, directory| list.library_directory(directory))
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        self.flags.as_slice()
+    }
+
+    /// The flags for the link steps run through libtool, which silently drops the compiler
+    /// driver flags it does not know when linking a shared library. `-Wc,` passes them on
+    pub fn for_libtool(&self) -> Self {
+        Self {
+            flags: self
+                .flags
+                .iter()
+                .map(|flag| {
+                    if LIBTOOL_DROPPED_FLAGS.contains(&flag.as_str()) {
+                        format!("-Wc,{}", flag)
+                    } else {
+                        flag.clone()
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// The flags as one string for a POSIX shell, configure and make split their flag
+    /// variables with it. Flags with spaces or shell characters are single quoted
+    pub fn to_unix_string(&self) -> String {
+        self.flags
+            .iter()
+            .map(|flag| quote_unix(flag))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+pub(crate) fn quote_unix(flag: &str) -> String {
+    let is_plain = flag
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric() || "-_=+.,/:@%^".contains(character));
+    if is_plain && !flag.is_empty() {
+        return flag.to_string();
+    }
+    format!("'{}'", flag.replace('\'', "'\\''"))
+}
+
+/// Quote an argument the way the MSVC tools split their command line and response files
+pub(crate) fn quote_windows(argument: &str) -> String {
+    if !argument.is_empty() && !argument.contains(|c: char| c.is_whitespace() || c == '"') {
+        return argument.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for character in argument.chars() {
+        match character {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            '"' => quoted.push_str(&"\\".repeat(backslashes * 2 + 1)),
+            _ => quoted.push_str(&"\\".repeat(backslashes)),
+        }
+        quoted.push(character);
+        backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Split a value into words like a POSIX shell, honoring single and double quotes and backslashes
+fn split_unix_words(value: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut characters = value.chars();
+
+    while let Some(character) = characters.next() {
+        match (quote, character) {
+            (Some(open), character) if character == open => quote = None,
+            (Some('"'), '\\') => {
+                if let Some(next) = characters.next() {
+                    word.get_or_insert_with(String::new).push(next);
+                }
+            }
+            (Some(_), character) => word.get_or_insert_with(String::new).push(character),
+            (None, '\'' | '"') => {
+                quote = Some(character);
+                word.get_or_insert_with(String::new);
+            }
+            (None, '\\') => {
+                if let Some(next) = characters.next() {
+                    word.get_or_insert_with(String::new).push(next);
+                }
+            }
+            (None, character) if character.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            (None, character) => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+    if let Some(word) = word {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_first_include_directory_and_define() {
+        let flags = FlagList::new().flags(vec![
+            "-I/usr/include",
+            "-DNDEBUG",
+            "-I/usr/include",
+            "-L/usr/lib",
+            "-DNDEBUG",
+            "-L/usr/lib",
+        ]);
+        assert_eq!(
+            flags.as_slice(),
+            ["-I/usr/include", "-DNDEBUG", "-L/usr/lib"]
+        );
+    }
+
+    #[test]
+    fn keeps_repeated_libraries() {
+        let flags = FlagList::new().flags(vec!["-lz", "-lpng16", "-lz"]);
+        assert_eq!(flags.as_slice(), ["-lz", "-lpng16", "-lz"]);
+    }
+
+    #[test]
+    fn keeps_repeated_flags_with_arguments() {
+        let flags = FlagList::new().flags(vec!["-arch", "x86_64", "-arch", "arm64", ""]);
+        assert_eq!(flags.as_slice(), ["-arch", "x86_64", "-arch", "arm64"]);
+    }
+
+    #[test]
+    fn passes_dropped_flags_through_libtool() {
+        let flags = FlagList::new()
+            .flags(vec!["-static-libgcc", "-L/usr/lib"])
+            .for_libtool();
+        assert_eq!(flags.as_slice(), ["-Wc,-static-libgcc", "-L/usr/lib"]);
+    }
+
+    #[test]
+    fn quotes_unix_flags() {
+        let flags = FlagList::new().flags(vec!["-I/build root/include", "-DNAME='cairo'", "-O2"]);
+        assert_eq!(
+            flags.to_unix_string(),
+            r#"'-I/build root/include' '-DNAME='\''cairo'\''' -O2"#
+        );
+    }
+
+    #[test]
+    fn quotes_windows_arguments() {
+        assert_eq!(quote_windows("/MT"), "/MT");
+        assert_eq!(quote_windows(""), r#""""#);
+        assert_eq!(
+            quote_windows(r#"/IC:\build root\include\"#),
+            r#""/IC:\build root\include\\""#
+        );
+        assert_eq!(quote_windows(r#"-DNAME="cairo""#), r#""-DNAME=\"cairo\"""#);
+    }
+
+    #[test]
+    fn splits_like_a_shell() {
+        assert_eq!(
+            split_unix_words(r#"-O2  '-I/build root' "-DA=\"b\"" c\ d"#),
+            ["-O2", "-I/build root", r#"-DA="b""#, "c d"]
+        );
+    }
+}
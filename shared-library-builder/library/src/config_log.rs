@@ -0,0 +1,202 @@
+use std::path::Path;
+use user_error::UserFacingError;
+
+/// The file autoconf's `configure` logs every check into, in the build directory
+pub const CONFIG_LOG: &str = "config.log";
+
+/// How many compiler errors of the failing check are reported
+const COMPILER_ERRORS: usize = 5;
+
+/// Explain a failed `configure` with what `config.log` recorded: the error configure stopped
+/// with, the last check it ran and the compiler errors of that check
+pub fn configure_failure(library: &str, build_directory: &Path) -> UserFacingError {
+    let log = build_directory.join(CONFIG_LOG);
+    let error = UserFacingError::new(format!("Could not configure {}", library));
+
+    let contents = match std::fs::read_to_string(&log) {
+        Ok(contents) => contents,
+        Err(_) => {
+            return error
+                .reason(format!("configure did not write {}", log.display()))
+                .help("Check the output of configure above")
+        }
+    };
+
+    let mut error = error;
+    for reason in ConfigLog::parse(&contents).reasons() {
+        error = error.reason(reason);
+    }
+    error.help(format!("See {} for the whole log", log.display()))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ConfigLog {
+    /// The message of `AC_MSG_ERROR`, with the lines of multi-line messages such as
+    /// the unmet package requirements of `pkg-config`
+    error: Vec<String>,
+    /// The `checking ...` line of the last check before the error
+    check: Option<String>,
+    compiler_errors: Vec<String>,
+}
+
+impl ConfigLog {
+    fn parse(contents: &str) -> Self {
+        // the checks are logged between the platform description and the cache variables
+        let tests = contents
+            .lines()
+            .skip_while(|line| !line.contains("## Core tests. ##"))
+            .take_while(|line| !line.contains("## Cache variables. ##"))
+            .collect::<Vec<&str>>();
+
+        let error_line = match tests
+            .iter()
+            .rposition(|line| message(line, "error: ").is_some())
+        {
+            Some(error_line) => error_line,
+            None => return Self::default(),
+        };
+
+        let mut error = vec![message(tests[error_line], "error: ")
+            .unwrap_or_default()
+            .trim_start_matches("error: ")
+            .to_string()];
+        error.extend(
+            tests[error_line + 1..]
+                .iter()
+                .take_while(|line| !line.starts_with("configure:") && !line.starts_with("##"))
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string()),
+        );
+
+        let check_line = tests[..error_line]
+            .iter()
+            .rposition(|line| message(line, "checking ").is_some());
+        let check = check_line
+            .and_then(|check_line| message(tests[check_line], "").map(|check| check.to_string()));
+
+        let compiler_errors = check_line
+            .map(|check_line| {
+                tests[check_line..error_line]
+                    .iter()
+                    .filter(|line| !line.starts_with("configure:") && !line.starts_with('|'))
+                    .filter(|line| line.contains("error:"))
+                    .take(COMPILER_ERRORS)
+                    .map(|line| line.trim().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            error,
+            check,
+            compiler_errors,
+        }
+    }
+
+    fn reasons(&self) -> Vec<String> {
+        let mut reasons = vec![];
+        if !self.error.is_empty() {
+            reasons.push(self.error.join("\n"));
+        }
+        if let Some(ref check) = self.check {
+            reasons.push(format!("The last check was `{}`", check));
+        }
+        reasons.extend(self.compiler_errors.iter().cloned());
+        if reasons.is_empty() {
+            reasons.push("config.log does not record an error".to_string());
+        }
+        reasons
+    }
+}
+
+/// The text after `configure:<line>: ` if it starts with the prefix
+fn message<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix("configure:")?;
+    let (number, text) = rest.split_once(": ")?;
+    if !number.chars().all(|character| character.is_ascii_digit()) {
+        return None;
+    }
+    text.starts_with(prefix).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "This file contains any messages produced by compilers while
+running configure, to aid debugging if configure makes a mistake.
+
+## --------- ##
+## Platform. ##
+## --------- ##
+
+uname -m = x86_64
+
+## ----------- ##
+## Core tests. ##
+## ----------- ##
+
+configure:4512: checking for gcc
+configure:4539: result: gcc
+configure:18204: checking for pixman-1 >= 0.30.0
+configure:18211: $PKG_CONFIG --exists --print-errors \"pixman-1 >= 0.30.0\"
+conftest.c:12:10: fatal error: pixman.h: No such file or directory
+conftest.c:14:1: error: unknown type name 'pixman_image_t'
+| #include <pixman.h>
+configure:18214: result: no
+configure:18290: error: Package requirements (pixman-1 >= 0.30.0) were not met:
+
+No package 'pixman-1' found
+
+## ---------------- ##
+## Cache variables. ##
+## ---------------- ##
+
+ac_cv_c_compiler_gnu=yes
+";
+
+    #[test]
+    fn parses_the_error_the_last_check_and_its_compiler_errors() {
+        let log = ConfigLog::parse(LOG);
+        assert_eq!(
+            log.error,
+            vec![
+                "Package requirements (pixman-1 >= 0.30.0) were not met:".to_string(),
+                "No package 'pixman-1' found".to_string(),
+            ]
+        );
+        assert_eq!(
+            log.check.as_deref(),
+            Some("checking for pixman-1 >= 0.30.0")
+        );
+        assert_eq!(
+            log.compiler_errors,
+            vec![
+                "conftest.c:12:10: fatal error: pixman.h: No such file or directory".to_string(),
+                "conftest.c:14:1: error: unknown type name 'pixman_image_t'".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_log_without_an_error_records_nothing() {
+        let log = ConfigLog::parse("## Core tests. ##\nconfigure:10: checking for gcc\n");
+        assert_eq!(log, ConfigLog::default());
+        assert_eq!(
+            log.reasons(),
+            vec!["config.log does not record an error".to_string()]
+        );
+    }
+
+    #[test]
+    fn only_configure_lines_are_messages() {
+        assert_eq!(
+            message("configure:42: error: failed", "error: "),
+            Some("error: failed")
+        );
+        assert_eq!(message("configure:4x: error: failed", "error: "), None);
+        assert_eq!(message("conftest.c:1: error: failed", "error: "), None);
+        assert_eq!(message("configure:42: checking for gcc", "error: "), None);
+    }
+}
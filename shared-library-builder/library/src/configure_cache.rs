@@ -0,0 +1,49 @@
+use crate::hash::short_hash;
+use shared_library_builder::LibraryCompilationContext;
+use std::error::Error;
+use std::process::Command;
+
+/// Environment variables of the build process that change the results of configure checks
+const CONFIGURE_ENVIRONMENT: &[&str] = &[
+    "CC", "CPP", "CFLAGS", "CPPFLAGS", "LDFLAGS", "LIBS", "PKG_CONFIG_PATH", "PATH",
+];
+
+/// The `--cache-file` argument that lets repeated runs of the given configure command reuse
+/// the results of its checks. The cache is named after a hash of the command with its arguments
+/// and environment, the version line of the compiler the build runs (see `compiler_version`,
+/// in the container or of the cross toolchain) and anything in `toolchain` (for example the
+/// container image), so changing any of them starts with a fresh cache instead of a stale one.
+/// Caches live in `configure-cache` of the build root, remove the folder to start over
+pub fn configure_cache_argument(
+    library: &str,
+    configure: &Command,
+    compiler_version: &str,
+    toolchain: &str,
+    context: &LibraryCompilationContext,
+) -> Result<String, Box<dyn Error>> {
+    let environment = CONFIGURE_ENVIRONMENT
+        .iter()
+        .map(|variable| format!("{}={}", variable, std::env::var(variable).unwrap_or_default()))
+        .collect::<Vec<String>>();
+
+    let key = format!(
+        "{:?} {:?} {:?} {:?} {} {}",
+        configure.get_program(),
+        configure.get_args().collect::<Vec<_>>(),
+        configure.get_envs().collect::<Vec<_>>(),
+        environment,
+        compiler_version,
+        toolchain
+    );
+
+    let directory = context.build_root().join("configure-cache");
+    std::fs::create_dir_all(&directory)?;
+
+    let file = directory.join(format!(
+        "{}-{}-{}.cache",
+        library,
+        context.target(),
+        short_hash(key)
+    ));
+    Ok(format!("--cache-file={}", file.display()))
+}
@@ -0,0 +1,122 @@
+use crate::requirements::Tool;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The program used to run build containers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContainerEngine {
+    #[default]
+    Docker,
+    Podman,
+}
+
+impl ContainerEngine {
+    pub fn tool(&self) -> Tool {
+        match self {
+            Self::Docker => Tool::DOCKER,
+            Self::Podman => Tool::PODMAN,
+        }
+    }
+}
+
+/// Runs the Linux build commands inside a container image, so that the produced binaries
+/// link against the glibc and system headers of the image rather than of the build machine.
+/// The sources and build roots are mounted at the same paths they have on the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerBuild {
+    engine: ContainerEngine,
+    image: String,
+    arguments: Vec<String>,
+}
+
+impl ContainerBuild {
+    pub fn new(engine: ContainerEngine, image: impl Into<String>) -> Self {
+        Self {
+            engine,
+            image: image.into(),
+            arguments: vec![],
+        }
+    }
+
+    pub fn docker(image: impl Into<String>) -> Self {
+        Self::new(ContainerEngine::Docker, image)
+    }
+
+    pub fn podman(image: impl Into<String>) -> Self {
+        Self::new(ContainerEngine::Podman, image)
+    }
+
+    /// An extra argument for `run`, for example `--platform=linux/amd64`
+    pub fn arg(mut self, argument: impl Into<String>) -> Self {
+        self.arguments.push(argument.into());
+        self
+    }
+
+    pub fn engine(&self) -> ContainerEngine {
+        self.engine
+    }
+
+    /// Wrap a build command so that it runs in a fresh container with the same
+    /// working directory and explicitly set environment variables
+    pub fn wrap(&self, command: &Command, context: &LibraryCompilationContext) -> Command {
+        let mut container = Command::new(self.engine.tool().name);
+        container.arg("run").arg("--rm");
+
+        // rootless podman already maps the container root to the calling user
+        if self.engine == ContainerEngine::Docker {
+            if let Some(user) = Self::current_user() {
+                container.arg("--user").arg(user);
+            }
+        }
+
+        for mount in [context.sources_root(), context.build_root()] {
+            let mount = Self::absolute(mount);
+            container
+                .arg("--volume")
+                .arg(format!("{}:{}", mount.display(), mount.display()));
+        }
+
+        if let Some(directory) = command.get_current_dir() {
+            container.arg("--workdir").arg(Self::absolute(directory));
+        }
+
+        for (key, value) in command.get_envs() {
+            if let Some(value) = value {
+                let mut variable = key.to_os_string();
+                variable.push("=");
+                variable.push(value);
+                container.arg("--env").arg(variable);
+            }
+        }
+
+        container
+            .args(&self.arguments)
+            .arg(&self.image)
+            .arg(Self::absolute(Path::new(command.get_program())))
+            .args(command.get_args());
+        container
+    }
+
+    /// Paths that exist on the host are made absolute, program names are looked up in the container
+    fn absolute(path: &Path) -> PathBuf {
+        if path.components().count() > 1 {
+            std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    fn current_user() -> Option<String> {
+        let id = |flag: &str| {
+            Command::new("id")
+                .arg(flag)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        Some(format!("{}:{}", id("-u")?, id("-g")?))
+    }
+}
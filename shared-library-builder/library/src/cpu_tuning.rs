@@ -0,0 +1,65 @@
+use crate::compiler_flags::CompilerFlags;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+
+/// The instruction set cairo and pixman are compiled for.
+/// Pixman detects SIMD extensions at runtime, so tuning mostly
+/// affects the code the compiler generates for cairo itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CpuTuning {
+    /// The portable baseline of the target architecture (x86-64 with SSE2, armv8-a),
+    /// the resulting binaries run on every CPU of that architecture. This is the default
+    #[default]
+    Portable,
+    /// Haswell and newer x86-64 CPUs with AVX2 and FMA, ignored on other architectures.
+    /// Only use it for controlled deployments, older CPUs will crash with illegal instructions
+    Avx2,
+    /// The CPU of the build machine, not suitable for distribution
+    Native,
+    /// Explicit `-march`/`-mtune` values and the MSVC `/arch` value
+    Custom {
+        march: String,
+        mtune: Option<String>,
+        msvc_arch: Option<String>,
+    },
+}
+
+impl CpuTuning {
+    pub fn custom(march: impl Into<String>) -> Self {
+        Self::Custom {
+            march: march.into(),
+            mtune: None,
+            msvc_arch: None,
+        }
+    }
+
+    pub fn flags(&self, context: &LibraryCompilationContext) -> CompilerFlags {
+        let is_x86_64 = context.target().to_string().starts_with("x86_64");
+        let flags = CompilerFlags::new();
+
+        if context.is_windows() {
+            return match self {
+                Self::Avx2 if is_x86_64 => flags.cflag("/arch:AVX2"),
+                Self::Custom {
+                    msvc_arch: Some(arch),
+                    ..
+                } => flags.cflag(format!("/arch:{}", arch)),
+                _ => flags,
+            };
+        }
+
+        match self {
+            Self::Portable => flags,
+            Self::Avx2 if is_x86_64 => flags.cflag("-march=haswell").cflag("-mtune=haswell"),
+            Self::Avx2 => flags,
+            Self::Native => flags.cflag("-march=native").cflag("-mtune=native"),
+            Self::Custom { march, mtune, .. } => {
+                let flags = flags.cflag(format!("-march={}", march));
+                match mtune {
+                    Some(mtune) => flags.cflag(format!("-mtune={}", mtune)),
+                    None => flags,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::compiler_flags::quote_unix;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{LibraryCompilationContext, LibraryTarget};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// The meson machine file written into the build folder of a cross build
+pub const MESON_CROSS_FILE: &str = "cross.ini";
+
+/// The pkg-config wrapper written into the build folder of a cross build
+pub const PKG_CONFIG_WRAPPER: &str = "cross-pkg-config";
+
+/// A toolchain that produces binaries for another machine than the one running the build.
+/// Autotools gets it as `--host` with `CC`, `AR`, `RANLIB` and `STRIP`, meson as a generated
+/// cross file, so no machine files have to be written by hand
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrossToolchain {
+    /// The GNU triple, for example `aarch64-linux-gnu`
+    triple: String,
+    /// The C compiler with the arguments it always needs
+    c_compiler: Vec<String>,
+    ar: String,
+    ranlib: String,
+    strip: String,
+    sysroot: Option<PathBuf>,
+}
+
+impl CrossToolchain {
+    /// The GNU toolchain prefixed with the triple, as installed by `gcc-aarch64-linux-gnu`
+    pub fn gcc(triple: impl Into<String>) -> Self {
+        let triple = triple.into();
+        Self {
+            c_compiler: vec![format!("{}-gcc", &triple)],
+            ar: format!("{}-ar", &triple),
+            ranlib: format!("{}-ranlib", &triple),
+            strip: format!("{}-strip", &triple),
+            sysroot: None,
+            triple,
+        }
+    }
+
+    /// clang and the LLVM binutils, which target every triple from a single installation
+    pub fn clang(triple: impl Into<String>) -> Self {
+        let triple = triple.into();
+        Self {
+            c_compiler: vec!["clang".to_string(), format!("--target={}", &triple)],
+            ar: "llvm-ar".to_string(),
+            ranlib: "llvm-ranlib".to_string(),
+            strip: "llvm-strip".to_string(),
+            sysroot: None,
+            triple,
+        }
+    }
+
+    /// The default toolchain for a target: the Xcode clang for macOS,
+    /// clang for Android and the GNU cross compilers for Linux
+    pub fn for_target(target: &LibraryTarget) -> Self {
+        let triple = gnu_triple(&target.to_string());
+        if target.is_mac() {
+            let architecture = triple
+                .split('-')
+                .next()
+                .unwrap_or("")
+                .replace("aarch64", "arm64");
+            let mut toolchain = Self::clang(triple);
+            toolchain.c_compiler = vec!["clang".to_string(), "-arch".to_string(), architecture];
+            toolchain.ar = "ar".to_string();
+            toolchain.ranlib = "ranlib".to_string();
+            toolchain.strip = "strip".to_string();
+            return toolchain;
+        }
+        if target.is_android() {
+            return Self::clang(triple);
+        }
+        Self::gcc(triple)
+    }
+
+    /// The C compiler, the first item is the program
+    pub fn with_c_compiler(mut self, c_compiler: Vec<String>) -> Self {
+        self.c_compiler = c_compiler;
+        self
+    }
+
+    pub fn with_ar(mut self, ar: impl Into<String>) -> Self {
+        self.ar = ar.into();
+        self
+    }
+
+    pub fn with_ranlib(mut self, ranlib: impl Into<String>) -> Self {
+        self.ranlib = ranlib.into();
+        self
+    }
+
+    pub fn with_strip(mut self, strip: impl Into<String>) -> Self {
+        self.strip = strip.into();
+        self
+    }
+
+    /// The root of the target's headers and libraries, passed to the compiler as `--sysroot`
+    pub fn with_sysroot(mut self, sysroot: impl Into<PathBuf>) -> Self {
+        self.sysroot = Some(sysroot.into());
+        self
+    }
+
+    pub fn triple(&self) -> &str {
+        self.triple.as_str()
+    }
+
+    pub fn sysroot(&self) -> Option<&Path> {
+        self.sysroot.as_deref()
+    }
+
+    pub(crate) fn c_compiler(&self) -> Vec<String> {
+        let mut compiler = self.c_compiler.clone();
+        if let Some(ref sysroot) = self.sysroot {
+            compiler.push(format!("--sysroot={}", sysroot.display()));
+        }
+        compiler
+    }
+
+    /// The `--host` argument of configure
+    pub fn host_argument(&self) -> String {
+        format!("--host={}", &self.triple)
+    }
+
+    /// The tools for configure, it does not derive them from `--host` when they are set
+    pub fn environment(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("CC", self.c_compiler().join(" ")),
+            ("AR", self.ar.clone()),
+            ("RANLIB", self.ranlib.clone()),
+            ("STRIP", self.strip.clone()),
+        ]
+    }
+
+    /// The environment that restricts pkg-config to the given folders. The `.pc` files of the
+    /// build machine describe host binaries and must never end up in a cross build
+    pub fn pkg_config_environment(&self, directories: &[PathBuf]) -> Vec<(&'static str, String)> {
+        let directories = directories
+            .iter()
+            .map(|directory| directory.display().to_string())
+            .collect::<Vec<String>>()
+            .join(":");
+        vec![
+            ("PKG_CONFIG_LIBDIR", directories),
+            ("PKG_CONFIG_PATH", String::new()),
+            // the prefixes we build are absolute paths of the build machine
+            ("PKG_CONFIG_SYSROOT_DIR", String::new()),
+        ]
+    }
+
+    /// Write a `pkg-config` wrapper script with the restricted environment into the directory,
+    /// configure and meson run it instead of the pkg-config of the build machine
+    pub fn write_pkg_config_wrapper(
+        &self,
+        pkg_config_directories: &[PathBuf],
+        directory: &Path,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let mut script = String::from("#!/bin/sh\n");
+        for (variable, value) in self.pkg_config_environment(pkg_config_directories) {
+            script.push_str(&format!("export {}={}\n", variable, quote_unix(&value)));
+        }
+        script.push_str("exec pkg-config \"$@\"\n");
+
+        std::fs::create_dir_all(directory)?;
+        let wrapper = directory.join(PKG_CONFIG_WRAPPER);
+        std::fs::write(&wrapper, script)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(wrapper)
+    }
+
+    /// A meson cross file describing the toolchain and the machine it produces binaries for
+    pub fn meson_cross_file(&self, pkg_config: &Path) -> String {
+        let array = |items: &[String]| {
+            format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| meson_string(item))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        };
+
+        let cpu = self.triple.split('-').next().unwrap_or("");
+        let cpu_family = match cpu {
+            "i386" | "i486" | "i586" | "i686" => "x86",
+            "arm64" => "aarch64",
+            cpu if cpu.starts_with("arm") => "arm",
+            cpu => cpu,
+        };
+        let system = if self.triple.contains("android") {
+            "android"
+        } else if self.triple.contains("darwin") {
+            "darwin"
+        } else if self.triple.contains("windows") || self.triple.contains("mingw") {
+            "windows"
+        } else {
+            "linux"
+        };
+
+        let mut contents = String::from("[binaries]\n");
+        contents.push_str(&format!("c = {}\n", array(&self.c_compiler())));
+        contents.push_str(&format!("ar = {}\n", meson_string(&self.ar)));
+        contents.push_str(&format!("strip = {}\n", meson_string(&self.strip)));
+        contents.push_str(&format!(
+            "pkgconfig = {}\n",
+            meson_string(&pkg_config.display().to_string())
+        ));
+
+        if let Some(ref sysroot) = self.sysroot {
+            contents.push_str("\n[properties]\n");
+            contents.push_str(&format!(
+                "sys_root = {}\n",
+                meson_string(&sysroot.display().to_string())
+            ));
+        }
+
+        contents.push_str("\n[host_machine]\n");
+        contents.push_str(&format!("system = {}\n", meson_string(system)));
+        contents.push_str(&format!("cpu_family = {}\n", meson_string(cpu_family)));
+        contents.push_str(&format!("cpu = {}\n", meson_string(cpu)));
+        contents.push_str("endian = 'little'\n");
+        contents
+    }
+
+    /// Write the meson cross file together with its pkg-config wrapper into the directory
+    /// and return the path of the cross file
+    pub fn write_meson_cross_file(
+        &self,
+        pkg_config_directories: &[PathBuf],
+        directory: &Path,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let pkg_config = self.write_pkg_config_wrapper(pkg_config_directories, directory)?;
+        let file = directory.join(MESON_CROSS_FILE);
+        std::fs::write(&file, self.meson_cross_file(&pkg_config))?;
+        Ok(file)
+    }
+}
+
+/// The toolchain to build for the target of the context with. An explicitly configured one wins,
+/// otherwise builds for another target than the host use the default toolchain of the target.
+/// A build container is expected to provide an environment for the target already
+pub fn cross_toolchain(
+    configured: Option<&CrossToolchain>,
+    in_container: bool,
+    context: &LibraryCompilationContext,
+) -> Option<CrossToolchain> {
+    if let Some(configured) = configured {
+        return Some(configured.clone());
+    }
+    if in_container {
+        return None;
+    }
+    let target = context.target();
+    let is_host = target.to_string() == LibraryTarget::for_current_platform().to_string();
+    if is_host || target.is_windows() {
+        return None;
+    }
+    Some(CrossToolchain::for_target(target))
+}
+
+/// The context with its target replaced by the given Rust target triple, unknown triples keep
+/// the target of the context. Lets one process drive builds for several targets
+pub fn retarget(
+    context: &LibraryCompilationContext,
+    target: Option<&str>,
+) -> LibraryCompilationContext {
+    match target.and_then(|target| target.parse::<LibraryTarget>().ok()) {
+        Some(target) => LibraryCompilationContext::new(
+            context.sources_root(),
+            context.build_root(),
+            target,
+            context.is_debug(),
+        ),
+        None => context.clone(),
+    }
+}
+
+/// The GNU triple of a Rust target triple, which has a vendor GNU tools leave out
+fn gnu_triple(triple: &str) -> String {
+    let parts = triple.split('-').collect::<Vec<&str>>();
+    match parts.as_slice() {
+        [cpu, "unknown" | "pc", rest @ ..] => std::iter::once(*cpu)
+            .chain(rest.iter().copied())
+            .collect::<Vec<&str>>()
+            .join("-"),
+        _ => triple.to_string(),
+    }
+}
+
+fn meson_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
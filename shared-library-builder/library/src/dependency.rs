@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryOptions;
+
+/// A native library in cairo's dependency graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Dependency {
+    Pixman,
+    Freetype,
+    Fontconfig,
+    Brotli,
+    /// Built together with freetype by the builders of feenkcom/libfreetype
+    Libpng,
+    /// Built together with freetype by the builders of feenkcom/libfreetype
+    Zlib,
+}
+
+impl Dependency {
+    /// The name of the library's pkg-config module
+    pub fn pkg_config_name(&self) -> &'static str {
+        match self {
+            Self::Pixman => "pixman-1",
+            Self::Freetype => "freetype2",
+            Self::Fontconfig => "fontconfig",
+            Self::Brotli => "libbrotlidec",
+            Self::Libpng => "libpng16",
+            Self::Zlib => "zlib",
+        }
+    }
+
+    /// Whether the library may be taken from the platform instead of being built from sources.
+    /// Distributions expect cairo to render text with their own font stack and hinting settings
+    pub fn can_use_system(&self) -> bool {
+        matches!(self, Self::Freetype | Self::Fontconfig)
+    }
+
+    /// Whether feenkcom publishes release binaries of the library
+    pub fn has_prebuilt_binaries(&self) -> bool {
+        matches!(self, Self::Pixman | Self::Freetype)
+    }
+}
+
+/// How a dependency we build from sources ends up in the distribution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkMode {
+    /// Built as a static library and folded into cairo
+    Static,
+    /// Built as a shared library and shipped alongside cairo
+    Shared,
+}
+
+impl LinkMode {
+    pub fn apply(&self, options: &mut LibraryOptions) {
+        match self {
+            Self::Static => options.be_static(),
+            Self::Shared => options.be_shared(),
+        }
+    }
+}
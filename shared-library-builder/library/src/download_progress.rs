@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::str::FromStr;
+
+/// How the downloads of release archives, source archives of cairo and its dependencies
+/// and remote build results show their progress. The choice is passed to curl through `LIBCAIRO_DOWNLOAD_PROGRESS`
+/// (`meter`, `bar` or `hidden`), which can also be set by hand
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DownloadProgress {
+    /// curl's progress meter with the bytes received, the percentage, the speed and the time left
+    #[default]
+    Meter,
+    /// A single bar of `#` with the percentage
+    Bar,
+    Hidden,
+}
+
+impl DownloadProgress {
+    pub const VARIABLE: &'static str = "LIBCAIRO_DOWNLOAD_PROGRESS";
+
+    pub fn from_environment() -> Self {
+        std::env::var(Self::VARIABLE)
+            .ok()
+            .and_then(|progress| progress.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Pass the choice to the command in `LIBCAIRO_DOWNLOAD_PROGRESS`
+    pub fn apply_to_environment(&self, command: &mut Command) {
+        let value = match self {
+            Self::Meter => "meter",
+            Self::Bar => "bar",
+            Self::Hidden => "hidden",
+        };
+        command.env(Self::VARIABLE, value);
+    }
+
+    /// The curl arguments, curl prints the progress to stderr
+    pub fn apply(&self, command: &mut Command) {
+        match self {
+            Self::Meter => {}
+            Self::Bar => {
+                command.arg("--progress-bar");
+            }
+            Self::Hidden => {
+                command.arg("--silent");
+            }
+        }
+    }
+}
+
+impl FromStr for DownloadProgress {
+    type Err = String;
+
+    fn from_str(progress: &str) -> Result<Self, Self::Err> {
+        match progress.trim().to_lowercase().as_str() {
+            "meter" => Ok(Self::Meter),
+            "bar" => Ok(Self::Bar),
+            "hidden" | "none" | "off" => Ok(Self::Hidden),
+            _ => Err(format!("Unknown download progress: {}", progress)),
+        }
+    }
+}
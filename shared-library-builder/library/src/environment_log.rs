@@ -0,0 +1,141 @@
+use crate::compiler_flags::quote_unix;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The file in the install prefix with the environment of every configure and make invocation
+pub const ENVIRONMENT_LOG: &str = "build-environment.log";
+
+/// The inherited variables that influence a build and are recorded, everything else in the
+/// environment of CI jobs may hold tokens and other secrets and is left out
+const RECORDED_VARIABLES: [&str; 33] = [
+    "PATH",
+    "PKG_CONFIG",
+    "PKG_CONFIG_PATH",
+    "PKG_CONFIG_LIBDIR",
+    "PKG_CONFIG_SYSROOT_DIR",
+    "CPPFLAGS",
+    "CFLAGS",
+    "CXXFLAGS",
+    "LDFLAGS",
+    "LIBS",
+    "CC",
+    "CXX",
+    "CPP",
+    "AR",
+    "LD",
+    "NM",
+    "RANLIB",
+    "STRIP",
+    "FREETYPE_CONFIG",
+    "CMAKE_PREFIX_PATH",
+    "MAKEFLAGS",
+    "LD_LIBRARY_PATH",
+    "DYLD_LIBRARY_PATH",
+    "MACOSX_DEPLOYMENT_TARGET",
+    "SDKROOT",
+    "INCLUDE",
+    "LIB",
+    "LIBPATH",
+    "CL",
+    "_CL_",
+    "LANG",
+    "LC_ALL",
+    "SOURCE_DATE_EPOCH",
+];
+
+/// Parts of variable names that mark a secret, their values are never written
+const SECRET_NAME_PARTS: [&str; 5] = ["TOKEN", "SECRET", "PASSWORD", "KEY", "CREDENTIAL"];
+
+/// Records the working directory, environment and arguments of the subprocesses of a build.
+/// Every entry is a shell command starting from an empty environment (`env -i`),
+/// so a failed CI build can be reproduced locally by running it. Only the variables that
+/// influence a build are recorded, and the values of variables
+/// named like secrets are redacted, since the log is shipped in the install prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvironmentLog {
+    path: PathBuf,
+}
+
+impl EnvironmentLog {
+    pub fn in_prefix(prefix: &Path) -> Self {
+        Self {
+            path: prefix.join(ENVIRONMENT_LOG),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Start a new log, entries of a previous build are removed
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Append the command with the build variables it inherits merged with the variables it sets
+    pub fn record(&self, command: &Command) -> Result<(), Box<dyn Error>> {
+        let mut environment = std::env::vars_os()
+            .filter(|(name, _)| RECORDED_VARIABLES.contains(&name.to_string_lossy().as_ref()))
+            .collect::<BTreeMap<_, _>>();
+        for (name, value) in command.get_envs() {
+            match value {
+                Some(value) => environment.insert(name.to_os_string(), value.to_os_string()),
+                None => environment.remove(name),
+            };
+        }
+
+        let mut entry = String::new();
+        if let Some(directory) = command.get_current_dir() {
+            entry.push_str(&format!("cd {} && \\\n", quote(directory.as_os_str())));
+        }
+        entry.push_str("env -i \\\n");
+        for (name, value) in &environment {
+            let name = name.to_string_lossy();
+            let value = if is_secret(&name) {
+                "<redacted>".into()
+            } else {
+                value.to_string_lossy()
+            };
+            entry.push_str(&format!(
+                "  {} \\\n",
+                quote_unix(&format!("{}={}", name, value))
+            ));
+        }
+        entry.push_str("  ");
+        entry.push_str(
+            &std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(quote)
+                .collect::<Vec<String>>()
+                .join(" "),
+        );
+        entry.push_str("\n\n");
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(entry.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn quote(value: &OsStr) -> String {
+    quote_unix(&value.to_string_lossy())
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
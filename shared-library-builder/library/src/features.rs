@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+use user_error::UserFacingError;
+
+/// The header in which cairo's build records the enabled surfaces and font backends
+pub const CAIRO_FEATURES_HEADER: &str = "cairo-features.h";
+
+/// A cairo surface, font backend or function set that can be enabled at build time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum CairoFeature {
+    Image,
+    Png,
+    FreeType,
+    Fontconfig,
+    Pdf,
+    Quartz,
+    Win32,
+    Xlib,
+    XlibXrender,
+}
+
+impl CairoFeature {
+    pub const ALL: [CairoFeature; 9] = [
+        Self::Image,
+        Self::Png,
+        Self::FreeType,
+        Self::Fontconfig,
+        Self::Pdf,
+        Self::Quartz,
+        Self::Win32,
+        Self::Xlib,
+        Self::XlibXrender,
+    ];
+
+    /// The macro defined in `cairo-features.h` when the feature is enabled
+    pub fn macro_name(&self) -> &'static str {
+        match self {
+            Self::Image => "CAIRO_HAS_IMAGE_SURFACE",
+            Self::Png => "CAIRO_HAS_PNG_FUNCTIONS",
+            Self::FreeType => "CAIRO_HAS_FT_FONT",
+            Self::Fontconfig => "CAIRO_HAS_FC_FONT",
+            Self::Pdf => "CAIRO_HAS_PDF_SURFACE",
+            Self::Quartz => "CAIRO_HAS_QUARTZ_SURFACE",
+            Self::Win32 => "CAIRO_HAS_WIN32_SURFACE",
+            Self::Xlib => "CAIRO_HAS_XLIB_SURFACE",
+            Self::XlibXrender => "CAIRO_HAS_XLIB_XRENDER_SURFACE",
+        }
+    }
+
+    /// A function cairo exports only when the feature is built
+    pub fn entry_point(&self) -> &'static str {
+        match self {
+            Self::Image => "cairo_image_surface_create",
+            Self::Png => "cairo_surface_write_to_png",
+            Self::FreeType => "cairo_ft_font_face_create_for_ft_face",
+            Self::Fontconfig => "cairo_ft_font_options_substitute",
+            Self::Pdf => "cairo_pdf_surface_create",
+            Self::Quartz => "cairo_quartz_surface_create",
+            Self::Win32 => "cairo_win32_surface_create",
+            Self::Xlib => "cairo_xlib_surface_create",
+            Self::XlibXrender => "cairo_xlib_surface_create_with_xrender_format",
+        }
+    }
+
+    /// The `configure` argument that enables the feature, if it has one
+    pub fn autotools_option(&self) -> Option<&'static str> {
+        match self {
+            Self::Image => None,
+            Self::Png => Some("--enable-png=yes"),
+            Self::FreeType => Some("--enable-ft=yes"),
+            Self::Fontconfig => Some("--enable-fc=yes"),
+            Self::Pdf => Some("--enable-pdf=yes"),
+            Self::Quartz => Some("--enable-quartz=yes"),
+            Self::Win32 => Some("--enable-win32=yes"),
+            Self::Xlib => Some("--enable-xlib=yes"),
+            Self::XlibXrender => Some("--enable-xlib-xrender=yes"),
+        }
+    }
+
+    /// The meson option that enables the feature, features without one are always built
+    /// or follow from another option
+    pub fn meson_option(&self) -> Option<&'static str> {
+        match self {
+            Self::Png => Some("-Dpng=enabled"),
+            Self::FreeType => Some("-Dfreetype=enabled"),
+            Self::Fontconfig => Some("-Dfontconfig=enabled"),
+            Self::Quartz => Some("-Dquartz=enabled"),
+            Self::Xlib | Self::XlibXrender => Some("-Dxlib=enabled"),
+            Self::Image | Self::Pdf | Self::Win32 => None,
+        }
+    }
+}
+
+/// The `configure` arguments that turn off every surface, font backend and function set
+/// except the image surface
+pub(crate) const MINIMAL_AUTOTOOLS_OPTIONS: [&str; 20] = [
+    "--enable-png=no",
+    "--enable-ft=no",
+    "--enable-fc=no",
+    "--enable-pdf=no",
+    "--enable-ps=no",
+    "--enable-svg=no",
+    "--enable-script=no",
+    "--enable-interpreter=no",
+    "--enable-tee=no",
+    "--enable-xml=no",
+    "--enable-xlib=no",
+    "--enable-xlib-xrender=no",
+    "--enable-xcb=no",
+    "--enable-xcb-shm=no",
+    "--enable-quartz=no",
+    "--enable-quartz-font=no",
+    "--enable-quartz-image=no",
+    "--enable-win32=no",
+    "--enable-win32-font=no",
+    "--enable-gobject=no",
+];
+
+/// The meson options that turn off everything but the image surface. Without zlib
+/// neither the PDF, PostScript nor script surfaces are built, SVG needs PNG
+pub(crate) const MINIMAL_MESON_OPTIONS: [&str; 11] = [
+    "-Dpng=disabled",
+    "-Dfreetype=disabled",
+    "-Dfontconfig=disabled",
+    "-Dzlib=disabled",
+    "-Dtee=disabled",
+    "-Dxlib=disabled",
+    "-Dxcb=disabled",
+    "-Dquartz=disabled",
+    "-Dglib=disabled",
+    "-Dspectre=disabled",
+    "-Dsymbol-lookup=disabled",
+];
+
+/// The features a configured cairo build actually ended up with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSummary {
+    defined: BTreeSet<String>,
+}
+
+impl FeatureSummary {
+    /// Collect the `#define CAIRO_HAS_* 1` lines, disabled features are either missing
+    /// or mentioned in an `#undef`/comment depending on the build system
+    pub fn parse(header: &str) -> Self {
+        let defined = header
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+            .filter(|tokens| tokens.len() >= 3 && tokens[0] == "#define" && tokens[2] == "1")
+            .map(|tokens| tokens[1].to_string())
+            .filter(|name| name.starts_with("CAIRO_HAS_"))
+            .collect();
+        Self { defined }
+    }
+
+    pub fn read(header: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(header).map_err(|error| {
+            UserFacingError::new(format!("Could not read {}", header.display()))
+                .reason(error.to_string())
+        })?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn is_enabled(&self, feature: CairoFeature) -> bool {
+        self.defined.contains(feature.macro_name())
+    }
+
+    pub fn enabled(&self) -> Vec<CairoFeature> {
+        CairoFeature::ALL
+            .iter()
+            .copied()
+            .filter(|feature| self.is_enabled(*feature))
+            .collect()
+    }
+
+    /// Fail if any of the requested features was silently disabled by the build,
+    /// usually because a header or pkg-config module could not be found
+    pub fn verify(&self, requested: &[CairoFeature]) -> Result<(), Box<dyn Error>> {
+        let missing = requested
+            .iter()
+            .filter(|feature| !self.is_enabled(**feature))
+            .collect::<Vec<&CairoFeature>>();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut error = UserFacingError::new("Cairo was configured without requested features");
+        for feature in missing {
+            error = error.reason(format!("{:?} is disabled ({} is not defined)", feature, feature.macro_name()));
+        }
+        Err(error
+            .help("Check the configure output for missing headers or pkg-config modules")
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// As written by the autotools build, which lists disabled features in comments
+    const AUTOTOOLS_HEADER: &str = "/* Generated by configure.  Do not edit. */
+#ifndef CAIRO_FEATURES_H
+#define CAIRO_FEATURES_H
+
+#define CAIRO_HAS_FT_FONT 1
+#define CAIRO_HAS_IMAGE_SURFACE 1
+#define CAIRO_HAS_PNG_FUNCTIONS 1
+
+/*#undef CAIRO_HAS_XLIB_SURFACE */
+/*#undef CAIRO_HAS_PDF_SURFACE */
+
+#endif
+";
+
+    #[test]
+    fn parses_the_enabled_features() {
+        let summary = FeatureSummary::parse(AUTOTOOLS_HEADER);
+        assert_eq!(
+            summary.enabled(),
+            vec![
+                CairoFeature::Image,
+                CairoFeature::Png,
+                CairoFeature::FreeType
+            ]
+        );
+        assert!(!summary.is_enabled(CairoFeature::Xlib));
+        assert!(!summary.is_enabled(CairoFeature::Pdf));
+    }
+
+    #[test]
+    fn ignores_macros_that_are_not_features() {
+        let summary = FeatureSummary::parse(
+            "#define CAIRO_FEATURES_H 1\n#define CAIRO_HAS_PDF_SURFACE 0\n#define OTHER_HAS 1\n",
+        );
+        assert_eq!(summary, FeatureSummary::default());
+    }
+
+    #[test]
+    fn verifies_the_requested_features() {
+        let summary = FeatureSummary::parse(AUTOTOOLS_HEADER);
+        summary
+            .verify(&[CairoFeature::Png, CairoFeature::FreeType])
+            .unwrap();
+
+        let error = summary
+            .verify(&[CairoFeature::Png, CairoFeature::Pdf])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("CAIRO_HAS_PDF_SURFACE"), "{}", error);
+        assert!(!error.contains("CAIRO_HAS_PNG_FUNCTIONS"), "{}", error);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::network::NetworkSettings;
+use crate::scheduler::library_key;
+use crate::source_archive::SourceArchive;
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use user_error::UserFacingError;
+
+/// The library together with all of its transitive dependencies, each of them once
+pub fn libraries_to_fetch(library: &dyn Library) -> Vec<Box<dyn Library>> {
+    fn collect(library: &dyn Library, libraries: &mut BTreeMap<String, Box<dyn Library>>) {
+        let key = library_key(library);
+        if libraries.contains_key(&key) {
+            return;
+        }
+        libraries.insert(key, library.clone_library());
+        if let Some(dependencies) = library.dependencies() {
+            for dependency in dependencies.iter() {
+                collect(dependency.as_ref(), libraries);
+            }
+        }
+    }
+
+    let mut libraries = BTreeMap::new();
+    collect(library, &mut libraries);
+    libraries.into_values().collect()
+}
+
+/// How many sources are downloaded at the same time, enough to overlap slow links
+/// without opening a connection per library of a large graph
+const PARALLEL_DOWNLOADS: usize = 4;
+
+/// Download the sources of the libraries concurrently, so that a slow link costs the time
+/// of the largest archive rather than of all of them. All failures are reported at once.
+/// Runs [`ensure_sources_async`] to completion, so it must not be called from within an async task
+pub fn ensure_sources(
+    libraries: &[&dyn Library],
+    context: &LibraryCompilationContext,
+) -> Result<(), Box<dyn Error>> {
+    block_on(ensure_sources_async(
+        libraries
+            .iter()
+            .map(|library| library.clone_library())
+            .collect(),
+        context.clone(),
+    ))
+}
+
+/// Download the sources of the libraries concurrently without blocking the threads of
+/// the async runtime. The downloads run on tokio's blocking pool, all failures are reported at once.
+/// Source archives are downloaded with the proxy and certificates of the environment
+pub async fn ensure_sources_async(
+    libraries: Vec<Box<dyn Library>>,
+    context: LibraryCompilationContext,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    download_sources(
+        libraries,
+        context,
+        Arc::new(|library, context| {
+            ensure_library_sources(library, context, &NetworkSettings::default())
+        }),
+    )
+    .await
+}
+
+/// Download the source archive of the library ourselves with the network settings,
+/// then let the library fetch whatever else it needs
+pub(crate) fn ensure_library_sources(
+    library: &dyn Library,
+    context: &LibraryCompilationContext,
+    network: &NetworkSettings,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(archive) = SourceArchive::of_location(library.location()) {
+        archive.ensure_unpacked(&library.source_directory(context), network)?;
+    }
+    library.ensure_sources(context)
+}
+
+/// How a library of the graph gets its sources
+pub(crate) type Download =
+    Arc<dyn Fn(&dyn Library, &LibraryCompilationContext) -> Result<(), Box<dyn Error>> + Send + Sync>;
+
+/// Run `download` for every library on tokio's blocking pool, at most
+/// [`PARALLEL_DOWNLOADS`] of them at the same time
+pub(crate) async fn download_sources(
+    libraries: Vec<Box<dyn Library>>,
+    context: LibraryCompilationContext,
+    download: Download,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut libraries = libraries.into_iter();
+    let mut downloads = JoinSet::new();
+    let mut failures = vec![];
+
+    loop {
+        while downloads.len() < PARALLEL_DOWNLOADS {
+            let library = match libraries.next() {
+                Some(library) => library,
+                None => break,
+            };
+            let context = context.clone();
+            let download = download.clone();
+            downloads.spawn_blocking(move || {
+                download(library.as_ref(), &context)
+                    .map_err(|error| format!("{}: {}", library.name(), error))
+            });
+        }
+        match downloads.join_next().await {
+            Some(Ok(Ok(()))) => {}
+            Some(Ok(Err(failure))) => failures.push(failure),
+            Some(Err(error)) => failures.push(error.to_string()),
+            None => break,
+        }
+    }
+    sources_failed(failures)?;
+    Ok(())
+}
+
+/// Run a future of the fetch phase to completion on a runtime of its own, for the sync API
+pub(crate) fn block_on<T>(
+    future: impl Future<Output = Result<T, Box<dyn Error + Send + Sync>>>,
+) -> Result<T, Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime
+        .block_on(future)
+        .map_err(|error| error as Box<dyn Error>)
+}
+
+fn sources_failed(failures: Vec<String>) -> Result<(), UserFacingError> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let mut error = UserFacingError::new("Could not download the sources");
+    for failure in failures {
+        error = error.reason(failure);
+    }
+    Err(error)
+}
@@ -0,0 +1,163 @@
+use crate::compiler_flags::CompilerFlags;
+use crate::patch::FilePatch;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// The options of `ftoption.h` color fonts are rendered with, and what they are needed for
+pub const COLOR_FONT_OPTIONS: [(&str, &str); 3] = [
+    (
+        "TT_CONFIG_OPTION_COLOR_LAYERS",
+        "the COLR and CPAL color layers",
+    ),
+    (
+        "TT_CONFIG_OPTION_EMBEDDED_BITMAPS",
+        "the embedded bitmaps of CBDT and sbix fonts",
+    ),
+    (
+        "FT_CONFIG_OPTION_USE_PNG",
+        "decoding the PNG bitmaps of emoji fonts",
+    ),
+];
+
+/// The options of `ftoption.h` WOFF2 web fonts are loaded with
+pub const WOFF2_OPTIONS: [(&str, &str); 1] = [(
+    "FT_CONFIG_OPTION_USE_BROTLI",
+    "decompressing WOFF2 fonts with brotli",
+)];
+
+/// Color layers and embedded bitmaps are enabled in FreeType by default, only the PNG
+/// support is commented out and depends on the libpng found by the build
+pub fn color_fonts_patch(freetype_sources: &Path) -> FilePatch {
+    FilePatch::new(
+        freetype_sources
+            .join("include")
+            .join("freetype")
+            .join("config")
+            .join("ftoption.h"),
+    )
+    .replace(
+        "/* #define FT_CONFIG_OPTION_USE_PNG */",
+        "#define FT_CONFIG_OPTION_USE_PNG",
+    )
+}
+
+/// Check the installed configuration headers of freetype, so that a FreeType missing
+/// a format is caught after the build instead of as blank glyphs in the application
+pub fn verify_freetype_options(
+    include_directories: &[PathBuf],
+    options: &[(&str, &str)],
+    support: &str,
+) -> Result<(), Box<dyn Error>> {
+    let config = include_directories
+        .iter()
+        .flat_map(|directory| {
+            [
+                directory.join("freetype").join("config"),
+                directory.join("freetype2").join("freetype").join("config"),
+            ]
+        })
+        .find(|config| config.join("ftoption.h").is_file())
+        .ok_or_else(|| {
+            UserFacingError::new(format!("Could not verify the {} of freetype", support))
+                .reason("freetype/config/ftoption.h is not installed")
+        })?;
+
+    let defined = std::fs::read_to_string(config.join("ftoption.h"))?;
+    let mut missing = options
+        .iter()
+        .filter(|(option, _)| !is_defined(&defined, option))
+        .map(|(option, purpose)| format!("{} is not defined, it is needed for {}", option, purpose))
+        .collect::<Vec<String>>();
+
+    // the sfnt module reads the tables of TrueType, OpenType and WOFF fonts
+    let modules = std::fs::read_to_string(config.join("ftmodule.h")).unwrap_or_default();
+    if !modules.contains("sfnt_module_class") {
+        missing.push("The sfnt module is not compiled into freetype".to_string());
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut error = UserFacingError::new(format!("freetype was built without {}", support));
+    for reason in missing {
+        error = error.reason(reason);
+    }
+    Err(error
+        .help("Make sure freetype is built from the fetched sources together with its dependencies")
+        .into())
+}
+
+fn is_defined(header: &str, option: &str) -> bool {
+    header.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words.next() == Some("#define") && words.next() == Some(option)
+    })
+}
+
+/// Our changes to the CMake project of freetype, in a single patch since every application
+/// of a patch starts from the original file. The flags compile and link only the freetype
+/// target, the libpng and zlib built alongside it are configured by their own CMake projects.
+/// The prefixes of libraries we built for freetype are searched by its `find_package` and
+/// `pkg_check_modules`, without changing the search paths of our own process
+pub fn freetype_cmake_patch(
+    freetype_sources: &Path,
+    flags: Option<&CompilerFlags>,
+    prefixes: &[PathBuf],
+) -> Option<FilePatch> {
+    if flags.is_none() && prefixes.is_empty() {
+        return None;
+    }
+    let mut patch = FilePatch::new(freetype_sources.join("CMakeLists.txt"));
+
+    if let Some(flags) = flags {
+        let anchor = "freetype PRIVATE FT2_BUILD_LIBRARY)";
+        let mut options = String::new();
+        if !flags.cflags().is_empty() {
+            options.push_str(&format!(
+                "\ntarget_compile_options(freetype PRIVATE {})",
+                cmake_arguments(flags.cflags())
+            ));
+        }
+        if !flags.ldflags().is_empty() {
+            options.push_str(&format!(
+                "\ntarget_link_options(freetype PRIVATE {})",
+                cmake_arguments(flags.ldflags())
+            ));
+        }
+        patch = patch.replace(anchor, format!("{}{}", anchor, options));
+    }
+
+    if !prefixes.is_empty() {
+        let anchor = "project(freetype C)";
+        let cmake_path = |path: &Path| path.to_string_lossy().replace('\\', "/");
+        let separator = if cfg!(windows) { ";" } else { ":" };
+        let pkg_config_path = prefixes
+            .iter()
+            .map(|prefix| cmake_path(&prefix.join("lib").join("pkgconfig")))
+            .collect::<Vec<String>>()
+            .join(separator);
+        let prefixes = prefixes
+            .iter()
+            .map(|prefix| cmake_path(prefix))
+            .collect::<Vec<String>>();
+        patch = patch.replace(
+            anchor,
+            format!(
+                "{}\nset(CMAKE_PREFIX_PATH {} ${{CMAKE_PREFIX_PATH}})\nset(ENV{{PKG_CONFIG_PATH}} {})",
+                anchor,
+                cmake_arguments(&prefixes),
+                cmake_arguments(&[format!("{}{}$ENV{{PKG_CONFIG_PATH}}", pkg_config_path, separator)])
+            ),
+        );
+    }
+    Some(patch)
+}
+
+fn cmake_arguments(flags: &[String]) -> String {
+    flags
+        .iter()
+        .map(|flag| format!("\"{}\"", flag.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
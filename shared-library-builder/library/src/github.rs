@@ -0,0 +1,196 @@
+use crate::checksums::{ChecksumManifest, CHECKSUMS_FILE};
+use crate::http::{url_encode, HttpClient};
+use std::error::Error;
+use std::path::PathBuf;
+use user_error::UserFacingError;
+
+/// A GitHub release that packaged artifacts are published to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitHubRelease {
+    owner: String,
+    repository: String,
+    tag: String,
+}
+
+impl GitHubRelease {
+    pub fn new(owner: impl Into<String>, repository: impl Into<String>, tag: impl Into<String>) -> Self {
+        Self {
+            owner: owner.into(),
+            repository: repository.into(),
+            tag: tag.into(),
+        }
+    }
+
+    /// A release of feenkcom/libcairo
+    pub fn libcairo(tag: impl Into<String>) -> Self {
+        Self::new("feenkcom", "libcairo", tag)
+    }
+
+    fn client(&self) -> Result<HttpClient, Box<dyn Error>> {
+        let token = std::env::var("GITHUB_TOKEN").map_err(|_| {
+            UserFacingError::new("GITHUB_TOKEN is not set")
+                .help("Create a token with the `repo` scope to publish release assets")
+        })?;
+        Ok(HttpClient::new()
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github+json"))
+    }
+
+    fn release(&self, client: &HttpClient) -> Result<serde_json::Value, Box<dyn Error>> {
+        client.get_json(&format!(
+            "https://api.github.com/repos/{}/{}/releases/tags/{}",
+            &self.owner, &self.repository, &self.tag
+        ))
+    }
+
+    /// The checksums of the assets published before, from the asset digests GitHub computes
+    /// or from the published checksum manifest
+    fn published_checksums(
+        &self,
+        client: &HttpClient,
+        assets: &[serde_json::Value],
+    ) -> ChecksumManifest {
+        let manifest = assets
+            .iter()
+            .find(|asset| asset["name"] == CHECKSUMS_FILE)
+            .and_then(|asset| asset["url"].as_str())
+            .and_then(|url| {
+                client
+                    .clone()
+                    .header("Accept", "application/octet-stream")
+                    .get(url)
+                    .ok()
+            })
+            .map(|contents| String::from_utf8_lossy(&contents).to_string())
+            .unwrap_or_default();
+
+        let digests = assets
+            .iter()
+            .filter_map(|asset| {
+                let digest = asset["digest"].as_str()?.strip_prefix("sha256:")?;
+                Some(format!("{}  {}\n", digest, asset["name"].as_str()?))
+            })
+            .collect::<String>();
+
+        ChecksumManifest::parse(&format!("{}{}", digests, manifest))
+    }
+
+    /// Upload the artifacts together with a checksum manifest that keeps the checksums of
+    /// the assets published before. Assets that already exist
+    /// with the same checksum are skipped, differing ones are replaced
+    pub fn publish(&self, artifacts: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let release = self.release(&client)?;
+
+        let release_id = release["id"].as_u64().ok_or_else(|| {
+            UserFacingError::new(format!("Could not find release {}", &self.tag))
+        })?;
+        let existing_assets = release["assets"].as_array().cloned().unwrap_or_default();
+        let published = self.published_checksums(&client, &existing_assets);
+
+        let manifest_directory = std::env::temp_dir().join(format!(
+            "libcairo-{}-{}",
+            &self.tag,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&manifest_directory)?;
+        let manifest = manifest_directory.join(CHECKSUMS_FILE);
+        let checksums = ChecksumManifest::for_files(artifacts)?;
+        // the other platforms publish their artifacts to the same release
+        published.merge(&checksums).write(&manifest)?;
+
+        let mut files = artifacts.to_vec();
+        files.push(manifest);
+
+        for file in files {
+            let name = file
+                .file_name()
+                .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+                .to_string_lossy()
+                .to_string();
+            if let Some(asset) = existing_assets.iter().find(|asset| asset["name"] == name.as_str()) {
+                let unchanged = name != CHECKSUMS_FILE
+                    && published.checksum(&name).is_some()
+                    && published.checksum(&name) == checksums.checksum(&name);
+                if unchanged {
+                    println!("Skipping {}, already published", &name);
+                    continue;
+                }
+                if let Some(asset_id) = asset["id"].as_u64() {
+                    client.delete(&format!(
+                        "https://api.github.com/repos/{}/{}/releases/assets/{}",
+                        &self.owner, &self.repository, asset_id
+                    ))?;
+                }
+            }
+
+            println!("Uploading {}", &name);
+            client
+                .clone()
+                .header("Content-Type", "application/octet-stream")
+                .send_file(
+                    "POST",
+                    &format!(
+                        "https://uploads.github.com/repos/{}/{}/releases/{}/assets?name={}",
+                        &self.owner,
+                        &self.repository,
+                        release_id,
+                        url_encode(&name)
+                    ),
+                    &file,
+                )?;
+        }
+        std::fs::remove_dir_all(&manifest_directory)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksums::sha256;
+    use std::path::Path;
+
+    fn artifact(name: &str, contents: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("libcairo github {}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let artifact = directory.join(name);
+        std::fs::write(&artifact, contents).unwrap();
+        artifact
+    }
+
+    fn checksums(artifact: &Path) -> ChecksumManifest {
+        ChecksumManifest::for_files(&[artifact.to_path_buf()]).unwrap()
+    }
+
+    /// The manifest as the next publish reads it back, after the digests of the assets
+    fn published(manifest: &ChecksumManifest) -> ChecksumManifest {
+        ChecksumManifest::parse(&format!(
+            "{}  {}\n{}",
+            sha256(manifest.to_string().as_bytes()),
+            CHECKSUMS_FILE,
+            manifest
+        ))
+    }
+
+    #[test]
+    fn publishing_keeps_the_checksums_of_other_platforms() {
+        let linux = artifact("libcairo-x86_64-unknown-linux-gnu.so", "linux");
+        let mac = artifact("libcairo-aarch64-apple-darwin.dylib", "mac");
+
+        let first = ChecksumManifest::new().merge(&checksums(&linux));
+        let second = published(&first).merge(&checksums(&mac));
+        second.verify(&linux).unwrap();
+        second.verify(&mac).unwrap();
+        assert_eq!(second.checksum(CHECKSUMS_FILE), None);
+
+        // a rebuilt artifact replaces its checksum
+        std::fs::write(&linux, "rebuilt").unwrap();
+        let third = published(&second).merge(&checksums(&linux));
+        third.verify(&linux).unwrap();
+        third.verify(&mac).unwrap();
+        assert_eq!(third.to_string().lines().count(), 2);
+    }
+}
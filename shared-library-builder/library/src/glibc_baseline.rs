@@ -0,0 +1,148 @@
+use crate::compiler_flags::CompilerFlags;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use user_error::UserFacingError;
+
+/// The oldest glibc the produced Linux binaries must run with, for example 2.17 for CentOS 7.
+/// The binaries are linked against an old glibc either by compiling against a sysroot of
+/// the baseline distribution or inside a container of it, see `ContainerBuild`.
+/// After the build the versioned symbols the binaries reference are checked against the baseline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlibcBaseline {
+    major: u32,
+    minor: u32,
+    sysroot: Option<PathBuf>,
+}
+
+impl GlibcBaseline {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self {
+            major,
+            minor,
+            sysroot: None,
+        }
+    }
+
+    /// Compile and link against the headers and libraries of the sysroot, which contains
+    /// `usr/include` and `usr/lib` of a distribution that ships the baseline glibc
+    pub fn with_sysroot(mut self, sysroot: impl Into<PathBuf>) -> Self {
+        self.sysroot = Some(sysroot.into());
+        self
+    }
+
+    pub fn sysroot(&self) -> Option<&PathBuf> {
+        self.sysroot.as_ref()
+    }
+
+    pub fn flags(&self) -> CompilerFlags {
+        match self.sysroot {
+            Some(ref sysroot) => {
+                CompilerFlags::new().flag(format!("--sysroot={}", sysroot.display()))
+            }
+            None => CompilerFlags::new(),
+        }
+    }
+
+    /// Compile and link a CMake project against the sysroot, see [`crate::patch::cmake_project_patch`]
+    pub fn cmake_options(&self) -> Vec<String> {
+        let sysroot = match self.sysroot {
+            Some(ref sysroot) => sysroot.display().to_string().replace('\\', "/"),
+            None => return vec![],
+        };
+        ["CMAKE_C_FLAGS", "CMAKE_SHARED_LINKER_FLAGS", "CMAKE_EXE_LINKER_FLAGS"]
+            .iter()
+            .map(|variable| {
+                format!(
+                    "set({0} \"${{{0}}} --sysroot={1}\")",
+                    variable,
+                    sysroot.replace('"', "\\\"")
+                )
+            })
+            .collect()
+    }
+
+    /// Check with `readelf` that no binary needs a symbol version of glibc newer than the baseline
+    pub fn verify(&self, binaries: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let mut too_new = vec![];
+        for binary in binaries {
+            let mut command = Command::new("readelf");
+            command.arg("--version-info").arg("--wide").arg(binary);
+            println!("{:?}", &command);
+
+            let output = command.output()?;
+            if !output.status.success() {
+                return Err(UserFacingError::new(format!(
+                    "Could not read the symbol versions of {}",
+                    binary.display()
+                ))
+                .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+                .into());
+            }
+
+            let mut versions = String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .filter_map(|word| word.strip_prefix("GLIBC_"))
+                .filter_map(|version| version.parse::<GlibcBaseline>().ok())
+                .map(|version| (version.major, version.minor))
+                .filter(|version| *version > (self.major, self.minor))
+                .collect::<Vec<(u32, u32)>>();
+            versions.sort();
+            versions.dedup();
+            if !versions.is_empty() {
+                let versions = versions
+                    .iter()
+                    .map(|(major, minor)| format!("GLIBC_{}.{}", major, minor))
+                    .collect::<Vec<String>>();
+                too_new.push(format!(
+                    "{} needs {}",
+                    binary.display(),
+                    versions.join(", ")
+                ));
+            }
+        }
+
+        if too_new.is_empty() {
+            return Ok(());
+        }
+        let mut error =
+            UserFacingError::new(format!("The binaries need a glibc newer than {}", self));
+        for reason in too_new {
+            error = error.reason(reason);
+        }
+        Err(error
+            .help("Build in a container or against a sysroot of a distribution with the baseline glibc")
+            .into())
+    }
+}
+
+impl Display for GlibcBaseline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for GlibcBaseline {
+    type Err = Box<dyn Error>;
+
+    /// A glibc version such as `2.17`, a patch level is ignored
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            UserFacingError::new(format!("Invalid glibc version: {}", version))
+                .help("glibc versions have the form major.minor, for example 2.17")
+        };
+        let mut components = version.trim().split('.');
+        let mut component = || {
+            components
+                .next()
+                .and_then(|component| component.parse::<u32>().ok())
+                .ok_or_else(invalid)
+        };
+        let major = component()?;
+        let minor = component()?;
+        Ok(Self::new(major, minor))
+    }
+}
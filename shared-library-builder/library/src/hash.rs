@@ -0,0 +1,12 @@
+/// A 64-bit FNV-1a hash. Unlike `DefaultHasher` its output is stable between
+/// Rust releases, which matters since it ends up in folder names
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A short hexadecimal digest suitable for paths and file names
+pub fn short_hash(data: impl AsRef<[u8]>) -> String {
+    format!("{:016x}", fnv1a(data.as_ref()))[..8].to_string()
+}
@@ -0,0 +1,214 @@
+use crate::download_progress::DownloadProgress;
+use crate::network::NetworkSettings;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use user_error::UserFacingError;
+
+/// A thin wrapper around `curl`, used for every network request this crate makes
+/// on its own (downloading source archives, publishing, uploading and verifying release artifacts).
+/// Requests go through the proxy of its network settings or of the environment,
+/// see [`crate::Proxy`].
+/// Headers and credentials are passed to curl as a config on stdin, never as arguments
+/// other users could read from the process list
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient {
+    headers: Vec<(String, String)>,
+    user: Option<String>,
+    aws_sigv4: Option<String>,
+    network: NetworkSettings,
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send the header with every request, replacing a header of the same name
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        self.headers
+            .retain(|(each, _)| !each.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Authenticate with `user:password`, or an access key and secret for AWS signing
+    pub fn user(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.user = Some(format!("{}:{}", user.into(), password.into()));
+        self
+    }
+
+    /// Send the requests with the proxy, certificates and download progress of a build
+    pub(crate) fn with_network(mut self, network: NetworkSettings) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Sign requests with AWS Signature Version 4, for example `aws:amz:eu-central-1:s3`
+    pub fn aws_sigv4(mut self, provider: impl Into<String>) -> Self {
+        self.aws_sigv4 = Some(provider.into());
+        self
+    }
+
+    fn command(&self, method: &str, url: &str) -> Command {
+        self.command_with_progress(method, url, DownloadProgress::Hidden)
+    }
+
+    fn command_with_progress(
+        &self,
+        method: &str,
+        url: &str,
+        progress: DownloadProgress,
+    ) -> Command {
+        let network = &self.network;
+        let mut command = Command::new("curl");
+        network.apply(&mut command);
+        progress.apply(&mut command);
+        command
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--location")
+            .arg("--request")
+            .arg(method)
+            .arg("--config")
+            .arg("-");
+        if let Some(ref provider) = self.aws_sigv4 {
+            command.arg("--aws-sigv4").arg(provider);
+        }
+        // curl ignores an upper case HTTP_PROXY, pass the proxy explicitly
+        let proxy = network.proxy();
+        match proxy.for_url(url) {
+            Some(proxy) => {
+                command.arg("--proxy").arg(proxy);
+            }
+            None if !proxy.is_empty() => {
+                command.arg("--noproxy").arg("*");
+            }
+            None => {}
+        }
+        command.arg(url);
+        command
+    }
+
+    /// The curl config with the headers and the credentials
+    fn config(&self) -> String {
+        let mut config = String::new();
+        for (name, value) in &self.headers {
+            config.push_str(&format!(
+                "header = {}\n",
+                config_string(&format!("{}: {}", name, value))
+            ));
+        }
+        if let Some(ref user) = self.user {
+            config.push_str(&format!("user = {}\n", config_string(user)));
+        }
+        config
+    }
+
+    fn run(&self, command: Command, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.run_with_stderr(command, url, Stdio::piped())
+    }
+
+    /// Run curl, its errors are only part of the error if `stderr` is piped.
+    /// The progress is printed to stderr, so a download showing it inherits stderr
+    fn run_with_stderr(
+        &self,
+        mut command: Command,
+        url: &str,
+        stderr: Stdio,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        which::which("curl").map_err(|_| {
+            UserFacingError::new("Could not find `curl`").help("Install curl to access the network")
+        })?;
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.config().as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let errors = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(UserFacingError::new(format!("Request to {} failed", url))
+                .reason(if errors.is_empty() {
+                    format!("curl exited with {}", output.status)
+                } else {
+                    errors
+                })
+                .into());
+        }
+        Ok(output.stdout)
+    }
+
+    pub fn get(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.run(self.command("GET", url), url)
+    }
+
+    pub fn get_json(&self, url: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&self.get(url)?)?)
+    }
+
+    pub fn delete(&self, url: &str) -> Result<(), Box<dyn Error>> {
+        self.run(self.command("DELETE", url), url)?;
+        Ok(())
+    }
+
+    /// Send the contents of a file as the request body
+    pub fn send_file(&self, method: &str, url: &str, file: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut command = self.command(method, url);
+        command
+            .arg("--data-binary")
+            .arg(format!("@{}", file.display()));
+        self.run(command, url)
+    }
+
+    /// Download into a file, showing the progress chosen with [`DownloadProgress`]
+    pub fn download(&self, url: &str, destination: &Path) -> Result<(), Box<dyn Error>> {
+        let progress = self.network.download_progress();
+        if progress != DownloadProgress::Hidden {
+            println!("Downloading {}", url);
+        }
+        let mut command = self.command_with_progress("GET", url, progress);
+        command.arg("--output").arg(destination);
+        match progress {
+            DownloadProgress::Hidden => self.run(command, url)?,
+            _ => self.run_with_stderr(command, url, Stdio::inherit())?,
+        };
+        Ok(())
+    }
+}
+
+/// A double quoted value of a curl config file
+fn config_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in value.chars() {
+        match character {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Percent-encode a value for the query of a url
+pub(crate) fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
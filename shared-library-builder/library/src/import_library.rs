@@ -0,0 +1,82 @@
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// Extract the exported symbol names from the output of `dumpbin /EXPORTS`
+pub fn parse_dumpbin_exports(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("ordinal"))
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .take_while(|columns| columns.is_empty() || columns[0].parse::<u32>().is_ok())
+        .filter(|columns| columns.len() >= 4)
+        .map(|columns| columns[3].to_string())
+        .collect()
+}
+
+/// The static or import library to link a dependency with, such as `freetype.lib` or the
+/// `freetyped.lib` of a debug build, found in the folders it installs its libraries into
+pub fn dependency_library(
+    library: &dyn Library,
+    context: &LibraryCompilationContext,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let directories = library.native_library_linker_libraries(context);
+    let mut libraries = directories
+        .iter()
+        .filter_map(|directory| std::fs::read_dir(directory).ok())
+        .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == "lib")
+                && path
+                    .file_stem()
+                    .is_some_and(|stem| stem.to_string_lossy().starts_with(library.name()))
+        })
+        .collect::<Vec<PathBuf>>();
+    // `freetype.lib` sorts before `freetyped.lib`
+    libraries.sort();
+    libraries.into_iter().next().ok_or_else(|| {
+        UserFacingError::new(format!("Could not find the library of {}", library.name()))
+            .reason(format!(
+                "There is no {}*.lib in {}",
+                library.name(),
+                directories
+                    .iter()
+                    .map(|directory| directory.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))
+            .into()
+    })
+}
+
+/// Write a module definition file listing every symbol exported by the DLL
+pub fn write_def_file(dll: &Path, directory: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = dll
+        .file_stem()
+        .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    let output = Command::new("dumpbin").arg("/EXPORTS").arg(dll).output()?;
+    if !output.status.success() {
+        return Err(UserFacingError::new(format!(
+            "Could not list the exports of {}",
+            dll.display()
+        ))
+        .into());
+    }
+
+    let exports = parse_dumpbin_exports(&String::from_utf8_lossy(&output.stdout));
+    let mut contents = format!("LIBRARY {}\nEXPORTS\n", name);
+    for export in exports {
+        contents.push_str(&format!("    {}\n", export));
+    }
+
+    let def_file = directory.join(format!("{}.def", name));
+    std::fs::write(&def_file, contents)?;
+    Ok(def_file)
+}
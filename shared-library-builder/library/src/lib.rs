@@ -1,11 +1,156 @@
+mod architecture;
+mod artifacts;
+mod batch;
+mod benchmark;
+mod binaries;
+mod brotli_library;
+mod bundled;
+mod build_manifest;
+mod build_output;
+mod build_tree;
 mod cairo_library;
+mod certificates;
+mod checksums;
+mod compilation_context;
+mod compile_commands;
+mod compiler_flags;
+mod config_log;
+mod configure_cache;
+mod container;
+mod cpu_tuning;
+mod cross;
+mod dependency;
+mod download_progress;
+mod environment_log;
+mod features;
+mod freetype_options;
+mod fetch;
+mod github;
+mod glibc_baseline;
+mod hash;
+mod http;
+mod import_library;
+mod local_git;
+mod long_paths;
+mod make_jobs;
+mod make_tool;
+mod msvc;
+mod naming;
+mod network;
+mod patch;
+mod pgo;
 mod pixman_library;
+mod pkg_config_leaks;
+mod prebuilt;
+mod proxy;
+mod relocatable;
+mod remote;
+mod requirements;
+mod rpath;
+mod security_patches;
+mod self_test;
+mod size_report;
+mod scheduler;
+mod signing;
+mod smoke_tests;
+mod source_archive;
+mod source_cache;
+mod source_lock;
+mod static_runtime;
+mod symbol_prefix;
+mod unified_diff;
+mod upload;
+mod version;
+mod version_resource;
+mod watch;
+mod windows_crt;
+mod x11;
 
-use crate::cairo_library::CairoLibrary;
-use shared_library_builder::{GitLocation, LibraryLocation};
+pub use crate::architecture::Architecture;
+pub use crate::brotli_library::BrotliLibrary;
+pub use crate::artifacts::BuiltArtifacts;
+pub use crate::batch::BuildBatch;
+pub use crate::benchmark::{Benchmark, BenchmarkReport, BenchmarkResult, BenchmarkedBuild};
+pub use crate::build_manifest::{
+    BuildManifest, DriftPolicy, ManifestFile, ManifestSource, Toolchain, BUILD_MANIFEST_FILE,
+};
+pub use crate::build_output::BuildOutput;
+pub use crate::build_tree::{BuildTree, CleanupPolicy};
+pub use crate::certificates::{CertificateAuthorities, CA_BUNDLE_FILE};
+pub use crate::cairo_library::{CairoLibrary, GitReference, CAIRO_GIT_REPOSITORY};
+pub use crate::checksums::{ChecksumManifest, CHECKSUMS_FILE};
+pub use crate::compilation_context::CompilationContext;
+pub use crate::compiler_flags::{CompilerFlags, FlagList};
+pub use crate::config_log::CONFIG_LOG;
+pub use crate::container::{ContainerBuild, ContainerEngine};
+pub use crate::environment_log::{EnvironmentLog, ENVIRONMENT_LOG};
+pub use crate::cpu_tuning::CpuTuning;
+pub use crate::cross::{CrossToolchain, MESON_CROSS_FILE, PKG_CONFIG_WRAPPER};
+pub use crate::dependency::{Dependency, LinkMode};
+pub use crate::download_progress::DownloadProgress;
+pub use crate::features::{CairoFeature, FeatureSummary, CAIRO_FEATURES_HEADER};
+pub use crate::github::GitHubRelease;
+pub use crate::glibc_baseline::GlibcBaseline;
+pub use crate::local_git::LocalGitCheckout;
+pub use crate::make_jobs::MakeJobs;
+pub use crate::make_tool::MakeTool;
+pub use crate::msvc::WindowsToolchain;
+pub use crate::naming::{ArtifactNameValues, ArtifactNaming};
+pub use crate::patch::{FilePatch, Occurrences, PatchReport};
+pub use crate::pgo::{PgoPhase, ProfileGuidedOptimization};
+pub use crate::pixman_library::PixmanLibrary;
+pub use crate::pkg_config_leaks::LeakPolicy;
+pub use crate::prebuilt::{PrebuiltRelease, VerificationFailurePolicy};
+pub use crate::proxy::Proxy;
+pub use crate::relocatable::RelocatableBundle;
+pub use crate::remote::{RemoteBuilder, REMOTE_CONFIGURATION};
+pub use crate::rpath::RpathPolicy;
+pub use crate::scheduler::{library_key, ParallelBuild};
+pub use crate::security_patches::{AppliedSecurityPatch, SecurityPatch, SECURITY_PATCHES_FILE};
+pub use crate::self_test::{SelfTest, SelfTestOutcome, SelfTestReport};
+pub use crate::signing::{MacSigning, WindowsCertificate, WindowsSigning};
+pub use crate::size_report::{BinarySize, NamedSize, SizeReport};
+pub use crate::smoke_tests::SmokeTest;
+pub use crate::source_archive::{ArchiveFormat, SourceArchive};
+pub use crate::source_cache::{CachedSources, SourceCache};
+pub use crate::source_lock::{LockedSource, SourceLock, SourceOrigin, SOURCE_LOCK_FILE};
+pub use crate::symbol_prefix::{SymbolPrefix, SYMBOL_PREFIX_HEADER};
+pub use crate::unified_diff::UnifiedDiff;
+pub use crate::upload::{UploadCredentials, UploadTarget};
+pub use crate::version::{BinaryVersion, BuildSystem, CairoVersion};
+pub use crate::watch::Watch;
+pub use crate::windows_crt::WindowsCrt;
+pub use crate::x11::{LinuxDistribution, X11Package};
+pub use shared_library_builder::{LibraryCompilationContext, LibraryOptions, LibraryTarget};
 
+/// cairo with its default dependencies, the binaries of a release tag are used when given.
+/// Everything else is configured fluently, for example `libcairo(None::<String>).be_static()`.
+/// The binary version is taken as the tag of the release, use [`try_libcairo`] to check it early
 pub fn libcairo(binary_version: Option<impl Into<String>>) -> CairoLibrary {
-    CairoLibrary::default().with_release_location(binary_version.map(|version| {
-        LibraryLocation::Git(GitLocation::github("feenkcom", "libcairo").tag(version))
-    }))
+    match binary_version {
+        Some(version) => CairoLibrary::gt_default().with_release(version),
+        None => CairoLibrary::gt_default(),
+    }
+}
+
+/// Like [`libcairo`], but without a binary version the binaries of the release built from
+/// this version of the crate are used, see [`CairoLibrary::try_with_crate_release`]
+pub fn libcairo_matching_crate(
+    binary_version: Option<impl Into<String>>,
+) -> Result<CairoLibrary, Box<dyn std::error::Error>> {
+    match binary_version {
+        Some(version) => Ok(libcairo(Some(version))),
+        None => CairoLibrary::gt_default().try_with_crate_release(),
+    }
+}
+
+/// Like [`libcairo`], but returns an error listing the available tags if there is no
+/// release with the binary version, such as `1.2.3` or `v1.2.3`
+pub fn try_libcairo(
+    binary_version: Option<impl Into<String>>,
+) -> Result<CairoLibrary, Box<dyn std::error::Error>> {
+    match binary_version {
+        Some(version) => CairoLibrary::gt_default().try_with_release(version.into()),
+        None => Ok(CairoLibrary::gt_default()),
+    }
 }
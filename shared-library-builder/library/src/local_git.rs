@@ -0,0 +1,93 @@
+use crate::cairo_library::GitReference;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// An existing local clone of cairo built at a branch, tag or commit. The sources are
+/// checked out as a linked `git worktree` that shares the objects of the clone, so nothing
+/// is downloaded or copied and the clone itself is never touched by the patches we apply
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalGitCheckout {
+    repository: PathBuf,
+    reference: GitReference,
+}
+
+impl LocalGitCheckout {
+    pub fn new(repository: impl Into<PathBuf>, reference: GitReference) -> Self {
+        Self {
+            repository: repository.into(),
+            reference,
+        }
+    }
+
+    pub fn repository(&self) -> &Path {
+        &self.repository
+    }
+
+    pub fn reference(&self) -> &GitReference {
+        &self.reference
+    }
+
+    fn revision(&self) -> &str {
+        match self.reference {
+            GitReference::Branch(ref branch) => branch,
+            GitReference::Tag(ref tag) => tag,
+            GitReference::Commit(ref commit) => commit,
+        }
+    }
+
+    /// Check out the reference into the source directory, discarding the patches and
+    /// generated files of the previous build so that every build starts from the commit
+    pub fn ensure_checkout(&self, source_directory: &Path) -> Result<(), Box<dyn Error>> {
+        if !source_directory.exists() {
+            if let Some(parent) = source_directory.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut command = Command::new("git");
+            command
+                .arg("-C")
+                .arg(&self.repository)
+                .arg("worktree")
+                .arg("add")
+                .arg("--force")
+                .arg("--detach")
+                .arg(source_directory)
+                .arg(self.revision());
+            return self.run(command);
+        }
+
+        let mut command = Command::new("git");
+        command
+            .arg("-C")
+            .arg(source_directory)
+            .arg("checkout")
+            .arg("--force")
+            .arg("--detach")
+            .arg(self.revision());
+        self.run(command)?;
+
+        let mut command = Command::new("git");
+        command
+            .arg("-C")
+            .arg(source_directory)
+            .arg("clean")
+            .arg("-fdxq");
+        self.run(command)
+    }
+
+    fn run(&self, mut command: Command) -> Result<(), Box<dyn Error>> {
+        println!("{:?}", &command);
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!(
+                "Could not check out {} of {}",
+                self.revision(),
+                self.repository.display()
+            ))
+            .help("Make sure the repository is a git clone of cairo that contains the reference")
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::{Component, Path, PathBuf};
+
+/// The longest path the MSVC tools and GNU make handle on Windows
+pub const MAX_PATH: usize = 260;
+
+/// The extended-length form (`\\?\C:\...` or `\\?\UNC\server\share\...`) of an absolute path,
+/// which lifts the `MAX_PATH` limit of the Windows file APIs. Extended-length paths are not
+/// normalized by Windows, so separators are converted here and paths with `..` are kept as is.
+/// Other platforms are unchanged
+pub fn extended_length(path: &Path) -> PathBuf {
+    if !cfg!(windows) || !path.is_absolute() {
+        return path.to_path_buf();
+    }
+    if path
+        .components()
+        .any(|component| matches!(component, Component::ParentDir))
+    {
+        return path.to_path_buf();
+    }
+
+    let path = path.display().to_string().replace('/', "\\");
+    if path.starts_with("\\\\?\\") {
+        return PathBuf::from(path);
+    }
+    match path.strip_prefix("\\\\") {
+        Some(share) => PathBuf::from(format!("\\\\?\\UNC\\{}", share)),
+        None => PathBuf::from(format!("\\\\?\\{}", path)),
+    }
+}
+
+/// The paths that are too long for the native toolchain
+pub fn too_long_paths(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    paths
+        .into_iter()
+        .filter(|path| path.as_os_str().len() >= MAX_PATH)
+        .collect()
+}
@@ -0,0 +1,70 @@
+use std::cell::Cell;
+use std::process::Command;
+
+thread_local! {
+    /// The jobs of the builds on this thread when other builds run next to it
+    static SHARE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// How many jobs the nested `make` invocations run. Inside a cargo build script make joins
+/// cargo's jobserver through `CARGO_MAKEFLAGS`, so that all builds together stay within
+/// `cargo build -j`. Otherwise make runs `NUM_JOBS` or as many jobs as the machine has cores,
+/// divided between the builds [`crate::ParallelBuild`] runs at the same time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MakeJobs {
+    jobs: usize,
+    jobserver: Option<String>,
+}
+
+impl MakeJobs {
+    pub fn from_environment() -> Self {
+        let jobs = std::env::var("NUM_JOBS")
+            .ok()
+            .and_then(|jobs| jobs.trim().parse::<usize>().ok())
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|jobs| jobs.get())
+                    .unwrap_or(1)
+            });
+        let jobs = SHARE.with(|share| share.get()).unwrap_or(jobs);
+        let jobserver = std::env::var("CARGO_MAKEFLAGS")
+            .ok()
+            .filter(|flags| !flags.trim().is_empty());
+        Self {
+            jobs: jobs.max(1),
+            jobserver,
+        }
+    }
+
+    /// A fixed number of jobs, for builds that can not reach the pipes or fifo of the
+    /// jobserver such as the ones running in a container
+    pub fn without_jobserver(mut self) -> Self {
+        self.jobserver = None;
+        self
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Run the builds on this thread with a share of the jobs, when `builds` of them run at
+    /// the same time. The jobserver is shared by all builds already
+    pub(crate) fn shared<T>(builds: usize, build: impl FnOnce() -> T) -> T {
+        let share = (Self::from_environment().jobs / builds.max(1)).max(1);
+        SHARE.with(|jobs| jobs.set(Some(share)));
+        let result = build();
+        SHARE.with(|jobs| jobs.set(None));
+        result
+    }
+
+    pub fn apply(&self, command: &mut Command) {
+        match self.jobserver {
+            Some(ref flags) => {
+                command.env("MAKEFLAGS", flags);
+            }
+            None => {
+                command.arg(format!("-j{}", self.jobs));
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::requirements::Tool;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// The make executable that runs the makefiles of the autotools and `Makefile.win32` builds.
+/// They need GNU make, which is installed as `gmake` on the BSDs and as `mingw32-make`
+/// by MinGW on Windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MakeTool {
+    /// `gmake` on the BSDs, `mingw32-make` on Windows when there is no `make`, `make` otherwise
+    #[default]
+    Auto,
+    Make,
+    Gmake,
+    /// BSD make, it can only run the makefiles generated by automake
+    Bmake,
+    Mingw32Make,
+}
+
+impl MakeTool {
+    /// The executable, an automatic choice is resolved for the current host
+    pub fn tool(&self) -> Tool {
+        match self {
+            Self::Auto => match std::env::consts::OS {
+                "freebsd" | "openbsd" | "netbsd" | "dragonfly" => Tool::GMAKE,
+                "windows"
+                    if which::which(Tool::MAKE.name).is_err()
+                        && which::which(Tool::MINGW32_MAKE.name).is_ok() =>
+                {
+                    Tool::MINGW32_MAKE
+                }
+                _ => Tool::MAKE,
+            },
+            Self::Make => Tool::MAKE,
+            Self::Gmake => Tool::GMAKE,
+            Self::Bmake => Tool::BMAKE,
+            Self::Mingw32Make => Tool::MINGW32_MAKE,
+        }
+    }
+
+    pub fn command(&self) -> Command {
+        Command::new(self.tool().name)
+    }
+
+    pub fn is_gnu_make(&self) -> bool {
+        self.tool() != Tool::BMAKE
+    }
+}
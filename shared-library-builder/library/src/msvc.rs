@@ -0,0 +1,543 @@
+use crate::compiler_flags::{quote_windows, CompilerFlags};
+use crate::make_jobs::MakeJobs;
+use crate::requirements::Tool;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// Variables of the Windows makefiles shipped with the autotools-era cairo and pixman sources,
+/// evaluated just far enough to read their source lists and feature switches without GNU make.
+/// Supports `=`, `:=`, `+=` and `?=` assignments, line continuations, `$(VARIABLE)` references,
+/// `include` and `ifeq`/`ifneq` conditionals. Make functions expand to nothing
+#[derive(Debug, Clone, Default)]
+pub struct MakefileVariables {
+    variables: BTreeMap<String, String>,
+}
+
+impl MakefileVariables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// The expanded value of a variable, empty if it is not defined
+    pub fn get(&self, name: &str) -> String {
+        self.expand(self.variables.get(name).map_or("", |value| value.as_str()), 0)
+    }
+
+    /// The expanded value of a variable split into whitespace separated words
+    pub fn words(&self, name: &str) -> Vec<String> {
+        self.get(name)
+            .split_whitespace()
+            .map(|word| word.to_string())
+            .collect()
+    }
+
+    /// Names of all variables whose expanded value is `value`
+    pub fn names_with_value(&self, value: &str) -> Vec<String> {
+        self.variables
+            .keys()
+            .filter(|name| self.get(name) == value)
+            .cloned()
+            .collect()
+    }
+
+    /// Read and evaluate a makefile, relative includes are resolved against its folder
+    pub fn include(&mut self, makefile: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = std::fs::read_to_string(makefile).map_err(|error| {
+            UserFacingError::new(format!("Could not read {}", makefile.display()))
+                .reason(error.to_string())
+        })?;
+        let directory = makefile.parent().unwrap_or_else(|| Path::new("."));
+        self.evaluate(&contents, directory)
+    }
+
+    fn evaluate(&mut self, contents: &str, directory: &Path) -> Result<(), Box<dyn Error>> {
+        // one entry per nested conditional, a line is active when all of them hold
+        let mut conditions: Vec<bool> = vec![];
+
+        for line in contents.replace("\\\r\n", " ").replace("\\\n", " ").lines() {
+            // recipes are not evaluated
+            if line.starts_with('\t') {
+                continue;
+            }
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(condition) = line.strip_prefix("ifeq ") {
+                conditions.push(self.is_equal(condition));
+                continue;
+            }
+            if let Some(condition) = line.strip_prefix("ifneq ") {
+                conditions.push(!self.is_equal(condition));
+                continue;
+            }
+            if line == "else" {
+                if let Some(condition) = conditions.last_mut() {
+                    *condition = !*condition;
+                }
+                continue;
+            }
+            if line == "endif" {
+                conditions.pop();
+                continue;
+            }
+            if !conditions.iter().all(|condition| *condition) {
+                continue;
+            }
+
+            if let Some(path) = line.strip_prefix("include ") {
+                let path = PathBuf::from(self.expand(path.trim(), 0));
+                let path = if path.is_relative() {
+                    directory.join(path)
+                } else {
+                    path
+                };
+                self.include(&path)?;
+                continue;
+            }
+
+            self.assign(line);
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, line: &str) {
+        let equals = match line.find('=') {
+            Some(equals) => equals,
+            None => return,
+        };
+        let (name, operator) = match line[..equals].chars().last() {
+            Some(modifier @ (':' | '+' | '?')) => (&line[..equals - 1], Some(modifier)),
+            _ => (&line[..equals], None),
+        };
+        let name = name.trim();
+        // rules with target specific variables are not assignments
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ':') {
+            return;
+        }
+        let value = line[equals + 1..].trim();
+
+        match operator {
+            Some(':') => {
+                let value = self.expand(value, 0);
+                self.set(name, value);
+            }
+            Some('+') => {
+                let value = match self.variables.get(name) {
+                    Some(previous) if !previous.is_empty() => format!("{} {}", previous, value),
+                    _ => value.to_string(),
+                };
+                self.set(name, value);
+            }
+            Some('?') => {
+                if !self.variables.contains_key(name) {
+                    self.set(name, value);
+                }
+            }
+            _ => {
+                self.set(name, value);
+            }
+        }
+    }
+
+    /// Evaluate the `($(A),B)` argument of `ifeq` and `ifneq`
+    fn is_equal(&self, condition: &str) -> bool {
+        let condition = condition
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')');
+        match condition.split_once(',') {
+            Some((left, right)) => {
+                self.expand(left, 0).trim() == self.expand(right, 0).trim()
+            }
+            None => false,
+        }
+    }
+
+    fn expand(&self, value: &str, depth: usize) -> String {
+        // recursively defined variables referencing themselves would never terminate
+        if depth > 32 {
+            return String::new();
+        }
+
+        let mut expanded = String::new();
+        let mut rest = value;
+        while let Some(dollar) = rest.find('$') {
+            expanded.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                expanded.push('$');
+                rest = after;
+                continue;
+            }
+            if !rest.starts_with('(') {
+                continue;
+            }
+
+            let mut nesting = 0;
+            let mut end = None;
+            for (index, character) in rest.char_indices() {
+                match character {
+                    '(' => nesting += 1,
+                    ')' => {
+                        nesting -= 1;
+                        if nesting == 0 {
+                            end = Some(index);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let end = match end {
+                Some(end) => end,
+                None => break,
+            };
+
+            let name = &rest[1..end];
+            if !name.contains(char::is_whitespace) {
+                if let Some(value) = self.variables.get(name) {
+                    expanded.push_str(&self.expand(value, depth + 1));
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+/// The `cairo-features.h` the Windows makefiles generate from the `CAIRO_HAS_*` switches set to 1
+pub fn cairo_features_header(variables: &MakefileVariables) -> String {
+    let mut header = String::from(
+        "/* Generated by the libcairo builder. Do not edit. */\n#ifndef CAIRO_FEATURES_H\n#define CAIRO_FEATURES_H 1\n\n",
+    );
+    for feature in variables
+        .names_with_value("1")
+        .iter()
+        .filter(|name| name.starts_with("CAIRO_HAS_"))
+    {
+        header.push_str(&format!("#define {} 1\n", feature));
+    }
+    header.push_str("\n#endif\n");
+    header
+}
+
+/// The compiler, linker and archiver of the Windows builds. Both take the command line
+/// syntax of `cl.exe`, so the makefiles and flags work with either of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowsToolchain {
+    /// `cl.exe`, `link.exe` and `lib.exe` of Visual Studio
+    #[default]
+    Msvc,
+    /// `clang-cl`, `lld-link` and `llvm-lib` of LLVM
+    ClangCl,
+}
+
+impl WindowsToolchain {
+    pub fn compiler(&self) -> Tool {
+        match self {
+            Self::Msvc => Tool::CL,
+            Self::ClangCl => Tool::CLANG_CL,
+        }
+    }
+
+    pub fn linker(&self) -> Tool {
+        match self {
+            Self::Msvc => Tool::LINK,
+            Self::ClangCl => Tool::LLD_LINK,
+        }
+    }
+
+    pub fn archiver(&self) -> Tool {
+        match self {
+            Self::Msvc => Tool::LIB,
+            Self::ClangCl => Tool::LLVM_LIB,
+        }
+    }
+
+    /// The variables that make the Windows makefiles use this toolchain
+    pub fn make_variables(&self) -> Vec<String> {
+        match self {
+            Self::Msvc => vec![],
+            Self::ClangCl => vec![
+                format!("CC={}", self.compiler().name),
+                format!("LD={}", self.linker().name),
+                format!("AR={}", self.archiver().name),
+            ],
+        }
+    }
+
+    /// Translate flags written for `cl.exe` and `link.exe`. clang-cl does link time
+    /// optimization with `-flto` instead of `/GL`, and lld-link needs no `/LTCG` for it
+    pub fn translate(&self, flags: CompilerFlags) -> CompilerFlags {
+        if *self == Self::Msvc {
+            return flags;
+        }
+        let is_flag = |flag: &str, name: &str| {
+            flag.strip_prefix('-')
+                .or_else(|| flag.strip_prefix('/'))
+                .is_some_and(|flag| flag.eq_ignore_ascii_case(name))
+        };
+
+        let mut translated = CompilerFlags::new();
+        for flag in flags.cflags() {
+            translated = if is_flag(flag, "GL") {
+                translated.cflag("-flto=thin")
+            } else {
+                translated.cflag(flag.as_str())
+            };
+        }
+        for flag in flags.ldflags() {
+            let is_ltcg = flag
+                .strip_prefix('-')
+                .or_else(|| flag.strip_prefix('/'))
+                .is_some_and(|flag| flag.to_ascii_uppercase().starts_with("LTCG"));
+            if !is_ltcg {
+                translated = translated.ldflag(flag.as_str());
+            }
+        }
+        translated
+    }
+}
+
+/// Run one of the MSVC tools with its arguments in a response file,
+/// cairo has more sources than fit on a Windows command line.
+/// The file is written as UTF-16 with a byte order mark, otherwise the tools
+/// read it in the ANSI code page and mangle paths with non-ASCII characters
+fn run_tool(tool: &str, arguments: &[String], response_file: &Path) -> Result<(), Box<dyn Error>> {
+    let contents = arguments
+        .iter()
+        .map(|argument| quote_windows(argument))
+        .collect::<Vec<String>>()
+        .join("\r\n");
+    let bytes = std::iter::once(0xFEFF)
+        .chain(contents.encode_utf16())
+        .flat_map(|unit: u16| unit.to_le_bytes())
+        .collect::<Vec<u8>>();
+    std::fs::write(response_file, bytes)?;
+
+    let mut command = Command::new(tool);
+    command
+        .arg("-nologo")
+        .arg(format!("@{}", response_file.display()));
+
+    println!("{:?}", &command);
+
+    if !command.status()?.success() {
+        return Err(UserFacingError::new(format!("{} failed", tool))
+            .reason(format!("The arguments are in {}", response_file.display()))
+            .into());
+    }
+    Ok(())
+}
+
+/// Compile the sources with the compiler of the toolchain into the object directory, returning the object files
+pub fn compile(
+    toolchain: WindowsToolchain,
+    sources: &[PathBuf],
+    flags: &[String],
+    object_directory: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    std::fs::create_dir_all(object_directory)?;
+
+    // cl compiles the sources in parallel itself, clang-cl ignores the flag
+    let mut arguments = vec![
+        "-c".to_string(),
+        format!("-MP{}", MakeJobs::from_environment().jobs()),
+    ];
+    arguments.extend(flags.iter().cloned());
+    // a trailing separator makes cl name every object after its source
+    arguments.push(format!("-Fo{}\\", object_directory.display()));
+    arguments.extend(sources.iter().map(|source| source.display().to_string()));
+
+    run_tool(
+        toolchain.compiler().name,
+        &arguments,
+        &object_directory.join("cl.rsp"),
+    )?;
+
+    Ok(sources
+        .iter()
+        .filter_map(|source| source.file_stem())
+        .map(|stem| object_directory.join(format!("{}.obj", stem.to_string_lossy())))
+        .collect())
+}
+
+/// Link the objects into a DLL with the linker of the toolchain, the import library is written next to it
+pub fn link_dll(
+    toolchain: WindowsToolchain,
+    objects: &[PathBuf],
+    libraries: &[String],
+    flags: &[String],
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut arguments = vec![
+        "-DLL".to_string(),
+        format!("-OUT:{}", output.display()),
+        format!("-IMPLIB:{}", output.with_extension("lib").display()),
+    ];
+    arguments.extend(flags.iter().cloned());
+    arguments.extend(objects.iter().map(|object| object.display().to_string()));
+    arguments.extend(libraries.iter().cloned());
+
+    run_tool(
+        toolchain.linker().name,
+        &arguments,
+        &output.with_extension("link.rsp"),
+    )
+}
+
+/// Archive the objects into a static library with the archiver of the toolchain
+pub fn archive(
+    toolchain: WindowsToolchain,
+    objects: &[PathBuf],
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut arguments = vec![format!("-OUT:{}", output.display())];
+    arguments.extend(objects.iter().map(|object| object.display().to_string()));
+
+    run_tool(
+        toolchain.archiver().name,
+        &arguments,
+        &output.with_extension("lib.rsp"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(contents: &str) -> MakefileVariables {
+        let mut variables = MakefileVariables::new();
+        variables.evaluate(contents, Path::new(".")).unwrap();
+        variables
+    }
+
+    #[test]
+    fn assigns_variables() {
+        let variables = evaluate(
+            "A = one
+B := $(A) two
+A = three
+C = $(A)
+C += four
+D ?= five
+D ?= six
+",
+        );
+        assert_eq!(variables.get("B"), "one two");
+        assert_eq!(variables.get("C"), "three four");
+        assert_eq!(variables.get("D"), "five");
+        assert_eq!(variables.get("E"), "");
+    }
+
+    #[test]
+    fn expands_recursively_defined_variables_when_read() {
+        let variables = evaluate("A = $(B) $$x\nB = late\nLOOP = $(LOOP)\n");
+        assert_eq!(variables.get("A"), "late $x");
+        assert_eq!(variables.get("LOOP"), "");
+    }
+
+    #[test]
+    fn joins_continued_lines_and_skips_comments_and_recipes() {
+        let variables = evaluate(
+            "sources = \\\r\n\tcairo.c \\\n\tcairo-array.c # the array\n\
+             target: $(sources)\n\tRECIPE = ignored\n\
+             rule: TARGET_SPECIFIC = ignored\n",
+        );
+        assert_eq!(variables.words("sources"), ["cairo.c", "cairo-array.c"]);
+        assert_eq!(variables.get("RECIPE"), "");
+        assert_eq!(variables.get("TARGET_SPECIFIC"), "");
+        assert_eq!(variables.get("rule"), "");
+    }
+
+    #[test]
+    fn evaluates_nested_conditionals() {
+        let variables = evaluate(
+            "CFG = release
+ifeq ($(CFG),release)
+OPT = -O2
+ifneq ($(CFG), release)
+INNER = wrong
+else
+INNER = right
+endif
+else
+OPT = -Od
+endif
+",
+        );
+        assert_eq!(variables.get("OPT"), "-O2");
+        assert_eq!(variables.get("INNER"), "right");
+    }
+
+    #[test]
+    fn expands_make_functions_to_nothing() {
+        let variables = evaluate("A = x$(shell echo y)z\n");
+        assert_eq!(variables.get("A"), "xz");
+    }
+
+    #[test]
+    fn includes_makefiles_relative_to_the_including_one() {
+        let directory = std::env::temp_dir()
+            .join(format!(
+                "libcairo-makefile-variables-{}",
+                std::process::id()
+            ))
+            .join("build");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("Makefile.sources"),
+            "sources = $(top)/cairo.c\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("Makefile.win32"),
+            "top = src\ninclude $(name).sources\n",
+        )
+        .unwrap();
+
+        let mut variables = MakefileVariables::new();
+        variables.set("name", "Makefile");
+        variables
+            .include(&directory.join("Makefile.win32"))
+            .unwrap();
+        assert_eq!(variables.get("sources"), "src/cairo.c");
+        assert!(variables.include(&directory.join("missing")).is_err());
+    }
+
+    #[test]
+    fn generates_the_features_header() {
+        let variables = evaluate("CAIRO_HAS_PNG_FUNCTIONS = 1\nCAIRO_HAS_FT_FONT = 0\nOTHER = 1\n");
+        let header = cairo_features_header(&variables);
+        assert!(header.contains("#define CAIRO_HAS_PNG_FUNCTIONS 1\n"));
+        assert!(!header.contains("CAIRO_HAS_FT_FONT"));
+        assert!(!header.contains("OTHER"));
+    }
+
+    #[test]
+    fn translates_link_time_optimization_for_clang_cl() {
+        let flags = CompilerFlags::new()
+            .cflag("/GL")
+            .cflag("-O2")
+            .ldflag("/LTCG:incremental")
+            .ldflag("-DEBUG");
+        assert_eq!(WindowsToolchain::Msvc.translate(flags.clone()), flags);
+
+        let translated = WindowsToolchain::ClangCl.translate(flags);
+        assert_eq!(translated.cflags(), ["-flto=thin", "-O2"]);
+        assert_eq!(translated.ldflags(), ["-DEBUG"]);
+    }
+}
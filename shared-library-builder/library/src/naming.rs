@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use user_error::UserFacingError;
+
+/// The values that can be referenced from a naming template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactNameValues {
+    pub name: String,
+    pub version: String,
+    pub target: String,
+    pub profile: String,
+    pub hash: String,
+}
+
+impl ArtifactNameValues {
+    fn as_map(&self) -> HashMap<&'static str, &str> {
+        let mut values = HashMap::new();
+        values.insert("name", self.name.as_str());
+        values.insert("version", self.version.as_str());
+        values.insert("target", self.target.as_str());
+        values.insert("profile", self.profile.as_str());
+        values.insert("hash", self.hash.as_str());
+        values
+    }
+}
+
+/// Templates for the names of packaged archives and produced libraries.
+/// Templates may reference `{name}`, `{version}`, `{target}`, `{profile}` and `{hash}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactNaming {
+    archive: String,
+    library: Option<String>,
+}
+
+impl Default for ArtifactNaming {
+    fn default() -> Self {
+        Self::gtoolkit()
+    }
+}
+
+impl ArtifactNaming {
+    /// The names used by the GToolkit releases, for example `cairo-x86_64-apple-darwin.zip`
+    pub fn gtoolkit() -> Self {
+        Self::new("{name}-{target}.zip")
+    }
+
+    /// Names including the version and configuration, for package repositories
+    pub fn versioned() -> Self {
+        Self::new("{name}-{version}-{target}-{profile}-{hash}.tar.gz")
+    }
+
+    pub fn new(archive: impl Into<String>) -> Self {
+        Self {
+            archive: archive.into(),
+            library: None,
+        }
+    }
+
+    /// Rename the produced library, the template describes the file name without
+    /// the platform specific prefix and extension
+    pub fn with_library(mut self, library: impl Into<String>) -> Self {
+        self.library = Some(library.into());
+        self
+    }
+
+    pub fn archive_name(&self, values: &ArtifactNameValues) -> Result<String, Box<dyn Error>> {
+        render_template(&self.archive, values)
+    }
+
+    /// The target of an archive named by this template, `None` if the name does not match.
+    /// All other placeholders must have the given values
+    pub fn target_of_archive(&self, archive_name: &str, values: &ArtifactNameValues) -> Option<String> {
+        const MARKER: &str = "\u{0}";
+        let mut values = values.clone();
+        values.target = MARKER.to_string();
+        let rendered = self.archive_name(&values).ok()?;
+        let (prefix, suffix) = rendered.split_once(MARKER)?;
+
+        let target = archive_name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+        if target.is_empty() {
+            return None;
+        }
+        Some(target.to_string())
+    }
+
+    /// The renamed library stem, `None` when the native name is kept
+    pub fn library_name(&self, values: &ArtifactNameValues) -> Result<Option<String>, Box<dyn Error>> {
+        self.library
+            .as_ref()
+            .map(|template| render_template(template, values))
+            .transpose()
+    }
+}
+
+/// Replace `{placeholder}` occurrences, failing on placeholders we don't know
+pub fn render_template(template: &str, values: &ArtifactNameValues) -> Result<String, Box<dyn Error>> {
+    let values = values.as_map();
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            UserFacingError::new(format!("Unterminated placeholder in {}", template))
+        })? + start;
+
+        let placeholder = &rest[start + 1..end];
+        let value = values.get(placeholder).ok_or_else(|| {
+            UserFacingError::new(format!("Unknown placeholder {{{}}} in {}", placeholder, template))
+                .help("Supported placeholders are {name}, {version}, {target}, {profile} and {hash}")
+        })?;
+        rendered.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> ArtifactNameValues {
+        ArtifactNameValues {
+            name: "cairo".to_string(),
+            version: "1.17.4".to_string(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            profile: "release".to_string(),
+            hash: "0123abcd".to_string(),
+        }
+    }
+
+    #[test]
+    fn renders_the_gtoolkit_names() {
+        assert_eq!(
+            ArtifactNaming::gtoolkit().archive_name(&values()).unwrap(),
+            "cairo-x86_64-unknown-linux-gnu.zip"
+        );
+    }
+
+    #[test]
+    fn renders_the_versioned_names() {
+        assert_eq!(
+            ArtifactNaming::versioned().archive_name(&values()).unwrap(),
+            "cairo-1.17.4-x86_64-unknown-linux-gnu-release-0123abcd.tar.gz"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_unterminated_placeholders() {
+        assert!(render_template("{name}-{arch}.zip", &values()).is_err());
+        assert!(render_template("{name-{target}.zip", &values()).is_err());
+        assert!(render_template("{name}-{target", &values()).is_err());
+    }
+
+    #[test]
+    fn keeps_the_library_name_unless_renamed() {
+        assert_eq!(
+            ArtifactNaming::gtoolkit().library_name(&values()).unwrap(),
+            None
+        );
+        assert_eq!(
+            ArtifactNaming::gtoolkit()
+                .with_library("{name}-{version}")
+                .library_name(&values())
+                .unwrap(),
+            Some("cairo-1.17.4".to_string())
+        );
+    }
+
+    #[test]
+    fn finds_the_target_of_an_archive() {
+        let naming = ArtifactNaming::versioned();
+        assert_eq!(
+            naming.target_of_archive(
+                "cairo-1.17.4-aarch64-apple-darwin-release-0123abcd.tar.gz",
+                &values()
+            ),
+            Some("aarch64-apple-darwin".to_string())
+        );
+        assert_eq!(
+            naming.target_of_archive(
+                "cairo-1.18.0-aarch64-apple-darwin-release-0123abcd.tar.gz",
+                &values()
+            ),
+            None
+        );
+        assert_eq!(
+            naming.target_of_archive("cairo-1.17.4--release-0123abcd.tar.gz", &values()),
+            None
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use crate::certificates::apply_bundle;
+use crate::download_progress::DownloadProgress;
+use crate::proxy::Proxy;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The proxy, the certificate bundle and the download progress configured on
+/// [`crate::CairoLibrary`]. They are passed explicitly to every curl command we run
+/// instead of being exported to the environment of the process, so that several
+/// configurations can download at the same time. The default uses the environment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct NetworkSettings {
+    pub proxy: Option<Proxy>,
+    /// Written by [`crate::CertificateAuthorities::write_bundle`]
+    pub ca_bundle: Option<PathBuf>,
+    pub download_progress: Option<DownloadProgress>,
+}
+
+impl NetworkSettings {
+    /// The configured proxy, the one of the environment otherwise
+    pub fn proxy(&self) -> Proxy {
+        self.proxy.clone().unwrap_or_else(Proxy::from_environment)
+    }
+
+    pub fn download_progress(&self) -> DownloadProgress {
+        self.download_progress
+            .unwrap_or_else(DownloadProgress::from_environment)
+    }
+
+    /// Set the proxy, the certificates and the progress in the environment of the command
+    pub fn apply(&self, command: &mut Command) {
+        if let Some(ref proxy) = self.proxy {
+            proxy.apply(command);
+        }
+        if let Some(ref bundle) = self.ca_bundle {
+            apply_bundle(bundle, command);
+        }
+        if let Some(download_progress) = self.download_progress {
+            download_progress.apply_to_environment(command);
+        }
+    }
+}
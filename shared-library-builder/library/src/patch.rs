@@ -0,0 +1,447 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use crate::compiler_flags::quote_windows;
+use crate::long_paths::extended_length;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// How many times the text replaced by a patch must occur in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occurrences {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Occurrences {
+    fn matches(&self, count: usize) -> bool {
+        match self {
+            Self::Exactly(expected) => count == *expected,
+            Self::AtLeast(expected) => count >= *expected,
+        }
+    }
+}
+
+impl Display for Occurrences {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exactly(1) => write!(f, "once"),
+            Self::Exactly(count) => write!(f, "{} times", count),
+            Self::AtLeast(count) => write!(f, "at least {} times", count),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Replacement {
+    from: String,
+    to: String,
+    occurrences: Occurrences,
+    /// Only replace `from` where it stands between whitespace, like a flag on a command line
+    whole_words: bool,
+}
+
+impl Replacement {
+    /// The byte offsets `from` occurs at
+    fn positions(&self, contents: &str) -> Vec<usize> {
+        contents
+            .match_indices(self.from.as_str())
+            .map(|(position, _)| position)
+            .filter(|position| {
+                if !self.whole_words {
+                    return true;
+                }
+                let before = contents[..*position].chars().next_back();
+                let after = contents[position + self.from.len()..].chars().next();
+                before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+            })
+            .collect()
+    }
+
+    fn apply(&self, contents: &str) -> String {
+        let mut replaced = String::with_capacity(contents.len());
+        let mut end = 0;
+        for position in self.positions(contents) {
+            replaced.push_str(&contents[end..position]);
+            replaced.push_str(&self.to);
+            end = position + self.from.len();
+        }
+        replaced.push_str(&contents[end..]);
+        replaced
+    }
+}
+
+/// Textual replacements in one source file. Every replacement states how often its text
+/// must occur, so that a new cairo or pixman release that no longer matches fails while
+/// patching instead of somewhere in the middle of make.
+/// The original file is kept next to it as `.bak` and every application starts from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    path: PathBuf,
+    replacements: Vec<Replacement>,
+}
+
+impl FilePatch {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            replacements: vec![],
+        }
+    }
+
+    /// Replace text that must occur exactly once
+    pub fn replace(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.replace_times(from, to, Occurrences::Exactly(1))
+    }
+
+    /// Replace every occurrence of text that must occur at least once
+    pub fn replace_all(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.replace_times(from, to, Occurrences::AtLeast(1))
+    }
+
+    pub fn replace_times(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        occurrences: Occurrences,
+    ) -> Self {
+        self.replacements.push(Replacement {
+            from: from.into(),
+            to: to.into(),
+            occurrences,
+            whole_words: false,
+        });
+        self
+    }
+
+    /// Replace a flag wherever it stands on its own between whitespace, so that replacing
+    /// `-MD` leaves `-MDd` alone
+    pub fn replace_flag(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        occurrences: Occurrences,
+    ) -> Self {
+        self.replacements.push(Replacement {
+            from: from.into(),
+            to: to.into(),
+            occurrences,
+            whole_words: true,
+        });
+        self
+    }
+
+    /// Quote the folders the recipes of the Windows makefiles create for their objects,
+    /// the backticked `dirname $<` splits a prerequisite in a build root with spaces
+    pub(crate) fn quote_recipe_directories(self) -> Self {
+        self.replace_all("`dirname $<`", "\"$(shell coreutils dirname \"$<\")\"")
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Apply the replacements to the pristine file, reporting every replacement
+    /// that did not match as expected at once. The file is left untouched on failure
+    pub fn apply(&self) -> Result<PatchReport, Box<dyn Error>> {
+        let path = extended_length(&self.path);
+        let backup_file = sibling(&path, ".bak")?;
+        let fixed_file = sibling(&path, ".fixed")?;
+
+        let original = if backup_file.exists() {
+            std::fs::read_to_string(&backup_file)?
+        } else {
+            std::fs::read_to_string(&path).map_err(|error| {
+                UserFacingError::new(format!("Could not read {}", self.path.display()))
+                    .reason(error.to_string())
+            })?
+        };
+
+        let mut contents = original.clone();
+        let mut error = UserFacingError::new(format!("Could not patch {}", self.path.display()));
+        let mut mismatches = 0;
+        for replacement in &self.replacements {
+            let count = replacement.positions(&contents).len();
+            if !replacement.occurrences.matches(count) {
+                error = error.reason(format!(
+                    "Expected `{}` to occur {}, found it {} times",
+                    replacement.from, replacement.occurrences, count
+                ));
+                mismatches += 1;
+                continue;
+            }
+            contents = replacement.apply(&contents);
+        }
+
+        if mismatches > 0 {
+            return Err(error
+                .help("The sources probably changed with a new release, update the patch to match them")
+                .into());
+        }
+
+        if !backup_file.exists() {
+            std::fs::copy(&path, &backup_file)?;
+        }
+        std::fs::write(&path, &contents)?;
+        std::fs::copy(&path, &fixed_file)?;
+
+        Ok(PatchReport::new(&self.path, &original, &contents))
+    }
+}
+
+/// Add the lines after the `project()` of a CMake project we do not configure ourselves,
+/// such as zlib and libpng. The lines apply to every target defined after them
+pub(crate) fn cmake_project_patch(
+    sources: &Path,
+    lines: &[String],
+) -> Result<FilePatch, Box<dyn Error>> {
+    let cmake_lists = sources.join("CMakeLists.txt");
+    let backup = sibling(&cmake_lists, ".bak")?;
+    let original = std::fs::read_to_string(if backup.exists() {
+        &backup
+    } else {
+        &cmake_lists
+    })?;
+    let project = original
+        .lines()
+        .find(|line| line.trim_start().to_lowercase().starts_with("project("))
+        .ok_or_else(|| {
+            UserFacingError::new(format!("Could not patch {}", cmake_lists.display()))
+                .reason("The CMake project has no project()")
+        })?;
+    Ok(FilePatch::new(&cmake_lists).replace(
+        project,
+        format!("{}\n{}", project, lines.join("\n")),
+    ))
+}
+
+/// A path quoted for a command line in a patched makefile. Quotes keep spaces together
+/// for the MSVC tools, `$` and `#` are escaped from make
+pub(crate) fn makefile_quoted(path: &Path) -> String {
+    format!("\"{}\"", escape_make(&path.display().to_string()))
+}
+
+/// A flag for a command line in a patched makefile, quoted for the MSVC tools when it
+/// contains spaces, such as the `/FI` of a header in a build root with spaces
+pub(crate) fn makefile_flag(flag: &str) -> String {
+    escape_make(&quote_windows(flag))
+}
+
+fn escape_make(text: &str) -> String {
+    text.replace('$', "$$").replace('#', "\\#")
+}
+
+/// A file next to the patched one, named after it with an extra extension
+pub(crate) fn sibling(path: &Path, extension: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let mut file_name: OsString = path
+        .file_name()
+        .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+        .to_os_string();
+    file_name.push(extension);
+    Ok(path.with_file_name(file_name))
+}
+
+/// The lines a patch removed and added, numbered as in the original and the patched file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchReport {
+    path: PathBuf,
+    changes: Vec<String>,
+    /// The hunks skipped since the sources contain them already, by diff name and number
+    skipped_hunks: Vec<(String, usize)>,
+}
+
+impl PatchReport {
+    pub(crate) fn new(path: impl Into<PathBuf>, before: &str, after: &str) -> Self {
+        Self {
+            path: path.into(),
+            changes: line_diff(before, after),
+            skipped_hunks: vec![],
+        }
+    }
+
+    pub(crate) fn with_skipped_hunks(mut self, skipped_hunks: Vec<(String, usize)>) -> Self {
+        self.skipped_hunks = skipped_hunks;
+        self
+    }
+
+    /// The hunks of the diff with the given name that were already part of the file
+    pub fn skipped_hunks(&self, diff: &str) -> Vec<usize> {
+        self.skipped_hunks
+            .iter()
+            .filter(|(name, _)| name == diff)
+            .map(|(_, hunk)| *hunk)
+            .collect()
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    pub fn changes(&self) -> &[String] {
+        self.changes.as_slice()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Display for PatchReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Patched {}", self.path.display())?;
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        for (diff, hunk) in &self.skipped_hunks {
+            writeln!(f, "Skipped hunk #{} of {}, the sources contain it already", hunk, diff)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line diff without context. Patches touch a few lines of a file, so the common
+/// beginning and end are skipped and only the rest is compared line by line
+fn line_diff(before: &str, after: &str) -> Vec<String> {
+    let old = before.lines().collect::<Vec<&str>>();
+    let new = after.lines().collect::<Vec<&str>>();
+
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+
+    // lengths of the longest common subsequences of the remaining suffixes
+    let mut common = vec![vec![0usize; new_changed.len() + 1]; old_changed.len() + 1];
+    for i in (0..old_changed.len()).rev() {
+        for j in (0..new_changed.len()).rev() {
+            common[i][j] = if old_changed[i] == new_changed[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old_changed.len() || j < new_changed.len() {
+        if i < old_changed.len() && j < new_changed.len() && old_changed[i] == new_changed[j] {
+            i += 1;
+            j += 1;
+        } else if j == new_changed.len()
+            || (i < old_changed.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            changes.push(format!("{:>6} -{}", prefix + i + 1, old_changed[i]));
+            i += 1;
+        } else {
+            changes.push(format!("{:>6} +{}", prefix + j + 1, new_changed[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty folder of its own for every test, in a build root with spaces
+    fn build_root(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir()
+            .join(format!("libcairo patch {}-{}", test, std::process::id()))
+            .join("build root");
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    const MAKEFILE: &str = "DEFAULT_CFLAGS += -I. -I$(top_srcdir)
+$(CFG)/%.obj: %.c
+\t@mkdir -p $(CFG)/`dirname $<`
+\t@$(CC) $(CFLAGS) -MD -c -Fo\"$@\" $<
+";
+
+    #[test]
+    fn quotes_paths_with_spaces() {
+        let directory = build_root("quotes");
+        assert_eq!(
+            makefile_quoted(&directory.join("include")),
+            format!("\"{}\"", directory.join("include").display())
+        );
+        assert_eq!(
+            makefile_quoted(Path::new("C:/build root/$cairo#1")),
+            "\"C:/build root/$$cairo\\#1\""
+        );
+        assert_eq!(makefile_flag("-MT"), "-MT");
+        assert_eq!(
+            makefile_flag("/FIC:\\build root\\cairo-symbol-prefix.h"),
+            "\"/FIC:\\build root\\cairo-symbol-prefix.h\""
+        );
+    }
+
+    #[test]
+    fn patches_a_makefile_in_a_build_root_with_spaces() {
+        let directory = build_root("makefile");
+        let makefile = directory.join("Makefile.win32.common");
+        std::fs::write(&makefile, MAKEFILE).unwrap();
+
+        let include = directory.join("pixman").join("include");
+        let patch = FilePatch::new(&makefile)
+            .replace(
+                "DEFAULT_CFLAGS += -I. -I$(top_srcdir)",
+                format!(
+                    "DEFAULT_CFLAGS += -I. -I$(top_srcdir)\nDEFAULT_CFLAGS += -I{}",
+                    makefile_quoted(&include)
+                ),
+            )
+            .replace_flag("-MD", "-MT", Occurrences::AtLeast(1))
+            .quote_recipe_directories();
+        patch.apply().unwrap();
+        // every application starts from the pristine makefile
+        let report = patch.apply().unwrap();
+
+        let patched = std::fs::read_to_string(&makefile).unwrap();
+        assert_eq!(
+            patched,
+            format!(
+                "DEFAULT_CFLAGS += -I. -I$(top_srcdir)
+DEFAULT_CFLAGS += -I\"{}\"
+$(CFG)/%.obj: %.c
+\t@mkdir -p $(CFG)/\"$(shell coreutils dirname \"$<\")\"
+\t@$(CC) $(CFLAGS) -MT -c -Fo\"$@\" $<
+",
+                include.display()
+            )
+        );
+        assert!(!report.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(sibling(&makefile, ".bak").unwrap()).unwrap(),
+            MAKEFILE
+        );
+    }
+
+    #[test]
+    fn reports_text_that_does_not_occur() {
+        let directory = build_root("mismatch");
+        let makefile = directory.join("Makefile.win32");
+        std::fs::write(&makefile, "all:\n").unwrap();
+
+        let error = FilePatch::new(&makefile)
+            .quote_recipe_directories()
+            .apply()
+            .unwrap_err();
+        assert!(error.to_string().contains("Could not patch"));
+        assert_eq!(std::fs::read_to_string(&makefile).unwrap(), "all:\n");
+    }
+}
@@ -0,0 +1,183 @@
+use crate::compiler_flags::CompilerFlags;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The two builds of a profile guided optimization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PgoPhase {
+    /// Compile with instrumentation that records execution profiles
+    Instrument,
+    /// Compile again using the recorded profiles
+    Optimize,
+}
+
+/// Configures a two-phase profile guided optimization build of cairo and pixman.
+/// The workload is any program that renders representative content with the
+/// instrumented cairo, for example `cairo-perf-trace` with a set of recorded traces
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileGuidedOptimization {
+    program: String,
+    arguments: Vec<String>,
+    profile_directory: Option<PathBuf>,
+}
+
+impl ProfileGuidedOptimization {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            arguments: vec![],
+            profile_directory: None,
+        }
+    }
+
+    pub fn arg(mut self, argument: impl Into<String>) -> Self {
+        self.arguments.push(argument.into());
+        self
+    }
+
+    pub fn with_profile_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.profile_directory = Some(directory.into());
+        self
+    }
+
+    /// Where the instrumented binaries write their profiles, by default `pgo` in the build root
+    pub fn profile_directory(&self, context: &LibraryCompilationContext) -> PathBuf {
+        self.profile_directory
+            .clone()
+            .unwrap_or_else(|| context.build_root().join("pgo"))
+    }
+
+    /// Clang writes raw profiles that must be merged with `llvm-profdata`,
+    /// while GCC reads its `.gcda` files directly
+    fn is_clang() -> bool {
+        let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+        Command::new(compiler)
+            .arg("--version")
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).contains("clang"))
+            .unwrap_or(false)
+    }
+
+    fn merged_profile(&self, context: &LibraryCompilationContext) -> PathBuf {
+        self.profile_directory(context).join("merged.profdata")
+    }
+
+    pub fn flags(&self, phase: PgoPhase, context: &LibraryCompilationContext) -> CompilerFlags {
+        let directory = self.profile_directory(context);
+        match phase {
+            PgoPhase::Instrument => {
+                CompilerFlags::new().flag(format!("-fprofile-generate={}", directory.display()))
+            }
+            PgoPhase::Optimize => {
+                if Self::is_clang() {
+                    CompilerFlags::new().flag(format!(
+                        "-fprofile-use={}",
+                        self.merged_profile(context).display()
+                    ))
+                } else {
+                    CompilerFlags::new()
+                        .flag(format!("-fprofile-use={}", directory.display()))
+                        .cflag("-fprofile-correction")
+                }
+            }
+        }
+    }
+
+    /// Run the workload against the instrumented libraries found in `library_directories`
+    pub fn run_workload(
+        &self,
+        library_directories: &[PathBuf],
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let library_path_variable = if context.is_mac() {
+            "DYLD_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+
+        let mut library_path = library_directories.to_vec();
+        if let Ok(ref path) = std::env::var(library_path_variable) {
+            std::env::split_paths(path).for_each(|path| library_path.push(path));
+        }
+
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.arguments)
+            .env(library_path_variable, std::env::join_paths(&library_path)?)
+            .env(
+                "LLVM_PROFILE_FILE",
+                self.profile_directory(context).join("cairo-%p.profraw"),
+            );
+
+        println!("{:?}", &command);
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new("PGO workload failed")
+                .reason(format!("{} exited with an error", &self.program))
+                .into());
+        }
+        Ok(())
+    }
+
+    /// Merge the raw clang profiles into a single `.profdata` file. GCC reads its `.gcda`
+    /// files as they are, they only have to exist
+    pub fn merge_profiles(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let directory = self.profile_directory(context);
+        if !Self::is_clang() {
+            if profiles_in(&directory, "gcda")?.is_empty() {
+                return Err(UserFacingError::new("PGO workload produced no profiles")
+                    .reason(format!("No .gcda files in {}", directory.display()))
+                    .help("Make sure the workload loads the instrumented cairo")
+                    .into());
+            }
+            return Ok(());
+        }
+
+        let raw_profiles = profiles_in(&directory, "profraw")?;
+        if raw_profiles.is_empty() {
+            return Err(UserFacingError::new("PGO workload produced no profiles")
+                .reason(format!("No .profraw files in {}", directory.display()))
+                .into());
+        }
+
+        let mut command = Command::new(Self::llvm_profdata());
+        command
+            .arg("merge")
+            .arg(format!("-output={}", self.merged_profile(context).display()))
+            .args(&raw_profiles);
+
+        println!("{:?}", &command);
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new("Could not merge PGO profiles").into());
+        }
+        Ok(())
+    }
+
+    fn llvm_profdata() -> PathBuf {
+        std::env::var("LLVM_PROFDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| Path::new("llvm-profdata").to_path_buf())
+    }
+}
+
+/// The profiles with the extension in the folder and below, GCC mirrors the object folders
+fn profiles_in(directory: &Path, extension: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut profiles = vec![];
+    if !directory.is_dir() {
+        return Ok(profiles);
+    }
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            profiles.extend(profiles_in(&path, extension)?);
+        } else if path.extension().is_some_and(|found| found == extension) {
+            profiles.push(path);
+        }
+    }
+    Ok(profiles)
+}
@@ -1,10 +1,30 @@
+use crate::binaries::shared_objects_in;
+use crate::build_manifest::compiler_version;
+use crate::build_output::BuildOutput;
+use crate::build_tree::{BuildTree, CleanupPolicy};
+use crate::compile_commands::{intercept_with_bear, COMPILE_COMMANDS};
+use crate::compiler_flags::{CompilerFlags, FlagList};
+use crate::config_log::configure_failure;
+use crate::configure_cache::configure_cache_argument;
+use crate::container::ContainerBuild;
+use crate::cpu_tuning::CpuTuning;
+use crate::cross::{cross_toolchain, retarget, CrossToolchain};
+use crate::environment_log::EnvironmentLog;
+use crate::glibc_baseline::GlibcBaseline;
+use crate::make_jobs::MakeJobs;
+use crate::make_tool::MakeTool;
+use crate::msvc::{self, MakefileVariables, WindowsToolchain};
+use crate::patch::{makefile_flag, makefile_quoted, FilePatch};
+use crate::windows_crt::WindowsCrt;
+use crate::requirements::{Requirements, Tool};
+use crate::source_cache::copy_tree;
+use crate::static_runtime::{verify_static_runtime, STATIC_RUNTIME_FLAGS};
 use shared_library_builder::{
     Library, LibraryCompilationContext, LibraryDependencies, LibraryLocation, LibraryOptions,
     TarArchive, TarUrlLocation,
 };
 use std::error::Error;
-use std::fs::{read_to_string, OpenOptions};
-use std::io::Write;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use user_error::UserFacingError;
@@ -13,7 +33,26 @@ use serde::{Serialize, Deserialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PixmanLibrary {
     location: LibraryLocation,
+    release_location: Option<LibraryLocation>,
     options: LibraryOptions,
+    flags: CompilerFlags,
+    pgo_flags: CompilerFlags,
+    cpu_tuning: CpuTuning,
+    windows_crt: WindowsCrt,
+    debug_symbols: bool,
+    compile_commands: bool,
+    configure_cache: bool,
+    build_output: Option<BuildOutput>,
+    make_tool: MakeTool,
+    build_tree: BuildTree,
+    static_runtime: bool,
+    glibc_baseline: Option<GlibcBaseline>,
+    tests: bool,
+    container: Option<ContainerBuild>,
+    msvc: bool,
+    windows_toolchain: WindowsToolchain,
+    cross_toolchain: Option<CrossToolchain>,
+    target: Option<String>,
 }
 
 impl Default for PixmanLibrary {
@@ -30,20 +69,219 @@ impl PixmanLibrary {
                     .archive(TarArchive::Gz)
                     .sources(Path::new("pixman-0.40.0")),
             ),
+            release_location: None,
             options: Default::default(),
+            flags: Default::default(),
+            pgo_flags: Default::default(),
+            cpu_tuning: Default::default(),
+            windows_crt: Default::default(),
+            debug_symbols: false,
+            compile_commands: false,
+            configure_cache: false,
+            build_output: None,
+            make_tool: MakeTool::default(),
+            build_tree: BuildTree::default(),
+            static_runtime: false,
+            glibc_baseline: None,
+            tests: false,
+            container: None,
+            msvc: false,
+            windows_toolchain: WindowsToolchain::default(),
+            cross_toolchain: None,
+            target: None,
         }
     }
 
+    /// Download prebuilt binaries from the release location instead of compiling pixman
+    pub fn with_release_location(mut self, release_location: Option<LibraryLocation>) -> Self {
+        self.release_location = release_location;
+        self
+    }
+
+    /// Adjust the options of pixman, for example `.with_options(|options| options.be_static())`
+    pub fn with_options(mut self, configure: impl FnOnce(&mut LibraryOptions)) -> Self {
+        configure(&mut self.options);
+        self
+    }
+
+    /// Build pixman as a static library
+    pub fn be_static(self) -> Self {
+        self.with_options(|options| options.be_static())
+    }
+
+    /// Build pixman as a shared library, the default
+    pub fn be_shared(self) -> Self {
+        self.with_options(|options| options.be_shared())
+    }
+
+    /// Extra compiler and linker flags used when building pixman
+    pub fn with_flags(mut self, flags: CompilerFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn flags(&self) -> &CompilerFlags {
+        &self.flags
+    }
+
+    /// The flags of the current phase of a profile guided optimization of cairo
+    pub(crate) fn with_pgo_flags(mut self, pgo_flags: CompilerFlags) -> Self {
+        self.pgo_flags = pgo_flags;
+        self
+    }
+
+    pub fn with_cpu_tuning(mut self, cpu_tuning: CpuTuning) -> Self {
+        self.cpu_tuning = cpu_tuning;
+        self
+    }
+
+    pub fn with_windows_crt(mut self, windows_crt: WindowsCrt) -> Self {
+        self.windows_crt = windows_crt;
+        self
+    }
+
+    /// Compile pixman with debug information. On Windows it is embedded into
+    /// the static library (`-Z7`) and ends up in the pdb of the library linking pixman
+    pub fn with_debug_symbols(mut self, debug_symbols: bool) -> Self {
+        self.debug_symbols = debug_symbols;
+        self
+    }
+
+    /// Record a `compile_commands.json` into the install prefix with `bear`
+    pub fn with_compile_commands(mut self, compile_commands: bool) -> Self {
+        self.compile_commands = compile_commands;
+        self
+    }
+
+    /// Reuse the results of configure checks between builds, see `configure_cache_argument`
+    pub fn with_configure_cache(mut self, configure_cache: bool) -> Self {
+        self.configure_cache = configure_cache;
+        self
+    }
+
+    /// Print either the short silent rules or every command line of the build
+    pub fn with_build_output(mut self, build_output: Option<BuildOutput>) -> Self {
+        self.build_output = build_output;
+        self
+    }
+
+    /// The make that runs the makefiles of the autotools and Windows builds
+    pub fn with_make_tool(mut self, make_tool: MakeTool) -> Self {
+        self.make_tool = make_tool;
+        self
+    }
+
+    /// Where the autotools build keeps its intermediate files and whether they are removed
+    pub fn with_build_tree(mut self, build_tree: BuildTree) -> Self {
+        self.build_tree = build_tree;
+        self
+    }
+
+    /// Link the GCC runtime statically into the Linux shared object
+    pub fn with_static_runtime(mut self, static_runtime: bool) -> Self {
+        self.static_runtime = static_runtime;
+        self
+    }
+
+    /// Compile against the sysroot of the glibc baseline, cairo verifies the binaries
+    pub fn with_glibc_baseline(mut self, glibc_baseline: Option<GlibcBaseline>) -> Self {
+        self.glibc_baseline = glibc_baseline;
+        self
+    }
+
+    /// Also build pixman's test programs and run them with `make check` after installing,
+    /// for verification builds. By default the demos and tests are skipped
+    pub fn with_tests(mut self, tests: bool) -> Self {
+        self.tests = tests;
+        self
+    }
+
+    /// The compiler, linker and archiver of the Windows build
+    pub fn with_windows_toolchain(mut self, windows_toolchain: WindowsToolchain) -> Self {
+        self.windows_toolchain = windows_toolchain;
+        self
+    }
+
+    /// Build for the given Rust target triple, for example `aarch64-unknown-linux-gnu`, whatever
+    /// the compilation context reports. Selects the cross toolchain, the prefix and the artifact names
+    pub fn with_target(mut self, target: Option<String>) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Compile for another machine with the given toolchain instead of the default one of the target
+    pub fn with_cross_toolchain(mut self, cross_toolchain: Option<CrossToolchain>) -> Self {
+        self.cross_toolchain = cross_toolchain;
+        self
+    }
+
+    /// Run the Linux build inside a container image
+    pub fn with_container(mut self, container: Option<ContainerBuild>) -> Self {
+        self.container = container;
+        self
+    }
+
+    /// On Windows compile pixman by driving `cl.exe` and `lib.exe` directly instead of GNU make
+    pub fn with_msvc(mut self, msvc: bool) -> Self {
+        self.msvc = msvc;
+        self
+    }
+
+    fn in_container(&self, command: Command, options: &LibraryCompilationContext) -> Command {
+        match self.container {
+            Some(ref container) if options.is_linux() => container.wrap(&command, options),
+            _ => command,
+        }
+    }
+
+    /// The version of the C compiler the build runs, the one of the cross toolchain
+    /// or of the Windows toolchain, inside the container if there is one
+    fn compiler_version(&self, context: &LibraryCompilationContext) -> String {
+        let compiler = if context.is_windows() {
+            Some(self.windows_toolchain.compiler().name.to_string())
+        } else {
+            self.cross_toolchain(context)
+                .map(|cross| cross.c_compiler().join(" "))
+        };
+        compiler_version(compiler, |command| self.in_container(command, context))
+    }
+
+    /// All extra flags pixman is compiled with, including the CPU tuning
+    fn compiler_flags(&self, options: &LibraryCompilationContext) -> CompilerFlags {
+        let mut flags = self.flags.clone().extend(&self.cpu_tuning.flags(options));
+        if self.debug_symbols {
+            flags = if options.is_windows() {
+                flags.cflag("-Z7")
+            } else {
+                flags.cflag("-g")
+            };
+        }
+        if self.static_runtime && options.is_linux() {
+            for flag in STATIC_RUNTIME_FLAGS {
+                flags = flags.ldflag(flag);
+            }
+        }
+        if let Some(ref glibc_baseline) = self.glibc_baseline {
+            if options.is_linux() {
+                flags = flags.extend(&glibc_baseline.flags());
+            }
+        }
+        flags = flags.extend(&self.pgo_flags);
+        if options.is_windows() {
+            flags = self.windows_toolchain.translate(flags);
+        }
+        flags
+    }
+
     fn patch_makefile(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
-        let makefile = self.source_directory(options).join("Makefile.in");
-
-        let contents = read_to_string(&makefile)?;
-        let new = contents.replace("SUBDIRS = pixman demos test", "SUBDIRS = pixman");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&makefile)?;
-        file.write_all(new.as_bytes())?;
+        let subdirectories = if self.tests {
+            "SUBDIRS = pixman test"
+        } else {
+            "SUBDIRS = pixman"
+        };
+        let patch = FilePatch::new(self.source_directory(options).join("Makefile.in"))
+            .replace("SUBDIRS = pixman demos test", subdirectories);
+        println!("{}", patch.apply()?);
         Ok(())
     }
 
@@ -51,66 +289,149 @@ impl PixmanLibrary {
         &self,
         options: &LibraryCompilationContext,
     ) -> Result<(), Box<dyn Error>> {
-        if self
-            .source_directory(options)
-            .join("Makefile.win32.common.fixed")
-            .exists()
-        {
-            return Ok(());
-        }
-
-        let makefile = self.source_directory(options).join("Makefile.win32.common");
-        std::fs::copy(
-            &makefile,
-            self.source_directory(options)
-                .join("Makefile.win32.common.bak"),
-        )?;
-
-        let mut contents = read_to_string(&makefile)?;
-        contents = contents.replace("-MD", "-MT");
-
         let include_flags_to_replace =
             "BASE_CFLAGS = -nologo -I. -I$(top_srcdir) -I$(top_srcdir)/pixman";
         let new_include_flags = self
             .msvc_include_directories()
             .into_iter()
-            .map(|path| format!("BASE_CFLAGS += -I\"{}\"", path.display()))
+            .map(|path| format!("BASE_CFLAGS += -I{}", makefile_quoted(&path)))
+            .chain(
+                self.compiler_flags(options)
+                    .cflags()
+                    .iter()
+                    .map(|flag| format!("BASE_CFLAGS += {}", makefile_flag(flag))),
+            )
             .collect::<Vec<String>>()
             .join("\n");
 
-        contents = contents.replace(
+        let patch = FilePatch::new(self.source_directory(options).join("Makefile.win32.common"));
+        let patch = self.windows_crt.patch_makefile(patch).replace(
             include_flags_to_replace,
-            &format!("{}\n{}", include_flags_to_replace, new_include_flags),
+            format!("{}\n{}", include_flags_to_replace, new_include_flags),
         );
+        println!("{}", patch.apply()?);
+        Ok(())
+    }
 
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&makefile)?;
-        file.write_all(contents.as_bytes())?;
+    /// Check that all tools and folders needed to compile pixman are present,
+    /// reporting every missing one at once
+    pub fn check_requirements(
+        &self,
+        options: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let options = &self.target_context(options);
+        let mut requirements = Requirements::new(self.name());
+        if options.is_windows() && self.msvc {
+            requirements
+                .tool(self.windows_toolchain.compiler())
+                .tool(self.windows_toolchain.archiver());
+        } else {
+            requirements.tool(self.make_tool.tool());
+            if options.is_windows() && !self.make_tool.is_gnu_make() {
+                requirements.unsupported("The Windows makefiles of pixman need GNU make");
+            }
+        }
 
-        std::fs::copy(
-            &makefile,
-            self.source_directory(options)
-                .join("Makefile.win32.common.fixed"),
-        )?;
+        if self.tests && (options.is_windows() || self.cross_toolchain(options).is_some()) {
+            requirements.unsupported("The tests of pixman only run in native autotools builds");
+        }
 
-        Ok(())
+        if options.is_unix() {
+            requirements.tool(Tool::AUTORECONF).tool(Tool::ACLOCAL);
+
+            if self.compile_commands {
+                requirements.tool(Tool::BEAR);
+            }
+
+            if options.is_linux() && self.static_runtime {
+                requirements.tool(Tool::PATCHELF);
+            }
+
+            if self.build_tree.cleanup() != CleanupPolicy::Keep && self.build_tree.location().is_none() {
+                requirements.unsupported(
+                    "pixman is configured in the install prefix, set a location with BuildTree::in_directory to remove the build tree",
+                );
+            }
+        }
+
+        if let Some(ref container) = self.container {
+            if options.is_linux() {
+                requirements.tool(container.engine().tool());
+                let outside = self
+                    .build_tree
+                    .location()
+                    .map(|location| !location.starts_with(options.build_root()))
+                    .unwrap_or(false);
+                if outside {
+                    requirements.unsupported("A container only sees build trees inside the build root");
+                }
+            }
+        }
+
+        if options.target().is_windows() {
+            if !self.msvc {
+                requirements.tool(Tool::COREUTILS);
+                if self.windows_toolchain != WindowsToolchain::Msvc {
+                    requirements
+                        .tool(self.windows_toolchain.compiler())
+                        .tool(self.windows_toolchain.archiver());
+                }
+            }
+
+            for path in self.msvc_lib_directories() {
+                requirements.directory(path, "Lib");
+            }
+            for path in self.msvc_include_directories() {
+                requirements.directory(path, "Include");
+            }
+        }
+
+        requirements.check()
+    }
+
+    /// The context with the target set by `with_target`
+    fn target_context(&self, context: &LibraryCompilationContext) -> LibraryCompilationContext {
+        retarget(context, self.target.as_deref())
+    }
+
+    /// The cross toolchain of builds for another machine, `None` for native builds
+    fn cross_toolchain(&self, context: &LibraryCompilationContext) -> Option<CrossToolchain> {
+        let in_container = self.container.is_some() && context.is_linux();
+        cross_toolchain(self.cross_toolchain.as_ref(), in_container, context)
+    }
+
+    /// The folder the autotools build is configured and compiled in, see [`BuildTree`]
+    fn build_directory(&self, context: &LibraryCompilationContext) -> PathBuf {
+        self.build_tree
+            .directory(self.name(), self.native_library_prefix(context), context)
+    }
+
+    /// The jobs of `make`, the jobserver of cargo is not reachable from a container
+    fn make_jobs(&self, context: &LibraryCompilationContext) -> MakeJobs {
+        let jobs = MakeJobs::from_environment();
+        if self.container.is_some() && context.is_linux() {
+            jobs.without_jobserver()
+        } else {
+            jobs
+        }
+    }
+
+    /// The log of the environments the configure and make steps run with
+    fn environment_log(&self, context: &LibraryCompilationContext) -> EnvironmentLog {
+        EnvironmentLog::in_prefix(&self.native_library_prefix(context))
     }
 
     fn compile_unix(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
         self.patch_makefile(options)?;
 
         let out_dir = self.native_library_prefix(options);
-        if !out_dir.exists() {
-            std::fs::create_dir_all(&out_dir)
-                .unwrap_or_else(|_| panic!("Could not create {:?}", &out_dir));
-        }
-        let makefile_dir = out_dir.clone();
+        std::fs::create_dir_all(&out_dir)?;
+        let makefile_dir = self.build_directory(options);
+        std::fs::create_dir_all(&makefile_dir)?;
 
         let mut command = Command::new(self.source_directory(options).join("configure"));
         command
-            .current_dir(&out_dir)
+            .current_dir(&makefile_dir)
             .arg(format!(
                 "--prefix={}",
                 self.native_library_prefix(options).display()
@@ -120,62 +441,234 @@ impl PixmanLibrary {
                 self.native_library_prefix(options).display()
             ))
             .arg("--disable-gtk")
-            .arg(format!("--enable-shared={}", self.is_shared()));
+            .arg(format!("--enable-shared={}", self.is_shared()))
+            .args(
+                self.build_output
+                    .map(|build_output| build_output.configure_argument()),
+            );
 
         if self.is_static() {
-            let mut cpp_flags = std::env::var("CPPFLAGS").unwrap_or_else(|_| "".to_owned());
-            cpp_flags = format!(
-                "{} -fPIC",
-                cpp_flags,
-            );
+            let cpp_flags = FlagList::from_env("CPPFLAGS").flag("-fPIC");
+            command.env("CPPFLAGS", cpp_flags.to_unix_string());
+        }
+
+        let flags = self.compiler_flags(options);
+        let c_flags = flags.autoconf_cflags_env();
+        if let Some(ref c_flags) = c_flags {
+            command.env("CFLAGS", c_flags);
+        }
+        if !flags.ldflags().is_empty() {
+            command.env("LDFLAGS", flags.ldflags_env());
+        }
+
+        if let Some(cross) = self.cross_toolchain(options) {
+            command.arg(cross.host_argument()).envs(cross.environment());
+        }
 
-            command.env("CPPFLAGS", &cpp_flags);
+        if self.configure_cache {
+            let cache = configure_cache_argument(
+                self.name(),
+                &command,
+                &self.compiler_version(options),
+                &format!("{:?}", self.container),
+                options,
+            )?;
+            command.arg(cache);
         }
 
+        let mut command = self.in_container(command, options);
+
         println!("{:?}", &command);
+        self.environment_log(options).record(&command)?;
 
         let configure = command.status()?;
 
         if !configure.success() {
-            panic!("Could not configure {}", self.name());
+            return Err(configure_failure(self.name(), &makefile_dir).into());
         }
 
-        let make = Command::new("make")
+        let mut command = self.make_tool.command();
+        command
             .current_dir(&makefile_dir)
             .arg("install")
-            .status()?;
+            .args(
+                self.build_output
+                    .map(|build_output| build_output.make_variable()),
+            )
+            .env("LDFLAGS", flags.ldflags_env());
+        if let Some(ref c_flags) = c_flags {
+            command.env("CFLAGS", c_flags);
+        }
+        if flags.libtool_ldflags_env() != flags.ldflags_env() {
+            command.arg(format!("LDFLAGS={}", flags.libtool_ldflags_env()));
+        }
+        self.make_jobs(options).apply(&mut command);
+
+        if self.compile_commands {
+            command = intercept_with_bear(&command, &out_dir.join(COMPILE_COMMANDS));
+        }
+
+        let mut command = self.in_container(command, options);
+
+        println!("{:?}", &command);
+        self.environment_log(options).record(&command)?;
+
+        let make = command.status()?;
 
         if !make.success() {
-            panic!("Could not compile {}", self.name());
+            return Err(UserFacingError::new(format!("Could not compile {}", self.name()))
+                .reason(format!("make failed in {}", makefile_dir.display()))
+                .into());
+        }
+
+        if self.tests {
+            let mut command = self.make_tool.command();
+            command.current_dir(&makefile_dir).arg("check");
+            self.make_jobs(options).apply(&mut command);
+            let mut command = self.in_container(command, options);
+
+            println!("{:?}", &command);
+            self.environment_log(options).record(&command)?;
+
+            if !command.status()?.success() {
+                return Err(UserFacingError::new(format!("The tests of {} failed", self.name()))
+                    .help(format!(
+                        "See {} for the logs of the failed tests",
+                        makefile_dir.join("test").join("test-suite.log").display()
+                    ))
+                    .into());
+            }
         }
 
         Ok(())
     }
 
     fn compile_windows(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
-        self.patch_makefile(options)
-            .expect("Failed to patch a Makefile");
-
-        self.patch_windows_makefile(options)
-            .expect("Failed to patch a Windows specific Makefile");
+        self.patch_makefile(options)?;
+        self.patch_windows_makefile(options)?;
 
-        let makefile = self.source_directory(options).join("Makefile.win32");
+        let tree = self.copy_sources_to_prefix(options)?;
+        let makefile = tree.join("Makefile.win32");
 
-        let mut command = Command::new("make");
+        let mut command = self.make_tool.command();
+        self.windows_crt.apply(&mut command);
         command
-            .current_dir(self.source_directory(options))
+            .current_dir(&tree)
             .arg("pixman")
             .arg("-f")
             .arg(&makefile)
             .arg("CFG=release")
-            .arg("MMX=off");
+            .arg("MMX=off")
+            .args(self.windows_toolchain.make_variables());
 
         println!("{:?}", &command);
+        self.environment_log(options).record(&command)?;
 
-        let configure = command.status().unwrap();
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!("Could not compile {}", self.name()))
+                .reason(format!("{} failed", makefile.display()))
+                .into());
+        }
+        Ok(())
+    }
 
-        if !configure.success() {
-            panic!("Could not configure {}", self.name());
+    /// The Windows makefiles compile inside the sources, so every target builds in a fresh
+    /// copy of the patched sources in its own prefix. It has the layout cairo's makefiles expect
+    fn copy_sources_to_prefix(
+        &self,
+        options: &LibraryCompilationContext,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let prefix = self.native_library_prefix(options);
+        if prefix.exists() {
+            std::fs::remove_dir_all(&prefix)?;
+        }
+        if let Some(parent) = prefix.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        copy_tree(&self.source_directory(options), &prefix)?;
+        Ok(prefix)
+    }
+
+    /// Generate `pixman-version.h` from its template with the version declared in `configure.ac`,
+    /// a step the Windows makefile does with sed
+    fn write_version_header(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let source = self.source_directory(options);
+        let configure = read_to_string(source.join("configure.ac"))?;
+        let component = |name: &str| {
+            let definition = format!("m4_define([pixman_{}],", name);
+            configure
+                .lines()
+                .find_map(|line| line.trim().strip_prefix(&definition))
+                .map(|value| value.trim().trim_end_matches(')').trim().to_string())
+                .ok_or_else(|| {
+                    UserFacingError::new("Could not determine the version of pixman")
+                        .reason(format!("configure.ac does not define pixman_{}", name))
+                })
+        };
+
+        let template = read_to_string(source.join("pixman").join("pixman-version.h.in"))?;
+        let header = template
+            .replace("@PIXMAN_VERSION_MAJOR@", &component("major")?)
+            .replace("@PIXMAN_VERSION_MINOR@", &component("minor")?)
+            .replace("@PIXMAN_VERSION_MICRO@", &component("micro")?);
+        std::fs::write(source.join("pixman").join("pixman-version.h"), header)?;
+        Ok(())
+    }
+
+    /// Build the static `pixman-1.lib` with `cl.exe` and `lib.exe`, reading the list of
+    /// sources from `pixman/Makefile.sources` the same way the Windows makefile does
+    fn compile_msvc(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        self.write_version_header(options)?;
+
+        let source = self.source_directory(options);
+        let pixman = source.join("pixman");
+
+        let mut variables = MakefileVariables::new();
+        variables.include(&pixman.join("Makefile.sources"))?;
+        let mut sources = variables.words("libpixman_sources");
+
+        let mut flags = vec![
+            if options.is_debug() { "-Od" } else { "-O2" }.to_string(),
+            self.windows_crt.msvc_flag().to_string(),
+            "-DPACKAGE=pixman-1".to_string(),
+            format!("-I{}", source.display()),
+            format!("-I{}", pixman.display()),
+        ];
+        // the SIMD fast paths are selected at runtime, MMX stays off like in the makefile build
+        if options.target().to_string().starts_with("x86_64") {
+            sources.push("pixman-sse2.c".to_string());
+            sources.push("pixman-ssse3.c".to_string());
+            flags.push("-DUSE_SSE2".to_string());
+            flags.push("-DUSE_SSSE3".to_string());
+        }
+        flags.extend(
+            self.msvc_include_directories()
+                .iter()
+                .map(|path| format!("-I{}", path.display())),
+        );
+        flags.extend(self.compiler_flags(options).cflags().iter().cloned());
+
+        let sources = sources
+            .iter()
+            .map(|file| pixman.join(file))
+            .collect::<Vec<PathBuf>>();
+
+        // the same layout as the makefile build, in the prefix of the target
+        let installed = self.native_library_prefix(options).join("pixman");
+        let output_directory = installed.join(options.profile());
+        let objects = msvc::compile(
+            self.windows_toolchain,
+            &sources,
+            &flags,
+            &output_directory.join("objects"),
+        )?;
+        msvc::archive(
+            self.windows_toolchain,
+            &objects,
+            &output_directory.join("pixman-1.lib"),
+        )?;
+        for header in ["pixman.h", "pixman-version.h"] {
+            std::fs::copy(pixman.join(header), installed.join(header))?;
         }
         Ok(())
     }
@@ -187,6 +680,10 @@ impl Library for PixmanLibrary {
         &self.location
     }
 
+    fn release_location(&self) -> &LibraryLocation {
+        self.release_location.as_ref().unwrap_or(&self.location)
+    }
+
     fn name(&self) -> &str {
         "pixman"
     }
@@ -204,29 +701,42 @@ impl Library for PixmanLibrary {
     }
 
     fn force_compile(&self, options: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let options = &self.target_context(options);
+        self.environment_log(options).clear()?;
         if options.target().is_unix() {
-            self.compile_unix(options)
-                .expect("Failed to compile pixman")
+            let compiled = self.compile_unix(options);
+            self.build_tree
+                .clean_up(&self.build_directory(options), compiled.is_ok())?;
+            compiled?;
+            if self.static_runtime && options.is_linux() {
+                let shared_objects = shared_objects_in(&self.native_library_linker_libraries(options));
+                verify_static_runtime(&shared_objects)?;
+            }
         }
         if options.target().is_windows() {
-            self.compile_windows(options)
-                .expect("Failed to compile pixman")
+            if self.msvc {
+                self.compile_msvc(options)?;
+            } else {
+                self.compile_windows(options)?;
+            }
         }
 
         Ok(())
     }
 
-    fn compiled_library_directories(&self, _options: &LibraryCompilationContext) -> Vec<PathBuf> {
-        unimplemented!()
+    fn compiled_library_directories(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let options = &self.target_context(options);
+        self.native_library_linker_libraries(options)
     }
 
     fn compiled_library_binary(
         &self,
         options: &LibraryCompilationContext,
     ) -> Result<PathBuf, Box<dyn Error>> {
+        let options = &self.target_context(options);
         if options.target().is_windows() {
             return Ok(self
-                .source_directory(options)
+                .native_library_prefix(options)
                 .join("pixman")
                 .join(options.profile())
                 .join("pixman-1.lib"));
@@ -235,40 +745,26 @@ impl Library for PixmanLibrary {
     }
 
     fn ensure_requirements(&self, options: &LibraryCompilationContext) {
-        which::which("make").expect("Could not find `make`");
-
-        if options.is_unix() {
-            which::which("autoreconf").expect("Could not find `autoreconf`");
-            which::which("aclocal").expect("Could not find `aclocal`");
-        }
-
-        if options.target().is_windows() {
-            which::which("coreutils").expect("Could not find `coreutils`");
-
-            for path in self.msvc_lib_directories() {
-                if !path.exists() {
-                    panic!("Lib folder does not exist: {}", &path.display())
-                }
-            }
-            for path in self.msvc_include_directories() {
-                if !path.exists() {
-                    panic!("Include folder does not exist: {}", &path.display())
-                }
-            }
+        let options = &self.target_context(options);
+        if let Err(error) = self.check_requirements(options) {
+            panic!("{}", error);
         }
     }
 
     fn native_library_prefix(&self, options: &LibraryCompilationContext) -> PathBuf {
-        if options.target().is_unix() {
-            return options.build_root().join(self.name());
-        }
-        if options.target().is_windows() {
-            return self.source_directory(options);
+        let options = &self.target_context(options);
+        if options.target().is_unix() || options.target().is_windows() {
+            return options
+                .build_root()
+                .join(self.name())
+                .join(options.target().to_string())
+                .join(options.profile());
         }
         panic!("Unknown platform!")
     }
 
     fn native_library_include_headers(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let options = &self.target_context(options);
         let library_prefix = self.native_library_prefix(options);
         if options.target().is_unix() {
             return vec![library_prefix.join("include").join("pixman-1")];
@@ -280,6 +776,7 @@ impl Library for PixmanLibrary {
     }
 
     fn native_library_linker_libraries(&self, options: &LibraryCompilationContext) -> Vec<PathBuf> {
+        let options = &self.target_context(options);
         let library_prefix = self.native_library_prefix(options);
         if options.target().is_unix() {
             return vec![library_prefix.join("lib")];
@@ -291,6 +788,7 @@ impl Library for PixmanLibrary {
     }
 
     fn pkg_config_directory(&self, context: &LibraryCompilationContext) -> Option<PathBuf> {
+        let context = &self.target_context(context);
         let directory = self
             .native_library_prefix(context)
             .join("lib")
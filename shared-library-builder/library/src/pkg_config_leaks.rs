@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// What to do when pkg-config resolves a library we build to a `.pc` file outside of our
+/// prefixes, for example a pixman installed on the system found through the ambient
+/// `PKG_CONFIG_PATH`. cairo would then be configured against a mix of both
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeakPolicy {
+    /// Print a warning and continue
+    #[default]
+    Warn,
+    /// Stop with an error listing the leaked libraries
+    Fail,
+}
+
+impl LeakPolicy {
+    /// Resolve the packages with pkg-config the way the build does and compare the folders
+    /// of their `.pc` files with the ones we install into. Packages pkg-config does not
+    /// find are left to the build to report
+    pub fn check(
+        &self,
+        packages: &[&str],
+        pkg_config_paths: &[PathBuf],
+        expected_directories: &[PathBuf],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut leaks = vec![];
+        for package in packages {
+            if let Some(directory) = resolved_directory(package, pkg_config_paths) {
+                if !expected_directories
+                    .iter()
+                    .any(|expected| same_directory(expected, &directory))
+                {
+                    leaks.push(format!(
+                        "{} resolves to {}",
+                        package,
+                        directory.join(format!("{}.pc", package)).display()
+                    ));
+                }
+            }
+        }
+        if leaks.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            Self::Warn => {
+                for leak in leaks {
+                    println!("Warning: {}, not to the one we build", leak);
+                }
+                Ok(())
+            }
+            Self::Fail => {
+                let mut error = UserFacingError::new(
+                    "pkg-config finds libraries we build outside of our prefixes",
+                );
+                for leak in leaks {
+                    error = error.reason(leak);
+                }
+                Err(error
+                    .help("Remove the folders of system libraries from PKG_CONFIG_PATH or use LeakPolicy::Warn")
+                    .into())
+            }
+        }
+    }
+}
+
+/// The folder of the `.pc` file pkg-config picks for the package
+fn resolved_directory(package: &str, pkg_config_paths: &[PathBuf]) -> Option<PathBuf> {
+    let mut command = Command::new("pkg-config");
+    command.arg("--variable=pcfiledir").arg(package).env(
+        "PKG_CONFIG_PATH",
+        std::env::join_paths(pkg_config_paths).ok()?,
+    );
+
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let directory = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if directory.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(directory))
+    }
+}
+
+fn same_directory(expected: &Path, resolved: &Path) -> bool {
+    match (expected.canonicalize(), resolved.canonicalize()) {
+        (Ok(expected), Ok(resolved)) => expected == resolved,
+        _ => expected == resolved,
+    }
+}
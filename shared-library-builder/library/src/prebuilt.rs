@@ -0,0 +1,271 @@
+use crate::checksums::{ChecksumManifest, CHECKSUMS_FILE};
+use crate::http::HttpClient;
+use crate::naming::{ArtifactNameValues, ArtifactNaming};
+use crate::network::NetworkSettings;
+use crate::requirements::{Requirements, Tool};
+use crate::version::BinaryVersion;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryTarget;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// What to do when a downloaded prebuilt binary can not be verified
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerificationFailurePolicy {
+    /// Discard the download and compile from sources instead
+    BuildFromSource,
+    /// Stop with an error
+    #[default]
+    Fail,
+}
+
+/// A GitHub release that provides prebuilt binaries together with a checksum manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrebuiltRelease {
+    owner: String,
+    repository: String,
+    tag: String,
+    /// A minisign (ed25519) public key that release artifacts must be signed with
+    public_key: Option<String>,
+    /// Belongs to the build that downloads the release, not to its configuration
+    #[serde(skip)]
+    network: NetworkSettings,
+}
+
+impl PrebuiltRelease {
+    pub fn new(owner: impl Into<String>, repository: impl Into<String>, tag: impl Into<String>) -> Self {
+        Self {
+            owner: owner.into(),
+            repository: repository.into(),
+            tag: tag.into(),
+            public_key: None,
+            network: NetworkSettings::default(),
+        }
+    }
+
+    pub fn libcairo(tag: impl Into<String>) -> Self {
+        Self::new("feenkcom", "libcairo", tag)
+    }
+
+    /// Pin the minisign public key, for example `RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3`.
+    /// Downloaded artifacts are then only used if their `.minisig` signature is valid
+    pub fn with_public_key(mut self, public_key: impl Into<String>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    /// Download with the proxy, certificates and download progress of a build
+    pub(crate) fn with_network(mut self, network: NetworkSettings) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// The same repository at another release
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = tag.into();
+        self
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn api_client(&self) -> HttpClient {
+        let client = HttpClient::new()
+            .with_network(self.network.clone())
+            .header("Accept", "application/vnd.github+json");
+        match std::env::var("GITHUB_TOKEN") {
+            Ok(token) => client.header("Authorization", format!("token {}", token)),
+            Err(_) => client,
+        }
+    }
+
+    /// The tags of the most recent releases of the repository, newest first
+    pub fn release_tags(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let releases = self.api_client().get_json(&format!(
+            "https://api.github.com/repos/{}/{}/releases?per_page=100",
+            &self.owner, &self.repository
+        ))?;
+        let releases = releases.as_array().ok_or_else(|| {
+            UserFacingError::new(format!(
+                "Could not list the releases of {}/{}",
+                &self.owner, &self.repository
+            ))
+            .reason(releases["message"].as_str().unwrap_or_default().to_string())
+        })?;
+        Ok(releases
+            .iter()
+            .filter_map(|release| release["tag_name"].as_str())
+            .map(|tag| tag.to_string())
+            .collect())
+    }
+
+    /// The release among the given tags that our tag names, so that `1.2.3` finds a release
+    /// tagged `v1.2.3` and the other way around. Fails listing the tags if there is none
+    pub fn resolve_in(&self, tags: &[String]) -> Result<Self, Box<dyn Error>> {
+        if tags.iter().any(|tag| tag == &self.tag) {
+            return Ok(self.clone());
+        }
+        let version = self.tag.parse::<BinaryVersion>().ok();
+        let matching = tags.iter().find(|tag| {
+            version
+                .as_ref()
+                .map(|version| version.matches_tag(tag))
+                .unwrap_or(false)
+        });
+        match matching {
+            Some(tag) => Ok(self.clone().with_tag(tag.clone())),
+            None => {
+                let available = if tags.is_empty() {
+                    "There are no releases".to_string()
+                } else {
+                    format!("Available releases: {}", tags.join(", "))
+                };
+                Err(UserFacingError::new(format!(
+                    "Could not find release {} of {}/{}",
+                    &self.tag, &self.owner, &self.repository
+                ))
+                .reason(available)
+                .help("Pass one of the available tags as the binary version, or None to build from sources")
+                .into())
+            }
+        }
+    }
+
+    /// The names of all assets attached to the release. `GITHUB_TOKEN` is used when set
+    /// to avoid the rate limit of anonymous requests
+    pub fn asset_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let release = self.api_client().get_json(&format!(
+            "https://api.github.com/repos/{}/{}/releases/tags/{}",
+            &self.owner, &self.repository, &self.tag
+        ))?;
+        let assets = release["assets"].as_array().ok_or_else(|| {
+            UserFacingError::new(format!("Could not find release {}", &self.tag))
+                .reason(release["message"].as_str().unwrap_or_default().to_string())
+        })?;
+        Ok(assets
+            .iter()
+            .filter_map(|asset| asset["name"].as_str())
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    /// The target triples the release has an archive for, given how archives are named.
+    /// Lets an orchestrator decide per platform whether to download or build before starting
+    pub fn available_targets(
+        &self,
+        naming: &ArtifactNaming,
+        values: &ArtifactNameValues,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut targets = self
+            .asset_names()?
+            .iter()
+            .filter_map(|name| naming.target_of_archive(name, values))
+            .filter(|target| target.parse::<LibraryTarget>().is_ok())
+            .collect::<Vec<String>>();
+        targets.sort();
+        targets.dedup();
+        Ok(targets)
+    }
+
+    pub fn asset_url(&self, name: &str) -> String {
+        format!(
+            "https://github.com/{}/{}/releases/download/{}/{}",
+            &self.owner, &self.repository, &self.tag, name
+        )
+    }
+
+    pub fn checksums(&self, client: &HttpClient) -> Result<ChecksumManifest, Box<dyn Error>> {
+        let contents = client.get(&self.asset_url(CHECKSUMS_FILE))?;
+        Ok(ChecksumManifest::parse(&String::from_utf8_lossy(&contents)))
+    }
+
+    /// Download an asset into the directory and verify it against the published checksums
+    /// and, if a public key is pinned, its signature. The downloaded file is removed if it does not match
+    pub fn download_verified(&self, name: &str, directory: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let client = HttpClient::new().with_network(self.network.clone());
+        let checksums = self.checksums(&client)?;
+
+        std::fs::create_dir_all(directory)?;
+        let archive = directory.join(name);
+        client.download(&self.asset_url(name), &archive)?;
+
+        let verification = checksums
+            .verify(&archive)
+            .and_then(|_| self.verify_signature(&client, name, &archive));
+
+        if let Err(error) = verification {
+            std::fs::remove_file(&archive)?;
+            return Err(error);
+        }
+        Ok(archive)
+    }
+
+    fn verify_signature(
+        &self,
+        client: &HttpClient,
+        name: &str,
+        archive: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let public_key = match self.public_key {
+            Some(ref public_key) => public_key,
+            None => return Ok(()),
+        };
+
+        let mut requirements = Requirements::new("prebuilt cairo");
+        requirements.tool(Tool::MINISIGN);
+        requirements.check()?;
+
+        let signature_name = format!("{}.minisig", name);
+        let signature = archive.with_file_name(&signature_name);
+        client.download(&self.asset_url(&signature_name), &signature)?;
+
+        let mut command = Command::new(Tool::MINISIGN.name);
+        command
+            .arg("-V")
+            .arg("-q")
+            .arg("-P")
+            .arg(public_key)
+            .arg("-m")
+            .arg(archive)
+            .arg("-x")
+            .arg(&signature);
+
+        println!("{:?}", &command);
+
+        let status = command.status()?;
+        std::fs::remove_file(&signature)?;
+
+        if !status.success() {
+            return Err(UserFacingError::new(format!("Invalid signature of {}", name))
+                .reason(format!("{} is not signed with {}", name, public_key))
+                .into());
+        }
+        Ok(())
+    }
+}
+
+/// Extract a `.zip` or tar archive into the destination
+pub fn unpack(archive: &Path, destination: &Path) -> Result<(), Box<dyn Error>> {
+    let is_zip = archive.extension().is_some_and(|extension| extension == "zip");
+
+    let mut command = if is_zip && which::which("unzip").is_ok() {
+        let mut command = Command::new("unzip");
+        command.arg("-o").arg(archive).arg("-d").arg(destination);
+        command
+    } else {
+        // bsdtar, the default on macOS and Windows, also extracts zip archives
+        let mut command = Command::new("tar");
+        command.arg("-xf").arg(archive).arg("-C").arg(destination);
+        command
+    };
+
+    println!("{:?}", &command);
+
+    if !command.status()?.success() {
+        return Err(UserFacingError::new(format!("Could not unpack {}", archive.display())).into());
+    }
+    Ok(())
+}
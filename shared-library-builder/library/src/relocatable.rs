@@ -0,0 +1,315 @@
+use crate::requirements::Tool;
+use crate::rpath::RpathPolicy;
+use crate::signing::MacSigning;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The folders macOS libraries may reference with an absolute path
+const MAC_SYSTEM_DIRECTORIES: [&str; 2] = ["/usr/lib/", "/System/Library/"];
+
+/// The system DLLs imported by cairo, pixman, freetype, libpng and zlib
+const WINDOWS_SYSTEM_DLLS: [&str; 14] = [
+    "kernel32.dll",
+    "user32.dll",
+    "gdi32.dll",
+    "msimg32.dll",
+    "advapi32.dll",
+    "ole32.dll",
+    "shell32.dll",
+    "ws2_32.dll",
+    "bcrypt.dll",
+    "dwrite.dll",
+    "d2d1.dll",
+    "windowscodecs.dll",
+    "ntdll.dll",
+    "msvcrt.dll",
+];
+
+/// A directory with cairo and all of its shared dependencies that resolve each other
+/// relative to where they are, so that an application can ship the directory as it is:
+/// `$ORIGIN` rpaths on Linux and `@loader_path` references on macOS.
+/// Windows resolves DLLs by name, the bundle only has to contain every imported DLL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelocatableBundle {
+    directory: PathBuf,
+    mac_signing: MacSigning,
+}
+
+impl RelocatableBundle {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            mac_signing: MacSigning::ad_hoc(),
+        }
+    }
+
+    /// Rewriting the references invalidates the signature of a dylib, they are signed
+    /// again with this signing, ad-hoc by default
+    pub fn with_mac_signing(mut self, mac_signing: MacSigning) -> Self {
+        self.mac_signing = mac_signing;
+        self
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Copy the binaries into the bundle, make them find each other there and verify
+    /// that they do. Loading is only checked with `ldd` when `native` is set, as cross
+    /// compiled binaries can not be loaded by the build machine
+    pub fn assemble(
+        &self,
+        binaries: &[PathBuf],
+        context: &LibraryCompilationContext,
+        native: bool,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        std::fs::create_dir_all(&self.directory)?;
+
+        let mut files = vec![];
+        for binary in binaries {
+            let file = self.directory.join(Self::bundled_name(binary, context)?);
+            if files.contains(&file) {
+                continue;
+            }
+            std::fs::copy(binary, &file)?;
+            files.push(file);
+        }
+
+        if context.is_linux() {
+            for file in &files {
+                RpathPolicy::Origin.apply(file, &[])?;
+            }
+        }
+        if context.is_mac() {
+            for file in &files {
+                self.relocate_dylib(file, &files)?;
+            }
+        }
+
+        self.verify(&files, context, native)?;
+        Ok(files)
+    }
+
+    /// Shared objects are loaded by their soname such as `libcairo.so.2`, not by the name
+    /// of the versioned file they were installed as
+    fn bundled_name(
+        binary: &Path,
+        context: &LibraryCompilationContext,
+    ) -> Result<String, Box<dyn Error>> {
+        if context.is_linux() {
+            let mut command = Command::new(Tool::PATCHELF.name);
+            command.arg("--print-soname").arg(binary);
+            let soname = output(command)?;
+            if !soname.trim().is_empty() {
+                return Ok(soname.trim().to_string());
+            }
+        }
+        Ok(binary
+            .file_name()
+            .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+            .to_string_lossy()
+            .to_string())
+    }
+
+    /// Identify the dylib by `@rpath`, point its references to bundled dylibs
+    /// to `@loader_path` and sign it again
+    fn relocate_dylib(&self, dylib: &Path, bundled: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let mut command = Command::new("install_name_tool");
+        command
+            .arg("-id")
+            .arg(format!("@rpath/{}", file_name(dylib)));
+        for reference in dylib_references(dylib)? {
+            let name = reference
+                .rsplit('/')
+                .next()
+                .unwrap_or(&reference)
+                .to_string();
+            if reference.starts_with("@loader_path/") {
+                continue;
+            }
+            if bundled.iter().any(|file| file_name(file) == name) {
+                command
+                    .arg("-change")
+                    .arg(&reference)
+                    .arg(format!("@loader_path/{}", name));
+            }
+        }
+        command.arg(dylib);
+        println!("{:?}", &command);
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!(
+                "Could not change the references of {}",
+                dylib.display()
+            ))
+            .into());
+        }
+        self.mac_signing.sign(dylib)
+    }
+
+    /// Every dependency of the bundled binaries is either bundled or provided by the system,
+    /// nothing refers to the folders it was built in
+    pub fn verify(
+        &self,
+        files: &[PathBuf],
+        context: &LibraryCompilationContext,
+        native: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let bundled = files
+            .iter()
+            .map(|file| file_name(file))
+            .collect::<Vec<String>>();
+        let mut problems = vec![];
+
+        for file in files {
+            if context.is_linux() && native {
+                let mut command = Command::new("ldd");
+                command.arg(file);
+                for line in output(command)?.lines() {
+                    let line = line.trim();
+                    let (name, resolved) = match line.split_once("=>") {
+                        Some((name, resolved)) => (name.trim(), resolved.trim()),
+                        None => continue,
+                    };
+                    if resolved.starts_with("not found") {
+                        problems.push(format!(
+                            "{} needs {} which is not found",
+                            file_name(file),
+                            name
+                        ));
+                    } else if bundled.iter().any(|each| each == name)
+                        && !is_inside(
+                            Path::new(resolved.split_whitespace().next().unwrap_or("")),
+                            &self.directory,
+                        )
+                    {
+                        problems.push(format!(
+                            "{} loads {} from outside the bundle: {}",
+                            file_name(file),
+                            name,
+                            resolved
+                        ));
+                    }
+                }
+            }
+            if context.is_mac() {
+                for reference in dylib_references(file)? {
+                    let name = reference.rsplit('/').next().unwrap_or(&reference);
+                    let resolves = if reference.starts_with('@') {
+                        name == file_name(file) || bundled.iter().any(|each| each == name)
+                    } else {
+                        MAC_SYSTEM_DIRECTORIES
+                            .iter()
+                            .any(|directory| reference.starts_with(directory))
+                    };
+                    if !resolves {
+                        problems.push(format!("{} refers to {}", file_name(file), reference));
+                    }
+                }
+            }
+            if context.is_windows() {
+                for import in dll_imports(file)? {
+                    let bundled = bundled
+                        .iter()
+                        .any(|each| each.eq_ignore_ascii_case(&import));
+                    if !bundled && !is_windows_system_dll(&import, native) {
+                        problems.push(format!(
+                            "{} imports {} which is not bundled",
+                            file_name(file),
+                            import
+                        ));
+                    }
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let mut error = UserFacingError::new(format!(
+            "The bundle in {} is not relocatable",
+            self.directory.display()
+        ));
+        for problem in problems {
+            error = error.reason(problem);
+        }
+        Err(error
+            .help("Build the missing dependencies as shared libraries or link them statically")
+            .into())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn is_inside(path: &Path, directory: &Path) -> bool {
+    match (
+        std::fs::canonicalize(path),
+        std::fs::canonicalize(directory),
+    ) {
+        (Ok(path), Ok(directory)) => path.starts_with(directory),
+        _ => false,
+    }
+}
+
+fn output(mut command: Command) -> Result<String, Box<dyn Error>> {
+    println!("{:?}", &command);
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(
+            UserFacingError::new(format!("{:?} failed", command.get_program()))
+                .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+                .into(),
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The libraries a dylib loads, as listed by `otool -L` after its own install name
+fn dylib_references(dylib: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut command = Command::new("otool");
+    command.arg("-L").arg(dylib);
+    Ok(output(command)?
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split(" (").next())
+        .map(|reference| reference.trim().to_string())
+        .filter(|reference| !reference.is_empty())
+        .collect())
+}
+
+/// The DLLs a DLL imports, read with `llvm-readobj` which also works when cross compiling
+fn dll_imports(dll: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut command = Command::new(Tool::LLVM_READOBJ.name);
+    command.arg("--coff-imports").arg(dll);
+    Ok(output(command)?
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Name: "))
+        .map(|name| name.trim().to_string())
+        .collect())
+}
+
+/// API sets, the C runtime and the DLLs of `System32`. Those can only be looked up on Windows
+/// itself, cross builds recognize the system DLLs the cairo stack is known to import
+fn is_windows_system_dll(name: &str, native: bool) -> bool {
+    let lowercase = name.to_lowercase();
+    if lowercase.starts_with("api-ms-win-")
+        || lowercase.starts_with("ext-ms-")
+        || lowercase.starts_with("vcruntime")
+        || lowercase.starts_with("msvcp")
+        || lowercase.starts_with("ucrtbase")
+        || WINDOWS_SYSTEM_DLLS.contains(&lowercase.as_str())
+    {
+        return true;
+    }
+    match std::env::var_os("SystemRoot") {
+        Some(root) if native => Path::new(&root).join("System32").join(name).is_file(),
+        _ => false,
+    }
+}
@@ -0,0 +1,373 @@
+use crate::compiler_flags::quote_unix;
+use crate::http::HttpClient;
+use crate::network::NetworkSettings;
+use crate::requirements::Tool;
+use crate::upload::UploadCredentials;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use user_error::UserFacingError;
+
+/// The name of the serialized library configuration sent to a remote builder
+pub const REMOTE_CONFIGURATION: &str = "cairo.json";
+
+/// A machine that compiles cairo and its dependencies on our behalf, for example a Mac
+/// building the macOS binaries while the rest of the pipeline runs on Linux. The remote
+/// builds for its own platform, which must be the target of the local compilation context.
+/// The prefixes of cairo and of its dependencies are copied back into the local build root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemoteBuilder {
+    /// Copy the configuration over ssh, run `<builder> compile <configuration> <build root> <profile>`
+    /// on the host and copy the installed prefixes back. The builder is a shell command,
+    /// everything else is quoted for the remote shell
+    Ssh {
+        host: String,
+        build_root: String,
+        builder: String,
+    },
+    /// A job server that accepts `POST <url>/jobs` with `{"library", "target", "profile"}`,
+    /// answering with an `id`, reports `GET <url>/jobs/<id>` as `{"status"}` with
+    /// `queued`, `running`, `succeeded` or `failed`, and serves the installed prefix
+    /// as `GET <url>/jobs/<id>/prefix.tar.gz` and the ones of the dependencies, relative to
+    /// the build root, as `GET <url>/jobs/<id>/dependencies.tar.gz`. `GET <url>/platform`
+    /// answers `{"target"}` with the target the server builds for
+    Http {
+        url: String,
+        credentials: UploadCredentials,
+    },
+}
+
+impl RemoteBuilder {
+    pub fn ssh(host: impl Into<String>, build_root: impl Into<String>) -> Self {
+        Self::Ssh {
+            host: host.into(),
+            build_root: build_root.into(),
+            builder: "cargo run --release --bin builder --".to_string(),
+        }
+    }
+
+    pub fn http(url: impl Into<String>, credentials: UploadCredentials) -> Self {
+        Self::Http {
+            url: url.into(),
+            credentials,
+        }
+    }
+
+    pub fn tool(&self) -> Option<Tool> {
+        match self {
+            Self::Ssh { .. } => Some(Tool::SSH),
+            Self::Http { .. } => None,
+        }
+    }
+
+    /// Build the serialized library remotely and install the result into `prefix`, the
+    /// prefixes of the dependencies are installed at the same place in the build root
+    pub(crate) fn build(
+        &self,
+        configuration: &str,
+        prefix: &Path,
+        dependency_prefixes: &[PathBuf],
+        network: &NetworkSettings,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let prefix = relative_prefix(prefix, context)?;
+        let dependency_prefixes = dependency_prefixes
+            .iter()
+            .map(|dependency_prefix| relative_prefix(dependency_prefix, context))
+            .collect::<Result<Vec<PathBuf>, Box<dyn Error>>>()?;
+
+        match self {
+            Self::Ssh {
+                host,
+                build_root,
+                builder,
+            } => {
+                let ssh = SshBuild {
+                    host,
+                    build_root,
+                    builder,
+                };
+                ssh.build(configuration, &prefix, &dependency_prefixes, context)
+            }
+            Self::Http { url, credentials } => {
+                let http = HttpBuild { url, credentials };
+                http.build(configuration, &prefix, &dependency_prefixes, network, context)
+            }
+        }
+    }
+
+    fn unpack(archive: &Path, directory: &Path, remote: &str) -> Result<(), Box<dyn Error>> {
+        let mut unpack = Command::new("tar");
+        unpack.arg("-xzf").arg(archive).arg("-C").arg(directory);
+        Self::run(unpack, remote)?;
+        std::fs::remove_file(archive)?;
+        Ok(())
+    }
+
+    fn run(mut command: Command, remote: &str) -> Result<(), Box<dyn Error>> {
+        println!("{:?}", &command);
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!("Remote build on {} failed", remote))
+                .reason(format!("{:?} exited with an error", command.get_program()))
+                .into());
+        }
+        Ok(())
+    }
+}
+
+/// The prefix relative to the build root, the same on both machines
+fn relative_prefix(
+    prefix: &Path,
+    context: &LibraryCompilationContext,
+) -> Result<PathBuf, Box<dyn Error>> {
+    prefix
+        .strip_prefix(context.build_root())
+        .map(|prefix| prefix.to_path_buf())
+        .map_err(|_| {
+            UserFacingError::new("Could not build remotely")
+                .reason(format!(
+                    "{} is not in the build root {}",
+                    prefix.display(),
+                    context.build_root().display()
+                ))
+                .into()
+        })
+}
+
+/// A path relative to the build root as an argument of the remote shell
+fn remote_path(relative: &Path) -> String {
+    quote_unix(
+        &relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+struct SshBuild<'a> {
+    host: &'a str,
+    build_root: &'a str,
+    builder: &'a str,
+}
+
+impl SshBuild<'_> {
+    fn ssh(&self, remote_command: String) -> Command {
+        let mut command = Command::new("ssh");
+        command.arg(self.host).arg(remote_command);
+        command
+    }
+
+    fn build(
+        &self,
+        configuration: &str,
+        prefix: &Path,
+        dependency_prefixes: &[PathBuf],
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_platform(context)?;
+
+        let build_root = quote_unix(self.build_root);
+        let remote_configuration =
+            quote_unix(&format!("{}/{}", self.build_root, REMOTE_CONFIGURATION));
+
+        RemoteBuilder::run(self.ssh(format!("mkdir -p {}", build_root)), self.host)?;
+
+        let local_configuration = context.build_root().join(REMOTE_CONFIGURATION);
+        std::fs::write(&local_configuration, configuration)?;
+        let mut copy = self.ssh(format!("cat > {}", remote_configuration));
+        copy.stdin(std::fs::File::open(&local_configuration)?);
+        RemoteBuilder::run(copy, self.host)?;
+
+        RemoteBuilder::run(
+            self.ssh(format!(
+                "{} compile {} {} {}",
+                self.builder,
+                remote_configuration,
+                build_root,
+                quote_unix(&context.profile())
+            )),
+            self.host,
+        )?;
+
+        let prefixes = std::iter::once(prefix)
+            .chain(dependency_prefixes.iter().map(|prefix| prefix.as_path()))
+            .collect::<Vec<&Path>>();
+        for relative in &prefixes {
+            let local = context.build_root().join(relative);
+            if local.exists() {
+                std::fs::remove_dir_all(&local)?;
+            }
+        }
+        std::fs::create_dir_all(context.build_root())?;
+
+        let mut pack = self.ssh(format!(
+            "tar -C {} -cf - {}",
+            build_root,
+            prefixes
+                .iter()
+                .map(|relative| remote_path(relative))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ));
+        pack.stdout(Stdio::piped());
+        println!("{:?}", &pack);
+        let mut pack = pack.spawn()?;
+        let archive = pack
+            .stdout
+            .take()
+            .ok_or_else(|| UserFacingError::new("Could not read the remote prefixes"))?;
+
+        let mut unpack = Command::new("tar");
+        unpack
+            .arg("-xf")
+            .arg("-")
+            .arg("-C")
+            .arg(context.build_root())
+            .stdin(archive);
+        RemoteBuilder::run(unpack, self.host)?;
+        if !pack.wait()?.success() {
+            return Err(
+                UserFacingError::new(format!("Remote build on {} failed", self.host))
+                    .reason("Could not copy the installed prefixes back")
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// The remote builds for its own platform, which must be the requested target
+    fn check_platform(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let output = self.ssh("uname -sm".to_string()).output()?;
+        let platform = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let (system, machine) = platform.split_once(' ').unwrap_or((platform.as_str(), ""));
+
+        let architecture = match machine {
+            "arm64" | "aarch64" => "aarch64",
+            "x86_64" | "amd64" => "x86_64",
+            other => other,
+        };
+        let system = match system {
+            "Darwin" => "apple-darwin",
+            "Linux" => "linux",
+            other => other,
+        };
+        let target = context.target().to_string();
+        if !output.status.success()
+            || !target.starts_with(architecture)
+            || !target.contains(system)
+        {
+            return Err(UserFacingError::new(format!(
+                "{} can not build for {}",
+                self.host, target
+            ))
+            .reason(format!("The remote machine is {}", platform))
+            .help("Use a remote builder of the target platform")
+            .into());
+        }
+        Ok(())
+    }
+}
+
+struct HttpBuild<'a> {
+    url: &'a str,
+    credentials: &'a UploadCredentials,
+}
+
+impl HttpBuild<'_> {
+    fn build(
+        &self,
+        configuration: &str,
+        prefix: &Path,
+        dependency_prefixes: &[PathBuf],
+        network: &NetworkSettings,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let client = self.credentials.apply(
+            HttpClient::new()
+                .with_network(network.clone())
+                .header("Content-Type", "application/json"),
+        )?;
+        let url = self.url.trim_end_matches('/');
+        self.check_platform(&client, context)?;
+
+        let request = serde_json::json!({
+            "library": serde_json::from_str::<serde_json::Value>(configuration)?,
+            "target": context.target().to_string(),
+            "profile": context.profile().to_string(),
+        });
+        let request_file = context.build_root().join(REMOTE_CONFIGURATION);
+        std::fs::write(&request_file, request.to_string())?;
+
+        let response: serde_json::Value =
+            serde_json::from_slice(&client.send_file("POST", &format!("{}/jobs", url), &request_file)?)?;
+        let id = response["id"]
+            .as_str()
+            .map(|id| id.to_string())
+            .or_else(|| response["id"].as_u64().map(|id| id.to_string()))
+            .ok_or_else(|| UserFacingError::new("The remote builder did not return a job id"))?;
+
+        let job = format!("{}/jobs/{}", url, id);
+        loop {
+            let status = client.get_json(&job)?;
+            match status["status"].as_str() {
+                Some("succeeded") => break,
+                Some("queued") | Some("running") => {
+                    println!("Waiting for remote job {}", &job);
+                    std::thread::sleep(Duration::from_secs(10));
+                }
+                other => {
+                    return Err(UserFacingError::new("Remote build failed")
+                        .reason(format!("Job {} finished with {:?}", &job, other))
+                        .into())
+                }
+            }
+        }
+
+        // the prefixes of an earlier build may have files the new ones do not
+        let prefixes = std::iter::once(prefix)
+            .chain(dependency_prefixes.iter().map(|prefix| prefix.as_path()))
+            .collect::<Vec<&Path>>();
+        for relative in &prefixes {
+            let local = context.build_root().join(relative);
+            if local.exists() {
+                std::fs::remove_dir_all(&local)?;
+            }
+        }
+
+        let prefix = context.build_root().join(prefix);
+        let archive = context.build_root().join(format!("{}.tar.gz", id));
+        client.download(&format!("{}/prefix.tar.gz", job), &archive)?;
+        std::fs::create_dir_all(&prefix)?;
+        RemoteBuilder::unpack(&archive, &prefix, url)?;
+
+        client.download(&format!("{}/dependencies.tar.gz", job), &archive)?;
+        RemoteBuilder::unpack(&archive, context.build_root(), url)
+    }
+
+    /// The server builds for its own platform, which must be the requested target
+    fn check_platform(
+        &self,
+        client: &HttpClient,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let platform = client.get_json(&format!("{}/platform", self.url.trim_end_matches('/')))?;
+        let target = context.target().to_string();
+        match platform["target"].as_str() {
+            Some(remote) if remote == target => Ok(()),
+            remote => Err(UserFacingError::new(format!(
+                "{} can not build for {}",
+                self.url, target
+            ))
+            .reason(format!(
+                "The remote builder builds for {}",
+                remote.unwrap_or("an unknown target")
+            ))
+            .help("Use a remote builder of the target platform")
+            .into()),
+        }
+    }
+}
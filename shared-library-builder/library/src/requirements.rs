@@ -0,0 +1,322 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// A command line tool that must be present in the PATH, together with
+/// the packages that provide it on the supported host platforms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tool {
+    pub name: &'static str,
+    pub apt: &'static str,
+    pub brew: &'static str,
+    pub choco: &'static str,
+}
+
+impl Tool {
+    pub const MAKE: Tool = Tool {
+        name: "make",
+        apt: "make",
+        brew: "make",
+        choco: "make",
+    };
+    pub const GMAKE: Tool = Tool {
+        name: "gmake",
+        apt: "make",
+        brew: "make",
+        choco: "make",
+    };
+    pub const BMAKE: Tool = Tool {
+        name: "bmake",
+        apt: "bmake",
+        brew: "bmake",
+        choco: "",
+    };
+    pub const MINGW32_MAKE: Tool = Tool {
+        name: "mingw32-make",
+        apt: "",
+        brew: "",
+        choco: "mingw",
+    };
+    pub const AUTORECONF: Tool = Tool {
+        name: "autoreconf",
+        apt: "autoconf",
+        brew: "autoconf",
+        choco: "autoconf",
+    };
+    pub const ACLOCAL: Tool = Tool {
+        name: "aclocal",
+        apt: "automake",
+        brew: "automake",
+        choco: "automake",
+    };
+    pub const LIBTOOLIZE: Tool = Tool {
+        name: "libtoolize",
+        apt: "libtool",
+        brew: "libtool",
+        choco: "libtool",
+    };
+    pub const PKG_CONFIG: Tool = Tool {
+        name: "pkg-config",
+        apt: "pkg-config",
+        brew: "pkg-config",
+        choco: "pkgconfiglite",
+    };
+    pub const MESON: Tool = Tool {
+        name: "meson",
+        apt: "meson",
+        brew: "meson",
+        choco: "meson",
+    };
+    pub const NINJA: Tool = Tool {
+        name: "ninja",
+        apt: "ninja-build",
+        brew: "ninja",
+        choco: "ninja",
+    };
+    pub const CMAKE: Tool = Tool {
+        name: "cmake",
+        apt: "cmake",
+        brew: "cmake",
+        choco: "cmake",
+    };
+    pub const BEAR: Tool = Tool {
+        name: "bear",
+        apt: "bear",
+        brew: "bear",
+        choco: "bear",
+    };
+    pub const PATCHELF: Tool = Tool {
+        name: "patchelf",
+        apt: "patchelf",
+        brew: "patchelf",
+        choco: "patchelf",
+    };
+    pub const RC: Tool = Tool {
+        name: "rc",
+        apt: "",
+        brew: "",
+        choco: "windows-sdk-10.1",
+    };
+    pub const MINISIGN: Tool = Tool {
+        name: "minisign",
+        apt: "minisign",
+        brew: "minisign",
+        choco: "minisign",
+    };
+    pub const SSH: Tool = Tool {
+        name: "ssh",
+        apt: "openssh-client",
+        brew: "openssh",
+        choco: "openssh",
+    };
+    pub const DOCKER: Tool = Tool {
+        name: "docker",
+        apt: "docker.io",
+        brew: "docker",
+        choco: "docker-cli",
+    };
+    pub const PODMAN: Tool = Tool {
+        name: "podman",
+        apt: "podman",
+        brew: "podman",
+        choco: "podman-cli",
+    };
+    pub const DUMPBIN: Tool = Tool {
+        name: "dumpbin",
+        apt: "",
+        brew: "",
+        choco: "visualstudio2022buildtools",
+    };
+    pub const CL: Tool = Tool {
+        name: "cl",
+        apt: "",
+        brew: "",
+        choco: "visualstudio2022buildtools",
+    };
+    pub const LINK: Tool = Tool {
+        name: "link",
+        apt: "",
+        brew: "",
+        choco: "visualstudio2022buildtools",
+    };
+    pub const LIB: Tool = Tool {
+        name: "lib",
+        apt: "",
+        brew: "",
+        choco: "visualstudio2022buildtools",
+    };
+    pub const CLANG_CL: Tool = Tool {
+        name: "clang-cl",
+        apt: "",
+        brew: "",
+        choco: "llvm",
+    };
+    pub const LLD_LINK: Tool = Tool {
+        name: "lld-link",
+        apt: "",
+        brew: "",
+        choco: "llvm",
+    };
+    pub const LLVM_LIB: Tool = Tool {
+        name: "llvm-lib",
+        apt: "",
+        brew: "",
+        choco: "llvm",
+    };
+    pub const LLVM_SIZE: Tool = Tool {
+        name: "llvm-size",
+        apt: "llvm",
+        brew: "llvm",
+        choco: "llvm",
+    };
+    pub const LLVM_NM: Tool = Tool {
+        name: "llvm-nm",
+        apt: "llvm",
+        brew: "llvm",
+        choco: "llvm",
+    };
+    pub const LLVM_READOBJ: Tool = Tool {
+        name: "llvm-readobj",
+        apt: "llvm",
+        brew: "llvm",
+        choco: "llvm",
+    };
+    pub const READELF: Tool = Tool {
+        name: "readelf",
+        apt: "binutils",
+        brew: "binutils",
+        choco: "mingw",
+    };
+    pub const COREUTILS: Tool = Tool {
+        name: "coreutils",
+        apt: "coreutils",
+        brew: "uutils-coreutils",
+        choco: "uutils-coreutils",
+    };
+
+    /// A command that installs this tool on the current host
+    pub fn install_hint(&self) -> String {
+        match std::env::consts::OS {
+            "macos" => format!("brew install {}", self.brew),
+            "windows" => format!("choco install {}", self.choco),
+            _ => format!("sudo apt install {}", self.apt),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MissingRequirement {
+    Tool(Tool),
+    Directory(PathBuf, String),
+    Package(String),
+    Unsupported(String),
+}
+
+/// Collects every missing tool or folder needed to build a library so that they
+/// can be reported all at once instead of failing on the first one
+#[derive(Debug, Clone)]
+pub struct Requirements {
+    library: String,
+    missing: Vec<MissingRequirement>,
+}
+
+impl Requirements {
+    pub fn new(library: impl Into<String>) -> Self {
+        Self {
+            library: library.into(),
+            missing: vec![],
+        }
+    }
+
+    pub fn tool(&mut self, tool: Tool) -> &mut Self {
+        if which::which(tool.name).is_err() {
+            self.missing.push(MissingRequirement::Tool(tool));
+        }
+        self
+    }
+
+    pub fn directory(&mut self, path: impl AsRef<Path>, description: impl Into<String>) -> &mut Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            self.missing.push(MissingRequirement::Directory(
+                path.to_path_buf(),
+                description.into(),
+            ));
+        }
+        self
+    }
+
+    /// A library installed on the system and discoverable with pkg-config
+    pub fn pkg_config_package(&mut self, package: impl Into<String>) -> &mut Self {
+        let package = package.into();
+        let found = std::process::Command::new(Tool::PKG_CONFIG.name)
+            .arg("--exists")
+            .arg(&package)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !found {
+            self.missing.push(MissingRequirement::Package(package));
+        }
+        self
+    }
+
+    /// A configuration that can not be built on this platform
+    pub fn unsupported(&mut self, reason: impl Into<String>) -> &mut Self {
+        self.missing.push(MissingRequirement::Unsupported(reason.into()));
+        self
+    }
+
+    pub fn is_satisfied(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.is_satisfied() {
+            return Ok(());
+        }
+
+        let mut error = UserFacingError::new(format!(
+            "Missing requirements to build {}",
+            &self.library
+        ));
+        let mut hints = vec![];
+
+        for requirement in &self.missing {
+            match requirement {
+                MissingRequirement::Tool(tool) => {
+                    error = error.reason(format!("Could not find `{}`", tool.name));
+                    hints.push(tool.install_hint());
+                }
+                MissingRequirement::Directory(path, description) => {
+                    error = error.reason(format!(
+                        "{} folder does not exist: {}",
+                        description,
+                        path.display()
+                    ));
+                }
+                MissingRequirement::Package(package) => {
+                    error = error.reason(format!("pkg-config could not find `{}`", package));
+                    hints.push(format!(
+                        "Install the development package that provides {}.pc or add its folder to PKG_CONFIG_PATH",
+                        package
+                    ));
+                }
+                MissingRequirement::Unsupported(reason) => {
+                    error = error.reason(reason.clone());
+                }
+            }
+        }
+
+        if self
+            .missing
+            .iter()
+            .any(|requirement| matches!(requirement, MissingRequirement::Directory(..)))
+        {
+            hints.push("Install the Visual Studio Build Tools with the \"Desktop development with C++\" workload and run from a Developer Command Prompt".to_string());
+        }
+
+        hints.dedup();
+        Err(error.help(hints.join("\n")).into())
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// Where the produced Linux shared objects look for their dependencies,
+/// and how the binaries linking the macOS dylibs find them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RpathPolicy {
+    /// Next to the shared object itself (`$ORIGIN`), for bundling with an application
+    Origin,
+    /// The absolute folders the dependencies were built into
+    Absolute,
+    /// No rpath, dependencies are resolved from the system library paths
+    None,
+}
+
+impl RpathPolicy {
+    /// Rewrite the rpath of the shared object with `patchelf`
+    pub fn apply(
+        &self,
+        shared_object: impl AsRef<Path>,
+        library_directories: &[PathBuf],
+    ) -> Result<(), Box<dyn Error>> {
+        let shared_object = shared_object.as_ref();
+
+        let mut command = Command::new("patchelf");
+        match self {
+            Self::Origin => {
+                command.arg("--set-rpath").arg("$ORIGIN");
+            }
+            Self::Absolute => {
+                command
+                    .arg("--set-rpath")
+                    .arg(std::env::join_paths(library_directories)?);
+            }
+            Self::None => {
+                command.arg("--remove-rpath");
+            }
+        }
+        command.arg(shared_object);
+
+        println!("{:?}", &command);
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!(
+                "Could not change the rpath of {}",
+                shared_object.display()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Set the install name of a macOS dylib, `@rpath/<file name>` to be found next to
+    /// the binary loading it or its absolute path. [`RpathPolicy::None`] keeps the
+    /// install name libtool gave it
+    pub fn apply_install_name(&self, dylib: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let dylib = dylib.as_ref();
+        let install_name = match self {
+            Self::Origin => {
+                let file_name = dylib
+                    .file_name()
+                    .ok_or_else(|| UserFacingError::new("Could not get file name"))?;
+                format!("@rpath/{}", file_name.to_string_lossy())
+            }
+            Self::Absolute => dylib.canonicalize()?.display().to_string(),
+            Self::None => return Ok(()),
+        };
+
+        let mut command = Command::new("install_name_tool");
+        command.arg("-id").arg(install_name).arg(dylib);
+
+        println!("{:?}", &command);
+
+        if !command.status()?.success() {
+            return Err(UserFacingError::new(format!(
+                "Could not change the install name of {}",
+                dylib.display()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::hash::short_hash;
+use crate::make_jobs::MakeJobs;
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use user_error::UserFacingError;
+
+/// The identity of a library in the dependency graph, its name together with its whole
+/// configuration. zlib required by both libpng and freetype is the same library only
+/// if it is configured the same way
+pub fn library_key(library: &dyn Library) -> String {
+    format!("{}-{}", library.name(), short_hash(format!("{:?}", library)))
+}
+
+/// Compiles independent libraries, such as pixman and freetype, concurrently
+/// with at most `jobs` builds running at the same time. Without a jobserver the jobs
+/// of their `make` are divided between them, see [`MakeJobs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelBuild {
+    jobs: usize,
+}
+
+impl ParallelBuild {
+    pub fn new(jobs: usize) -> Self {
+        Self { jobs: jobs.max(1) }
+    }
+
+    /// As many jobs as the machine has cores
+    pub fn available() -> Self {
+        Self::new(
+            std::thread::available_parallelism()
+                .map(|jobs| jobs.get())
+                .unwrap_or(1),
+        )
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Compile the libraries and all of their transitive dependencies, each exactly once.
+    /// Libraries are built in stages, a stage only depends on the stages before it
+    pub fn compile<'a>(
+        &self,
+        libraries: impl IntoIterator<Item = &'a Box<dyn Library>>,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        for stage in Self::stages(libraries.into_iter().map(|library| library.as_ref())) {
+            self.compile_stage(&stage, context)?;
+        }
+        Ok(())
+    }
+
+    /// Group the deduplicated dependency graph by height, leaves first
+    fn stages<'a>(libraries: impl IntoIterator<Item = &'a dyn Library>) -> Vec<Vec<&'a dyn Library>> {
+        let mut graph = BTreeMap::new();
+        for library in libraries {
+            Self::collect(library, &mut graph);
+        }
+
+        let mut stages: Vec<Vec<&dyn Library>> = vec![];
+        for (height, library) in graph.into_values() {
+            if stages.len() <= height {
+                stages.resize_with(height + 1, Vec::new);
+            }
+            stages[height].push(library);
+        }
+        stages
+    }
+
+    /// Add the library and its dependencies to the graph, returning its height
+    fn collect<'a>(
+        library: &'a dyn Library,
+        graph: &mut BTreeMap<String, (usize, &'a dyn Library)>,
+    ) -> usize {
+        let key = library_key(library);
+        if let Some((height, _)) = graph.get(&key) {
+            return *height;
+        }
+
+        let height = library
+            .dependencies()
+            .map(|dependencies| {
+                dependencies
+                    .iter()
+                    .map(|dependency| Self::collect(dependency.as_ref(), graph) + 1)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .unwrap_or(0);
+
+        graph.insert(key, (height, library));
+        height
+    }
+
+    /// Compile independent libraries, reporting all failed builds at once
+    fn compile_stage(
+        &self,
+        libraries: &[&dyn Library],
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let queue = Mutex::new(libraries.iter());
+        let failures = Mutex::new(vec![]);
+
+        // the builds split the jobs of make between them
+        let builds = self.jobs.min(libraries.len());
+        std::thread::scope(|scope| {
+            for _ in 0..builds {
+                scope.spawn(|| {
+                    MakeJobs::shared(builds, || loop {
+                        let library = match queue.lock().unwrap().next() {
+                            Some(library) => library,
+                            None => break,
+                        };
+                        if let Err(error) = compile_without_panic(*library, context) {
+                            failures
+                                .lock()
+                                .unwrap()
+                                .push(format!("{}: {}", library.name(), error));
+                        }
+                    })
+                });
+            }
+        });
+
+        let failures = failures.into_inner().unwrap();
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        let mut error = UserFacingError::new("Could not compile the dependencies");
+        for failure in failures {
+            error = error.reason(failure);
+        }
+        Err(error.into())
+    }
+}
+
+/// Compile a library, turning the panic of [`Library::ensure_requirements`] about a
+/// missing tool into an error so that the other builds are still reported
+fn compile_without_panic(
+    library: &dyn Library,
+    context: &LibraryCompilationContext,
+) -> Result<PathBuf, Box<dyn Error>> {
+    match std::panic::catch_unwind(AssertUnwindSafe(|| library.compile(context))) {
+        Ok(compiled) => compiled,
+        Err(panic) => Err(panic
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| panic.downcast_ref::<&str>().map(|message| message.to_string()))
+            .unwrap_or_else(|| "the build panicked".to_string())
+            .into()),
+    }
+}
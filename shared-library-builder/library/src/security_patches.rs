@@ -0,0 +1,135 @@
+use crate::patch::PatchReport;
+use crate::unified_diff::UnifiedDiff;
+use crate::version::CairoVersion;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+use user_error::UserFacingError;
+
+/// The file in the install prefix listing the security patches cairo was built with
+pub const SECURITY_PATCHES_FILE: &str = "security-patches.json";
+
+/// A fix for a known vulnerability backported onto a cairo release
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityPatch {
+    id: String,
+    description: String,
+    diff: UnifiedDiff,
+}
+
+/// The identification of a security patch as recorded in the install prefix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedSecurityPatch {
+    pub id: String,
+    pub description: String,
+    /// The hunks left out since the release contains them already, as `path hunk #number`
+    #[serde(default)]
+    pub skipped_hunks: Vec<String>,
+}
+
+impl SecurityPatch {
+    pub fn new(id: impl Into<String>, description: impl Into<String>, diff: UnifiedDiff) -> Self {
+        Self {
+            id: id.into(),
+            description: description.into(),
+            diff,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    pub fn description(&self) -> &str {
+        self.description.as_str()
+    }
+
+    pub fn diff(&self) -> &UnifiedDiff {
+        &self.diff
+    }
+
+    /// The record of the patch, with the hunks the reports of its application list as skipped
+    pub fn applied(&self, reports: &[PatchReport], sources: &Path) -> AppliedSecurityPatch {
+        let skipped_hunks = reports
+            .iter()
+            .flat_map(|report| {
+                let path = report
+                    .path()
+                    .strip_prefix(sources)
+                    .unwrap_or_else(|_| report.path())
+                    .display()
+                    .to_string();
+                report
+                    .skipped_hunks(self.diff.name())
+                    .into_iter()
+                    .map(move |hunk| format!("{} hunk #{}", path, hunk))
+            })
+            .collect();
+        AppliedSecurityPatch {
+            id: self.id.clone(),
+            description: self.description.clone(),
+            skipped_hunks,
+        }
+    }
+
+    /// The backports shipped with the crate for the given release of cairo.
+    /// Hunks that are already part of the sources are skipped, see [`UnifiedDiff::skipping_applied_hunks`]
+    pub fn for_version(version: CairoVersion) -> Vec<SecurityPatch> {
+        if version == CairoVersion::new(1, 17, 4) {
+            return vec![Self::new(
+                "CVE-2019-6462",
+                "Infinite loop in _arc_max_angle_for_tolerance_normalized for tiny tolerances",
+                UnifiedDiff::new(
+                    "CVE-2019-6462.patch",
+                    include_str!("../patches/cairo-1.17.4/CVE-2019-6462.patch"),
+                )
+                .skipping_applied_hunks(),
+            )];
+        }
+        vec![]
+    }
+
+    /// Record the applied patches in the folder, together with the hunks
+    /// the reports of their application in the sources list as skipped
+    pub fn write_applied(
+        patches: &[SecurityPatch],
+        reports: &[PatchReport],
+        sources: &Path,
+        directory: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let applied = patches
+            .iter()
+            .map(|patch| patch.applied(reports, sources))
+            .collect::<Vec<AppliedSecurityPatch>>();
+        write_records(&applied, directory)
+    }
+
+    /// Copy the record written next to the patched sources into the install prefix
+    pub fn copy_applied(sources: &Path, prefix: &Path) -> Result<(), Box<dyn Error>> {
+        write_records(&Self::read_applied(sources)?, prefix)
+    }
+
+    /// The security patches recorded in an install prefix or next to the patched sources,
+    /// empty if there were none
+    pub fn read_applied(prefix: &Path) -> Result<Vec<AppliedSecurityPatch>, Box<dyn Error>> {
+        let file = prefix.join(SECURITY_PATCHES_FILE);
+        if !file.exists() {
+            return Ok(vec![]);
+        }
+        let contents = std::fs::read_to_string(&file)?;
+        serde_json::from_str(&contents).map_err(|error| {
+            UserFacingError::new(format!("Could not read {}", file.display()))
+                .reason(error.to_string())
+                .into()
+        })
+    }
+}
+
+fn write_records(applied: &[AppliedSecurityPatch], directory: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(directory)?;
+    std::fs::write(
+        directory.join(SECURITY_PATCHES_FILE),
+        serde_json::to_string_pretty(applied)?,
+    )?;
+    Ok(())
+}
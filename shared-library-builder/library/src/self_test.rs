@@ -0,0 +1,306 @@
+use crate::binaries::shared_libraries_in;
+use crate::cairo_library::CairoLibrary;
+use crate::compilation_context::CompilationContext;
+use crate::features::{FeatureSummary, CAIRO_FEATURES_HEADER};
+use crate::requirements::{Requirements, Tool};
+use crate::scheduler::ParallelBuild;
+use crate::symbol_prefix::SymbolPrefix;
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// An end-to-end build of cairo and its dependencies for the current host in a fresh
+/// workspace, followed by the smoke tests and a check of the exported symbols.
+/// Validates that a machine is set up correctly before it is trusted with release builds
+#[derive(Debug, Clone)]
+pub struct SelfTest {
+    cairo: CairoLibrary,
+    workspace: PathBuf,
+    keep_workspace: bool,
+}
+
+/// How one step of the self test went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelfTestOutcome {
+    Passed,
+    Failed(String),
+    /// Not run, because an earlier step failed or it does not apply to this build
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    workspace: PathBuf,
+    steps: Vec<(&'static str, SelfTestOutcome)>,
+}
+
+impl Default for SelfTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SelfTest {
+    /// Build cairo as Glamorous Toolkit ships it in a workspace in the temporary folder,
+    /// which is removed when the self test passes
+    pub fn new() -> Self {
+        Self {
+            cairo: CairoLibrary::gt_default(),
+            workspace: std::env::temp_dir()
+                .join(format!("libcairo-self-test-{}", std::process::id())),
+            keep_workspace: false,
+        }
+    }
+
+    /// Test another configuration, it is built for the current host
+    pub fn with_cairo(mut self, cairo: CairoLibrary) -> Self {
+        self.cairo = cairo;
+        self
+    }
+
+    /// Build in another folder. It is only removed after a passing test if the self test created it
+    pub fn with_workspace(mut self, workspace: impl Into<PathBuf>) -> Self {
+        self.workspace = workspace.into();
+        self
+    }
+
+    /// Keep the workspace of a passing self test, the one of a failing test is always kept
+    pub fn with_keep_workspace(mut self, keep_workspace: bool) -> Self {
+        self.keep_workspace = keep_workspace;
+        self
+    }
+
+    pub fn run(&self) -> SelfTestReport {
+        let mut report = SelfTestReport {
+            workspace: self.workspace.clone(),
+            steps: vec![],
+        };
+
+        // a workspace that already exists belongs to the caller and is never removed
+        let created_workspace = !self.workspace.exists();
+        let context = match CompilationContext::new(&self.workspace).create() {
+            Ok(context) => context,
+            Err(error) => {
+                report
+                    .steps
+                    .push(("workspace", SelfTestOutcome::Failed(error.to_string())));
+                return report;
+            }
+        };
+        report.steps.push(("workspace", SelfTestOutcome::Passed));
+
+        let cairo = &self.cairo;
+        report.step("requirements", || cairo.check_requirements(&context));
+        report.step("sources", || cairo.ensure_all_sources(&context));
+        report.step("dependencies", || match cairo.dependencies() {
+            Some(dependencies) => ParallelBuild::available().compile(dependencies.iter(), &context),
+            None => Ok(()),
+        });
+        report.step("cairo", || cairo.compile(&context).map(|_| ()));
+        report.step("smoke tests", || cairo.run_smoke_tests(&context));
+        if cairo.is_static() {
+            report.skip("exported symbols", "cairo is built as a static library");
+        } else {
+            report.step("exported symbols", || {
+                verify_exported_symbols(cairo, &context)
+            });
+        }
+
+        if report.passed() && created_workspace && !self.keep_workspace {
+            if let Err(error) = std::fs::remove_dir_all(&self.workspace) {
+                println!("Could not remove {}: {}", self.workspace.display(), error);
+            }
+        }
+        report
+    }
+}
+
+impl SelfTestReport {
+    /// Run the step unless an earlier one failed, a panic counts as a failure
+    fn step(&mut self, name: &'static str, step: impl FnOnce() -> Result<(), Box<dyn Error>>) {
+        if !self.passed() {
+            self.skip(name, "an earlier step failed");
+            return;
+        }
+        let outcome = match std::panic::catch_unwind(AssertUnwindSafe(step)) {
+            Ok(Ok(())) => SelfTestOutcome::Passed,
+            Ok(Err(error)) => SelfTestOutcome::Failed(error.to_string()),
+            Err(panic) => SelfTestOutcome::Failed(
+                panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| {
+                        panic
+                            .downcast_ref::<&str>()
+                            .map(|message| message.to_string())
+                    })
+                    .unwrap_or_else(|| "panicked".to_string()),
+            ),
+        };
+        self.steps.push((name, outcome));
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &str) {
+        self.steps
+            .push((name, SelfTestOutcome::Skipped(reason.to_string())));
+    }
+
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|(_, outcome)| !matches!(outcome, SelfTestOutcome::Failed(_)))
+    }
+
+    pub fn steps(&self) -> &[(&'static str, SelfTestOutcome)] {
+        &self.steps
+    }
+
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// An error describing the failed steps, if any
+    pub fn into_result(self) -> Result<(), Box<dyn Error>> {
+        if self.passed() {
+            return Ok(());
+        }
+        let mut error = UserFacingError::new("The self test failed");
+        for (name, outcome) in &self.steps {
+            if let SelfTestOutcome::Failed(reason) = outcome {
+                error = error.reason(format!("{}: {}", name, reason));
+            }
+        }
+        Err(error
+            .help(format!(
+                "The workspace is kept in {}",
+                self.workspace.display()
+            ))
+            .into())
+    }
+}
+
+impl Display for SelfTestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, outcome) in &self.steps {
+            match outcome {
+                SelfTestOutcome::Passed => writeln!(f, "PASS {}", name)?,
+                SelfTestOutcome::Failed(reason) => writeln!(f, "FAIL {}: {}", name, reason)?,
+                SelfTestOutcome::Skipped(reason) => writeln!(f, "SKIP {} ({})", name, reason)?,
+            }
+        }
+        write!(
+            f,
+            "{}",
+            if self.passed() {
+                "The self test passed"
+            } else {
+                "The self test failed"
+            }
+        )
+    }
+}
+
+/// Check that the shared cairo exports the entry point of every enabled feature,
+/// renamed with the symbol prefix if there is one
+fn verify_exported_symbols(
+    cairo: &CairoLibrary,
+    context: &LibraryCompilationContext,
+) -> Result<(), Box<dyn Error>> {
+    let mut requirements = Requirements::new("the symbol check");
+    requirements.tool(if context.is_windows() {
+        Tool::LLVM_READOBJ
+    } else {
+        Tool::LLVM_NM
+    });
+    requirements.check()?;
+
+    let prefix = cairo.native_library_prefix(context);
+    let features_header = prefix
+        .join("include")
+        .join("cairo")
+        .join(CAIRO_FEATURES_HEADER);
+    let features = FeatureSummary::read(&features_header)?.enabled();
+
+    let binary = shared_libraries_in(&cairo.compiled_library_directories(context), context)
+        .into_iter()
+        .find(|binary| {
+            binary
+                .file_name()
+                .map(|name| name.to_string_lossy().contains("cairo"))
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| UserFacingError::new("Could not find the shared cairo library"))?;
+
+    // a prefixed build exports the renamed entry points
+    let symbol_prefix = cairo.symbol_prefix().map_or("", SymbolPrefix::prefix);
+    let symbols = exported_symbols(&binary, context)?;
+    let missing = features
+        .iter()
+        .map(|feature| format!("{}{}", symbol_prefix, feature.entry_point()))
+        .filter(|entry_point| !symbols.contains(entry_point))
+        .collect::<Vec<String>>();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(
+        UserFacingError::new(format!("{} does not export all features", binary.display()))
+            .reason(format!("Missing {}", missing.join(", ")))
+            .into(),
+    )
+}
+
+/// The functions a shared library exports, without the leading underscore of Mach-O
+fn exported_symbols(
+    binary: &Path,
+    context: &LibraryCompilationContext,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut command = if context.is_windows() {
+        let mut command = Command::new(Tool::LLVM_READOBJ.name);
+        command.arg("--coff-exports");
+        command
+    } else {
+        let mut command = Command::new(Tool::LLVM_NM.name);
+        command
+            .arg(if context.is_mac() {
+                "--extern-only"
+            } else {
+                "--dynamic"
+            })
+            .arg("--defined-only");
+        command
+    };
+    command.arg(binary);
+    println!("{:?}", &command);
+
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(UserFacingError::new(format!(
+            "Could not list the symbols of {}",
+            binary.display()
+        ))
+        .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        .into());
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            if context.is_windows() {
+                line.trim().strip_prefix("Name: ")
+            } else {
+                line.split_whitespace().last()
+            }
+        })
+        .map(|symbol| {
+            if context.is_mac() {
+                symbol.strip_prefix('_').unwrap_or(symbol).to_string()
+            } else {
+                symbol.to_string()
+            }
+        })
+        .collect())
+}
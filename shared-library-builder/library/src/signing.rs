@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// Signs macOS binaries with `codesign`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacSigning {
+    identity: String,
+    hardened_runtime: bool,
+    timestamp: bool,
+    entitlements: Option<PathBuf>,
+}
+
+impl Default for MacSigning {
+    fn default() -> Self {
+        Self::ad_hoc()
+    }
+}
+
+impl MacSigning {
+    /// Ad-hoc signature, enough for Apple Silicon to load the library
+    pub fn ad_hoc() -> Self {
+        Self {
+            identity: "-".to_string(),
+            hardened_runtime: false,
+            timestamp: false,
+            entitlements: None,
+        }
+    }
+
+    /// Sign with a certificate from the keychain, for example `Developer ID Application: ...`.
+    /// Enables the hardened runtime and a secure timestamp as required for notarization
+    pub fn identity(identity: impl Into<String>) -> Self {
+        Self {
+            identity: identity.into(),
+            hardened_runtime: true,
+            timestamp: true,
+            entitlements: None,
+        }
+    }
+
+    pub fn with_hardened_runtime(mut self, hardened_runtime: bool) -> Self {
+        self.hardened_runtime = hardened_runtime;
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: bool) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_entitlements(mut self, entitlements: impl Into<PathBuf>) -> Self {
+        self.entitlements = Some(entitlements.into());
+        self
+    }
+
+    pub fn sign(&self, binary: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let binary = binary.as_ref();
+
+        let mut command = Command::new("codesign");
+        command
+            .arg("--force")
+            .arg("--sign")
+            .arg(&self.identity);
+
+        if self.hardened_runtime {
+            command.arg("--options").arg("runtime");
+        }
+        if self.timestamp {
+            command.arg("--timestamp");
+        }
+        if let Some(ref entitlements) = self.entitlements {
+            command.arg("--entitlements").arg(entitlements);
+        }
+        command.arg(binary);
+
+        println!("{:?}", &command);
+
+        if !command.status()?.success() {
+            return Err(
+                UserFacingError::new(format!("Could not sign {}", binary.display()))
+                    .help("Make sure the signing identity is available in the keychain")
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The certificate used by `signtool`. signtool only takes the password of a `.pfx` file as
+/// an argument, which other processes can read, so protected keys are used from the
+/// certificate store or a cryptographic provider instead
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowsCertificate {
+    /// A `.pfx` file without a password
+    File(PathBuf),
+    /// A certificate from the Windows certificate store selected by subject name
+    Subject(String),
+    /// A certificate from the Windows certificate store selected by its SHA1 thumbprint,
+    /// for example a `.pfx` imported with `Import-PfxCertificate`
+    Thumbprint(String),
+    /// A public certificate whose private key is kept by a CSP or KSP, for example
+    /// a hardware token or a cloud key vault
+    KeyContainer {
+        certificate: PathBuf,
+        provider: String,
+        key_container: String,
+    },
+}
+
+/// Authenticode signing of the produced DLLs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowsSigning {
+    SignTool {
+        certificate: WindowsCertificate,
+        timestamp_server: Option<String>,
+    },
+    /// Any other signer, `{}` in the arguments is replaced by the path of the DLL,
+    /// otherwise the path is passed as the last argument
+    Command {
+        program: String,
+        arguments: Vec<String>,
+    },
+}
+
+impl WindowsSigning {
+    pub const DEFAULT_TIMESTAMP_SERVER: &'static str = "http://timestamp.digicert.com";
+
+    pub fn signtool(certificate: WindowsCertificate) -> Self {
+        Self::SignTool {
+            certificate,
+            timestamp_server: Some(Self::DEFAULT_TIMESTAMP_SERVER.to_string()),
+        }
+    }
+
+    pub fn command(program: impl Into<String>, arguments: Vec<String>) -> Self {
+        Self::Command {
+            program: program.into(),
+            arguments,
+        }
+    }
+
+    /// Whether the certificate is a file of this machine, which another machine can not read
+    pub fn uses_certificate_file(&self) -> bool {
+        matches!(
+            self,
+            Self::SignTool {
+                certificate: WindowsCertificate::File(_) | WindowsCertificate::KeyContainer { .. },
+                ..
+            }
+        )
+    }
+
+    pub fn sign(&self, binary: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let binary = binary.as_ref();
+
+        let mut command = match self {
+            Self::SignTool {
+                certificate,
+                timestamp_server,
+            } => {
+                let mut command = Command::new("signtool");
+                command.arg("sign").arg("/fd").arg("SHA256");
+                match certificate {
+                    WindowsCertificate::File(path) => {
+                        command.arg("/f").arg(path);
+                    }
+                    WindowsCertificate::Subject(subject) => {
+                        command.arg("/n").arg(subject);
+                    }
+                    WindowsCertificate::Thumbprint(thumbprint) => {
+                        command.arg("/sha1").arg(thumbprint);
+                    }
+                    WindowsCertificate::KeyContainer {
+                        certificate,
+                        provider,
+                        key_container,
+                    } => {
+                        command
+                            .arg("/f")
+                            .arg(certificate)
+                            .arg("/csp")
+                            .arg(provider)
+                            .arg("/kc")
+                            .arg(key_container);
+                    }
+                }
+                if let Some(server) = timestamp_server {
+                    command.arg("/tr").arg(server).arg("/td").arg("SHA256");
+                }
+                command.arg(binary);
+                command
+            }
+            Self::Command { program, arguments } => {
+                let mut command = Command::new(program);
+                let path = binary.display().to_string();
+                if arguments.iter().any(|argument| argument.contains("{}")) {
+                    command.args(arguments.iter().map(|argument| argument.replace("{}", &path)));
+                } else {
+                    command.args(arguments).arg(binary);
+                }
+                command
+            }
+        };
+
+        if !command.status()?.success() {
+            return Err(
+                UserFacingError::new(format!("Could not sign {}", binary.display())).into(),
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use crate::requirements::Tool;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// How many of the largest symbols are kept per binary
+const LARGEST_SYMBOLS: usize = 25;
+
+/// The size of the produced binaries, per section and for the largest symbols,
+/// to see what each enabled backend costs and to catch growth between releases
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeReport {
+    pub binaries: Vec<BinarySize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinarySize {
+    /// The file name of the binary
+    pub name: String,
+    /// The size of the file in bytes
+    pub size: u64,
+    pub sections: Vec<NamedSize>,
+    /// The largest defined symbols, only known for formats that record symbol sizes such as ELF
+    pub largest_symbols: Vec<NamedSize>,
+}
+
+/// A section or symbol with its size in bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedSize {
+    pub name: String,
+    pub size: u64,
+}
+
+impl SizeReport {
+    /// Measure the binaries with `llvm-size` and `llvm-nm`, which read ELF, Mach-O and PE alike
+    pub fn for_binaries(binaries: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        let mut report = Self::default();
+        for binary in binaries {
+            report.binaries.push(BinarySize::measure(binary)?);
+        }
+        Ok(report)
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.binaries.iter().map(|binary| binary.size).sum()
+    }
+
+    /// The binaries and sections that grew by more than the given percentage since
+    /// the previous report, for example the one of the last release
+    pub fn growth(&self, previous: &SizeReport, tolerance_percent: u64) -> Vec<String> {
+        let grew = |before: u64, after: u64| after * 100 > before * (100 + tolerance_percent);
+
+        let mut growth = vec![];
+        for binary in &self.binaries {
+            let before = match previous.binaries.iter().find(|each| each.name == binary.name) {
+                Some(before) => before,
+                None => continue,
+            };
+            if grew(before.size, binary.size) {
+                growth.push(format!(
+                    "{} grew from {} to {} bytes",
+                    &binary.name, before.size, binary.size
+                ));
+            }
+            for section in &binary.sections {
+                if let Some(previous_section) = before
+                    .sections
+                    .iter()
+                    .find(|each| each.name == section.name)
+                {
+                    if grew(previous_section.size, section.size) {
+                        growth.push(format!(
+                            "{} section {} grew from {} to {} bytes",
+                            &binary.name, &section.name, previous_section.size, section.size
+                        ));
+                    }
+                }
+            }
+        }
+        growth
+    }
+}
+
+impl BinarySize {
+    pub fn measure(binary: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = binary
+            .file_name()
+            .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+            .to_string_lossy()
+            .to_string();
+
+        let mut command = Command::new(Tool::LLVM_SIZE.name);
+        command.arg("-A").arg(binary);
+        let sections = parse_sections(&run(command)?);
+
+        let mut largest_symbols = parse_symbols(&run(symbols_command(binary, false))?);
+        // stripped shared objects only keep their exported symbols
+        if largest_symbols.is_empty() {
+            if let Ok(output) = run(symbols_command(binary, true)) {
+                largest_symbols = parse_symbols(&output);
+            }
+        }
+
+        Ok(Self {
+            name,
+            size: std::fs::metadata(binary)?.len(),
+            sections,
+            largest_symbols,
+        })
+    }
+}
+
+fn symbols_command(binary: &Path, dynamic: bool) -> Command {
+    let mut command = Command::new(Tool::LLVM_NM.name);
+    if dynamic {
+        command.arg("--dynamic");
+    }
+    command
+        .arg("--print-size")
+        .arg("--size-sort")
+        .arg("--reverse-sort")
+        .arg("--defined-only")
+        .arg(binary);
+    command
+}
+
+fn run(mut command: Command) -> Result<String, Box<dyn Error>> {
+    println!("{:?}", &command);
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(UserFacingError::new("Could not measure the size of the binaries")
+            .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+            .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Sum the `section size address` rows of `llvm-size -A`, archives list every member separately
+fn parse_sections(output: &str) -> Vec<NamedSize> {
+    let mut sections = BTreeMap::<String, u64>::new();
+    for line in output.lines() {
+        let columns = line.split_whitespace().collect::<Vec<&str>>();
+        if let [name, size, address] = columns.as_slice() {
+            if *name == "Total" || address.parse::<u64>().is_err() {
+                continue;
+            }
+            if let Ok(size) = size.parse::<u64>() {
+                *sections.entry(name.to_string()).or_default() += size;
+            }
+        }
+    }
+    let mut sections = sections
+        .into_iter()
+        .map(|(name, size)| NamedSize { name, size })
+        .collect::<Vec<NamedSize>>();
+    sections.sort_by_key(|section| std::cmp::Reverse(section.size));
+    sections
+}
+
+/// The `value size type name` rows of `llvm-nm --print-size`, already sorted by size
+fn parse_symbols(output: &str) -> Vec<NamedSize> {
+    let mut symbols = output
+        .lines()
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [_, size, _, name] => u64::from_str_radix(size, 16).ok().map(|size| NamedSize {
+                name: name.to_string(),
+                size,
+            }),
+            _ => None,
+        })
+        .collect::<Vec<NamedSize>>();
+    // archives are sorted per member
+    symbols.sort_by_key(|symbol| std::cmp::Reverse(symbol.size));
+    symbols.truncate(LARGEST_SYMBOLS);
+    symbols
+}
@@ -0,0 +1,236 @@
+use crate::features::CairoFeature;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// A tiny C program exercising one feature against the installed headers and libraries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmokeTest {
+    pub feature: CairoFeature,
+    /// The pkg-config module that provides the feature's compiler and linker flags
+    pub pkg_config_module: &'static str,
+    /// The import libraries to link against when compiling with MSVC
+    pub msvc_libraries: &'static [&'static str],
+    pub source: &'static str,
+}
+
+impl SmokeTest {
+    pub fn for_feature(feature: CairoFeature) -> Self {
+        let (pkg_config_module, msvc_libraries, source): (_, &'static [&'static str], _) =
+            match feature {
+                CairoFeature::Image => ("cairo", &["cairo.lib"], IMAGE),
+                CairoFeature::Png => ("cairo-png", &["cairo.lib"], PNG),
+                CairoFeature::FreeType => ("cairo-ft", &["cairo.lib", "freetype.lib"], FREETYPE),
+                CairoFeature::Fontconfig => ("cairo-fc", &["cairo.lib", "fontconfig.lib"], FONTCONFIG),
+                CairoFeature::Pdf => ("cairo-pdf", &["cairo.lib"], PDF),
+                CairoFeature::Quartz => ("cairo-quartz", &["cairo.lib"], QUARTZ),
+                CairoFeature::Win32 => ("cairo-win32", &["cairo.lib", "user32.lib", "gdi32.lib"], WIN32),
+                CairoFeature::Xlib => ("cairo-xlib", &[], XLIB),
+                CairoFeature::XlibXrender => ("cairo-xlib-xrender", &[], XLIB_XRENDER),
+            };
+        Self {
+            feature,
+            pkg_config_module,
+            msvc_libraries,
+            source,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("smoke-{:?}", self.feature).to_lowercase()
+    }
+
+    /// Compile with the flags reported by pkg-config and run with the given library folders
+    pub fn run_with_pkg_config(
+        &self,
+        directory: &Path,
+        pkg_config_paths: &[PathBuf],
+        library_directories: &[PathBuf],
+        library_path_variable: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let source = self.write_source(directory)?;
+        let executable = directory.join(self.name());
+        let pkg_config_path = std::env::join_paths(pkg_config_paths)?;
+
+        let output = Command::new("pkg-config")
+            .env("PKG_CONFIG_PATH", &pkg_config_path)
+            .arg("--cflags")
+            .arg("--libs")
+            .arg(self.pkg_config_module)
+            .output()?;
+        if !output.status.success() {
+            return Err(self
+                .error("pkg-config failed")
+                .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+                .into());
+        }
+        let flags = String::from_utf8_lossy(&output.stdout).to_string();
+
+        let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+        let mut command = Command::new(compiler);
+        command
+            .arg(&source)
+            .arg("-o")
+            .arg(&executable)
+            .args(flags.split_whitespace());
+
+        self.compile_and_run(command, &executable, library_directories, library_path_variable)
+    }
+
+    /// Compile with `cl` or `clang-cl` against explicit include folders, import libraries are looked up
+    /// in the library folders which are also where the DLLs are loaded from
+    pub fn run_with_msvc(
+        &self,
+        compiler: &str,
+        directory: &Path,
+        include_directories: &[PathBuf],
+        library_directories: &[PathBuf],
+    ) -> Result<(), Box<dyn Error>> {
+        let source = self.write_source(directory)?;
+        let executable = directory.join(format!("{}.exe", self.name()));
+
+        let mut command = Command::new(compiler);
+        command.current_dir(directory).arg("/nologo").arg(&source);
+        for include in include_directories {
+            command.arg(format!("/I{}", include.display()));
+        }
+        command
+            .arg(format!("/Fe{}", executable.display()))
+            .arg("/link")
+            .args(self.msvc_libraries);
+        for library in library_directories {
+            command.arg(format!("/LIBPATH:{}", library.display()));
+        }
+
+        self.compile_and_run(command, &executable, library_directories, "PATH")
+    }
+
+    fn write_source(&self, directory: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        std::fs::create_dir_all(directory)?;
+        let source = directory.join(format!("{}.c", self.name()));
+        std::fs::write(&source, self.source)?;
+        Ok(source)
+    }
+
+    fn compile_and_run(
+        &self,
+        mut compile: Command,
+        executable: &Path,
+        library_directories: &[PathBuf],
+        library_path_variable: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        println!("{:?}", &compile);
+        if !compile.status()?.success() {
+            return Err(self.error("the program does not compile or link").into());
+        }
+
+        let mut library_path = library_directories.to_vec();
+        if let Ok(ref path) = std::env::var(library_path_variable) {
+            std::env::split_paths(path).for_each(|path| library_path.push(path));
+        }
+
+        let mut run = Command::new(executable);
+        run.current_dir(executable.parent().unwrap_or_else(|| Path::new(".")))
+            .env(library_path_variable, std::env::join_paths(&library_path)?);
+        println!("{:?}", &run);
+
+        if !run.status()?.success() {
+            return Err(self.error("the program failed").into());
+        }
+        Ok(())
+    }
+
+    fn error(&self, reason: &str) -> UserFacingError {
+        UserFacingError::new(format!("{:?} smoke test failed", self.feature)).reason(reason)
+    }
+}
+
+const IMAGE: &str = r#"#include <cairo.h>
+int main(void) {
+    cairo_surface_t *surface = cairo_image_surface_create(CAIRO_FORMAT_ARGB32, 16, 16);
+    cairo_t *cr = cairo_create(surface);
+    cairo_set_source_rgb(cr, 1, 0, 0);
+    cairo_paint(cr);
+    int failed = cairo_status(cr) != CAIRO_STATUS_SUCCESS;
+    cairo_destroy(cr);
+    cairo_surface_destroy(surface);
+    return failed;
+}
+"#;
+
+const PNG: &str = r#"#include <cairo.h>
+int main(void) {
+    cairo_surface_t *surface = cairo_image_surface_create(CAIRO_FORMAT_ARGB32, 16, 16);
+    int failed = cairo_surface_write_to_png(surface, "smoke-png.png") != CAIRO_STATUS_SUCCESS;
+    cairo_surface_destroy(surface);
+    return failed;
+}
+"#;
+
+const FREETYPE: &str = r#"#include <cairo-ft.h>
+int main(void) {
+    FT_Library library;
+    if (FT_Init_FreeType(&library)) return 1;
+    cairo_font_options_t *options = cairo_font_options_create();
+    int failed = cairo_font_options_status(options) != CAIRO_STATUS_SUCCESS;
+    cairo_font_options_destroy(options);
+    FT_Done_FreeType(library);
+    return failed || cairo_ft_font_face_create_for_ft_face == 0;
+}
+"#;
+
+const FONTCONFIG: &str = r#"#include <cairo-ft.h>
+int main(void) {
+    FcPattern *pattern = FcNameParse((const FcChar8 *) "sans");
+    cairo_font_face_t *face = cairo_ft_font_face_create_for_pattern(pattern);
+    int failed = cairo_font_face_status(face) != CAIRO_STATUS_SUCCESS;
+    cairo_font_face_destroy(face);
+    FcPatternDestroy(pattern);
+    return failed;
+}
+"#;
+
+const PDF: &str = r#"#include <cairo-pdf.h>
+int main(void) {
+    cairo_surface_t *surface = cairo_pdf_surface_create("smoke-pdf.pdf", 16, 16);
+    cairo_t *cr = cairo_create(surface);
+    cairo_paint(cr);
+    cairo_destroy(cr);
+    cairo_surface_finish(surface);
+    int failed = cairo_surface_status(surface) != CAIRO_STATUS_SUCCESS;
+    cairo_surface_destroy(surface);
+    return failed;
+}
+"#;
+
+const QUARTZ: &str = r#"#include <cairo-quartz.h>
+int main(void) {
+    cairo_surface_t *surface = cairo_quartz_surface_create(CAIRO_FORMAT_ARGB32, 16, 16);
+    int failed = cairo_surface_status(surface) != CAIRO_STATUS_SUCCESS;
+    cairo_surface_destroy(surface);
+    return failed;
+}
+"#;
+
+const WIN32: &str = r#"#include <cairo-win32.h>
+int main(void) {
+    cairo_surface_t *surface = cairo_win32_surface_create_with_dib(CAIRO_FORMAT_ARGB32, 16, 16);
+    int failed = cairo_surface_status(surface) != CAIRO_STATUS_SUCCESS;
+    cairo_surface_destroy(surface);
+    return failed;
+}
+"#;
+
+const XLIB: &str = r#"#include <cairo-xlib.h>
+int main(void) {
+    /* there may be no X server on the build machine, only check that everything links */
+    return cairo_xlib_surface_create == 0;
+}
+"#;
+
+const XLIB_XRENDER: &str = r#"#include <cairo-xlib-xrender.h>
+int main(void) {
+    return cairo_xlib_surface_create_with_xrender_format == 0;
+}
+"#;
@@ -0,0 +1,188 @@
+use crate::http::HttpClient;
+use crate::network::NetworkSettings;
+use crate::prebuilt::unpack;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{LibraryLocation, TarArchive, TarUrlLocation};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// The compression of a source archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    TarXz,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format of the archive the url points to, judging by its extension
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+        if path.ends_with(".tar.xz") || path.ends_with(".txz") {
+            Some(Self::TarXz)
+        } else if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if path.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Self::TarXz => &[".tar.xz", ".txz"],
+            Self::TarGz => &[".tar.gz", ".tgz"],
+            Self::Zip => &[".zip"],
+        }
+    }
+}
+
+/// A source archive on a mirror or an artifact store, as `.tar.xz`, `.tar.gz` or `.zip`.
+/// The archive must contain a single folder with the sources, by default named like
+/// the archive without its extension
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceArchive {
+    url: String,
+    format: ArchiveFormat,
+    sources: PathBuf,
+}
+
+impl SourceArchive {
+    /// The archive at the url, its format is inferred from the extension
+    pub fn new(url: impl Into<String>) -> Result<Self, Box<dyn Error>> {
+        let url = url.into();
+        let format = ArchiveFormat::from_url(&url).ok_or_else(|| {
+            UserFacingError::new(format!("Unknown format of the source archive {}", &url))
+                .reason("Only .tar.xz, .tar.gz and .zip archives are supported")
+                .help("Use SourceArchive::with_format to set the format explicitly")
+        })?;
+        Ok(Self::with_format(url, format))
+    }
+
+    /// The archive at the url in the given format, for urls without a telling extension
+    pub fn with_format(url: impl Into<String>, format: ArchiveFormat) -> Self {
+        let url = url.into();
+        let file_name = url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(&url)
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let sources = format
+            .extensions()
+            .iter()
+            .find_map(|extension| {
+                file_name
+                    .to_lowercase()
+                    .ends_with(extension)
+                    .then(|| file_name[..file_name.len() - extension.len()].to_string())
+            })
+            .unwrap_or(file_name);
+        Self {
+            url,
+            format,
+            sources: PathBuf::from(sources),
+        }
+    }
+
+    /// The name of the folder the archive extracts to
+    pub fn with_sources(mut self, sources: impl Into<PathBuf>) -> Self {
+        self.sources = sources.into();
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+
+    /// The archive of a tar location, so that we download it ourselves with the network
+    /// settings of the build instead of shared-library-builder. `None` for other locations
+    pub(crate) fn of_location(location: &LibraryLocation) -> Option<Self> {
+        let location = match location {
+            LibraryLocation::Tar(location) => serde_json::to_value(location).ok()?,
+            _ => return None,
+        };
+        let url = location["url"].as_str()?;
+        let format = ArchiveFormat::from_url(url).or(match location["archive"].as_str() {
+            Some("Xz") => Some(ArchiveFormat::TarXz),
+            Some("Gz") => Some(ArchiveFormat::TarGz),
+            _ => None,
+        })?;
+        let archive = Self::with_format(url, format);
+        Some(match location["sources"].as_str() {
+            Some(sources) => archive.with_sources(sources),
+            None => archive,
+        })
+    }
+
+    /// The location the sources are described by, the archive itself is downloaded
+    /// with the network settings of the build
+    pub fn location(&self) -> LibraryLocation {
+        let archive = match self.format {
+            ArchiveFormat::TarXz => TarArchive::Xz,
+            ArchiveFormat::TarGz | ArchiveFormat::Zip => TarArchive::Gz,
+        };
+        LibraryLocation::Tar(
+            TarUrlLocation::new(self.url.clone())
+                .archive(archive)
+                .sources(&self.sources),
+        )
+    }
+
+    /// Download and extract the archive into the source directory unless it exists
+    pub(crate) fn ensure_unpacked(
+        &self,
+        source_directory: &Path,
+        network: &NetworkSettings,
+    ) -> Result<(), Box<dyn Error>> {
+        if source_directory.exists() {
+            return Ok(());
+        }
+        let parent = source_directory
+            .parent()
+            .ok_or_else(|| UserFacingError::new("The source directory has no parent"))?;
+        std::fs::create_dir_all(parent)?;
+
+        // the folder names contain dots, such as cairo-1.16.0
+        let archive = PathBuf::from(format!(
+            "{}{}",
+            source_directory.display(),
+            self.format.extensions()[0]
+        ));
+        let unpacked = PathBuf::from(format!("{}.unpacked", source_directory.display()));
+        if unpacked.exists() {
+            std::fs::remove_dir_all(&unpacked)?;
+        }
+        std::fs::create_dir_all(&unpacked)?;
+
+        HttpClient::new()
+            .with_network(network.clone())
+            .download(&self.url, &archive)?;
+        unpack(&archive, &unpacked)?;
+
+        let extracted = unpacked.join(&self.sources);
+        if !extracted.is_dir() {
+            return Err(
+                UserFacingError::new(format!("Could not unpack {}", &self.url))
+                    .reason(format!(
+                        "The archive does not contain the folder {}",
+                        self.sources.display()
+                    ))
+                    .help("Set the folder the archive extracts to with SourceArchive::with_sources")
+                    .into(),
+            );
+        }
+        std::fs::rename(&extracted, source_directory)?;
+        std::fs::remove_dir_all(&unpacked)?;
+        std::fs::remove_file(&archive)?;
+        Ok(())
+    }
+}
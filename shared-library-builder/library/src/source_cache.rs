@@ -0,0 +1,281 @@
+use crate::checksums::sha256;
+use crate::hash::short_hash;
+use crate::source_lock::{sources_hash, SourceOrigin};
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Extracted source trees shared between build roots, so that a clean build copies the
+/// sources of cairo, pixman and freetype instead of downloading and extracting them again.
+/// Trees are keyed by the checksum of the sources as they were fetched, see [`sources_hash`],
+/// and trees we patch are also kept patched, keyed by that checksum and the patches.
+/// The checksum of the sources last fetched from a location is remembered, except for git
+/// branches which are fetched again by every clean build.
+/// On Linux the trees are copied as reflinks where the file system supports them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceCache {
+    directory: PathBuf,
+}
+
+/// Sources copied from the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedSources {
+    /// The checksum of the sources as they were fetched
+    pub sha256: String,
+    /// Whether the sources were copied with our patches applied
+    pub patched: bool,
+}
+
+impl SourceCache {
+    pub const VARIABLE: &'static str = "LIBCAIRO_SOURCE_CACHE";
+
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// The cache in the folder set in `LIBCAIRO_SOURCE_CACHE`, if any
+    pub fn from_environment() -> Option<Self> {
+        std::env::var_os(Self::VARIABLE)
+            .filter(|directory| !directory.is_empty())
+            .map(Self::new)
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The sources of the library, as fetched or with the given patches applied
+    fn entry(&self, library: &dyn Library, sources: &str, patches: Option<&str>) -> PathBuf {
+        let name = match patches {
+            Some(patches) => format!("{}-{}", sources, &sha256(patches.as_bytes())[..16]),
+            None => sources.to_string(),
+        };
+        self.directory.join(library.name()).join(name)
+    }
+
+    /// The file with the checksum of the sources last fetched from the location of the
+    /// library, `None` for local sources and git branches
+    fn index(&self, library: &dyn Library) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let origin = SourceOrigin::of(library)?;
+        if !origin.is_fetched() || origin.is_branch() {
+            return Ok(None);
+        }
+        Ok(Some(self.directory.join(library.name()).join(format!(
+            "{}.sha256",
+            short_hash(serde_json::to_string(&origin)?)
+        ))))
+    }
+
+    /// The file with the checksum of a patched entry, taken when it was stored
+    fn patched_checksum(entry: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.sha256", entry.display()))
+    }
+
+    /// Copy the cached sources of the library into its source directory, patched with
+    /// the given patches if the cache has them so. With `verify` a copy is only kept if
+    /// it has the checksum recorded for it, as builds with a source lock must not trust
+    /// a cache that was changed
+    pub fn restore(
+        &self,
+        library: &dyn Library,
+        patches: Option<&str>,
+        verify: bool,
+        context: &LibraryCompilationContext,
+    ) -> Result<Option<CachedSources>, Box<dyn Error>> {
+        let source_directory = library.source_directory(context);
+        if source_directory.exists() {
+            return Ok(None);
+        }
+        let index = match self.index(library)? {
+            Some(index) if index.is_file() => index,
+            _ => return Ok(None),
+        };
+        let sha256 = std::fs::read_to_string(index)?.trim().to_string();
+
+        let patched = patches.map(|patches| (self.entry(library, &sha256, Some(patches)), true));
+        let fetched = (self.entry(library, &sha256, None), false);
+        for (entry, patched) in patched.into_iter().chain([fetched]) {
+            if !entry.is_dir() {
+                continue;
+            }
+            let expected = match (verify, patched) {
+                (false, _) => None,
+                (true, false) => Some(sha256.clone()),
+                (true, true) => match std::fs::read_to_string(Self::patched_checksum(&entry)) {
+                    Ok(checksum) => Some(checksum.trim().to_string()),
+                    Err(_) => continue,
+                },
+            };
+            println!(
+                "Copying the cached sources of {} from {}",
+                library.name(),
+                entry.display()
+            );
+            copy_atomically(&entry, &source_directory)?;
+            if let Some(expected) = expected {
+                let actual = sources_hash(&source_directory)?;
+                if actual != expected {
+                    std::fs::remove_dir_all(&source_directory)?;
+                    println!(
+                        "Warning: the cached sources in {} have the checksum {}, {} was recorded",
+                        entry.display(),
+                        actual,
+                        expected
+                    );
+                    continue;
+                }
+            }
+            return Ok(Some(CachedSources { sha256, patched }));
+        }
+        Ok(None)
+    }
+
+    /// Keep the freshly fetched sources of the library, unless they are cached already.
+    /// Returns their checksum, `None` for local sources
+    pub fn store(
+        &self,
+        library: &dyn Library,
+        context: &LibraryCompilationContext,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        if !SourceOrigin::of(library)?.is_fetched() {
+            return Ok(None);
+        }
+        let source_directory = library.source_directory(context);
+        let sha256 = sources_hash(&source_directory)?;
+        let entry = self.entry(library, &sha256, None);
+        if !entry.exists() {
+            copy_atomically(&source_directory, &entry)?;
+        }
+        if let Some(index) = self.index(library)? {
+            write_atomically(&index, &sha256)?;
+        }
+        Ok(Some(sha256))
+    }
+
+    /// Keep the sources of the library after applying the given patches to the sources
+    /// with the checksum
+    pub fn store_patched(
+        &self,
+        library: &dyn Library,
+        sha256: &str,
+        patches: &str,
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let entry = self.entry(library, sha256, Some(patches));
+        if entry.exists() {
+            return Ok(());
+        }
+        let source_directory = library.source_directory(context);
+        copy_atomically(&source_directory, &entry)?;
+        write_atomically(
+            &Self::patched_checksum(&entry),
+            &sources_hash(&source_directory)?,
+        )
+    }
+}
+
+/// A sibling of the path that no other process or thread writes to
+fn partial(path: &Path) -> PathBuf {
+    static PARTIALS: AtomicUsize = AtomicUsize::new(0);
+    PathBuf::from(format!(
+        "{}.partial-{}-{}",
+        path.display(),
+        std::process::id(),
+        PARTIALS.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+/// Move a finished copy into place. When another build stored the same entry first,
+/// the copy is dropped and theirs is kept
+fn finish(partial: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if let Err(error) = std::fs::rename(partial, to) {
+        let _ = if partial.is_dir() {
+            std::fs::remove_dir_all(partial)
+        } else {
+            std::fs::remove_file(partial)
+        };
+        if !to.exists() {
+            return Err(error.into());
+        }
+    }
+    Ok(())
+}
+
+/// Copy into a sibling first, so that an interrupted copy never looks complete
+fn copy_atomically(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    let partial = partial(to);
+    if let Some(parent) = partial.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Err(error) = copy_tree(from, &partial) {
+        let _ = std::fs::remove_dir_all(&partial);
+        return Err(error);
+    }
+    finish(&partial, to)
+}
+
+fn write_atomically(to: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    let partial = partial(to);
+    if let Some(parent) = partial.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&partial, contents)?;
+    if to.exists() {
+        std::fs::remove_file(to)?;
+    }
+    finish(&partial, to)
+}
+
+pub(crate) fn copy_tree(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if cfg!(target_os = "linux") {
+        let mut command = Command::new("cp");
+        command.arg("-a").arg("--reflink=auto").arg(from).arg(to);
+        println!("{:?}", &command);
+        if command
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+        {
+            return Ok(());
+        }
+        if to.exists() {
+            std::fs::remove_dir_all(to)?;
+        }
+    }
+
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        let destination = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_tree(&source, &destination)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&source, &destination)?;
+        } else {
+            std::fs::copy(&source, &destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, destination: &Path) -> Result<(), Box<dyn Error>> {
+    std::os::unix::fs::symlink(std::fs::read_link(source)?, destination)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(source: &Path, destination: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::copy(source, destination).map_err(|error| {
+        user_error::UserFacingError::new(format!("Could not copy {}", source.display()))
+            .reason(error.to_string())
+    })?;
+    Ok(())
+}
@@ -0,0 +1,318 @@
+use crate::checksums::{sha256, sha256_file};
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// The conventional name of the lock file
+pub const SOURCE_LOCK_FILE: &str = "libcairo.lock";
+
+/// The exact sources of cairo and of every library it is built with, so that a build
+/// with a newer release of this crate can not silently pick up other sources.
+/// Written by [`crate::CairoLibrary::lock_sources`], checked by
+/// [`crate::CairoLibrary::ensure_all_sources`] when set with [`crate::CairoLibrary::with_source_lock`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLock {
+    pub sources: Vec<LockedSource>,
+}
+
+/// The locked sources of a library and where they come from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedSource {
+    pub library: String,
+    pub origin: SourceOrigin,
+    /// The checksum of the fetched source tree, see [`sources_hash`]
+    pub sha256: String,
+}
+
+/// Where the sources of a library come from: the kind of its location (`Tar`, `Git`, `Path`),
+/// the urls of the archives or repositories and the git tag, branch or commit
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SourceOrigin {
+    pub kind: String,
+    pub urls: Vec<String>,
+    pub reference: Option<String>,
+}
+
+impl SourceOrigin {
+    pub fn of(library: &dyn Library) -> Result<Self, Box<dyn Error>> {
+        let location = serde_json::to_value(library.location())?;
+        let kind = match &location {
+            serde_json::Value::Object(variant) => variant.keys().next().cloned(),
+            serde_json::Value::String(variant) => Some(variant.clone()),
+            _ => None,
+        }
+        .unwrap_or_default();
+
+        let mut urls = vec![];
+        let mut references = vec![];
+        collect_origin(&location, &mut urls, &mut references);
+        if urls.is_empty() {
+            return Err(UserFacingError::new(format!(
+                "Could not tell where the sources of {} come from",
+                library.name()
+            ))
+            .reason(format!("Its location has no url: {}", location))
+            .into());
+        }
+        Ok(Self {
+            kind,
+            urls,
+            reference: if references.is_empty() {
+                None
+            } else {
+                Some(references.join(" "))
+            },
+        })
+    }
+
+    /// Sources that were downloaded into the build root, rather than a folder of the user
+    pub fn is_fetched(&self) -> bool {
+        self.kind != "Path"
+    }
+
+    /// A git location without a tag or commit follows a branch, so its sources change
+    /// while the location stays the same
+    pub fn is_branch(&self) -> bool {
+        self.kind == "Git"
+            && !self.reference.as_ref().is_some_and(|reference| {
+                reference.contains("tag:") || reference.contains("commit:")
+            })
+    }
+}
+
+impl std::fmt::Display for SourceOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.urls.join(", "))?;
+        if let Some(ref reference) = self.reference {
+            write!(f, " at {}", reference)?;
+        }
+        Ok(())
+    }
+}
+
+const URL_KEYS: [&str; 3] = ["url", "repository", "path"];
+const REFERENCE_KEYS: [&str; 3] = ["tag", "branch", "commit"];
+
+fn collect_origin(value: &serde_json::Value, urls: &mut Vec<String>, references: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields {
+                let key = key.to_lowercase();
+                match field {
+                    serde_json::Value::String(text) if URL_KEYS.contains(&key.as_str()) => {
+                        urls.push(text.clone())
+                    }
+                    serde_json::Value::String(text) if REFERENCE_KEYS.contains(&key.as_str()) => {
+                        references.push(format!("{}:{}", key, text))
+                    }
+                    _ => collect_origin(field, urls, references),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_origin(item, urls, references);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl SourceLock {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|error| {
+            UserFacingError::new(format!("Could not read the lock file {}", path.display()))
+                .reason(error.to_string())
+                .help("Create it with CairoLibrary::lock_sources")
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Hash the freshly fetched sources of the libraries
+    pub fn for_libraries(
+        libraries: &[&dyn Library],
+        context: &LibraryCompilationContext,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut sources = vec![];
+        for library in libraries {
+            sources.push(LockedSource {
+                library: library.name().to_string(),
+                origin: SourceOrigin::of(*library)?,
+                sha256: sources_hash(&library.source_directory(context))?,
+            });
+        }
+        sources.sort_by(|a, b| (&a.library, &a.origin).cmp(&(&b.library, &b.origin)));
+        Ok(Self { sources })
+    }
+
+    /// Check that exactly the locked libraries are fetched, from the locked locations
+    pub fn verify_locations(&self, libraries: &[&dyn Library]) -> Result<(), Box<dyn Error>> {
+        let mut mismatches = vec![];
+        for library in libraries {
+            let origin = SourceOrigin::of(*library)?;
+            if self.locked(library.name(), &origin).is_some() {
+                continue;
+            }
+            match self
+                .sources
+                .iter()
+                .find(|source| source.library == library.name())
+            {
+                Some(locked) => mismatches.push(format!(
+                    "{} is locked to {}, but fetched from {}",
+                    library.name(),
+                    locked.origin,
+                    origin
+                )),
+                None => mismatches.push(format!("{} is not locked", library.name())),
+            }
+        }
+        for locked in &self.sources {
+            if !libraries
+                .iter()
+                .any(|library| library.name() == locked.library)
+            {
+                mismatches.push(format!("{} is locked, but no longer built", locked.library));
+            }
+        }
+        lock_mismatch(mismatches)
+    }
+
+    /// Check the sources of libraries that were just fetched. Downloaded sources that do not
+    /// match are removed, so that the next build fetches them again instead of using them
+    pub fn verify_sources(
+        &self,
+        libraries: &[&dyn Library],
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut mismatches = vec![];
+        for library in libraries {
+            let sha256 = sources_hash(&library.source_directory(context))?;
+            self.verify_checksum(*library, &sha256, context, &mut mismatches)?;
+        }
+        lock_mismatch(mismatches)
+    }
+
+    /// Check sources copied from a [`crate::SourceCache`] already patched by the checksum
+    /// the cache recorded for them when they were fetched
+    pub fn verify_cached_sources(
+        &self,
+        libraries: &[(&dyn Library, &str)],
+        context: &LibraryCompilationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut mismatches = vec![];
+        for (library, sha256) in libraries {
+            self.verify_checksum(*library, sha256, context, &mut mismatches)?;
+        }
+        lock_mismatch(mismatches)
+    }
+
+    fn verify_checksum(
+        &self,
+        library: &dyn Library,
+        sha256: &str,
+        context: &LibraryCompilationContext,
+        mismatches: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let origin = SourceOrigin::of(library)?;
+        let locked = match self.locked(library.name(), &origin) {
+            Some(locked) => locked,
+            None => return Ok(()),
+        };
+        if sha256 == locked.sha256 {
+            return Ok(());
+        }
+        let directory = library.source_directory(context);
+        mismatches.push(format!(
+            "The sources of {} in {} have the checksum {}, {} is locked",
+            library.name(),
+            directory.display(),
+            sha256,
+            locked.sha256
+        ));
+        if origin.is_fetched() {
+            std::fs::remove_dir_all(&directory)?;
+            mismatches.push(format!("Removed {}", directory.display()));
+        }
+        Ok(())
+    }
+
+    pub fn locked(&self, library: &str, origin: &SourceOrigin) -> Option<&LockedSource> {
+        self.sources
+            .iter()
+            .find(|source| source.library == library && &source.origin == origin)
+    }
+}
+
+fn lock_mismatch(mismatches: Vec<String>) -> Result<(), Box<dyn Error>> {
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    let mut error = UserFacingError::new("The sources do not match the lock file");
+    for mismatch in mismatches {
+        error = error.reason(mismatch);
+    }
+    Err(error
+        .help("Regenerate the lock file with CairoLibrary::lock_sources if the change is intended")
+        .into())
+}
+
+/// A checksum of every file in the source tree by its relative path. Patched files count with
+/// the original content kept in their `.bak`, the `.git` folder of a checkout is left out
+pub fn sources_hash(directory: &Path) -> Result<String, Box<dyn Error>> {
+    fn collect(
+        directory: &Path,
+        relative: &Path,
+        entries: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut children = std::fs::read_dir(directory)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, std::io::Error>>()?;
+        children.sort();
+
+        for child in children {
+            let name = child
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            if name == ".git" || name.ends_with(".bak") || name.ends_with(".fixed") {
+                continue;
+            }
+            let relative = relative.join(&name);
+            let key = relative.to_string_lossy().replace('\\', "/");
+            let metadata = std::fs::symlink_metadata(&child)?;
+            if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(&child)?;
+                entries.push(format!("{} -> {}", key, target.to_string_lossy()));
+            } else if metadata.is_dir() {
+                collect(&child, &relative, entries)?;
+            } else {
+                let original = directory.join(format!("{}.bak", name));
+                let file = if original.is_file() { original } else { child };
+                entries.push(format!("{} {}", key, sha256_file(&file)?));
+            }
+        }
+        Ok(())
+    }
+
+    if !directory.is_dir() {
+        return Err(UserFacingError::new(format!(
+            "Could not hash the sources in {}",
+            directory.display()
+        ))
+        .reason("The folder does not exist")
+        .into());
+    }
+    let mut entries = vec![];
+    collect(directory, Path::new(""), &mut entries)?;
+    Ok(sha256(entries.join("\n").as_bytes()))
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The linker flags that link the GCC runtime into the shared objects instead of depending
+/// on the `libgcc_s` and `libstdc++` of the machine they run on
+pub const STATIC_RUNTIME_FLAGS: [&str; 2] = ["-static-libgcc", "-static-libstdc++"];
+
+/// The toolchain runtimes that differ between distributions and must not be needed
+const TOOLCHAIN_RUNTIMES: [&str; 3] = ["libgcc_s.so", "libstdc++.so", "libatomic.so"];
+
+/// Check with `patchelf` that none of the shared objects needs a toolchain runtime
+pub fn verify_static_runtime(binaries: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut dependent = vec![];
+    for binary in binaries {
+        let mut command = Command::new("patchelf");
+        command.arg("--print-needed").arg(binary);
+        println!("{:?}", &command);
+
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(UserFacingError::new(format!(
+                "Could not read the dependencies of {}",
+                binary.display()
+            ))
+            .reason(String::from_utf8_lossy(&output.stderr).trim().to_string())
+            .into());
+        }
+        for needed in String::from_utf8_lossy(&output.stdout).lines() {
+            if TOOLCHAIN_RUNTIMES
+                .iter()
+                .any(|runtime| needed.trim().starts_with(runtime))
+            {
+                dependent.push(format!("{} needs {}", binary.display(), needed.trim()));
+            }
+        }
+    }
+
+    if dependent.is_empty() {
+        return Ok(());
+    }
+    let mut error = UserFacingError::new("The binaries depend on the toolchain runtime");
+    for reason in dependent {
+        error = error.reason(reason);
+    }
+    Err(error
+        .help("Make sure the static libgcc and libstdc++ of the toolchain are installed")
+        .into())
+}
@@ -0,0 +1,103 @@
+use crate::compiler_flags::CompilerFlags;
+use serde::{Deserialize, Serialize};
+use shared_library_builder::LibraryCompilationContext;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// The header that maps every public cairo function to its prefixed name
+pub const SYMBOL_PREFIX_HEADER: &str = "cairo-symbol-prefix.h";
+
+/// Renames cairo's exported functions, for example `cairo_create` to `gt_cairo_create`,
+/// so that our cairo can be loaded into a process that already uses the system cairo.
+/// The renaming header is force-included when compiling cairo and installed next to `cairo.h`,
+/// consumers include it before `cairo.h` to call the prefixed functions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolPrefix {
+    prefix: String,
+}
+
+impl SymbolPrefix {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The names of functions declared `cairo_public` in the public headers of cairo's `src` folder
+    pub fn public_functions(source_directory: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut functions = vec![];
+        for entry in std::fs::read_dir(source_directory.join("src"))? {
+            let path = entry?.path();
+            let is_public_header = path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| name.starts_with("cairo") && name.ends_with(".h"));
+            if is_public_header {
+                functions.extend(Self::declared_functions(&std::fs::read_to_string(&path)?));
+            }
+        }
+        functions.sort();
+        functions.dedup();
+        Ok(functions)
+    }
+
+    /// Declarations span several lines, for example `cairo_public cairo_t *\ncairo_create (...)`,
+    /// so we take the last identifier before the opening parenthesis
+    fn declared_functions(header: &str) -> Vec<String> {
+        header
+            .split("cairo_public")
+            .skip(1)
+            .filter_map(|declaration| declaration.split('(').next())
+            .filter_map(|signature| {
+                signature
+                    .split(|character: char| !(character.is_alphanumeric() || character == '_'))
+                    .rfind(|token| !token.is_empty())
+            })
+            .filter(|name| name.starts_with("cairo_"))
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    pub fn header(&self, functions: &[String]) -> String {
+        let mut header = String::from("#ifndef CAIRO_SYMBOL_PREFIX_H\n#define CAIRO_SYMBOL_PREFIX_H\n\n");
+        for function in functions {
+            header.push_str(&format!("#define {} {}{}\n", function, &self.prefix, function));
+        }
+        header.push_str("\n#endif\n");
+        header
+    }
+
+    /// Generate the renaming header from the sources into `source/src`
+    pub fn write_header(&self, source_directory: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let functions = Self::public_functions(source_directory)?;
+        if functions.is_empty() {
+            return Err(UserFacingError::new("Could not prefix cairo symbols")
+                .reason(format!(
+                    "No public functions found in {}",
+                    source_directory.join("src").display()
+                ))
+                .into());
+        }
+
+        let header = source_directory.join("src").join(SYMBOL_PREFIX_HEADER);
+        std::fs::write(&header, self.header(&functions))?;
+        Ok(header)
+    }
+
+    /// Force-include the renaming header in every compiled file. The header path is quoted
+    /// where the flags are rendered for a makefile or command line, it may contain spaces
+    pub fn flags(&self, header: &Path, context: &LibraryCompilationContext) -> CompilerFlags {
+        if context.is_windows() {
+            CompilerFlags::new().cflag(format!("/FI{}", header.display()))
+        } else {
+            CompilerFlags::new()
+                .cflag("-include")
+                .cflag(header.display().to_string())
+        }
+    }
+}
@@ -0,0 +1,672 @@
+use crate::long_paths::extended_length;
+use crate::patch::{sibling, PatchReport};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use user_error::UserFacingError;
+
+/// How many lines away from where the diff expects them the lines of a hunk are searched for
+const FUZZ_WINDOW: usize = 100;
+
+/// A patch in the unified diff format produced by `diff -u` or `git diff`, applied
+/// in Rust without an external `patch` binary. The contents are kept in memory so that
+/// changing a patch changes the configuration hash of the library it is applied to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnifiedDiff {
+    name: String,
+    contents: String,
+    strip: usize,
+    #[serde(default)]
+    skip_applied_hunks: bool,
+}
+
+#[derive(Debug, Clone)]
+struct FileDiff {
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone)]
+struct Hunk {
+    old_start: usize,
+    /// lines prefixed with ' ', '-' or '+'
+    lines: Vec<(char, String)>,
+}
+
+impl Hunk {
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|(kind, _)| *kind != '+')
+            .map(|(_, line)| line.as_str())
+            .collect()
+    }
+
+    fn new_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|(kind, _)| *kind != '-')
+            .map(|(_, line)| line.as_str())
+            .collect()
+    }
+
+    fn removed_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|(kind, _)| *kind == '-')
+            .map(|(_, line)| line.as_str())
+            .collect()
+    }
+}
+
+impl UnifiedDiff {
+    /// A diff whose paths start with one folder to strip, like the `a/` and `b/` of git
+    pub fn new(name: impl Into<String>, contents: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            contents: contents.into(),
+            strip: 1,
+            skip_applied_hunks: false,
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|error| {
+            UserFacingError::new(format!("Could not read patch {}", path.display()))
+                .reason(error.to_string())
+        })?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+        Ok(Self::new(name, contents))
+    }
+
+    /// How many leading folders to remove from the paths in the diff, like `patch -p`
+    pub fn with_strip(mut self, strip: usize) -> Self {
+        self.strip = strip;
+        self
+    }
+
+    /// Skip hunks whose changes the sources contain already instead of failing, for backports
+    /// that a release may include. The skipped hunks are listed in the [`PatchReport`]
+    pub fn skipping_applied_hunks(mut self) -> Self {
+        self.skip_applied_hunks = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn contents(&self) -> &str {
+        self.contents.as_str()
+    }
+
+    fn error(&self, reason: impl Into<String>) -> Box<dyn Error> {
+        UserFacingError::new(format!("Could not apply patch {}", &self.name))
+            .reason(reason.into())
+            .into()
+    }
+
+    /// The path relative to the root of the sources, a diff must not reach outside of it
+    fn parse_path(&self, line: &str) -> Result<Option<String>, Box<dyn Error>> {
+        // the path may be followed by a tab and a timestamp
+        let path = line.split('\t').next().unwrap_or("").trim();
+        if path == "/dev/null" {
+            return Ok(None);
+        }
+        let components = path.split('/').skip(self.strip).collect::<Vec<&str>>();
+        let relative = components.join("/");
+        let escapes = relative.is_empty()
+            || relative.starts_with('/')
+            || relative.starts_with('\\')
+            || relative.chars().nth(1) == Some(':')
+            || Path::new(&relative).is_absolute()
+            || relative.split(['/', '\\']).any(|component| component == "..");
+        if escapes {
+            return Err(self.error(format!(
+                "{} is not a path inside of the sources",
+                path
+            )));
+        }
+        Ok(Some(relative))
+    }
+
+    fn parse(&self) -> Result<Vec<FileDiff>, Box<dyn Error>> {
+        let mut files: Vec<FileDiff> = vec![];
+        let mut lines = self.contents.lines().peekable();
+
+        while let Some(line) = lines.next() {
+            if let Some(old_path) = line.strip_prefix("--- ") {
+                let new_path = lines
+                    .next()
+                    .and_then(|line| line.strip_prefix("+++ "))
+                    .ok_or_else(|| self.error(format!("Expected +++ after {}", line)))?;
+                files.push(FileDiff {
+                    old_path: self.parse_path(old_path)?,
+                    new_path: self.parse_path(new_path)?,
+                    hunks: vec![],
+                });
+                continue;
+            }
+
+            let header = match line.strip_prefix("@@ -") {
+                Some(header) => header,
+                None => continue,
+            };
+            let file = files
+                .last_mut()
+                .ok_or_else(|| self.error(format!("Hunk without a file header: {}", line)))?;
+
+            // @@ -old_start,old_count +new_start,new_count @@
+            let ranges = header.split(" @@").next().unwrap_or("");
+            let (old_range, new_range) = ranges
+                .split_once(" +")
+                .ok_or_else(|| self.error(format!("Malformed hunk header: {}", line)))?;
+            let range = |range: &str| -> Result<(usize, usize), Box<dyn Error>> {
+                let mut parts = range.split(',');
+                let start = parts.next().unwrap_or("").parse::<usize>()?;
+                let count = parts.next().map_or(Ok(1), |count| count.parse::<usize>())?;
+                Ok((start, count))
+            };
+            let (old_start, mut old_remaining) = range(old_range)
+                .map_err(|_| self.error(format!("Malformed hunk header: {}", line)))?;
+            let (_, mut new_remaining) = range(new_range)
+                .map_err(|_| self.error(format!("Malformed hunk header: {}", line)))?;
+
+            let mut hunk = Hunk {
+                old_start,
+                lines: vec![],
+            };
+            while old_remaining > 0 || new_remaining > 0 {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| self.error("The diff ends in the middle of a hunk"))?;
+                let (kind, text) = match line.chars().next() {
+                    Some(kind @ (' ' | '-' | '+')) => (kind, &line[1..]),
+                    // editors tend to strip the space of empty context lines
+                    None => (' ', ""),
+                    Some('\\') => continue,
+                    Some(_) => return Err(self.error(format!("Unexpected line in a hunk: {}", line))),
+                };
+                if kind != '+' {
+                    old_remaining = old_remaining.saturating_sub(1);
+                }
+                if kind != '-' {
+                    new_remaining = new_remaining.saturating_sub(1);
+                }
+                hunk.lines.push((kind, text.trim_end_matches('\r').to_string()));
+            }
+            // skip a trailing "\ No newline at end of file"
+            while lines.peek().is_some_and(|line| line.starts_with('\\')) {
+                lines.next();
+            }
+            file.hunks.push(hunk);
+        }
+
+        if files.is_empty() {
+            return Err(self.error("The file does not contain a unified diff"));
+        }
+        Ok(files)
+    }
+
+    /// The contents to apply the diff to. Files patched before start from their
+    /// original, see `FilePatch`
+    fn original(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+        let path = extended_length(path);
+        for candidate in [sibling(&path, ".orig")?, sibling(&path, ".bak")?, path.clone()] {
+            if candidate.exists() {
+                return Ok(Some(std::fs::read_to_string(&candidate)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The patched contents and the numbers of the hunks that were skipped
+    fn apply_hunks(
+        &self,
+        path: &str,
+        original: &str,
+        hunks: &[Hunk],
+    ) -> Result<(String, Vec<usize>), Box<dyn Error>> {
+        let mut lines = original
+            .lines()
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .collect::<Vec<String>>();
+        // how far the hunks applied so far moved the following lines
+        let mut offset: isize = 0;
+        let mut skipped = vec![];
+
+        for (index, hunk) in hunks.iter().enumerate() {
+            let old_lines = hunk.old_lines();
+            // a hunk that only adds lines starts after its line instead of at it
+            let start = if old_lines.is_empty() {
+                hunk.old_start
+            } else {
+                hunk.old_start.saturating_sub(1)
+            };
+            let expected = (start as isize + offset).max(0) as usize;
+
+            let new_lines = hunk.new_lines();
+
+            // sources with different indentation still match, like `patch --ignore-whitespace`
+            let position = find_closest(&lines, &old_lines, expected, same_line)
+                .or_else(|| find_closest(&lines, &old_lines, expected, same_ignoring_whitespace));
+            let position = match position {
+                Some(position) => position,
+                None => {
+                    // a backport that is already part of the sources, which no longer
+                    // contain the removed lines
+                    let removed_lines = hunk.removed_lines();
+                    let is_removed = removed_lines.is_empty()
+                        || find_closest(&lines, &removed_lines, expected, same_ignoring_whitespace)
+                            .is_none();
+                    let applied = if self.skip_applied_hunks && is_removed {
+                        find_closest(&lines, &new_lines, expected, same_line).or_else(|| {
+                            find_closest(&lines, &new_lines, expected, same_ignoring_whitespace)
+                        })
+                    } else {
+                        None
+                    };
+                    if let Some(position) = applied {
+                        offset += position as isize - expected as isize;
+                        skipped.push(index + 1);
+                        continue;
+                    }
+                    return Err(self.error(format!(
+                        "Hunk #{} does not match {} within {} lines of line {}",
+                        index + 1,
+                        path,
+                        FUZZ_WINDOW,
+                        hunk.old_start
+                    )));
+                }
+            };
+
+            offset += new_lines.len() as isize - old_lines.len() as isize
+                + (position as isize - expected as isize);
+            // context lines keep their text from the sources
+            let mut matched = lines[position..position + old_lines.len()].iter();
+            let replacement = hunk
+                .lines
+                .iter()
+                .filter_map(|(kind, line)| match kind {
+                    '+' => Some(line.clone()),
+                    '-' => {
+                        matched.next();
+                        None
+                    }
+                    _ => matched.next().cloned(),
+                })
+                .collect::<Vec<String>>();
+            lines.splice(position..position + old_lines.len(), replacement);
+        }
+
+        // files keep their line endings
+        let line_ending = if original.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut contents = lines.join(line_ending);
+        if !lines.is_empty() {
+            contents.push_str(line_ending);
+        }
+        Ok((contents, skipped))
+    }
+
+    /// Apply the diff to the files in the directory. Every hunk is checked before
+    /// any file is written, so a diff that does not apply leaves the sources untouched
+    pub fn apply(&self, directory: &Path) -> Result<Vec<PatchReport>, Box<dyn Error>> {
+        Self::apply_series(std::slice::from_ref(self), directory)
+    }
+
+    /// Apply the diffs one after another, each on top of the result of the previous ones.
+    /// Files always start from their original contents, so applying a series again is idempotent
+    pub fn apply_series(
+        diffs: &[UnifiedDiff],
+        directory: &Path,
+    ) -> Result<Vec<PatchReport>, Box<dyn Error>> {
+        // the original and the current contents of every file touched by the series,
+        // `None` stands for a file that does not exist
+        let mut files: BTreeMap<PathBuf, (Option<String>, Option<String>)> = BTreeMap::new();
+        let mut skipped_hunks: BTreeMap<PathBuf, Vec<(String, usize)>> = BTreeMap::new();
+
+        for diff in diffs {
+            for file in diff.parse()? {
+                let relative_path = file
+                    .new_path
+                    .clone()
+                    .or_else(|| file.old_path.clone())
+                    .ok_or_else(|| diff.error("A file diff without a path"))?;
+                let path = directory.join(&relative_path);
+
+                if !files.contains_key(&path) {
+                    // files created by the series are replaced on every application
+                    let original = match file.old_path {
+                        Some(_) => Self::original(&path)?,
+                        None => None,
+                    };
+                    files.insert(path.clone(), (original.clone(), original));
+                }
+                let (_, current) = files
+                    .get_mut(&path)
+                    .ok_or_else(|| diff.error("A file diff without a path"))?;
+
+                let before = match (&file.old_path, current.as_ref()) {
+                    (Some(_), Some(contents)) => contents.clone(),
+                    (Some(_), None) => {
+                        return Err(diff.error(format!("{} does not exist", path.display())))
+                    }
+                    (None, _) => String::new(),
+                };
+                *current = match file.new_path {
+                    Some(_) => {
+                        let (contents, skipped) =
+                            diff.apply_hunks(&relative_path, &before, &file.hunks)?;
+                        skipped_hunks.entry(path.clone()).or_default().extend(
+                            skipped.into_iter().map(|hunk| (diff.name.clone(), hunk)),
+                        );
+                        Some(contents)
+                    }
+                    None => None,
+                };
+            }
+        }
+
+        let mut reports = vec![];
+        for (report_path, (original, patched)) in files {
+            let path = extended_length(&report_path);
+            if let Some(ref original) = original {
+                let pristine = sibling(&path, ".orig")?;
+                if !pristine.exists() {
+                    std::fs::write(&pristine, original)?;
+                }
+            }
+            match patched {
+                Some(ref contents) => {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&path, contents)?;
+                    // textual patches applied afterwards start from the diffed file
+                    let backup = sibling(&path, ".bak")?;
+                    if backup.exists() {
+                        std::fs::write(&backup, contents)?;
+                    }
+                }
+                None => {
+                    if path.exists() {
+                        std::fs::remove_file(&path)?;
+                    }
+                }
+            }
+            let skipped = skipped_hunks.remove(&report_path).unwrap_or_default();
+            reports.push(
+                PatchReport::new(
+                    &report_path,
+                    original.as_deref().unwrap_or(""),
+                    patched.as_deref().unwrap_or(""),
+                )
+                .with_skipped_hunks(skipped),
+            );
+        }
+        Ok(reports)
+    }
+}
+
+fn same_line(line: &str, expected: &str) -> bool {
+    line == expected
+}
+
+fn same_ignoring_whitespace(line: &str, expected: &str) -> bool {
+    line.split_whitespace().eq(expected.split_whitespace())
+}
+
+/// The position of the lines closest to the expected one, the sources may have moved a little
+/// but not further than [`FUZZ_WINDOW`]
+fn find_closest(
+    lines: &[String],
+    needle: &[&str],
+    expected: usize,
+    same: fn(&str, &str) -> bool,
+) -> Option<usize> {
+    let matches_at = |position: usize| {
+        position + needle.len() <= lines.len()
+            && lines[position..position + needle.len()]
+                .iter()
+                .zip(needle.iter())
+                .all(|(line, expected)| same(line, expected))
+    };
+
+    (0..=FUZZ_WINDOW)
+        .flat_map(|distance| [expected.checked_sub(distance), Some(expected + distance)])
+        .flatten()
+        .filter(|position| *position <= lines.len())
+        .find(|position| matches_at(*position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty folder of its own for every test
+    fn sources(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "libcairo-unified-diff-{}-{}",
+            test,
+            std::process::id()
+        ));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    const DIFF: &str = "--- a/source.c
++++ b/source.c
+@@ -1,3 +1,3 @@
+ int main() {
+-    return 1;
++    return 0;
+ }
+";
+
+    #[test]
+    fn applies_hunks() {
+        let directory = sources("applies");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\n    return 1;\n}\n",
+        )
+        .unwrap();
+
+        UnifiedDiff::new("fix.diff", DIFF)
+            .apply(&directory)
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(directory.join("source.c")).unwrap(),
+            "int main() {\n    return 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn applying_again_is_idempotent() {
+        let directory = sources("idempotent");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\n    return 1;\n}\n",
+        )
+        .unwrap();
+
+        let diff = UnifiedDiff::new("fix.diff", DIFF);
+        diff.apply(&directory).unwrap();
+        diff.apply(&directory).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(directory.join("source.c")).unwrap(),
+            "int main() {\n    return 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let directory = sources("crlf");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\r\n    return 1;\r\n}\r\n",
+        )
+        .unwrap();
+
+        UnifiedDiff::new("fix.diff", DIFF)
+            .apply(&directory)
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(directory.join("source.c")).unwrap(),
+            "int main() {\r\n    return 0;\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn rejects_parent_directories() {
+        let directory = sources("parent");
+        let diff = UnifiedDiff::new("escape.diff", DIFF.replace("a/source.c", "a/../outside.c"));
+
+        assert!(diff.apply(&directory).is_err());
+        assert!(!directory.join("..").join("outside.c").exists());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let directory = sources("absolute");
+        let diff = UnifiedDiff::new(
+            "absolute.diff",
+            DIFF.replace("a/source.c", "/etc/source.c")
+                .replace("b/source.c", "/etc/source.c"),
+        )
+        .with_strip(0);
+
+        assert!(diff.apply(&directory).is_err());
+    }
+
+    #[test]
+    fn fails_on_changes_the_sources_contain_already() {
+        let directory = sources("contained");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\n    return 0;\n}\n",
+        )
+        .unwrap();
+
+        assert!(UnifiedDiff::new("fix.diff", DIFF)
+            .apply(&directory)
+            .is_err());
+    }
+
+    #[test]
+    fn skips_hunks_of_backports_the_sources_contain_already() {
+        let directory = sources("backport");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\n    return 0;\n}\n",
+        )
+        .unwrap();
+
+        let reports = UnifiedDiff::new("fix.diff", DIFF)
+            .skipping_applied_hunks()
+            .apply(&directory)
+            .unwrap();
+
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].skipped_hunks("fix.diff"), vec![1]);
+    }
+
+    #[test]
+    fn rejects_hunks_that_do_not_match() {
+        let directory = sources("mismatch");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\n    return 2;\n}\n",
+        )
+        .unwrap();
+
+        assert!(UnifiedDiff::new("fix.diff", DIFF)
+            .apply(&directory)
+            .is_err());
+        assert_eq!(
+            std::fs::read_to_string(directory.join("source.c")).unwrap(),
+            "int main() {\n    return 2;\n}\n"
+        );
+    }
+
+    #[test]
+    fn finds_hunks_that_moved_within_the_fuzz_window() {
+        let directory = sources("moved");
+        let filler = "// filler\n".repeat(FUZZ_WINDOW);
+        std::fs::write(
+            directory.join("source.c"),
+            format!("{}int main() {{\n    return 1;\n}}\n", filler),
+        )
+        .unwrap();
+
+        UnifiedDiff::new("fix.diff", DIFF)
+            .apply(&directory)
+            .unwrap();
+
+        assert!(std::fs::read_to_string(directory.join("source.c"))
+            .unwrap()
+            .ends_with("int main() {\n    return 0;\n}\n"));
+    }
+
+    #[test]
+    fn rejects_hunks_outside_of_the_fuzz_window() {
+        let directory = sources("far");
+        let filler = "// filler\n".repeat(FUZZ_WINDOW + 1);
+        std::fs::write(
+            directory.join("source.c"),
+            format!("{}int   main() {{\n    return 1;\n}}\n", filler),
+        )
+        .unwrap();
+
+        assert!(UnifiedDiff::new("fix.diff", DIFF)
+            .apply(&directory)
+            .is_err());
+    }
+
+    const DELETION: &str = "--- a/source.c
++++ b/source.c
+@@ -1,3 +1,2 @@
+ int main() {
+-    debug();
+ }
+";
+
+    #[test]
+    fn skips_deletions_the_sources_contain_already() {
+        let directory = sources("deleted");
+        std::fs::write(directory.join("source.c"), "int main() {\n}\n").unwrap();
+
+        let reports = UnifiedDiff::new("delete.diff", DELETION)
+            .skipping_applied_hunks()
+            .apply(&directory)
+            .unwrap();
+
+        assert_eq!(reports[0].skipped_hunks("delete.diff"), vec![1]);
+    }
+
+    #[test]
+    fn deletions_are_not_applied_while_the_removed_lines_remain() {
+        let directory = sources("not-deleted");
+        std::fs::write(
+            directory.join("source.c"),
+            "int main() {\n}\n\nvoid trace() {\n    debug();\n    return;\n}\n",
+        )
+        .unwrap();
+
+        let diff = UnifiedDiff::new("delete.diff", DELETION).skipping_applied_hunks();
+        assert!(diff.apply(&directory).is_err());
+    }
+}
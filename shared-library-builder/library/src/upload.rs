@@ -0,0 +1,215 @@
+use crate::checksums::{ChecksumManifest, CHECKSUMS_FILE};
+use crate::http::HttpClient;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::PathBuf;
+use user_error::UserFacingError;
+
+/// How to authenticate against an upload target. Secrets are read from
+/// environment variables so that they never end up in a serialized configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UploadCredentials {
+    None,
+    Basic {
+        user_variable: String,
+        password_variable: String,
+    },
+    Bearer {
+        token_variable: String,
+    },
+}
+
+/// A place packaged artifacts are mirrored to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UploadTarget {
+    /// An S3 bucket, or any S3 compatible storage when an endpoint is given.
+    /// Uses `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    S3 {
+        bucket: String,
+        region: String,
+        prefix: String,
+        endpoint: Option<String>,
+    },
+    /// Any server accepting `PUT <base_url>/<file name>`
+    Http {
+        base_url: String,
+        credentials: UploadCredentials,
+    },
+}
+
+impl UploadCredentials {
+    /// Authenticate the requests of the client
+    pub fn apply(&self, client: HttpClient) -> Result<HttpClient, Box<dyn Error>> {
+        Ok(match self {
+            Self::None => client,
+            Self::Basic {
+                user_variable,
+                password_variable,
+            } => client.user(variable(user_variable)?, variable(password_variable)?),
+            Self::Bearer { token_variable } => client.header(
+                "Authorization",
+                format!("Bearer {}", variable(token_variable)?),
+            ),
+        })
+    }
+}
+
+fn variable(name: &str) -> Result<String, Box<dyn Error>> {
+    std::env::var(name).map_err(|_| UserFacingError::new(format!("{} is not set", name)).into())
+}
+
+impl UploadTarget {
+    pub fn s3(bucket: impl Into<String>, region: impl Into<String>) -> Self {
+        Self::S3 {
+            bucket: bucket.into(),
+            region: region.into(),
+            prefix: "".to_string(),
+            endpoint: None,
+        }
+    }
+
+    pub fn http(base_url: impl Into<String>, credentials: UploadCredentials) -> Self {
+        Self::Http {
+            base_url: base_url.into(),
+            credentials,
+        }
+    }
+
+    /// Parse `s3://bucket/prefix` (with the region from `AWS_REGION`) or an http(s) url
+    pub fn parse(destination: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(path) = destination.strip_prefix("s3://") {
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+            return Ok(Self::S3 {
+                bucket: bucket.to_string(),
+                region: std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                prefix: prefix.trim_end_matches('/').to_string(),
+                endpoint: None,
+            });
+        }
+        if destination.starts_with("http://") || destination.starts_with("https://") {
+            return Ok(Self::http(destination, UploadCredentials::None));
+        }
+        Err(UserFacingError::new(format!("Unsupported upload destination {}", destination))
+            .help("Use s3://bucket/prefix or an http(s) url")
+            .into())
+    }
+
+    fn client(&self) -> Result<HttpClient, Box<dyn Error>> {
+        let client = HttpClient::new().header("Content-Type", "application/octet-stream");
+        Ok(match self {
+            Self::S3 { region, .. } => client
+                .user(variable("AWS_ACCESS_KEY_ID")?, variable("AWS_SECRET_ACCESS_KEY")?)
+                .aws_sigv4(format!("aws:amz:{}:s3", region)),
+            Self::Http { credentials, .. } => credentials.apply(client)?,
+        })
+    }
+
+    fn url(&self, name: &str) -> String {
+        match self {
+            Self::S3 {
+                bucket,
+                region,
+                prefix,
+                endpoint,
+            } => {
+                let key = if prefix.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{}/{}", prefix, name)
+                };
+                match endpoint {
+                    Some(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, key),
+                    None => format!("https://{}.s3.{}.amazonaws.com/{}", bucket, region, key),
+                }
+            }
+            Self::Http { base_url, .. } => format!("{}/{}", base_url.trim_end_matches('/'), name),
+        }
+    }
+
+    /// The checksum manifest uploaded before, empty if there is none yet
+    fn uploaded_checksums(&self, client: &HttpClient) -> ChecksumManifest {
+        client
+            .get(&self.url(CHECKSUMS_FILE))
+            .map(|contents| ChecksumManifest::parse(&String::from_utf8_lossy(&contents)))
+            .unwrap_or_default()
+    }
+
+    /// Upload the artifacts followed by a checksum manifest that keeps the checksums of
+    /// the artifacts uploaded before
+    pub fn upload(&self, artifacts: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+
+        let manifest_directory =
+            std::env::temp_dir().join(format!("libcairo-upload-{}", std::process::id()));
+        std::fs::create_dir_all(&manifest_directory)?;
+        let manifest = manifest_directory.join(CHECKSUMS_FILE);
+        // the other platforms upload their artifacts to the same place
+        self.uploaded_checksums(&client)
+            .merge(&ChecksumManifest::for_files(artifacts)?)
+            .write(&manifest)?;
+
+        let mut files = artifacts.to_vec();
+        files.push(manifest);
+
+        for file in files {
+            let name = file
+                .file_name()
+                .ok_or_else(|| UserFacingError::new("Could not get file name"))?
+                .to_string_lossy()
+                .to_string();
+            let url = self.url(&name);
+            println!("Uploading {} to {}", &name, &url);
+            client.send_file("PUT", &url, &file)?;
+        }
+        std::fs::remove_dir_all(&manifest_directory)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A target uploading into a folder, curl reads it back through a file url
+    fn folder_target(name: &str) -> (UploadTarget, PathBuf) {
+        let directory = std::env::temp_dir().join(format!(
+            "libcairo-upload-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let target = UploadTarget::http(
+            format!("file://{}", directory.display()),
+            UploadCredentials::None,
+        );
+        (target, directory)
+    }
+
+    #[test]
+    fn nothing_was_uploaded_without_a_manifest() {
+        let (target, directory) = folder_target("empty");
+        let uploaded = target.uploaded_checksums(&target.client().unwrap());
+        assert_eq!(uploaded, ChecksumManifest::new());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn uploading_keeps_the_checksums_of_other_platforms() {
+        let (target, directory) = folder_target("merge");
+        let linux = directory.join("libcairo-x86_64-unknown-linux-gnu.so");
+        let mac = directory.join("libcairo-aarch64-apple-darwin.dylib");
+        std::fs::write(&linux, "linux").unwrap();
+        std::fs::write(&mac, "mac").unwrap();
+        ChecksumManifest::for_files(std::slice::from_ref(&linux))
+            .unwrap()
+            .write(&directory.join(CHECKSUMS_FILE))
+            .unwrap();
+
+        let uploaded = target.uploaded_checksums(&target.client().unwrap());
+        let merged =
+            uploaded.merge(&ChecksumManifest::for_files(std::slice::from_ref(&mac)).unwrap());
+        merged.verify(&linux).unwrap();
+        merged.verify(&mac).unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
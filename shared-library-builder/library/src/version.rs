@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use user_error::UserFacingError;
+
+/// A version of cairo following its `major.minor.micro` numbering.
+/// Odd minor versions are development snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CairoVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub micro: u32,
+}
+
+/// The build system shipped with a given cairo release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildSystem {
+    Autotools,
+    Meson,
+    /// The autotools-era sources compiled on Windows by driving `cl.exe`, `link.exe`
+    /// and `lib.exe` directly, without GNU make or coreutils
+    Msvc,
+}
+
+impl CairoVersion {
+    /// The version we ship by default
+    pub const DEFAULT: CairoVersion = CairoVersion::new(1, 17, 4);
+    /// The first version that can only be built with meson
+    pub const MESON_ONLY: CairoVersion = CairoVersion::new(1, 17, 6);
+
+    pub const fn new(major: u32, minor: u32, micro: u32) -> Self {
+        Self {
+            major,
+            minor,
+            micro,
+        }
+    }
+
+    pub fn is_development_snapshot(&self) -> bool {
+        self.minor % 2 == 1
+    }
+
+    pub fn build_system(&self) -> BuildSystem {
+        if *self >= Self::MESON_ONLY {
+            BuildSystem::Meson
+        } else {
+            BuildSystem::Autotools
+        }
+    }
+
+    /// The name of the folder the source archive extracts to
+    pub fn sources_directory_name(&self) -> String {
+        format!("cairo-{}", self)
+    }
+
+    /// The official download location of the source archive. Development snapshots
+    /// are published separately from stable releases
+    pub fn upstream_archive_url(&self) -> String {
+        let channel = if self.is_development_snapshot() {
+            "snapshots"
+        } else {
+            "releases"
+        };
+        format!(
+            "https://cairographics.org/{}/cairo-{}.tar.xz",
+            channel, self
+        )
+    }
+
+    /// The location of the source archive on feenk's mirror
+    pub fn mirror_archive_url(&self) -> String {
+        format!("https://dl.feenk.com/cairo/cairo-{}.tar.xz", self)
+    }
+}
+
+impl Default for CairoVersion {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Display for CairoVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.micro)
+    }
+}
+
+impl FromStr for CairoVersion {
+    type Err = Box<dyn Error>;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            UserFacingError::new(format!("Invalid cairo version: {}", version))
+                .help("Cairo versions have the form major.minor.micro, for example 1.17.4")
+        };
+
+        let components = version
+            .trim()
+            .split('.')
+            .map(|component| component.parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| invalid())?;
+
+        match components.as_slice() {
+            [major, minor, micro] => Ok(Self::new(*major, *minor, *micro)),
+            _ => Err(invalid().into()),
+        }
+    }
+}
+
+/// The version of a feenkcom/libcairo release with prebuilt binaries, a semantic version
+/// such as `1.2.3` or `1.2.3-rc.1`. Release tags may be prefixed with `v`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BinaryVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre_release: Option<String>,
+    /// The tag exactly as given, with or without the `v` prefix
+    tag: String,
+}
+
+impl BinaryVersion {
+    /// The release tag the version was parsed from
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether the release tag names this version, ignoring the `v` prefix
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        tag.parse::<BinaryVersion>()
+            .map(|version| {
+                version.major == self.major
+                    && version.minor == self.minor
+                    && version.patch == self.patch
+                    && version.pre_release == self.pre_release
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl Display for BinaryVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(ref pre_release) = self.pre_release {
+            write!(f, "-{}", pre_release)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BinaryVersion {
+    type Err = Box<dyn Error>;
+
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        let tag = tag.trim();
+        let invalid = || {
+            UserFacingError::new(format!("Invalid binary version: {:?}", tag))
+                .help("Binary versions are release tags of the form major.minor.patch, optionally prefixed with v, for example v1.2.3")
+        };
+
+        let version = tag.strip_prefix(['v', 'V']).unwrap_or(tag);
+        // build metadata does not take part in the version
+        let version = version.split('+').next().unwrap_or(version);
+        let (numbers, pre_release) = match version.split_once('-') {
+            Some((numbers, pre_release)) => (numbers, Some(pre_release)),
+            None => (version, None),
+        };
+
+        let components = numbers
+            .split('.')
+            .map(|component| {
+                let is_number = !component.is_empty()
+                    && component.chars().all(|character| character.is_ascii_digit())
+                    && (component == "0" || !component.starts_with('0'));
+                if is_number {
+                    component.parse::<u64>().ok()
+                } else {
+                    None
+                }
+            })
+            .collect::<Option<Vec<u64>>>()
+            .ok_or_else(invalid)?;
+
+        if let Some(pre_release) = pre_release {
+            let is_valid = pre_release.split('.').all(|identifier| {
+                !identifier.is_empty()
+                    && identifier
+                        .chars()
+                        .all(|character| character.is_ascii_alphanumeric() || character == '-')
+            });
+            if !is_valid {
+                return Err(invalid().into());
+            }
+        }
+
+        match components.as_slice() {
+            [major, minor, patch] => Ok(Self {
+                major: *major,
+                minor: *minor,
+                patch: *patch,
+                pre_release: pre_release.map(|pre_release| pre_release.to_string()),
+                tag: tag.to_string(),
+            }),
+            _ => Err(invalid().into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_versions_accept_a_v_prefix() {
+        let version = "v1.2.3".parse::<BinaryVersion>().unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+        assert_eq!(version.pre_release, None);
+        assert_eq!(version.tag(), "v1.2.3");
+        assert_eq!(version.to_string(), "1.2.3");
+    }
+
+    #[test]
+    fn binary_versions_keep_the_pre_release_and_drop_build_metadata() {
+        let version = "1.2.3-rc.1+build.5".parse::<BinaryVersion>().unwrap();
+        assert_eq!(version.pre_release.as_deref(), Some("rc.1"));
+        assert_eq!(version.to_string(), "1.2.3-rc.1");
+    }
+
+    #[test]
+    fn invalid_binary_versions_are_rejected() {
+        for tag in [
+            "",
+            "latest",
+            "v1.2",
+            "1.2.3.4",
+            "01.2.3",
+            "1.2.x",
+            "1.2.3-",
+            "1.2.3-rc..1",
+        ] {
+            assert!(
+                tag.parse::<BinaryVersion>().is_err(),
+                "{:?} was accepted",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn binary_versions_match_tags_with_or_without_the_prefix() {
+        let version = "1.2.3".parse::<BinaryVersion>().unwrap();
+        assert!(version.matches_tag("v1.2.3"));
+        assert!(version.matches_tag("1.2.3"));
+        assert!(!version.matches_tag("v1.2.4"));
+        assert!(!version.matches_tag("v1.2.3-rc.1"));
+        assert!(!version.matches_tag("nightly"));
+    }
+
+    #[test]
+    fn cairo_versions_parse_and_display() {
+        let version = " 1.17.4 ".parse::<CairoVersion>().unwrap();
+        assert_eq!(version, CairoVersion::new(1, 17, 4));
+        assert_eq!(version.to_string(), "1.17.4");
+        assert!(version.is_development_snapshot());
+        assert_eq!(version.build_system(), BuildSystem::Autotools);
+        assert_eq!(
+            "1.18.0".parse::<CairoVersion>().unwrap().build_system(),
+            BuildSystem::Meson
+        );
+    }
+
+    #[test]
+    fn invalid_cairo_versions_are_rejected() {
+        for version in ["", "1.17", "1.17.4.1", "v1.17.4", "1.17.x"] {
+            assert!(
+                version.parse::<CairoVersion>().is_err(),
+                "{:?} was accepted",
+                version
+            );
+        }
+    }
+}
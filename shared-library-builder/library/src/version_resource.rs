@@ -0,0 +1,88 @@
+use crate::version::{BinaryVersion, CairoVersion};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// The release of this crate that produced the binaries
+pub const RELEASE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The tag of the feenkcom/libcairo release built from this version of the crate, `v` followed
+/// by the crate version. A release pipeline whose tags differ sets `LIBCAIRO_RELEASE_TAG`
+/// when compiling the crate. Whether the release exists is checked when its binaries are fetched
+pub fn crate_release_tag() -> Result<BinaryVersion, Box<dyn Error>> {
+    match option_env!("LIBCAIRO_RELEASE_TAG") {
+        Some(tag) => tag.parse::<BinaryVersion>(),
+        None => format!("v{}", RELEASE_VERSION).parse::<BinaryVersion>(),
+    }
+}
+
+/// The source of a VERSIONINFO resource describing a cairo DLL
+pub fn version_resource(version: CairoVersion, release: &str) -> String {
+    format!(
+        r#"#include <winver.h>
+
+VS_VERSION_INFO VERSIONINFO
+FILEVERSION {major},{minor},{micro},0
+PRODUCTVERSION {major},{minor},{micro},0
+FILEFLAGSMASK VS_FFI_FILEFLAGSMASK
+FILEFLAGS 0
+FILEOS VOS_NT_WINDOWS32
+FILETYPE VFT_DLL
+FILESUBTYPE VFT2_UNKNOWN
+BEGIN
+    BLOCK "StringFileInfo"
+    BEGIN
+        BLOCK "040904B0"
+        BEGIN
+            VALUE "CompanyName", "feenk gmbh"
+            VALUE "FileDescription", "cairo graphics library"
+            VALUE "FileVersion", "{version}"
+            VALUE "InternalName", "cairo"
+            VALUE "OriginalFilename", "cairo.dll"
+            VALUE "ProductName", "cairo"
+            VALUE "ProductVersion", "{version} (libcairo {release})"
+        END
+    END
+    BLOCK "VarFileInfo"
+    BEGIN
+        VALUE "Translation", 0x409, 1200
+    END
+END
+"#,
+        major = version.major,
+        minor = version.minor,
+        micro = version.micro,
+        version = version,
+        release = release
+    )
+}
+
+/// Write and compile the version resource with `rc.exe`, returning the `.res` file to link
+pub fn compile_version_resource(
+    version: CairoVersion,
+    release: &str,
+    directory: &Path,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let source = directory.join("cairo-version.rc");
+    let resource = directory.join("cairo-version.res");
+
+    std::fs::write(&source, version_resource(version, release))?;
+
+    let mut command = Command::new("rc");
+    command
+        .current_dir(directory)
+        .arg("/nologo")
+        .arg("/fo")
+        .arg(&resource)
+        .arg(&source);
+
+    println!("{:?}", &command);
+
+    if !command.status()?.success() {
+        return Err(UserFacingError::new("Could not compile the version resource")
+            .help("rc.exe is part of the Windows SDK, run from a Developer Command Prompt")
+            .into());
+    }
+    Ok(resource)
+}
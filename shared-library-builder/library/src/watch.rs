@@ -0,0 +1,150 @@
+use crate::cairo_library::CairoLibrary;
+use crate::scheduler::ParallelBuild;
+use shared_library_builder::{Library, LibraryCompilationContext};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The file extensions of the sources that trigger a rebuild. Changes to the build files
+/// need a new configuration and thus a full compile
+const WATCHED_EXTENSIONS: [&str; 2] = ["c", "h"];
+
+/// An edit-compile-test loop for a local cairo source tree: cairo and its dependencies are
+/// compiled once, then the sources are polled and every change is recompiled and installed
+/// with [`CairoLibrary::rebuild`], optionally followed by the smoke tests.
+/// Failing rebuilds are reported and the watch goes on until the process is stopped
+#[derive(Debug, Clone)]
+pub struct Watch {
+    cairo: CairoLibrary,
+    interval: Duration,
+    smoke_tests: bool,
+}
+
+impl Watch {
+    pub fn new(cairo: CairoLibrary) -> Self {
+        Self {
+            cairo,
+            interval: Duration::from_secs(1),
+            smoke_tests: false,
+        }
+    }
+
+    /// How often the source tree is checked for changes, every second by default
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_smoke_tests(mut self, smoke_tests: bool) -> Self {
+        self.smoke_tests = smoke_tests;
+        self
+    }
+
+    /// Build everything once and rebuild cairo on every change, only returns when the
+    /// first build fails
+    pub fn run(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        let cairo = &self.cairo;
+        cairo.check_requirements(context)?;
+        cairo.ensure_all_sources(context)?;
+        if let Some(dependencies) = cairo.dependencies() {
+            ParallelBuild::available().compile(dependencies.iter(), context)?;
+        }
+        cairo.compile(context)?;
+        self.test(context)?;
+
+        let sources = cairo.source_directory(context);
+        let mut snapshot = SourceSnapshot::of(&sources)?;
+        println!("Watching {}", sources.display());
+        loop {
+            std::thread::sleep(self.interval);
+            let current = SourceSnapshot::of(&sources)?;
+            let changed = snapshot.changed_files(&current);
+            snapshot = current;
+            if changed.is_empty() {
+                continue;
+            }
+
+            for file in &changed {
+                println!("Changed {}", file.display());
+            }
+            match cairo.rebuild(context).and_then(|_| self.test(context)) {
+                Ok(()) => println!("Rebuilt {}", cairo.name()),
+                Err(error) => println!("Could not rebuild {}: {}", cairo.name(), error),
+            }
+        }
+    }
+
+    fn test(&self, context: &LibraryCompilationContext) -> Result<(), Box<dyn Error>> {
+        if self.smoke_tests {
+            self.cairo.run_smoke_tests(context)?;
+        }
+        Ok(())
+    }
+}
+
+/// The modification times of the watched files in a source tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SourceSnapshot {
+    files: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl SourceSnapshot {
+    fn of(directory: &Path) -> Result<Self, Box<dyn Error>> {
+        fn collect(
+            directory: &Path,
+            files: &mut BTreeMap<PathBuf, SystemTime>,
+        ) -> Result<(), Box<dyn Error>> {
+            for entry in std::fs::read_dir(directory)? {
+                let path = entry?.path();
+                // .git and the folders of editors
+                let hidden = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().starts_with('.'))
+                    .unwrap_or(false);
+                if hidden {
+                    continue;
+                }
+                if path.is_dir() {
+                    collect(&path, files)?;
+                    continue;
+                }
+                let watched = path
+                    .extension()
+                    .map(|extension| {
+                        WATCHED_EXTENSIONS.contains(&extension.to_string_lossy().as_ref())
+                    })
+                    .unwrap_or(false);
+                if watched {
+                    // files may be replaced by editors while we look at them
+                    if let Ok(modified) = std::fs::metadata(&path).and_then(|file| file.modified())
+                    {
+                        files.insert(path, modified);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        let mut files = BTreeMap::new();
+        collect(directory, &mut files)?;
+        Ok(Self { files })
+    }
+
+    /// Files that were added, modified or removed since this snapshot
+    fn changed_files(&self, current: &SourceSnapshot) -> Vec<PathBuf> {
+        let mut changed = current
+            .files
+            .iter()
+            .filter(|(file, modified)| self.files.get(*file) != Some(modified))
+            .map(|(file, _)| file.clone())
+            .collect::<Vec<PathBuf>>();
+        changed.extend(
+            self.files
+                .keys()
+                .filter(|file| !current.files.contains_key(*file))
+                .cloned(),
+        );
+        changed
+    }
+}
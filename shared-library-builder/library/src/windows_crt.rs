@@ -0,0 +1,56 @@
+use crate::patch::{FilePatch, Occurrences};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// The runtime flags of MSVC, with and without debug information
+const RUNTIME_FLAGS: [&str; 8] = ["-MT", "-MD", "/MT", "/MD", "-MTd", "-MDd", "/MTd", "/MDd"];
+
+/// How the MSVC C runtime is linked into cairo and its dependencies.
+/// All libraries loaded into the same process should agree on it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowsCrt {
+    /// Link the runtime statically (`/MT`), the resulting DLLs have no runtime dependencies
+    #[default]
+    Static,
+    /// Link against the shared runtime DLL (`/MD`), required when embedding cairo
+    /// into a host process that itself uses `/MD`
+    Dynamic,
+}
+
+impl WindowsCrt {
+    pub fn msvc_flag(&self) -> &'static str {
+        match self {
+            Self::Static => "-MT",
+            Self::Dynamic => "-MD",
+        }
+    }
+
+    /// Replace the runtime flags in a makefile that defaults to `-MD`, and `-MDd` for debug builds
+    pub fn patch_makefile(&self, patch: FilePatch) -> FilePatch {
+        patch
+            .replace_flag("-MD", self.msvc_flag(), Occurrences::AtLeast(1))
+            .replace_flag(
+                "-MDd",
+                format!("{}d", self.msvc_flag()),
+                Occurrences::AtLeast(0),
+            )
+    }
+
+    /// Force the runtime for every `cl.exe` the command runs. Options in `_CL_` are appended
+    /// to the command line and override the flags of the makefiles
+    pub fn apply(&self, command: &mut Command) {
+        let options = std::env::var("_CL_").unwrap_or_default();
+        let options = options
+            .split_whitespace()
+            .filter(|option| !RUNTIME_FLAGS.contains(option))
+            .chain([self.msvc_flag()])
+            .collect::<Vec<&str>>()
+            .join(" ");
+        command.env("_CL_", options);
+    }
+
+    /// Force the runtime in a CMake project, see [`crate::patch::cmake_project_patch`]
+    pub fn cmake_option(&self) -> String {
+        format!("add_compile_options({})", self.msvc_flag())
+    }
+}
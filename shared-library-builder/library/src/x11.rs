@@ -0,0 +1,145 @@
+use crate::features::CairoFeature;
+use std::process::Command;
+use user_error::UserFacingError;
+
+/// An X11 library cairo's X backends need development headers for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct X11Package {
+    pub pkg_config: &'static str,
+    pub apt: &'static str,
+    pub dnf: &'static str,
+    pub pacman: &'static str,
+    pub zypper: &'static str,
+    pub apk: &'static str,
+}
+
+impl X11Package {
+    pub const X11: X11Package = X11Package {
+        pkg_config: "x11",
+        apt: "libx11-dev",
+        dnf: "libX11-devel",
+        pacman: "libx11",
+        zypper: "libX11-devel",
+        apk: "libx11-dev",
+    };
+    pub const XEXT: X11Package = X11Package {
+        pkg_config: "xext",
+        apt: "libxext-dev",
+        dnf: "libXext-devel",
+        pacman: "libxext",
+        zypper: "libXext-devel",
+        apk: "libxext-dev",
+    };
+    pub const XRENDER: X11Package = X11Package {
+        pkg_config: "xrender",
+        apt: "libxrender-dev",
+        dnf: "libXrender-devel",
+        pacman: "libxrender",
+        zypper: "libXrender-devel",
+        apk: "libxrender-dev",
+    };
+
+    /// The packages needed by the requested features
+    pub fn required_by(features: &[CairoFeature]) -> Vec<X11Package> {
+        let mut packages = vec![];
+        if features.contains(&CairoFeature::Xlib) || features.contains(&CairoFeature::XlibXrender) {
+            packages.push(Self::X11);
+            packages.push(Self::XEXT);
+        }
+        if features.contains(&CairoFeature::XlibXrender) {
+            packages.push(Self::XRENDER);
+        }
+        packages
+    }
+
+    pub fn is_installed(&self) -> bool {
+        Command::new("pkg-config")
+            .arg("--exists")
+            .arg(self.pkg_config)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+}
+
+/// The Linux distribution families we know the X11 package names for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxDistribution {
+    Debian,
+    Fedora,
+    Arch,
+    Suse,
+    Alpine,
+    Unknown,
+}
+
+impl LinuxDistribution {
+    /// Read `ID` and `ID_LIKE` from `/etc/os-release`
+    pub fn current() -> Self {
+        std::fs::read_to_string("/etc/os-release")
+            .map(|os_release| Self::parse(&os_release))
+            .unwrap_or(Self::Unknown)
+    }
+
+    pub fn parse(os_release: &str) -> Self {
+        let ids = os_release
+            .lines()
+            .filter(|line| line.starts_with("ID=") || line.starts_with("ID_LIKE="))
+            .flat_map(|line| line.split('=').nth(1).unwrap_or("").trim_matches('"').split(' '))
+            .collect::<Vec<&str>>();
+
+        let is = |names: &[&str]| ids.iter().any(|id| names.contains(id));
+
+        if is(&["debian", "ubuntu"]) {
+            Self::Debian
+        } else if is(&["fedora", "rhel", "centos"]) {
+            Self::Fedora
+        } else if is(&["arch"]) {
+            Self::Arch
+        } else if is(&["suse", "opensuse"]) {
+            Self::Suse
+        } else if is(&["alpine"]) {
+            Self::Alpine
+        } else {
+            Self::Unknown
+        }
+    }
+
+    pub fn install_command(&self, packages: &[X11Package]) -> String {
+        let names = |name: fn(&X11Package) -> &'static str| {
+            packages.iter().map(name).collect::<Vec<&str>>().join(" ")
+        };
+        match self {
+            Self::Debian => format!("sudo apt install {}", names(|package| package.apt)),
+            Self::Fedora => format!("sudo dnf install {}", names(|package| package.dnf)),
+            Self::Arch => format!("sudo pacman -S {}", names(|package| package.pacman)),
+            Self::Suse => format!("sudo zypper install {}", names(|package| package.zypper)),
+            Self::Alpine => format!("sudo apk add {}", names(|package| package.apk)),
+            Self::Unknown => format!(
+                "Install the development packages providing the pkg-config modules: {}",
+                names(|package| package.pkg_config)
+            ),
+        }
+    }
+}
+
+/// Explain a failed configure if it is caused by missing X11 development headers
+pub fn missing_x11_headers(features: &[CairoFeature]) -> Option<UserFacingError> {
+    let missing = X11Package::required_by(features)
+        .into_iter()
+        .filter(|package| !package.is_installed())
+        .collect::<Vec<X11Package>>();
+
+    if missing.is_empty() {
+        return None;
+    }
+
+    let mut error = UserFacingError::new("Could not configure cairo with the X backends");
+    for package in &missing {
+        error = error.reason(format!(
+            "Missing development headers for `{}`",
+            package.pkg_config
+        ));
+    }
+    Some(error.help(LinuxDistribution::current().install_command(&missing)))
+}